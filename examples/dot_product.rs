@@ -0,0 +1,42 @@
+//! Times `math::dot` against ndarray's dot product over a synthetic store.
+//!
+//! cargo run --release --example dot_product -- [rows] [dimensions]
+//!
+//! Natively this measures the scalar fallback. To measure the simd128 path, build the
+//! example for `wasm32-wasip1` (simd128 is enabled for every wasm target) and run it
+//! with a WASI runtime such as wasmtime.
+
+use std::env;
+use std::hint::black_box;
+use std::time::Instant;
+
+use ndarray::ArrayView1;
+use obsidian_rust_plugin::math;
+
+/// Times each dot product over every row this many times and keeps the fastest run
+const RUNS: usize = 5;
+
+fn time(name: &str, query: &[f32], rows: &[Vec<f32>], dot: impl Fn(&[f32], &[f32]) -> f32) {
+    let mut best = f64::MAX;
+    let mut checksum = 0.0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        checksum = rows.iter().map(|row| dot(black_box(query), black_box(row))).sum::<f32>();
+        best = best.min(start.elapsed().as_secs_f64());
+    }
+    println!("{:>8}: {:8.2} ms, {:6.1} ns per row (checksum {:.3})", name, best * 1000.0, best * 1e9 / rows.len() as f64, checksum);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let arg = |i: usize, default: usize| args.get(i).map_or(default, |n| n.parse().expect("arguments must be numbers"));
+    let (rows, dimensions) = (arg(0, 50_000), arg(1, 1536));
+
+    let vector = |seed: usize| -> Vec<f32> { (0..dimensions).map(|i| ((seed * dimensions + i) as f32 * 0.618).sin()).collect() };
+    let query = vector(rows);
+    let store: Vec<Vec<f32>> = (0..rows).map(vector).collect();
+    println!("{} rows of {} dimensions", rows, dimensions);
+
+    time("math", &query, &store, math::dot);
+    time("ndarray", &query, &store, |left, right| ArrayView1::from(left).dot(&ArrayView1::from(right)));
+}
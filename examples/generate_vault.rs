@@ -0,0 +1,33 @@
+//! Writes a synthetic vault to disk.
+//!
+//! cargo run --example generate_vault --features test-vault -- <dir> [notes] [topics] [edge case rate] [seed]
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+use obsidian_rust_plugin::test_vault::{GeneratedVault, VaultSpec};
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let dir = match args.first() {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            eprintln!("usage: generate_vault <dir> [notes] [topics] [edge case rate] [seed]");
+            process::exit(1);
+        }
+    };
+    let defaults = VaultSpec::default();
+    let arg = |i: usize| args.get(i).map(|arg| arg.as_str());
+    let spec = VaultSpec {
+        notes: arg(1).map_or(defaults.notes, |n| n.parse().expect("notes must be a number")),
+        topics: arg(2).map_or(defaults.topics, |n| n.parse().expect("topics must be a number")),
+        edge_case_rate: arg(3).map_or(defaults.edge_case_rate, |n| n.parse().expect("edge case rate must be a number")),
+        seed: arg(4).map_or(defaults.seed, |n| n.parse().expect("seed must be a number")),
+        ..defaults
+    };
+
+    let vault = GeneratedVault::generate(&spec);
+    vault.write_to(&dir).expect("failed to write vault");
+    println!("Wrote {} notes to {}", vault.notes.len(), dir.display());
+}
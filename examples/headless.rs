@@ -0,0 +1,53 @@
+//! Builds an index of a folder of notes, searches it and updates it after an edit, without Obsidian.
+//!
+//! cargo run --example headless --features headless -- <vault dir> [query]
+//!
+//! Notes go through the plugin's embedding pipeline, with embeddings from the offline
+//! `HashingEmbedder`, so results only reflect shared words; implement `Embedder` over an
+//! HTTP client to use a real model. The folder is only read, the edit is made to the notes
+//! in memory.
+
+use std::env;
+use std::process;
+
+use obsidian_rust_plugin::headless::{FolderVault, HashingEmbedder, HeadlessIndex, Settings, UpdateReport};
+
+fn print_report(step: &str, report: &UpdateReport) {
+    println!("{}: {} added, {} changed, {} removed, {} sections embedded",
+        step, report.added.len(), report.changed.len(), report.removed.len(), report.records_embedded);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let dir = match args.first() {
+        Some(dir) => dir,
+        None => {
+            eprintln!("usage: headless <vault dir> [query]");
+            process::exit(1);
+        }
+    };
+    let query = args.get(1).map_or("notes", |query| query.as_str());
+    let settings = Settings { section_delimeter_regex: "^#{1,6} ".to_string(), ..Default::default() };
+
+    let mut notes = FolderVault::new(dir).read_notes(&settings.ignored_folders).expect("failed to read vault");
+    let (mut index, report) = HeadlessIndex::build(settings, &notes, HashingEmbedder::new(256)).expect("failed to build index");
+    print_report("build", &report);
+
+    println!("top results for '{}':", query);
+    for candidate in index.query(query, 5).expect("query failed") {
+        println!("  {:.3}  {} > {}", candidate.score, candidate.name, candidate.header);
+    }
+
+    let report = index.update(&notes).expect("failed to update index");
+    print_report("update without changes", &report);
+
+    if let Some(note) = notes.first_mut() {
+        note.content.push_str(&format!("\n# Appended\nA new section about {}\n", query));
+        let edited = note.path.clone();
+        let report = index.update(&notes).expect("failed to update index");
+        print_report(&format!("update after editing {}", edited), &report);
+    }
+
+    let csv = index.to_csv().expect("failed to serialize index");
+    println!("{} sections, {} bytes as embedding.csv", index.len(), csv.len());
+}
@@ -0,0 +1,53 @@
+//! Times writing a 10k record run in batches of 100, with the `RowWriter` the embedding pipeline
+//! keeps for the whole run against a fresh CSV writer, per-float strings and cloned provenance
+//! for every batch.
+//!
+//! cargo run --release --example row_writer -- [records] [dimensions]
+
+use std::env;
+use std::time::Instant;
+
+use obsidian_rust_plugin::embedding_file::RowWriter;
+use obsidian_rust_plugin::provenance::Provenance;
+use obsidian_rust_plugin::quantization::Quantization;
+
+/// Records written per batch, as the pipeline persists them
+const BATCH_SIZE: usize = 100;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let arg = |i: usize, default: usize| args.get(i).map_or(default, |n| n.parse().expect("arguments must be numbers"));
+    let (records, dimensions) = (arg(0, 10_000), arg(1, 1536));
+
+    let embedding: Vec<f32> = (0..dimensions).map(|i| (i as f32 * 0.618).sin()).collect();
+    let names: Vec<String> = (0..records).map(|i| format!("note {}.md", i)).collect();
+    let provenance = Provenance { model: "text-embedding-3-small".to_string(), provider: "https://api.openai.com/v1".to_string(), embedded_at: 1.7e12, settings_hash: "0123456789abcdef".to_string(), lines: None, note_times: None, language: None, path: None };
+
+    let start = Instant::now();
+    let mut per_batch = 0;
+    for batch in names.chunks(BATCH_SIZE) {
+        let provenance: Vec<Provenance> = batch.iter().map(|_| provenance.clone()).collect();
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        for (name, provenance) in batch.iter().zip(&provenance) {
+            let embedding = embedding.iter().map(|f| f.to_string()).collect::<Vec<String>>().join(",");
+            wtr.write_record([name.as_str(), "Header", embedding.as_str(), &provenance.model, &provenance.provider, &provenance.embedded_at.to_string(), &provenance.settings_hash, "", ""]).unwrap();
+        }
+        per_batch += String::from_utf8(wtr.into_inner().unwrap()).unwrap().len();
+    }
+    let per_batch_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut per_run = 0;
+    let mut writer = RowWriter::new(Quantization::None);
+    for batch in names.chunks(BATCH_SIZE) {
+        for name in batch {
+            writer.write(name, "Header", &embedding, Some(&provenance)).unwrap();
+        }
+        per_run += writer.take().unwrap().len();
+    }
+    let per_run_time = start.elapsed();
+
+    println!("{} records of {} dimensions, {} MB written", records, dimensions, per_run / 1_000_000);
+    println!("writer per batch: {:?} ({} bytes)", per_batch_time, per_batch);
+    println!("writer per run:   {:?} ({} bytes)", per_run_time, per_run);
+}
@@ -1,27 +1,60 @@
-import { Editor, MarkdownView, Menu, Notice, Plugin } from 'obsidian';
+import { Editor, MarkdownView, Menu, Notice, Plugin, TAbstractFile, TFile } from 'obsidian';
+import { CompactionReport, MigrationReport, ReindexProgress, ReindexReport, SemanticSearchApi } from 'src/api';
 import { semanticSearchSettings, SemanticSearchSettingTab } from 'src/settings/settings';
+import { AnswerModal } from 'src/ui/answerModal';
+import { ClustersModal } from 'src/ui/clustersModal';
+import { CoverageModal } from 'src/ui/coverageModal';
+import { DuplicatesModal } from 'src/ui/duplicatesModal';
+import { noticeForError } from 'src/ui/errors';
 import { GenerateEmbeddingsModal } from 'src/ui/generateEmbeddingsModal';
+import { VaultEstimateModal } from 'src/ui/vaultEstimateModal';
+import { ExportEmbeddingsModal } from 'src/ui/exportModal';
+import { ImportEmbeddingsModal } from 'src/ui/importModal';
+import { IndexStats, summarizeIndexStats } from 'src/ui/indexStats';
 import { LinkSuggest } from 'src/ui/linkSuggest';
-import { LinkSuggestQueryModal, QueryModal } from 'src/ui/queryModal';
+import { MentionsModal } from 'src/ui/mentionsModal';
+import { LinkSuggestQueryModal, QueryModal, RelatedTextModal, SimilarNotesModal } from 'src/ui/queryModal';
+import { currentParagraph, LinkCandidate, RelatedLinkModal } from 'src/ui/relatedLinkModal';
 
 import * as plugin from "./pkg/obsidian_rust_plugin.js";
 import * as wasmbin from './pkg/obsidian_rust_plugin_bg.wasm';
 
+// How often a pending batch job is checked on
+const BATCH_POLL_INTERVAL_MS = 5 * 60 * 1000;
+// How often to wait for Obsidian to be idle and index a few changed notes, when indexing when idle
+const IDLE_INDEX_INTERVAL_MS = 5 * 1000;
+
 export default class SemanticSearch extends Plugin {
 	settings: semanticSearchSettings;
+	api: SemanticSearchApi;
+	// Embeddings kept in memory so repeated searches don't re-read the embedding file
+	index: plugin.SemanticIndex;
 
 	async onload() {
 		await this.loadSettings();
 
+		// here's the Rust bit
+		await plugin.default(Promise.resolve(wasmbin.default));
+		plugin.onload(this);
+		plugin.configure_logging(this.app, this.settings).catch((error: unknown) => console.error(error));
+		plugin.configure_encryption(this.settings);
+		this.index = new plugin.SemanticIndex();
+		this.api = new SemanticSearchApi(this.app, this.settings, this.index);
+		// files written by older versions are upgraded before anything reads them, and there is
+		// nothing to load until embeddings have been generated
+		this.migrateStores()
+			.then(() => this.index.load(this.app, this.settings))
+			.catch((error: unknown) => console.debug(error));
+
 		this.addRibbonIcon('file-search-2', 'Semantic Search', (_: MouseEvent) => {
-      new QueryModal(this.app, this.settings).open();
+      new QueryModal(this.app, this.settings, this.index).open();
 		});
 
 		this.addCommand({
 			id: 'open-query-modal',
 			name: 'Open query modal',
 			callback: () => {
-				new QueryModal(this.app, this.settings).open();
+				new QueryModal(this.app, this.settings, this.index).open();
 			}
 		});
 
@@ -29,19 +62,109 @@ export default class SemanticSearch extends Plugin {
 			id: 'open-link-suggest-query-modal',
 			name: 'Recommend links using current selection',
 			editorCallback: (editor: Editor, view: MarkdownView) => {
-				new LinkSuggestQueryModal(this.app, this.settings, editor).open();
+				new LinkSuggestQueryModal(this.app, this.settings, editor, this.index).open();
+			}
+		});
+
+		const relatedTextCommand = this.addCommand({
+			id: 'open-related-text-modal',
+			name: 'Find notes related to current selection',
+			editorCallback: (editor: Editor, view: MarkdownView) => {
+				new RelatedTextModal(this.app, this.settings, editor).open();
+			}
+		});
+
+		const relatedLinkCommand = this.addCommand({
+			id: 'insert-related-link',
+			name: 'Insert related link',
+			editorCallback: async (editor: Editor, view: MarkdownView) => {
+        const paragraph = currentParagraph(editor);
+        if (paragraph.trim() === "") {
+          new Notice("The cursor is not in a paragraph");
+          return;
+        }
+        try {
+          const candidates: LinkCandidate[] = await plugin.suggest_links(this.app, this.settings, paragraph, view.file?.path ?? "");
+          if (candidates.length === 0) {
+            new Notice("No related sections found");
+            return;
+          }
+          new RelatedLinkModal(this.app, editor, candidates).open();
+        } catch (error) {
+          noticeForError(error, "Failed to suggest links");
+        }
+			}
+		});
+
+		this.addCommand({
+			id: 'open-similar-notes-modal',
+			name: 'Find notes similar to current note',
+			checkCallback: (checking: boolean) => {
+				const file = this.app.workspace.getActiveFile();
+				if (file) {
+					if (!checking) {
+						new SimilarNotesModal(this.app, this.settings, [file]).open();
+					}
+					return true;
+				}
+				return false;
+			}
+		});
+
+		this.addCommand({
+			id: 'open-unlinked-mentions-modal',
+			name: 'Find unlinked mentions of current note',
+			checkCallback: (checking: boolean) => {
+				const file = this.app.workspace.getActiveFile();
+				if (file) {
+					if (!checking) {
+						new MentionsModal(this.app, this.settings, file).open();
+					}
+					return true;
+				}
+				return false;
+			}
+		});
+
+		this.addCommand({
+			id: 'open-answer-modal',
+			name: 'Answer question from notes',
+			// only offered once an answer model is configured
+			checkCallback: (checking: boolean) => {
+				if (this.settings.answerModel.trim() === '') {
+					return false;
+				}
+				if (!checking) {
+					new AnswerModal(this.app, this.settings).open();
+				}
+				return true;
+			}
+		});
+
+		this.addCommand({
+			id: 'open-duplicates-modal',
+			name: 'Find near-duplicate sections',
+			callback: () => {
+				new DuplicatesModal(this.app, this.settings).open();
+			}
+		});
+
+		this.addCommand({
+			id: 'open-clusters-modal',
+			name: 'Cluster notes by topic',
+			callback: () => {
+				new ClustersModal(this.app, this.settings).open();
 			}
 		});
 
 		this.addCommand({
 			id: 'generate-input',
 			name: 'Generate Input',
-			callback: () => {
+			callback: async () => {
         try {
-          new plugin.GenerateInputCommand(this.app, this.settings).callback();
+          await new plugin.GenerateInputCommand(this.app, this.settings).callback();
         } catch (error) {
-          new Notice("Failed to generate input");
-          console.error(error);
+          noticeForError(error, "Failed to generate input");
         }
 			}
 		});
@@ -54,8 +177,127 @@ export default class SemanticSearch extends Plugin {
 			}
 		});
 
+		this.addCommand({
+			id: 'reindex-vault',
+			name: 'Reindex vault',
+			callback: async () => {
+        const notice = new Notice("Reindexing vault...", 0);
+        try {
+          const report: ReindexReport = await this.index.reindex_vault(this.app, this.settings, (progress: ReindexProgress) => {
+            notice.setMessage(`Reindexing vault: ${progress.stage} ${progress.done}/${progress.total}`);
+          });
+          notice.hide();
+          new Notice(`Reindexed ${report.inputRecords} sections into ${report.indexed} embeddings`);
+        } catch (error) {
+          notice.hide();
+          noticeForError(error, "Failed to reindex the vault");
+        }
+			}
+		});
+
+		this.addCommand({
+			id: 'open-vault-estimate-modal',
+			name: 'Estimate embedding cost',
+			callback: () => {
+				new VaultEstimateModal(this.app, this.settings).open();
+			}
+		});
+
+		this.addCommand({
+			id: 'open-import-embeddings-modal',
+			name: 'Import embeddings from another plugin',
+			callback: () => {
+				new ImportEmbeddingsModal(this.app, this.settings).open();
+			}
+		});
+
+		this.addCommand({
+			id: 'open-export-embeddings-modal',
+			name: 'Export embeddings',
+			callback: () => {
+				new ExportEmbeddingsModal(this.app, this.settings).open();
+			}
+		});
+
+		this.addCommand({
+			id: 'show-index-health',
+			name: 'Show index health',
+			callback: async () => {
+        try {
+          const stats: IndexStats = await plugin.index_stats(this.app, this.settings);
+          const problems = [
+            stats.orphans > 0 ? `${stats.orphans} sections of deleted notes` : '',
+            stats.malformed > 0 ? `${stats.malformed} unreadable rows` : '',
+          ].filter(problem => problem !== '');
+          new Notice(`${summarizeIndexStats(stats)}${problems.length > 0 ? `. ${problems.join(', ')}, see the plugin settings.` : ''}`);
+        } catch (error) {
+          noticeForError(error, "Failed to read the index");
+        }
+			}
+		});
+
+		this.addCommand({
+			id: 'list-notes-missing-from-index',
+			name: 'List notes missing from the index',
+			callback: () => {
+				new CoverageModal(this.app, this.settings).open();
+			}
+		});
+
+		this.addCommand({
+			id: 'check-index-files',
+			name: 'Check index files',
+			callback: async () => {
+        try {
+          const check = await plugin.check_index_files(this.app, this.settings);
+          if (check.malformed.length === 0) {
+            new Notice(`${check.inputRows} input rows and ${check.embeddingRows} embeddings, all readable`);
+            return;
+          }
+          const rows = check.malformed.slice(0, 5)
+            .map((row: { file: string, line?: number, reason: string }) => `${row.file}${row.line ? ` line ${row.line}` : ''}: ${row.reason}`);
+          const more = check.malformed.length > rows.length ? `\n...and ${check.malformed.length - rows.length} more` : '';
+          new Notice(`${check.malformed.length} malformed rows:\n${rows.join('\n')}${more}`, 0);
+        } catch (error) {
+          noticeForError(error, "Failed to check index files");
+        }
+			}
+		});
+
+		this.addCommand({
+			id: 'compact-embedding-store',
+			name: 'Compact embedding store',
+			callback: async () => {
+        try {
+          const command = new plugin.GenerateEmbeddingsCommand(this.app, this.settings);
+          const report: CompactionReport = await command.compact_store();
+          const kilobytes = (report.bytes_reclaimed / 1024).toFixed(1);
+          new Notice(`Removed ${report.orphans_removed} orphaned, ${report.duplicates_removed} duplicate and ${report.malformed_removed} malformed rows, reclaiming ${kilobytes} KB. ${report.rows_after} rows remain.`);
+        } catch (error) {
+          noticeForError(error, "Failed to compact the embedding store");
+        }
+			}
+		});
+
+		this.addCommand({
+			id: 'check-batch-job',
+			name: 'Check batch job',
+			callback: async () => {
+        try {
+          const status = await this.pollBatchJob();
+          if (!status) {
+            new Notice("No batch job is pending");
+          } else if (!status.finished) {
+            new Notice(`Batch job ${status.id} is ${status.status}: ${status.completed} of ${status.requests} requests done`);
+          }
+        } catch (error) {
+          noticeForError(error, "Failed to check the batch job");
+        }
+			}
+		});
+
     if (this.settings.enableLinkRecommendationSuggestor) {
-      const linksSuggest = new LinkSuggest(this.app, this.settings);
+      const linksSuggest = new LinkSuggest(this.app, this.settings, this.index);
       this.registerEditorSuggest(linksSuggest);
     }
 
@@ -69,34 +311,186 @@ export default class SemanticSearch extends Plugin {
             this.app.commands.executeCommandById(linkSuggestQueryCommand.id);
           });
         });
+        menu.addItem((item) => {
+          item.setTitle(relatedTextCommand.name)
+          .setIcon('file-search-2')
+          .onClick(() => {
+            //@ts-ignore
+            this.app.commands.executeCommandById(relatedTextCommand.id);
+          });
+        });
+        menu.addItem((item) => {
+          item.setTitle(relatedLinkCommand.name)
+          .setIcon('link')
+          .onClick(() => {
+            //@ts-ignore
+            this.app.commands.executeCommandById(relatedLinkCommand.id);
+          });
+        });
       })
     );
 
+    const addSimilarNotesItem = (menu: Menu, files: TAbstractFile[]) => {
+      const notes = files.filter((file): file is TFile => file instanceof TFile && file.extension === "md");
+      if (notes.length === 0) {
+        return;
+      }
+      menu.addItem((item) => {
+        item.setTitle("Find similar notes")
+        .setIcon('file-search-2')
+        .onClick(() => new SimilarNotesModal(this.app, this.settings, notes).open());
+      });
+    };
+    this.registerEvent(this.app.workspace.on("file-menu", (menu: Menu, file: TAbstractFile) => addSimilarNotesItem(menu, [file])));
+    this.registerEvent(this.app.workspace.on("files-menu", (menu: Menu, files: TAbstractFile[]) => addSimilarNotesItem(menu, files)));
+
+    // registered once the vault has loaded, since Obsidian reports every existing file as created on startup
+    this.app.workspace.onLayoutReady(() => {
+      const forward = (hook: typeof plugin.on_file_modified, path: string) => {
+        try {
+          hook(this.app, this.settings, path);
+        } catch (error) {
+          console.error(error);
+        }
+      };
+      this.registerEvent(this.app.vault.on("create", (file: TAbstractFile) => forward(plugin.on_file_created, file.path)));
+      this.registerEvent(this.app.vault.on("modify", (file: TAbstractFile) => forward(plugin.on_file_modified, file.path)));
+      this.registerEvent(this.app.vault.on("delete", (file: TAbstractFile) => forward(plugin.on_file_deleted, file.path)));
+      this.registerEvent(this.app.vault.on("rename", (file: TAbstractFile, oldPath: string) => {
+        forward(plugin.on_file_deleted, oldPath);
+        forward(plugin.on_file_created, file.path);
+      }));
+      plugin.resume_auto_index(this.app, this.settings).catch((error: unknown) => console.error(error));
+      if (this.settings.autoIndex && this.settings.idleIndexing) {
+        this.registerInterval(window.setInterval(() => window.requestIdleCallback(async (deadline: IdleDeadline) => {
+          try {
+            await plugin.auto_index_tick(this.app, this.settings, deadline.timeRemaining());
+          } catch (error) {
+            console.error(error);
+          }
+        }), IDLE_INDEX_INTERVAL_MS));
+      }
+      this.pollBatchJob().catch((error: unknown) => console.error(error));
+    });
+    this.registerInterval(window.setInterval(() => this.pollBatchJob().catch((error: unknown) => console.error(error)), BATCH_POLL_INTERVAL_MS));
+
 		this.addSettingTab(new SemanticSearchSettingTab(this.app, this));
+	}
 
-		// here's the Rust bit
-		await plugin.default(Promise.resolve(wasmbin.default));
-		plugin.onload(this);
+	// Checks on a pending batch job, telling the user once its results have been written or it has failed
+	async pollBatchJob() {
+		const status = await new plugin.GenerateEmbeddingsCommand(this.app, this.settings).poll_batch_status();
+		if (status?.report) {
+			new Notice(`Batch job ${status.id} completed: saved ${status.report.records_embedded} embeddings in '${this.settings.embeddingFileName}'`);
+		} else if (status?.finished) {
+			new Notice(`Batch job ${status.id} ${status.status} without results. Submit it again from Generate Embeddings.`);
+		}
+		return status;
+	}
+
+	// Upgrades input.csv and the embedding files to the current format, telling the user about
+	// the files that were upgraded and the ones that couldn't be
+	async migrateStores() {
+		let report: MigrationReport;
+		try {
+			report = await plugin.migrate_stores(this.app, this.settings);
+		} catch (error) {
+			noticeForError(error, "Failed to upgrade the index files");
+			return;
+		}
+		report.migrated.forEach(migration => {
+			new Notice(`Upgraded '${migration.path}' to the current format, the old file is kept as '${migration.backup}'`);
+		});
+		report.refused.forEach(refusal => {
+			new Notice(`'${refusal.path}' was left as it is: ${refusal.reason}`);
+		});
 	}
 
 	onunload() {
+		this.index?.free();
+		plugin.onunload(this).catch((error: unknown) => console.error(error));
+	}
 
+	passphraseKey() {
+		return `semantic-search-passphrase-${this.app.vault.getName()}`;
 	}
 
 	async loadSettings() {
     const DEFAULT_SETTINGS: semanticSearchSettings = {
+      embeddingProvider: 'openai',
+      customProviders: '',
       apiKey: '',
+      apiBase: 'https://lai.rambhat.la/v1',
+      organizationId: '',
+      projectId: '',
+      model: 'text-embedding-ada-002',
+      languageModels: '',
       ignoredFolders: "",
       sectionDelimeterRegex: '.',
-      numBatches: 1,
-      enableLinkRecommendationSuggestor: false
+      attachmentTypes: "",
+      stripCodeBlocks: false,
+      stripInlineCode: false,
+      stripMath: false,
+      stripDataview: false,
+      chunkSize: 0,
+      chunkOverlap: 0,
+      embeddingGranularity: 'heading',
+      minSectionChars: 1,
+      storageFolder: "",
+      embeddingFileName: "embedding.csv",
+      embeddingStore: "",
+      numBatches: 0,
+      requestsPerMinute: 0,
+      tokensPerMinute: 0,
+      maxTokensPerRequest: 250000,
+      maxTokensPerRecord: 8191,
+      maxCostPerRun: 1,
+      requestTimeout: 60,
+      runDeadline: 0,
+      useBatchApi: false,
+      embedContent: 'headingAndBody',
+      embedTemplate: '',
+      embeddingQuantization: 'none',
+      embeddingDimensions: 0,
+      scoreNormalization: 'minMax',
+      adaptiveResultCount: false,
+      enableMmr: false,
+      mmrLambda: 0.7,
+      groupByFile: 'off',
+      tagBoosts: '',
+      recencyHalfLifeDays: 0,
+      showSnippets: true,
+      skipMalformedRows: true,
+      encryptEmbeddings: false,
+      encryptionPassphrase: '',
+      queryExpansionModel: '',
+      queryExpansionCount: 3,
+      answerModel: '',
+      answerContextTokens: 3000,
+      enableRerank: false,
+      rerankFormat: 'cohere',
+      rerankApiBase: '',
+      rerankApiKey: '',
+      rerankModel: 'rerank-v3.5',
+      enableLinkRecommendationSuggestor: false,
+      autoIndex: false,
+      autoIndexDelay: 30,
+      idleIndexing: false,
+      logLevel: 'info',
+      logToFile: false,
     }
 
 		this.settings = Object.assign({}, DEFAULT_SETTINGS, await this.loadData());
+		this.settings.encryptionPassphrase = window.localStorage.getItem(this.passphraseKey()) ?? '';
 	}
 
 	async saveSettings() {
-		await this.saveData(this.settings);
+		// the passphrase stays on this device so syncing the vault doesn't give it away
+		const { encryptionPassphrase, ...data } = this.settings;
+		await this.saveData(data);
+		window.localStorage.setItem(this.passphraseKey(), encryptionPassphrase);
+		plugin.configure_encryption(this.settings);
+		await plugin.configure_logging(this.app, this.settings).catch((error: unknown) => console.error(error));
 	}
 }
 
@@ -0,0 +1,365 @@
+import { App, TAbstractFile } from "obsidian";
+import * as plugin from "../pkg/obsidian_rust_plugin.js";
+import { semanticSearchSettings } from "./settings/settings";
+import { ExportFormat, ExportReport } from "./ui/exportModal";
+import { CoverageDiff } from "./ui/coverageModal";
+import { IndexStats } from "./ui/indexStats";
+import { LinkCandidate } from "./ui/relatedLinkModal";
+
+// A stored section, as handed to post-build hooks
+export type IndexRecord = {
+  name: string
+  header: string
+  embedding: Float32Array
+}
+
+export type IndexRecords = Iterable<IndexRecord> & {
+  model: string | undefined
+  length: number
+}
+
+export type PostBuildHook = (records: IndexRecords) => unknown
+
+// Mirrors Provenance in src/provenance.rs
+export type Provenance = {
+  model: string
+  provider: string
+  embedded_at: number
+  settings_hash: string
+  // note lines the record was embedded from, counted from 0
+  lines?: { start: number, end: number }
+  // when the note was created and last modified, in ms since the epoch
+  note_times?: { created: number, modified: number }
+  // ISO 639-1 code of the language the embedded text was detected to be in
+  language?: string
+}
+
+// Mirrors QueryFilters in src/session.rs, every field is optional
+export type QueryFilters = {
+  folder?: string
+  path?: string
+  exclude?: string
+  modifiedAfter?: number
+  modifiedBefore?: number
+  createdAfter?: number
+  createdBefore?: number
+}
+
+// Mirrors ProvenanceFilter in src/provenance.rs, every field is optional
+export type ProvenanceFilter = {
+  model?: string
+  exclude_model?: string
+  provider?: string
+  settings_hash?: string
+  embedded_before?: number
+  embedded_after?: number
+  language?: string
+}
+
+// Mirrors ReembedFilter in src/maintenance.rs: a folder plus any provenance constraints
+export type ReembedFilter = ProvenanceFilter & {
+  folder?: string
+}
+
+// Mirrors ReembedReport in src/maintenance.rs
+export type ReembedReport = {
+  notes: number
+  records_replaced: number
+  missing: string[]
+  report: { records_embedded: number, records_rejected: number, batches: number }
+}
+
+// Mirrors CompactionReport in src/compaction.rs
+export type CompactionReport = {
+  rows_before: number
+  rows_after: number
+  orphans_removed: number
+  duplicates_removed: number
+  malformed_removed: number
+  bytes_before: number
+  bytes_after: number
+  bytes_reclaimed: number
+}
+
+// Mirrors Document in src/documents.rs
+export type Document = {
+  id: string
+  title?: string
+  text: string
+}
+
+// Mirrors DocumentsReport in src/documents.rs
+export type DocumentsReport = {
+  documents: number
+  records_replaced: number
+  report: { records_embedded: number, records_rejected: number, batches: number }
+}
+
+// Mirrors ProvenanceReport in src/provenance.rs, provenance is null for records
+// written before it was recorded
+export type ProvenanceReport = {
+  groups: { provenance: Provenance | null, records: number, oldest: number, newest: number }[]
+  records: { name: string, header: string, provenance: Provenance | null }[]
+}
+
+// Mirrors RagContext in src/context.rs. Each source is the section labelled [index] in context
+export type RagContext = {
+  context: string
+  tokens: number
+  sources: { index: number, name: string, header: string, score: number, tokens: number, truncated: boolean }[]
+}
+
+// Mirrors Answer in src/chat.rs. The answer cites notes as [[note]] links
+export type Answer = {
+  answer: string
+  model: string
+  sources: RagContext['sources']
+}
+
+// Mirrors MocSource in src/moc.rs: either a topic query or a cluster number. k is the number
+// of clusters the cluster was picked from, 0 (the default) like the Cluster notes command
+export type MocSource = { query: string } | { cluster: number, k?: number }
+
+// Mirrors Moc in src/moc.rs
+export type Moc = {
+  title: string
+  markdown: string
+  notes: number
+  path: string | null
+}
+
+// Mirrors ReindexProgress in src/reindex.rs: done of total files read (input), records
+// embedded (embeddings) or embeddings loaded (index)
+export type ReindexProgress = {
+  stage: 'input' | 'embeddings' | 'index'
+  done: number
+  total: number
+}
+
+// Mirrors ReindexReport in src/reindex.rs
+export type ReindexReport = {
+  inputRecords: number
+  skippedSections: number
+  embeddings: { records_embedded: number, duplicates_reused: number, records_rejected: number, batches: number }
+  indexed: number
+}
+
+// Mirrors MigrationReport in src/migrations.rs: the index files upgraded to the current format
+// when the plugin loaded, and those left as they are with the reason why
+export type MigrationReport = {
+  migrated: { kind: 'input' | 'embeddings', path: string, from: number, to: number, backup: string }[]
+  refused: { path: string, reason: string }[]
+}
+
+// Mirrors VaultEstimate in src/pricing.rs. folder is the vault path of a folder, "" for
+// the root, and only counts the notes directly in it
+export type VaultEstimate = {
+  estimate: { model: string, tokens: number, requests: number, dollars: number }
+  folders: { folder: string, notes: number, chunks: number, tokens: number, dollars: number }[]
+}
+
+// Mirrors Suggestions in src/lib.rs. lines locates the result in its note,
+// counted from 0, and is null for PDF pages, canvas cards and older indexes.
+// snippet is an excerpt of the result's text around the sentence best matching
+// the query, null when snippets are off or the section is no longer in input.csv.
+// link opens the result at its section, with app.workspace.openLinkText(link.linkText, "")
+// or link.url from outside Obsidian, and is null for documents that aren't notes
+export type Suggestion = {
+  name: string
+  header: string
+  score: number
+  lines: { start: number, end: number } | null
+  snippet: { before: string, sentence: string, after: string } | null
+  link: { path: string, headings: string[], line: number | null, linkText: string, url: string } | null
+}
+
+// Mirrors SemanticRow in src/dataview.rs, a search result shaped for DataviewJS. path is the
+// note's vault path, tags carry their # like Dataview's file.tags, and file is only set when
+// files were asked for, null for notes no longer in the vault
+export type SemanticRow = {
+  path: string
+  heading: string
+  score: number
+  tags: string[]
+  file?: TAbstractFile | null
+}
+
+// Mirrors CommandSpec in src/rpc.rs. Commands are invoked as `${name}.v${version}`
+export type CommandSpec = {
+  name: string
+  version: number
+  payload: string
+  result: string
+}
+
+// Available to user scripts and other plugins as
+// app.plugins.plugins['bbawj-semantic-search'].api
+export class SemanticSearchApi {
+  app: App;
+  settings: semanticSearchSettings;
+  index: plugin.SemanticIndex;
+
+  constructor(app: App, settings: semanticSearchSettings, index: plugin.SemanticIndex) {
+    this.app = app;
+    this.settings = settings;
+    this.index = index;
+  }
+
+  // Registers a hook run after every successful Generate Embeddings, e.g. to
+  // push the index to your own server. The hook may be async. Returns a
+  // function that unregisters it. The records can be iterated once.
+  onIndexBuilt(hook: PostBuildHook): () => void {
+    const callback = (iterator: plugin.IndexRecordIterator) => hook(toIterable(iterator));
+    plugin.registerPostBuildHook(callback);
+    return () => plugin.unregisterPostBuildHook(callback);
+  }
+
+  // Searches every phrasing and merges the rankings with reciprocal rank fusion.
+  // A single query is rephrased first when a query expansion model is configured.
+  // Only sections of notes under `folder` or matching the `path` glob are searched, and
+  // sections similar to `exclude` are ranked lower, e.g. { path: "Projects/**", exclude: "raft" }.
+  // `modifiedAfter`, `modifiedBefore`, `createdAfter` and `createdBefore` take ms since the epoch.
+  // The results' `skipped` property counts sections left out because their embeddings are unusable,
+  // and `malformed` the rows of the embedding file that could not be read.
+  async search(queries: string | string[], filters: QueryFilters = {}): Promise<Suggestion[]> {
+    return await plugin.get_suggestions_for_queries(this.app, this.settings, Array.isArray(queries) ? queries : [queries], filters);
+  }
+
+  // Search results as rows for DataviewJS tables, e.g.
+  // dv.table(["Note", "Score"], (await api.semantic("raft", { limit: 10 })).map(row => [dv.fileLink(row.path), row.score]))
+  // A limit of 0 (the default) returns every result; with files: true each row also gets its TFile
+  async semantic(query: string, options: { filters?: QueryFilters, limit?: number, files?: boolean } = {}): Promise<SemanticRow[]> {
+    return await plugin.semantic(this.app, this.settings, query, options.filters ?? {}, options.limit ?? 0, options.files ?? false);
+  }
+
+  // Wiki links to sections related to a paragraph, most similar first, leaving out the note
+  // at currentPath, e.g. suggestLinks(paragraph, "Daily/2024-05-01.md")[0].wikilink
+  async suggestLinks(paragraph: string, currentPath: string): Promise<LinkCandidate[]> {
+    return await plugin.suggest_links(this.app, this.settings, paragraph, currentPath);
+  }
+
+  // Context for answering `query` with a language model: the text of the best matching section
+  // of each note, labelled [1], [2], ... and cut to fit `tokenBudget` tokens (counted with
+  // cl100k_base), with the sources the labels refer to so answers can cite them
+  async buildContext(query: string, tokenBudget: number): Promise<RagContext> {
+    return await plugin.build_context(this.app, this.settings, query, tokenBudget);
+  }
+
+  // Answers a question with the answer model from settings, from the context buildContext
+  // would return for it. Fails if no answer model is set.
+  async answerQuestion(question: string): Promise<Answer> {
+    return await plugin.answer_query(this.app, this.settings, question);
+  }
+
+  // Searches for `query` and writes the results, as links to their sections with scores and
+  // snippets, to a new note at `path`, e.g. writeResultsNote('raft', 'Research/raft.md')
+  async writeResultsNote(query: string, path: string): Promise<{ path: string, results: number }> {
+    return await plugin.write_results_note(this.app, this.settings, query, path);
+  }
+
+  // Outlines the notes on a topic, grouped by sub-topic, as markdown: the sections best matching
+  // a query, e.g. { query: "sourdough" }, or a cluster from the Cluster notes command, e.g.
+  // { cluster: 0, k: 0 }. With a path the outline is also written to a new note there.
+  async generateMoc(source: MocSource, path?: string): Promise<Moc> {
+    return await plugin.generate_moc(this.app, this.settings, source, path);
+  }
+
+  // Re-embeds, from the current input.csv, every note with a stored section matching the
+  // filter and keeps the rest of the store, e.g. { embedded_before: Date.parse("2024-01-01") }.
+  // Switching models requires selecting every note, since vectors from different models
+  // cannot be compared. Notes input.csv doesn't list are left as they are and returned in
+  // missing. Runs in the background like Generate Embeddings and can be followed in the
+  // last run report.
+  async reembed(filter: ReembedFilter): Promise<ReembedReport> {
+    const command = new plugin.GenerateEmbeddingsCommand(this.app, this.settings);
+    return await command.reembed(filter);
+  }
+
+  // Embeds content that isn't in the vault, such as highlights or web clippings, into the
+  // same index without going through input.csv. Each document becomes one searchable
+  // section named by its id; embedding a document again replaces its previous embedding.
+  // Pick ids that can't clash with note paths, e.g. "readwise/1234".
+  async embedDocuments(documents: Document[]): Promise<DocumentsReport> {
+    const command = new plugin.GenerateEmbeddingsCommand(this.app, this.settings);
+    return await command.embed_documents(documents);
+  }
+
+  // Rewrites the embedding store without sections of notes deleted from the vault, exact
+  // duplicates and unreadable rows, e.g. after many incremental updates
+  async compactStore(): Promise<CompactionReport> {
+    const command = new plugin.GenerateEmbeddingsCommand(this.app, this.settings);
+    return await command.compact_store();
+  }
+
+  // Generates input.csv, embeds it and loads the embeddings for searching in one go. onProgress
+  // is called as each file is read, before each batch is embedded and when loading
+  async reindex(onProgress?: (progress: ReindexProgress) => void): Promise<ReindexReport> {
+    return await this.index.reindex_vault(this.app, this.settings, onProgress);
+  }
+
+  // Updates notes changed since the last update, a few at a time, until budgetMs have passed.
+  // Only does anything with automatic index updates and indexing when idle enabled
+  async autoIndexTick(budgetMs: number): Promise<{ indexed: number, remaining: number }> {
+    return await plugin.auto_index_tick(this.app, this.settings, budgetMs);
+  }
+
+  // Estimates what embedding the vault as it is now would cost, per folder, without
+  // needing input.csv
+  async estimateCost(): Promise<VaultEstimate> {
+    return await plugin.estimate_vault_cost(this.app, this.settings);
+  }
+
+  // Counts what the embedding store holds, how much of the vault it covers and what is left
+  // over from deleted notes
+  async indexStats(): Promise<IndexStats> {
+    return await plugin.index_stats(this.app, this.settings);
+  }
+
+  // Vault paths of the notes without embeddings, and of those edited since they were embedded,
+  // which is what the next incremental update or reindex will embed
+  async coverageDiff(): Promise<CoverageDiff> {
+    return await plugin.coverage_diff(this.app, this.settings);
+  }
+
+  // Writes the embedding store next to itself as JSON lines or as a .npy matrix plus a
+  // JSON manifest describing its rows, and returns the paths written
+  async exportEmbeddings(format: ExportFormat): Promise<ExportReport> {
+    return await plugin.export_embeddings(this.app, this.settings, format);
+  }
+
+  // Merges embeddings computed elsewhere, e.g. on a local GPU, into the store: a JSON lines
+  // file of { name, header, vector } or a .npy matrix with a .manifest.json naming its rows,
+  // as written by exportEmbeddings. Imported notes replace their stored sections. The model
+  // defaults to the manifest's and must match the configured one.
+  async importEmbeddings(path: string, format: ExportFormat, model?: string): Promise<{ imported: number, skipped: number, replaced: number, path: string }> {
+    return await plugin.import_embeddings(this.app, this.settings, format, path, model ?? "", true);
+  }
+
+  // Runs a versioned command, e.g. invoke('query.v1', { query: 'raft' }), for integrations
+  // that want shapes that stay put across plugin updates. invoke('commands.v1') lists the
+  // commands with the payload each takes and what it returns
+  async invoke(command: string, payload: object = {}): Promise<unknown> {
+    return await this.index.invoke(this.app, this.settings, command, payload);
+  }
+
+  // Summarises which model, provider and settings each stored record was embedded
+  // with, and lists the records matching the filter, e.g. { exclude_model: "text-embedding-3-small" }
+  async queryProvenance(filter?: ProvenanceFilter): Promise<ProvenanceReport> {
+    return await plugin.query_provenance(this.app, this.settings, filter ?? null);
+  }
+}
+
+function toIterable(iterator: plugin.IndexRecordIterator): IndexRecords {
+  return {
+    model: iterator.model,
+    length: iterator.length,
+    *[Symbol.iterator]() {
+      let record = iterator.next();
+      while (record !== undefined) {
+        yield { name: record.name, header: record.header, embedding: record.embedding };
+        record.free();
+        record = iterator.next();
+      }
+    }
+  };
+}
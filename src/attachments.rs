@@ -0,0 +1,126 @@
+use js_sys::{Array, Reflect, Uint8Array};
+use lazy_static::lazy_static;
+use regex::Regex;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+use crate::SemanticSearchError;
+use crate::generate_input::{clean_text, extract_sections};
+use crate::obsidian::{self, PdfDocument, PdfJs, PdfPage, PdfTextContent};
+
+/// Kinds of non-markdown files that can be embedded alongside notes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttachmentKind {
+    Pdf,
+    Text,
+    Org,
+}
+
+impl AttachmentKind {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.trim().trim_start_matches('.').to_lowercase().as_str() {
+            "pdf" => Some(AttachmentKind::Pdf),
+            "txt" => Some(AttachmentKind::Text),
+            "org" => Some(AttachmentKind::Org),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AttachmentKind::Pdf => "pdf",
+            AttachmentKind::Text => "txt",
+            AttachmentKind::Org => "org",
+        }
+    }
+}
+
+/// Parses the comma or whitespace separated attachment types setting, ignoring unsupported types
+pub fn parse_attachment_kinds(setting: &str) -> Vec<AttachmentKind> {
+    let mut kinds = Vec::new();
+    for kind in setting.split(|c: char| c == ',' || c.is_whitespace()).filter_map(AttachmentKind::from_extension) {
+        if !kinds.contains(&kind) {
+            kinds.push(kind);
+        }
+    }
+    kinds
+}
+
+/// Splits a plaintext attachment into sections the same way notes are split.
+/// Org headings are rewritten as markdown headings first so the section delimiter applies to both.
+pub fn text_sections(kind: AttachmentKind, name: &str, text: &str, delimeter: &str) -> Result<Vec<(String, String, String)>, SemanticSearchError> {
+    match kind {
+        AttachmentKind::Org => extract_sections(name, &org_to_markdown_headings(text), delimeter),
+        _ => extract_sections(name, text, delimeter),
+    }
+}
+
+fn org_to_markdown_headings(text: &str) -> String {
+    lazy_static! {
+        static ref ORG_HEADING: Regex = Regex::new(r"(?m)^(\*{1,6}) ").unwrap();
+    }
+    ORG_HEADING.replace_all(text, |caps: &regex::Captures| format!("{} ", "#".repeat(caps[1].len()))).to_string()
+}
+
+/// Extracts one section per PDF page using the pdf.js build bundled with Obsidian.
+/// Pages without a text layer, such as scans, produce no section.
+pub async fn pdf_sections(name: &str, data: &[u8]) -> Result<Vec<(String, String, String)>, SemanticSearchError> {
+    let pdfjs: PdfJs = obsidian::loadPdfJs().await?.unchecked_into();
+    let source = js_sys::Object::new();
+    Reflect::set(&source, &JsValue::from_str("data"), &Uint8Array::from(data))?;
+    let document: PdfDocument = JsFuture::from(pdfjs.getDocument(&source).promise()).await?.unchecked_into();
+
+    let mut sections = Vec::new();
+    for number in 1..=document.numPages() {
+        let page: PdfPage = JsFuture::from(document.getPage(number)).await?.unchecked_into();
+        let content: PdfTextContent = JsFuture::from(page.getTextContent()).await?.unchecked_into();
+        let text = page_text(text_items(&content.items()));
+        let body = clean_text(&text);
+        if !body.is_empty() {
+            sections.push((name.to_string(), format!("Page {}", number), body));
+        }
+    }
+    document.destroy();
+    Ok(sections)
+}
+
+fn text_items(items: &Array) -> Vec<String> {
+    items.iter()
+        .filter_map(|item| Reflect::get(&item, &JsValue::from_str("str")).ok().and_then(|text| text.as_string()))
+        .collect()
+}
+
+/// Joins pdf.js text items into a single line of text, collapsing runs of whitespace
+fn page_text(items: Vec<String>) -> String {
+    items.iter().flat_map(|item| item.split_whitespace()).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_kinds() {
+        assert_eq!(parse_attachment_kinds("pdf, .TXT org"), vec![AttachmentKind::Pdf, AttachmentKind::Text, AttachmentKind::Org]);
+        assert_eq!(parse_attachment_kinds("pdf,pdf,docx"), vec![AttachmentKind::Pdf]);
+        assert!(parse_attachment_kinds("").is_empty());
+    }
+
+    #[test]
+    fn org_headings_become_sections() {
+        let text = "* Tasks\nwrite report\n** Later\n*bold* text";
+        let sections = text_sections(AttachmentKind::Org, "todo.org", text, r"^#{1,6} ").unwrap();
+
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0], ("todo.org".to_string(), "Tasks".to_string(), "Tasks write report".to_string()));
+        assert_eq!(sections[1].1, "Later");
+        assert_eq!(sections[1].2, "Later *bold* text");
+    }
+
+    #[test]
+    fn page_text_joins_items() {
+        let items = vec!["Hello".to_string(), "world".to_string(), "  next  line ".to_string()];
+
+        assert_eq!(page_text(items), "Hello world next line");
+    }
+}
@@ -0,0 +1,280 @@
+//! Keeps the index fresh as the vault changes. The plugin forwards Obsidian's vault events to
+//! the `on_file_*` hooks, which queue the affected paths. Once no new change has come in for the
+//! configured delay, the queued notes are re-extracted into input.csv and re-embedded in the background.
+//! The queue is saved to the storage folder so unfinished work resumes when the plugin loads.
+//!
+//! With idle indexing the queue is left for `auto_index_tick` instead, which the plugin calls
+//! when Obsidian is idle and which updates a few files at a time within a time budget.
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+
+use log::{debug, error, info};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::FileProcessor;
+use crate::GenerateEmbeddingsCommand;
+use crate::SemanticSearchError;
+use crate::generate_input::GenerateInputCommand;
+use crate::index_queue::{Change, IndexQueue};
+use crate::input::{self, InputRecord, write_input};
+use crate::maintenance::NoteSet;
+use crate::obsidian::App;
+use crate::rate_limit;
+use crate::settings::Settings;
+
+/// Stored record name of the file at `path`, as written by Generate Input
+pub fn record_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+thread_local! {
+    static PENDING: RefCell<IndexQueue> = const { RefCell::new(IndexQueue::new()) };
+    /// Changes taken from the queue by the running update
+    static IN_FLIGHT: RefCell<BTreeMap<String, Change>> = const { RefCell::new(BTreeMap::new()) };
+    static UPDATE_SCHEDULED: Cell<bool> = const { Cell::new(false) };
+    static SAVING: Cell<bool> = const { Cell::new(false) };
+    static SAVE_AGAIN: Cell<bool> = const { Cell::new(false) };
+    static TICKING: Cell<bool> = const { Cell::new(false) };
+    /// Set when a tick failed in a way retrying won't fix, until the next change comes in
+    static TICKS_PAUSED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Files updated together by one step of `auto_index_tick`. Each step rewrites input.csv and
+/// the embedding file, so steps can't be cut short, only kept small.
+const IDLE_SLICE_FILES: usize = 5;
+
+/// What an `auto_index_tick` did
+#[derive(Debug, Serialize)]
+pub struct IndexTick {
+    /// Changed files whose records were updated
+    pub indexed: usize,
+    /// Changed files still queued
+    pub remaining: usize,
+}
+
+/// Picks up the changes saved by an earlier session, scheduling an update if there are any
+#[wasm_bindgen]
+pub async fn resume_auto_index(app: &App, settings: JsValue) -> Result<usize, SemanticSearchError> {
+    let parsed = Settings::from_js(&settings)?;
+    if !parsed.auto_index {
+        return Ok(0);
+    }
+    let mut saved = IndexQueue::load(&FileProcessor::new(app.vault()), &parsed.index_queue_path()).await?;
+    if saved.is_empty() {
+        return Ok(0);
+    }
+    let changes = saved.take();
+    let count = changes.len();
+    debug!("Resuming auto-indexing of {} changed files", count);
+    PENDING.with(|pending| pending.borrow_mut().requeue(changes, js_sys::Date::now()));
+    if !parsed.idle_indexing {
+        schedule(app.clone(), settings, delay_ms(&parsed));
+    }
+    Ok(count)
+}
+
+#[wasm_bindgen]
+pub fn on_file_created(app: &App, settings: JsValue, path: String) -> Result<(), SemanticSearchError> {
+    queue(app, settings, &path, Change::Modified)
+}
+
+#[wasm_bindgen]
+pub fn on_file_modified(app: &App, settings: JsValue, path: String) -> Result<(), SemanticSearchError> {
+    queue(app, settings, &path, Change::Modified)
+}
+
+#[wasm_bindgen]
+pub fn on_file_deleted(app: &App, settings: JsValue, path: String) -> Result<(), SemanticSearchError> {
+    queue(app, settings, &path, Change::Deleted)
+}
+
+fn queue(app: &App, settings_value: JsValue, path: &str, change: Change) -> Result<(), SemanticSearchError> {
+    let settings = Settings::from_js(&settings_value)?;
+    if !settings.auto_index {
+        return Ok(());
+    }
+    let input_cmd = GenerateInputCommand::new(app.clone(), settings_value.clone())?;
+    if !FileProcessor::new(app.vault()).is_listed(path, &input_cmd.file_filter()) {
+        return Ok(());
+    }
+    debug!("Queued {:?} {} for auto-indexing", change, path);
+    PENDING.with(|pending| pending.borrow_mut().record(path, change, js_sys::Date::now()));
+    save_queue(app, settings.index_queue_path());
+    match settings.idle_indexing {
+        true => TICKS_PAUSED.with(|paused| paused.set(false)),
+        false => schedule(app.clone(), settings_value, delay_ms(&settings)),
+    }
+    Ok(())
+}
+
+/// Saves the queued and in-flight changes. Only one save runs at a time; changes made
+/// while it runs are written by one more save once it finishes.
+fn save_queue(app: &App, path: String) {
+    if SAVING.with(|saving| saving.replace(true)) {
+        SAVE_AGAIN.with(|again| again.set(true));
+        return;
+    }
+    let file_processor = FileProcessor::new(app.vault());
+    wasm_bindgen_futures::spawn_local(async move {
+        loop {
+            SAVE_AGAIN.with(|again| again.set(false));
+            let queue = IN_FLIGHT.with(|in_flight| PENDING.with(|pending| pending.borrow().with_in_flight(&in_flight.borrow())));
+            if let Err(e) = queue.save(&file_processor, &path).await {
+                error!("Failed to save the auto-index queue: {}", e);
+            }
+            if !SAVE_AGAIN.with(|again| again.get()) {
+                break;
+            }
+        }
+        SAVING.with(|saving| saving.set(false));
+    });
+}
+
+fn delay_ms(settings: &Settings) -> f64 {
+    f64::from(settings.auto_index_delay) * 1000.0
+}
+
+/// Starts waiting for the vault to settle, unless an update is already waiting
+fn schedule(app: App, settings: JsValue, delay_ms: f64) {
+    if UPDATE_SCHEDULED.with(|scheduled| scheduled.replace(true)) {
+        return;
+    }
+    wasm_bindgen_futures::spawn_local(async move {
+        loop {
+            let wait = PENDING.with(|pending| pending.borrow().wait(js_sys::Date::now(), delay_ms));
+            if wait <= 0.0 {
+                break;
+            }
+            rate_limit::sleep(wait).await;
+        }
+        let changes = PENDING.with(|pending| pending.borrow_mut().take());
+        IN_FLIGHT.with(|in_flight| in_flight.replace(changes.clone()));
+        // changes made while this update runs schedule the next one
+        UPDATE_SCHEDULED.with(|scheduled| scheduled.set(false));
+        let result = update(&app, &settings, &changes).await;
+        IN_FLIGHT.with(|in_flight| in_flight.borrow_mut().clear());
+        match result {
+            Ok(()) => (),
+            Err(SemanticSearchError::Locked(path)) => {
+                debug!("'{}' is locked, retrying the auto-index update later", path);
+                PENDING.with(|pending| pending.borrow_mut().requeue(changes, js_sys::Date::now()));
+                schedule(app.clone(), settings.clone(), delay_ms);
+            },
+            Err(e) if e.code().is_retriable() => {
+                error!("Auto-indexing {} changed files failed, retrying later: {}", changes.len(), e);
+                PENDING.with(|pending| pending.borrow_mut().requeue(changes, js_sys::Date::now()));
+                schedule(app.clone(), settings.clone(), delay_ms);
+            },
+            // left queued for the next change or the next time the plugin loads
+            Err(e) => {
+                error!("Auto-indexing {} changed files failed: {}", changes.len(), e);
+                PENDING.with(|pending| pending.borrow_mut().requeue(changes, js_sys::Date::now()));
+            },
+        }
+        if let Ok(parsed) = Settings::from_js(&settings) {
+            save_queue(&app, parsed.index_queue_path());
+        }
+    });
+}
+
+/// Updates queued changes a few files at a time until `budget_ms` milliseconds have passed,
+/// for the plugin to call when Obsidian is idle with idle indexing enabled, and returns an
+/// `IndexTick`. Changes are only taken once the vault has settled for the auto-index delay, and
+/// a tick does nothing while another is running. A step that has started always finishes, so a
+/// tick can overrun its budget by one step.
+#[wasm_bindgen]
+pub async fn auto_index_tick(app: &App, settings: JsValue, budget_ms: f64) -> Result<JsValue, SemanticSearchError> {
+    let parsed = Settings::from_js(&settings)?;
+    let start = js_sys::Date::now();
+    let mut tick = IndexTick { indexed: 0, remaining: PENDING.with(|pending| pending.borrow().len()) };
+    let settled = PENDING.with(|pending| pending.borrow().wait(start, delay_ms(&parsed))) <= 0.0;
+    if !parsed.auto_index || !settled || TICKS_PAUSED.with(Cell::get) || TICKING.with(|ticking| ticking.replace(true)) {
+        return Ok(serde_wasm_bindgen::to_value(&tick)?);
+    }
+    while js_sys::Date::now() - start < budget_ms {
+        let changes = PENDING.with(|pending| pending.borrow_mut().take_up_to(IDLE_SLICE_FILES));
+        if changes.is_empty() {
+            break;
+        }
+        IN_FLIGHT.with(|in_flight| in_flight.replace(changes.clone()));
+        let result = update(app, &settings, &changes).await;
+        IN_FLIGHT.with(|in_flight| in_flight.borrow_mut().clear());
+        if let Err(e) = result {
+            // retried on a later tick, or after the next change when retrying won't help
+            if !matches!(e, SemanticSearchError::Locked(_)) && !e.code().is_retriable() {
+                TICKS_PAUSED.with(|paused| paused.set(true));
+            }
+            error!("Auto-indexing {} changed files failed: {}", changes.len(), e);
+            PENDING.with(|pending| pending.borrow_mut().requeue(changes, js_sys::Date::now()));
+            break;
+        }
+        tick.indexed += changes.len();
+    }
+    TICKING.with(|ticking| ticking.set(false));
+    tick.remaining = PENDING.with(|pending| pending.borrow().len());
+    if tick.indexed > 0 {
+        debug!("Auto-indexed {} changed files in {}ms, {} left", tick.indexed, js_sys::Date::now() - start, tick.remaining);
+        save_queue(app, parsed.index_queue_path());
+    }
+    Ok(serde_wasm_bindgen::to_value(&tick)?)
+}
+
+/// Re-extracts the changed files into input.csv and replaces their embeddings.
+/// Does nothing until embeddings have been generated once.
+async fn update(app: &App, settings_value: &JsValue, changes: &BTreeMap<String, Change>) -> Result<(), SemanticSearchError> {
+    let settings = Settings::from_js(settings_value)?;
+    let file_processor = FileProcessor::new(app.vault());
+    if changes.is_empty() || !file_processor.check_file_exists_at_path(&settings.embedding_path()).await? {
+        return Ok(());
+    }
+    let input_cmd = GenerateInputCommand::new(app.clone(), settings_value.clone())?;
+    let paths: Vec<String> = changes.keys().cloned().collect();
+    let notes = NoteSet::paths(&paths);
+    let mut records: Vec<InputRecord> = Vec::new();
+    for (path, change) in changes {
+        let file = match change {
+            Change::Modified => file_processor.file_at_path(path),
+            Change::Deleted => None,
+        };
+        if let Some(file) = file {
+            match input_cmd.file_records(file).await {
+                Ok(file_records) => records.extend(file_records),
+                Err(e) => error!("Could not read {} for auto-indexing: {}", path, e),
+            }
+        }
+    }
+
+    let input_path = settings.input_path();
+    if file_processor.check_file_exists_at_path(&input_path).await? {
+        file_processor.acquire_lock(&input_path).await?;
+        let result = replace_input_records(&file_processor, &input_path, &notes, &records, &input_cmd).await;
+        file_processor.release_lock_or_log(&input_path).await;
+        result?;
+    }
+
+    let embeddings_cmd = GenerateEmbeddingsCommand::new(app.clone(), settings_value.clone())?;
+    let report = embeddings_cmd.update_notes(records, &notes, input_cmd.granularity()).await?;
+    info!("Auto-indexed {} changed notes: {} records embedded, {} replaced", report.notes, report.report.records_embedded, report.records_replaced);
+    Ok(())
+}
+
+/// Swaps the records of `notes` in input.csv for `records`. The caller holds the input lock.
+async fn replace_input_records(file_processor: &FileProcessor, input_path: &str, notes: &NoteSet, records: &[InputRecord], input_cmd: &GenerateInputCommand) -> Result<(), SemanticSearchError> {
+    let (mut input_records, _) = input::read_input_lenient(&file_processor.read_consistent(input_path).await?)?;
+    input_records.retain(|record| !notes.contains(&record.name, record.path.as_deref()));
+    input_records.extend(records.iter().cloned());
+    file_processor.replace_file(input_path, &write_input(&input_records, input_cmd.granularity())?).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_names() {
+        assert_eq!(record_name("Projects/Plugin.md"), "Plugin.md");
+        assert_eq!(record_name("Inbox.md"), "Inbox.md");
+    }
+}
@@ -0,0 +1,259 @@
+//! Embedding through OpenAI's Batch API, which answers within a day at half the price.
+//! Generate Embeddings uploads every request as one JSONL file and records the submitted
+//! batch in batch_job.json; polling the batch later downloads the results and writes them
+//! to the embedding file as if they had been embedded right away.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::FileProcessor;
+use crate::SemanticSearchError;
+use crate::chunking::Granularity;
+use crate::embedding::{EmbeddingInput, EmbeddingRequest, EmbeddingResponse};
+use crate::pipeline::{PipelineRecord, PipelineReport};
+
+/// Name of the file recording the submitted batch, stored next to input.csv
+pub const BATCH_JOB_FILE_NAME: &str = "batch_job.json";
+/// Endpoint every request in the batch is sent to
+pub const BATCH_ENDPOINT: &str = "/v1/embeddings";
+/// Share of the regular price charged for batched requests
+pub const BATCH_PRICE_FACTOR: f32 = 0.5;
+/// Most embedding inputs OpenAI accepts across all requests of a batch
+pub const MAX_BATCH_INPUTS: usize = 50_000;
+
+/// A batch as returned by the `/batches` endpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Batch {
+    pub id: String,
+    /// validating, in_progress, finalizing, completed, failed, expired, cancelling or cancelled
+    pub status: String,
+    #[serde(default)]
+    pub output_file_id: Option<String>,
+    #[serde(default)]
+    pub request_counts: RequestCounts,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RequestCounts {
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+}
+
+impl Batch {
+    /// Whether the batch has stopped changing, successfully or not
+    pub fn is_finished(&self) -> bool {
+        matches!(self.status.as_str(), "completed" | "failed" | "expired" | "cancelled")
+    }
+}
+
+/// A file as returned by the `/files` endpoint
+#[derive(Debug, Deserialize)]
+pub struct FileObject {
+    pub id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateBatchRequest<'a> {
+    pub input_file_id: &'a str,
+    pub endpoint: &'static str,
+    pub completion_window: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct RequestLine<'a> {
+    custom_id: String,
+    method: &'static str,
+    url: &'static str,
+    body: &'a EmbeddingRequest,
+}
+
+#[derive(Debug, Deserialize)]
+struct OutputLine {
+    custom_id: String,
+    response: Option<OutputResponse>,
+    error: Option<OutputError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OutputResponse {
+    status_code: u16,
+    body: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct OutputError {
+    message: String,
+}
+
+fn custom_id(index: usize) -> String {
+    format!("request-{}", index)
+}
+
+/// One JSONL line per request, each embedding the texts of a batch of records
+pub fn request_lines(model: &str, requests: &[Vec<PipelineRecord>]) -> Result<String, SemanticSearchError> {
+    let inputs: usize = requests.iter().map(Vec::len).sum();
+    if inputs > MAX_BATCH_INPUTS {
+        return Err(SemanticSearchError::InvalidArgument(format!(
+            "{} sections are more than the {} the batch API accepts in one batch", inputs, MAX_BATCH_INPUTS)));
+    }
+    let mut lines = String::new();
+    for (i, records) in requests.iter().enumerate() {
+        let body = EmbeddingRequest {
+            model: model.to_string(),
+            input: EmbeddingInput::StringArray(records.iter().map(|record| record.text.clone()).collect()),
+            ..Default::default()
+        };
+        let line = RequestLine { custom_id: custom_id(i), method: "POST", url: BATCH_ENDPOINT, body: &body };
+        lines.push_str(&serde_json::to_string(&line).map_err(|e| SemanticSearchError::ConversionError(Box::new(e)))?);
+        lines.push('\n');
+    }
+    Ok(lines)
+}
+
+/// Responses read from a batch's output file
+#[derive(Debug, Default)]
+pub struct BatchOutput {
+    /// By request index, `None` where the request failed or is missing from the output
+    pub responses: Vec<Option<EmbeddingResponse>>,
+    /// Why each failed request failed
+    pub failures: Vec<String>,
+}
+
+/// Matches the lines of an output file to the `requests` submitted, by custom id
+pub fn parse_output(output: &str, requests: usize) -> Result<BatchOutput, SemanticSearchError> {
+    let ids: HashMap<String, usize> = (0..requests).map(|i| (custom_id(i), i)).collect();
+    let mut parsed = BatchOutput { responses: vec![None; requests], failures: Vec::new() };
+    for line in output.lines().filter(|line| !line.trim().is_empty()) {
+        let line: OutputLine = serde_json::from_str(line).map_err(SemanticSearchError::JSONDeserialize)?;
+        let index = match ids.get(&line.custom_id) {
+            Some(&index) => index,
+            None => {
+                parsed.failures.push(format!("unknown request {}", line.custom_id));
+                continue;
+            }
+        };
+        match (line.response, line.error) {
+            (_, Some(error)) => parsed.failures.push(format!("{}: {}", line.custom_id, error.message)),
+            (Some(response), None) if response.status_code == 200 => {
+                let response = serde_json::from_value(response.body).map_err(SemanticSearchError::JSONDeserialize)?;
+                parsed.responses[index] = Some(response);
+            },
+            (Some(response), None) => parsed.failures.push(format!("{}: returned status {}", line.custom_id, response.status_code)),
+            (None, None) => parsed.failures.push(format!("{}: no response", line.custom_id)),
+        }
+    }
+    let missing = parsed.responses.iter().filter(|response| response.is_none()).count();
+    if missing > parsed.failures.len() {
+        parsed.failures.push(format!("{} requests missing from the output", missing - parsed.failures.len()));
+    }
+    Ok(parsed)
+}
+
+/// A submitted batch along with the records of every request in it, so results can be
+/// written even if input.csv has changed since
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchJob {
+    pub batch: Batch,
+    pub model: String,
+    pub submitted_at: f64,
+    pub requests: Vec<Vec<PipelineRecord>>,
+    /// Granularity input.csv was extracted at when the batch was submitted
+    #[serde(default)]
+    pub granularity: Granularity,
+}
+
+impl BatchJob {
+    pub async fn load(file_processor: &FileProcessor, path: &str) -> Result<Option<Self>, SemanticSearchError> {
+        if !file_processor.check_file_exists_at_path(path).await? {
+            return Ok(None);
+        }
+        let data = file_processor.read_from_path(path).await?;
+        serde_json::from_str(&data).map(Some).map_err(SemanticSearchError::JSONDeserialize)
+    }
+
+    pub async fn save(&self, file_processor: &FileProcessor, path: &str) -> Result<(), SemanticSearchError> {
+        let data = serde_json::to_string(self).map_err(|e| SemanticSearchError::ConversionError(Box::new(e)))?;
+        file_processor.delete_file_at_path(path).await?;
+        file_processor.write_to_path(path, &data).await
+    }
+
+    pub fn status(&self) -> BatchJobStatus {
+        BatchJobStatus {
+            id: self.batch.id.clone(),
+            status: self.batch.status.clone(),
+            finished: self.batch.is_finished(),
+            requests: self.requests.len(),
+            completed: self.batch.request_counts.completed,
+            failed: self.batch.request_counts.failed,
+            submitted_at: self.submitted_at,
+            report: None,
+        }
+    }
+}
+
+/// Progress of a batch job handed to the plugin
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchJobStatus {
+    pub id: String,
+    pub status: String,
+    pub finished: bool,
+    pub requests: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub submitted_at: f64,
+    /// Set once the results have been written to the embedding file
+    pub report: Option<PipelineReport>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(names: &[&str]) -> Vec<PipelineRecord> {
+        names.iter().map(|name| PipelineRecord { name: name.to_string(), header: String::new(), body: String::new(), text: format!("text of {}", name), lines: None, times: None, path: None }).collect()
+    }
+
+    #[test]
+    fn writes_one_line_per_request() {
+        let res = request_lines("text-embedding-3-small", &[records(&["a.md", "b.md"]), records(&["c.md"])]).unwrap();
+
+        assert_eq!(res, concat!(
+            r#"{"custom_id":"request-0","method":"POST","url":"/v1/embeddings","body":{"model":"text-embedding-3-small","input":["text of a.md","text of b.md"]}}"#, "\n",
+            r#"{"custom_id":"request-1","method":"POST","url":"/v1/embeddings","body":{"model":"text-embedding-3-small","input":["text of c.md"]}}"#, "\n"));
+    }
+
+    #[test]
+    fn refuses_oversized_batches() {
+        let res = request_lines("text-embedding-3-small", &[records(&["a.md"; MAX_BATCH_INPUTS + 1])]);
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn matches_output_to_requests() {
+        let embedding = r#"{"object":"list","model":"text-embedding-3-small","data":[{"index":0,"object":"embedding","embedding":[0.5,1.0]}],"usage":{"prompt_tokens":3,"total_tokens":3}}"#;
+        let output = format!(concat!(
+            r#"{{"id":"r2","custom_id":"request-2","response":{{"status_code":200,"body":{}}},"error":null}}"#, "\n",
+            r#"{{"id":"r0","custom_id":"request-0","response":{{"status_code":400,"body":{{}}}},"error":null}}"#, "\n",
+            r#"{{"id":"r1","custom_id":"request-1","response":null,"error":{{"code":"server_error","message":"try again"}}}}"#, "\n"), embedding);
+
+        let res = parse_output(&output, 4).unwrap();
+
+        assert!(res.responses[0].is_none() && res.responses[1].is_none() && res.responses[3].is_none());
+        assert_eq!(res.responses[2].as_ref().unwrap().data[0].embedding, vec![0.5, 1.0]);
+        assert_eq!(res.failures, vec!["request-0: returned status 400", "request-1: try again", "1 requests missing from the output"]);
+    }
+
+    #[test]
+    fn finished_statuses() {
+        let batch = |status: &str| Batch { id: "batch_1".to_string(), status: status.to_string(), output_file_id: None, request_counts: RequestCounts::default() };
+
+        assert!(!batch("in_progress").is_finished());
+        assert!(!batch("finalizing").is_finished());
+        assert!(batch("completed").is_finished());
+        assert!(batch("expired").is_finished());
+    }
+}
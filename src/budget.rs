@@ -0,0 +1,75 @@
+use tiktoken_rs::CoreBPE;
+
+use crate::SemanticSearchError;
+use crate::pipeline::{PipelineHook, PipelineRecord};
+use crate::pricing::{self, CostEstimate};
+
+/// Refuses a run before any batch is sent when embedding its records would cost more than the cap.
+///
+/// Runs after truncation so the projected cost matches what is actually sent.
+pub struct BudgetHook {
+    bpe: &'static CoreBPE,
+    model: String,
+    dollars_per_million_tokens: f32,
+    max_dollars: f32,
+    price_factor: f32,
+}
+
+impl BudgetHook {
+    pub fn new(model: &str, dollars_per_million_tokens: f32, max_dollars: f32) -> Self {
+        Self { bpe: pricing::bpe(), model: model.to_string(), dollars_per_million_tokens, max_dollars, price_factor: 1.0 }
+    }
+
+    /// Checks the cap against `factor` times the regular price, for discounted APIs
+    pub fn price_factor(mut self, factor: f32) -> Self {
+        self.price_factor = factor;
+        self
+    }
+}
+
+impl PipelineHook for BudgetHook {
+    fn preprocess(&mut self, records: Vec<PipelineRecord>) -> Result<Vec<PipelineRecord>, SemanticSearchError> {
+        let tokens = records.iter().map(|record| self.bpe.encode_with_special_tokens(&record.text).len()).sum();
+        check(&CostEstimate::at_price(&self.model, self.dollars_per_million_tokens, tokens, 0).discounted(self.price_factor), self.max_dollars)?;
+        Ok(records)
+    }
+}
+
+/// Errors when `estimate` exceeds `max_dollars`; a cap of 0 or less allows any cost
+pub fn check(estimate: &CostEstimate, max_dollars: f32) -> Result<(), SemanticSearchError> {
+    if max_dollars <= 0.0 || estimate.dollars <= max_dollars {
+        return Ok(());
+    }
+    Err(SemanticSearchError::BudgetExceeded(format!(
+        "embedding {} tokens with {} would cost about ${:.2}, more than the ${:.2} cap per run",
+        estimate.tokens, estimate.model, estimate.dollars, max_dollars)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(text: &str) -> PipelineRecord {
+        PipelineRecord { name: "note.md".to_string(), header: String::new(), body: String::new(), text: text.to_string(), lines: None, times: None, path: None }
+    }
+
+    #[test]
+    fn refuses_runs_over_the_cap() {
+        let estimate = CostEstimate::from_tokens("text-embedding-ada-002", 20_000_000, 80);
+
+        assert!(check(&estimate, 0.0).is_ok());
+        assert!(check(&estimate, 2.0).is_ok());
+        let e = check(&estimate, 1.5).unwrap_err();
+        assert_eq!(e.code(), crate::ErrorCode::BudgetExceeded);
+        assert_eq!(e.to_string(), "Over budget: embedding 20000000 tokens with text-embedding-ada-002 would cost about $2.00, more than the $1.50 cap per run");
+    }
+
+    #[test]
+    fn hook_counts_the_records_sent() {
+        let records = vec![record("a short section"), record("another one")];
+
+        assert_eq!(BudgetHook::new("text-embedding-3-small", 0.02, 0.01).preprocess(records.clone()).unwrap().len(), 2);
+        assert!(BudgetHook::new("text-embedding-3-small", 0.02, 0.000_000_01).preprocess(records.clone()).is_err());
+        assert!(BudgetHook::new("text-embedding-3-small", 0.02, 0.000_000_01).price_factor(0.0).preprocess(records).is_ok());
+    }
+}
@@ -0,0 +1,74 @@
+use serde::Deserialize;
+
+use crate::SemanticSearchError;
+use crate::generate_input::clean_text;
+
+pub const CANVAS_EXTENSION: &str = "canvas";
+
+/// The parts of an Obsidian `.canvas` file (JSON Canvas) needed to embed its cards
+#[derive(Debug, Deserialize)]
+struct Canvas {
+    #[serde(default)]
+    nodes: Vec<CanvasNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CanvasNode {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+/// One section per text card: the header is the card's first heading, or its id if it has none.
+/// File, link and group nodes are skipped since their content lives elsewhere.
+pub fn canvas_sections(name: &str, data: &str) -> Result<Vec<(String, String, String)>, SemanticSearchError> {
+    let canvas: Canvas = serde_json::from_str(data).map_err(SemanticSearchError::JSONDeserialize)?;
+    let sections = canvas.nodes.into_iter()
+        .filter(|node| node.kind == "text")
+        .filter_map(|node| {
+            let body = clean_text(&node.text.lines().collect::<Vec<_>>().join(" "));
+            if body.is_empty() {
+                return None;
+            }
+            let header = node.text.lines()
+                .find(|line| line.trim_start().starts_with('#'))
+                .map(clean_text)
+                .unwrap_or(node.id);
+            Some((name.to_string(), header, body))
+        })
+        .collect();
+    Ok(sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_cards_become_sections() {
+        let data = r###"{
+            "nodes": [
+                {"id": "a1", "type": "text", "text": "## Goals\nShip the plugin", "x": 0, "y": 0, "width": 250, "height": 60},
+                {"id": "b2", "type": "text", "text": "loose idea", "x": 0, "y": 100, "width": 250, "height": 60},
+                {"id": "c3", "type": "file", "file": "Notes/Plan.md", "x": 300, "y": 0, "width": 250, "height": 60},
+                {"id": "d4", "type": "text", "text": "  ", "x": 0, "y": 200, "width": 250, "height": 60}
+            ],
+            "edges": []
+        }"###;
+
+        let sections = canvas_sections("Board.canvas", data).unwrap();
+
+        assert_eq!(sections, vec![
+            ("Board.canvas".to_string(), "Goals".to_string(), "Goals Ship the plugin".to_string()),
+            ("Board.canvas".to_string(), "b2".to_string(), "loose idea".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn empty_canvas() {
+        assert!(canvas_sections("Empty.canvas", "{}").unwrap().is_empty());
+        assert!(canvas_sections("Broken.canvas", "not json").is_err());
+    }
+}
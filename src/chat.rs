@@ -0,0 +1,77 @@
+//! Requests to an OpenAI-compatible `/chat/completions` endpoint, used to rephrase queries and
+//! to answer questions from the notes.
+
+use serde::{Deserialize, Serialize};
+
+use crate::context::{ContextSource, RagContext};
+
+/// Request body for an OpenAI-compatible `/chat/completions` endpoint
+#[derive(Debug, Serialize)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub temperature: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatResponse {
+    pub choices: Vec<ChatChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatChoice {
+    pub message: ChatMessage,
+}
+
+impl ChatResponse {
+    /// Text of the first choice, empty if there is none
+    pub fn reply(self) -> String {
+        self.choices.into_iter().next().map(|choice| choice.message.content).unwrap_or_default()
+    }
+}
+
+/// An answer to a question written from the notes in `sources`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Answer {
+    pub answer: String,
+    pub model: String,
+    /// Sections the answer was written from, as labelled in the context sent to the model
+    pub sources: Vec<ContextSource>,
+}
+
+/// Asks `model` to answer `question` from `context` alone, citing the notes it uses
+pub fn answer_request(model: &str, question: &str, context: &RagContext) -> ChatRequest {
+    let instructions = "You answer questions from the user's personal notes. Use only the notes below. \
+         After each statement, cite the notes it comes from by their name without the .md extension as a wiki link, e.g. [[raft]]. \
+         If the notes don't answer the question, say so.";
+    ChatRequest {
+        model: model.to_string(),
+        messages: vec![
+            ChatMessage { role: "system".to_string(), content: format!("{}\n\nNotes:\n\n{}", instructions, context.context) },
+            ChatMessage { role: "user".to_string(), content: question.to_string() },
+        ],
+        temperature: 0.2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answer_request_carries_the_context() {
+        let context = RagContext { context: "[1] raft.md - Election\nRaft elects a leader.".to_string(), tokens: 10, sources: Vec::new() };
+
+        let request = answer_request("gpt-4o-mini", "How does Raft elect a leader?", &context);
+
+        assert_eq!(request.messages.len(), 2);
+        assert!(request.messages[0].content.ends_with("Notes:\n\n[1] raft.md - Election\nRaft elects a leader."));
+        assert_eq!(request.messages[1].content, "How does Raft elect a leader?");
+    }
+}
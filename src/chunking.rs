@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ranking::FileGrouping;
+use crate::settings::Settings;
+
+/// How much of a markdown note each embedding covers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Granularity {
+    /// The whole note
+    Note,
+    /// A section starting at a line matching the section delimiter
+    #[default]
+    Heading,
+    /// A block of lines without blank lines, within a section
+    Paragraph,
+}
+
+impl Granularity {
+    /// How input.csv records the granularity, matching its name in settings
+    pub fn name(self) -> &'static str {
+        match self {
+            Granularity::Note => "note",
+            Granularity::Heading => "heading",
+            Granularity::Paragraph => "paragraph",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Granularity::Note, Granularity::Heading, Granularity::Paragraph].iter().copied().find(|granularity| granularity.name() == name)
+    }
+
+    /// Grouping used when searching a store of this granularity. A note is a single result,
+    /// even when it had to be embedded in several parts.
+    pub fn file_grouping(self, configured: FileGrouping) -> FileGrouping {
+        match (self, configured) {
+            (Granularity::Note, FileGrouping::Off) => FileGrouping::Max,
+            (_, configured) => configured,
+        }
+    }
+}
+
+/// Sliding-window splitting of long sections into chunks of words
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Chunking {
+    /// Words per chunk, 0 keeps whole sections
+    pub size: usize,
+    /// Words shared by consecutive chunks
+    pub overlap: usize,
+    pub granularity: Granularity,
+}
+
+impl Chunking {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self { size: settings.chunk_size, overlap: settings.chunk_overlap, granularity: settings.embedding_granularity }
+    }
+
+    /// Word index ranges of the chunks covering `words` words. Each chunk starts
+    /// `size - overlap` words after the previous one and the last ends at the final word.
+    pub fn windows(&self, words: usize) -> Vec<(usize, usize)> {
+        if self.size == 0 || words <= self.size {
+            return vec![(0, words)];
+        }
+        // at least one new word per chunk, however large the overlap
+        let step = self.size - self.overlap.min(self.size - 1);
+        let mut windows = Vec::new();
+        let mut start = 0;
+        loop {
+            let end = (start + self.size).min(words);
+            windows.push((start, end));
+            if end == words {
+                return windows;
+            }
+            start += step;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunking(size: usize, overlap: usize) -> Chunking {
+        Chunking { size, overlap, ..Default::default() }
+    }
+
+    #[test]
+    fn windows() {
+        assert_eq!(chunking(0, 0).windows(100), vec![(0, 100)]);
+        assert_eq!(chunking(4, 1).windows(4), vec![(0, 4)]);
+        assert_eq!(chunking(4, 1).windows(10), vec![(0, 4), (3, 7), (6, 10)]);
+        assert_eq!(chunking(4, 0).windows(9), vec![(0, 4), (4, 8), (8, 9)]);
+        assert_eq!(chunking(2, 5).windows(3), vec![(0, 2), (1, 3)]);
+    }
+
+    #[test]
+    fn note_stores_group_by_file() {
+        assert_eq!(Granularity::Note.file_grouping(FileGrouping::Off), FileGrouping::Max);
+        assert_eq!(Granularity::Note.file_grouping(FileGrouping::Mean), FileGrouping::Mean);
+        assert_eq!(Granularity::Paragraph.file_grouping(FileGrouping::Off), FileGrouping::Off);
+    }
+}
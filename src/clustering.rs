@@ -0,0 +1,159 @@
+use std::cmp::Reverse;
+
+use serde::Serialize;
+
+use crate::math::dot;
+
+const MAX_ITERATIONS: usize = 50;
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ClusterChunk {
+    pub name: String,
+    pub header: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NoteCluster {
+    /// Header of the chunk closest to the cluster centre
+    pub label: String,
+    /// Distinct notes with at least one chunk in the cluster, most chunks first
+    pub notes: Vec<String>,
+    pub chunks: Vec<ClusterChunk>,
+}
+
+/// Picks a cluster count for `n` chunks when none is given, using the `sqrt(n / 2)` rule of thumb
+pub fn default_k(n: usize) -> usize {
+    ((n as f64 / 2.0).sqrt().round() as usize).max(1)
+}
+
+/// Groups chunks into `k` clusters with spherical k-means.
+///
+/// Centroids are seeded deterministically with farthest-point selection so the
+/// same store always produces the same clusters.
+pub fn cluster_notes(rows: &[(String, String, Vec<f32>)], k: usize) -> Vec<NoteCluster> {
+    if rows.is_empty() {
+        return Vec::new();
+    }
+    let k = k.clamp(1, rows.len());
+    let vectors: Vec<Vec<f32>> = rows.iter().map(|row| normalized(&row.2)).collect();
+    let mut centroids = seed_centroids(&vectors, k);
+    let mut assignments = vec![0; vectors.len()];
+
+    for _ in 0..MAX_ITERATIONS {
+        let mut changed = false;
+        for (i, vector) in vectors.iter().enumerate() {
+            let nearest = nearest_centroid(vector, &centroids);
+            if nearest != assignments[i] {
+                assignments[i] = nearest;
+                changed = true;
+            }
+        }
+        for (c, centroid) in centroids.iter_mut().enumerate() {
+            let mut sum = vec![0.0; centroid.len()];
+            for (vector, _) in vectors.iter().zip(&assignments).filter(|(_, assignment)| **assignment == c) {
+                for (s, v) in sum.iter_mut().zip(vector) {
+                    *s += v;
+                }
+            }
+            // an empty cluster keeps its previous centroid
+            if sum.iter().any(|s| *s != 0.0) {
+                *centroid = normalized(&sum);
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut clusters: Vec<NoteCluster> = centroids.iter().enumerate().filter_map(|(c, centroid)| {
+        let members: Vec<usize> = (0..rows.len()).filter(|i| assignments[*i] == c).collect();
+        let closest = *members.iter().max_by(|a, b| dot(&vectors[**a], centroid).total_cmp(&dot(&vectors[**b], centroid)))?;
+
+        let mut note_counts: Vec<(String, usize)> = Vec::new();
+        for i in members.iter() {
+            match note_counts.iter_mut().find(|(name, _)| *name == rows[*i].0) {
+                Some((_, count)) => *count += 1,
+                None => note_counts.push((rows[*i].0.clone(), 1)),
+            }
+        }
+        note_counts.sort_by_key(|(_, count)| Reverse(*count));
+
+        Some(NoteCluster {
+            label: rows[closest].1.clone(),
+            notes: note_counts.into_iter().map(|(name, _)| name).collect(),
+            chunks: members.iter().map(|i| ClusterChunk { name: rows[*i].0.clone(), header: rows[*i].1.clone() }).collect(),
+        })
+    }).collect();
+    clusters.sort_by_key(|cluster| Reverse(cluster.chunks.len()));
+    clusters
+}
+
+fn seed_centroids(vectors: &[Vec<f32>], k: usize) -> Vec<Vec<f32>> {
+    let mut centroids = vec![vectors[0].clone()];
+    while centroids.len() < k {
+        let farthest = vectors.iter()
+            .max_by(|a, b| {
+                let a_best = centroids.iter().map(|c| dot(a, c)).fold(f32::MIN, f32::max);
+                let b_best = centroids.iter().map(|c| dot(b, c)).fold(f32::MIN, f32::max);
+                b_best.total_cmp(&a_best)
+            })
+            .unwrap();
+        centroids.push(farthest.clone());
+    }
+    centroids
+}
+
+fn nearest_centroid(vector: &[f32], centroids: &[Vec<f32>]) -> usize {
+    centroids.iter().enumerate()
+        .max_by(|a, b| dot(vector, a.1).total_cmp(&dot(vector, b.1)))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+fn normalized(vector: &[f32]) -> Vec<f32> {
+    let norm = dot(vector, vector).sqrt();
+    if norm == 0.0 {
+        return vector.to_vec();
+    }
+    vector.iter().map(|v| v / norm).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(name: &str, header: &str, embedding: Vec<f32>) -> (String, String, Vec<f32>) {
+        (name.to_string(), header.to_string(), embedding)
+    }
+
+    #[test]
+    fn separates_topics() {
+        let rows = vec![
+            row("cooking.md", "Pasta", vec![1.0, 0.1, 0.0]),
+            row("cooking.md", "Sauce", vec![0.9, 0.2, 0.0]),
+            row("recipes.md", "Bread", vec![0.95, 0.0, 0.1]),
+            row("rust.md", "Traits", vec![0.0, 0.1, 1.0]),
+            row("rust.md", "Lifetimes", vec![0.1, 0.0, 0.9]),
+        ];
+
+        let res = cluster_notes(&rows, 2);
+
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].notes, vec!["cooking.md".to_string(), "recipes.md".to_string()]);
+        assert_eq!(res[1].notes, vec!["rust.md".to_string()]);
+        assert_eq!(res[0].chunks.len(), 3);
+    }
+
+    #[test]
+    fn k_larger_than_rows() {
+        let rows = vec![row("a.md", "A", vec![1.0, 0.0])];
+
+        assert_eq!(cluster_notes(&rows, 5).len(), 1);
+    }
+
+    #[test]
+    fn default_k_grows_with_store() {
+        assert_eq!(default_k(1), 1);
+        assert_eq!(default_k(200), 10);
+    }
+}
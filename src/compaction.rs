@@ -0,0 +1,99 @@
+//! Rewriting the embedding store without the rows incremental updates leave behind.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::embedding_file::EmbeddingFile;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct CompactionReport {
+    pub rows_before: usize,
+    pub rows_after: usize,
+    /// Rows of notes that are no longer in the vault
+    pub orphans_removed: usize,
+    /// Rows with the same note, header and vector as a later row
+    pub duplicates_removed: usize,
+    /// Rows that could not be read, moved from the rewritten file to `<embedding file>.corrupt`
+    pub malformed_removed: usize,
+    pub bytes_before: usize,
+    pub bytes_after: usize,
+    pub bytes_reclaimed: usize,
+}
+
+/// Whether a stored row named `name` belongs to a note that has left the vault. Names without
+/// an extension are documents embedded through the API rather than notes, and are kept.
+pub fn is_orphan(name: &str, vault_names: &HashSet<String>) -> bool {
+    Path::new(name).extension().is_some() && !vault_names.contains(name)
+}
+
+/// Drops orphaned rows and exact duplicates from `store`, keeping the last copy of a duplicate
+/// since later rows were written more recently. The other rows keep their order.
+pub fn compact(store: EmbeddingFile, vault_names: &HashSet<String>) -> (EmbeddingFile, CompactionReport) {
+    let mut report = CompactionReport { rows_before: store.rows.len(), ..CompactionReport::default() };
+    let mut seen: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut keep = vec![false; store.rows.len()];
+    for (i, (name, header, embedding)) in store.rows.iter().enumerate().rev() {
+        if is_orphan(name, vault_names) {
+            report.orphans_removed += 1;
+            continue;
+        }
+        let kept = seen.entry(row_hash(name, header, embedding)).or_default();
+        if kept.iter().any(|&j| store.rows[j] == store.rows[i]) {
+            report.duplicates_removed += 1;
+            continue;
+        }
+        kept.push(i);
+        keep[i] = true;
+    }
+    let (rows, provenance) = store.rows.into_iter().zip(store.provenance).zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(row, _)| row)
+        .unzip();
+    let compacted = EmbeddingFile { metadata: store.metadata, rows, provenance };
+    report.rows_after = compacted.rows.len();
+    (compacted, report)
+}
+
+/// Hash of a row's note, header and exact vector, so identical rows land in the same bucket
+fn row_hash(name: &str, header: &str, embedding: &[f32]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    header.hash(&mut hasher);
+    for value in embedding {
+        value.to_bits().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provenance::Provenance;
+
+    fn provenance(embedded_at: f64) -> Option<Provenance> {
+        Some(Provenance { model: "m".to_string(), provider: String::new(), embedded_at, settings_hash: String::new(), lines: None, note_times: None, language: None, path: None })
+    }
+
+    #[test]
+    fn drops_orphans_and_duplicates() {
+        let rows = vec![
+            ("a.md".to_string(), "A".to_string(), vec![1.0, 0.0]),
+            ("gone.md".to_string(), "G".to_string(), vec![0.0, 1.0]),
+            ("a.md".to_string(), "A".to_string(), vec![0.5, 0.5]),
+            ("readwise/1".to_string(), "R".to_string(), vec![1.0, 1.0]),
+            ("a.md".to_string(), "A".to_string(), vec![1.0, 0.0]),
+        ];
+        let store = EmbeddingFile { metadata: None, rows, provenance: (0..5).map(|i| provenance(i as f64)).collect() };
+        let vault_names = HashSet::from(["a.md".to_string()]);
+
+        let (compacted, report) = compact(store, &vault_names);
+
+        assert_eq!(compacted.rows.iter().map(|(name, _, embedding)| (name.as_str(), embedding[0])).collect::<Vec<_>>(), vec![("a.md", 0.5), ("readwise/1", 1.0), ("a.md", 1.0)]);
+        assert_eq!(compacted.provenance.iter().map(|p| p.as_ref().unwrap().embedded_at).collect::<Vec<_>>(), vec![2.0, 3.0, 4.0]);
+        assert_eq!((report.rows_before, report.rows_after, report.orphans_removed, report.duplicates_removed), (5, 3, 1, 1));
+    }
+}
@@ -0,0 +1,120 @@
+//! Context for question answering: the text of the sections best matching a question, packed
+//! into a token budget for another plugin's language model prompt.
+
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::pricing;
+use crate::truncation;
+
+/// Sections with less room left than this are left out rather than cut to a few words
+const MIN_SECTION_TOKENS: usize = 32;
+
+/// A ranked section with the text to put in the context
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextSection {
+    pub name: String,
+    pub header: String,
+    pub score: f32,
+    pub text: String,
+}
+
+/// Where a part of the context came from. `index` is the `[n]` it is labelled with in the context.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContextSource {
+    pub index: usize,
+    pub name: String,
+    pub header: String,
+    pub score: f32,
+    pub tokens: usize,
+    /// Whether the section's text was shortened to fit the budget
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RagContext {
+    /// The sections, best first, each labelled `[n] note - header` and separated by a blank line
+    pub context: String,
+    pub tokens: usize,
+    pub sources: Vec<ContextSource>,
+}
+
+/// Packs the best section of each note of `sections`, which must be ranked, into at most
+/// `token_budget` tokens. The first section that does not fit is shortened to the room left,
+/// and the rest are left out.
+pub fn build_context(sections: impl IntoIterator<Item = ContextSection>, token_budget: usize) -> RagContext {
+    let mut notes = HashSet::new();
+    let mut blocks = Vec::new();
+    let mut sources = Vec::new();
+    let mut used = 0;
+    for section in sections.into_iter().filter(|section| notes.insert(section.name.clone())) {
+        let index = sources.len() + 1;
+        let label = format!("[{}] {} - {}\n", index, section.name, section.header.trim());
+        // blocks after the first are preceded by a blank line
+        let overhead = pricing::count_tokens(&label) + if blocks.is_empty() { 0 } else { 1 };
+        let room = token_budget.saturating_sub(used + overhead);
+        let text = section.text.trim();
+        let tokens = pricing::count_tokens(text);
+        let (text, tokens, truncated) = match tokens <= room {
+            true => (text.to_string(), tokens, false),
+            false if room >= MIN_SECTION_TOKENS => {
+                let text = truncation::truncate_text(pricing::bpe(), text, room);
+                let tokens = pricing::count_tokens(&text);
+                (text, tokens, true)
+            },
+            false => break,
+        };
+        used += overhead + tokens;
+        blocks.push(format!("{}{}", label, text));
+        sources.push(ContextSource { index, name: section.name, header: section.header, score: section.score, tokens, truncated });
+        if truncated {
+            break;
+        }
+    }
+    RagContext { context: blocks.join("\n\n"), tokens: used, sources }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(name: &str, header: &str, text: &str) -> ContextSection {
+        ContextSection { name: name.to_string(), header: header.to_string(), score: 1.0, text: text.to_string() }
+    }
+
+    #[test]
+    fn one_section_per_note() {
+        let sections = vec![
+            section("raft.md", "Election", "Raft elects a leader with randomized timeouts."),
+            section("raft.md", "Log", "The leader replicates its log."),
+            section("paxos.md", "Phases", "Paxos has a prepare and an accept phase."),
+        ];
+
+        let context = build_context(sections, 1000);
+
+        assert_eq!(context.context, "[1] raft.md - Election\nRaft elects a leader with randomized timeouts.\n\n[2] paxos.md - Phases\nPaxos has a prepare and an accept phase.");
+        assert_eq!(context.sources.iter().map(|source| (source.index, source.header.as_str())).collect::<Vec<_>>(), vec![(1, "Election"), (2, "Phases")]);
+    }
+
+    #[test]
+    fn stays_within_the_budget() {
+        let long = "Consensus needs a majority of nodes to agree. ".repeat(50);
+        let sections = vec![
+            section("short.md", "Intro", "Consensus is agreement among nodes."),
+            section("long.md", "Details", &long),
+            section("later.md", "Skipped", "Never reached."),
+        ];
+
+        let context = build_context(sections, 100);
+
+        assert!(context.tokens <= 100, "{}", context.tokens);
+        assert!(pricing::count_tokens(&context.context) <= 100);
+        assert_eq!(context.sources.len(), 2);
+        assert!(context.sources[1].truncated);
+        assert!(!context.context.contains("Never reached"));
+
+        let tiny = build_context(vec![section("long.md", "Details", &long)], 10);
+        assert!(tiny.sources.is_empty() && tiny.context.is_empty());
+    }
+}
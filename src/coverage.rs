@@ -0,0 +1,138 @@
+//! Which notes of the vault the index is missing or holds an outdated version of, so it is clear
+//! what an incremental update or a reindex of a folder will touch.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::{QueryCommand, SemanticSearchError};
+use crate::embedding_file::{self, EmbeddingFile};
+use crate::file_processor::FileFilter;
+use crate::obsidian;
+use crate::settings::Settings;
+
+/// A note of the vault, as Generate Input would read it
+#[derive(Debug, Clone, PartialEq)]
+pub struct VaultNote {
+    pub path: String,
+    pub name: String,
+    /// When the note was last modified, in ms since the epoch
+    pub modified: f64,
+}
+
+/// A note edited since the version of it in the index
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StaleNote {
+    pub path: String,
+    pub modified: f64,
+    /// When the indexed version of the note was last modified, or embedded if that wasn't recorded
+    pub indexed_at: f64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct CoverageDiff {
+    /// Vault paths of notes without any stored section
+    pub unindexed: Vec<String>,
+    pub stale: Vec<StaleNote>,
+}
+
+/// The notes of `notes` that `store` has no sections of, and those modified after their sections
+/// were taken from them, both by path. Sections record the modification time of the note they
+/// were read from; older sections fall back to when they were embedded, or when the store was
+/// created, and notes none of whose sections say are taken to be up to date.
+pub fn diff(store: &EmbeddingFile, notes: &[VaultNote]) -> CoverageDiff {
+    let created_at = store.metadata.as_ref().map(|metadata| metadata.created_at);
+    let mut indexed_at: HashMap<&str, Option<f64>> = HashMap::new();
+    for (row, (name, _, _)) in store.rows.iter().enumerate() {
+        let provenance = store.provenance.get(row).and_then(Option::as_ref);
+        let at = provenance.map(|provenance| provenance.note_times.map_or(provenance.embedded_at, |times| times.modified)).or(created_at);
+        let latest = indexed_at.entry(name.as_str()).or_insert(None);
+        *latest = match (*latest, at) {
+            (Some(latest), Some(at)) => Some(latest.max(at)),
+            (latest, at) => latest.or(at),
+        };
+    }
+    let mut coverage = CoverageDiff::default();
+    for note in notes {
+        match indexed_at.get(note.name.as_str()) {
+            None => coverage.unindexed.push(note.path.clone()),
+            Some(Some(indexed_at)) if note.modified > *indexed_at => {
+                coverage.stale.push(StaleNote { path: note.path.clone(), modified: note.modified, indexed_at: *indexed_at });
+            },
+            Some(_) => {},
+        }
+    }
+    coverage.unindexed.sort();
+    coverage.stale.sort_by(|a, b| a.path.cmp(&b.path));
+    coverage
+}
+
+/// Returns the `CoverageDiff` of the markdown notes Generate Input would read against the
+/// configured embedding store. Without a store every note is unindexed.
+#[wasm_bindgen]
+pub async fn coverage_diff(app: &obsidian::App, settings: JsValue) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let query_cmd = QueryCommand::new(app, &settings);
+    let file_processor = &query_cmd.file_processor;
+    let store = match file_processor.check_file_exists_at_path(&query_cmd.embedding_path).await? {
+        true => embedding_file::read_embedding_file_lenient(&file_processor.read_from_path(&query_cmd.embedding_path).await?)?.0,
+        false => EmbeddingFile { metadata: None, rows: Vec::new(), provenance: Vec::new() },
+    };
+    let notes: Vec<VaultNote> = file_processor.list_markdown_files(&FileFilter::indexed(&settings.ignored_folders)).await.iter()
+        .map(|file| VaultNote { path: file.path(), name: file.name(), modified: file.stat().mtime() })
+        .collect();
+    Ok(serde_wasm_bindgen::to_value(&diff(&store, &notes))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding_file::StoreMetadata;
+    use crate::input::NoteTimes;
+    use crate::provenance::{fingerprint, Provenance};
+
+    fn note(path: &str, modified: f64) -> VaultNote {
+        VaultNote { path: path.to_string(), name: path.rsplit('/').next().unwrap().to_string(), modified }
+    }
+
+    fn provenance(embedded_at: f64, note_modified: Option<f64>) -> Option<Provenance> {
+        Some(Provenance {
+            model: "m".to_string(),
+            provider: "p".to_string(),
+            embedded_at,
+            settings_hash: fingerprint("s"),
+            lines: None,
+            note_times: note_modified.map(|modified| NoteTimes { created: 0.0, modified }),
+            language: None,
+            path: None,
+        })
+    }
+
+    #[test]
+    fn lists_unindexed_and_stale_notes() {
+        let row = |name: &str| (name.to_string(), "H".to_string(), vec![1.0]);
+        let store = EmbeddingFile {
+            metadata: Some(StoreMetadata::new("m", 1, 50.0)),
+            rows: vec![row("fresh.md"), row("edited.md"), row("edited.md"), row("old.md"), row("unknown.md")],
+            provenance: vec![provenance(100.0, Some(90.0)), provenance(100.0, Some(10.0)), provenance(100.0, Some(20.0)), provenance(40.0, None), None],
+        };
+        let notes = vec![note("b/fresh.md", 90.0), note("a/edited.md", 30.0), note("old.md", 45.0), note("unknown.md", 45.0), note("z/new.md", 1.0), note("a/new2.md", 1.0)];
+
+        let diff = diff(&store, &notes);
+
+        assert_eq!(diff.unindexed, vec!["a/new2.md", "z/new.md"]);
+        assert_eq!(diff.stale, vec![
+            StaleNote { path: "a/edited.md".to_string(), modified: 30.0, indexed_at: 20.0 },
+            StaleNote { path: "old.md".to_string(), modified: 45.0, indexed_at: 40.0 },
+        ]);
+    }
+
+    #[test]
+    fn notes_without_times_are_up_to_date() {
+        let store = EmbeddingFile { metadata: None, rows: vec![("a.md".to_string(), "A".to_string(), vec![1.0])], provenance: vec![None] };
+
+        assert_eq!(diff(&store, &[note("a.md", 1e12)]), CoverageDiff::default());
+    }
+}
@@ -0,0 +1,88 @@
+//! Search results shaped for DataviewJS, so a note can hold a table of what is semantically
+//! related to a query, e.g. `dv.table(["Note", "Score"], rows.map(row => [dv.fileLink(row.path), row.score]))`.
+
+use std::collections::HashMap;
+
+use js_sys::{Array, Reflect};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::{QueryCommand, SemanticSearchError, Suggestions};
+use crate::obsidian;
+use crate::settings::Settings;
+
+/// One search result as a Dataview row
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SemanticRow {
+    /// Vault path of the note, or its file name for results whose note is no longer in the vault
+    pub path: String,
+    /// Heading of the matching section
+    pub heading: String,
+    pub score: f32,
+    /// Tags of the note, with their `#`, as Dataview's `file.tags`
+    pub tags: Vec<String>,
+}
+
+/// The first `limit` of `suggestions` as rows, all of them for a `limit` of 0, with the tags of
+/// their notes from `tags`, which is keyed by file name
+fn rows(suggestions: Vec<Suggestions>, tags: &HashMap<String, Vec<String>>, limit: usize) -> Vec<SemanticRow> {
+    let limit = if limit == 0 { usize::MAX } else { limit };
+    suggestions.into_iter().take(limit).map(|suggestion| {
+        let tags = tags.get(&suggestion.name).cloned().unwrap_or_default();
+        let (path, heading) = match suggestion.link {
+            Some(link) => (link.path, link.headings.last().cloned().unwrap_or(suggestion.header)),
+            None => (suggestion.name, suggestion.header),
+        };
+        SemanticRow { path, heading, score: suggestion.score, tags }
+    }).collect()
+}
+
+/// Returns the best `limit` results for `query` among the chunks matching the optional
+/// `QueryFilters` as `SemanticRow`s, ranked like the search modal. With `resolve_files` each row
+/// also gets the note's `TFile` as `file`, null if it is no longer in the vault.
+#[wasm_bindgen]
+pub async fn semantic(app: &obsidian::App, settings: JsValue, query: String, filters: JsValue, limit: usize, resolve_files: bool) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let mut query_cmd = QueryCommand::new(app, &settings);
+    let suggestions = query_cmd.suggest(&query, &filters).await?;
+    let tags = query_cmd.note_tags(suggestions.iter().map(|suggestion| suggestion.name.as_str()).collect());
+    let rows = rows(suggestions, &tags, limit);
+    let value = serde_wasm_bindgen::to_value(&rows)?;
+    if resolve_files {
+        let vault = app.vault();
+        for (row, object) in rows.iter().zip(Array::from(&value).iter()) {
+            // setting a property of a plain object cannot fail
+            let _ = Reflect::set(&object, &"file".into(), &vault.getAbstractFileByPath(row.path.clone()).into());
+        }
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::links::SectionLink;
+
+    fn suggestion(name: &str, header: &str, score: f32, link: Option<SectionLink>) -> Suggestions {
+        Suggestions { name: name.to_string(), header: header.to_string(), score, lines: None, snippet: None, link }
+    }
+
+    #[test]
+    fn rows_use_the_section_link() {
+        let link = SectionLink::new("vault", "Projects/plan.md", vec!["Goals".to_string(), "2024".to_string()], Some(3));
+        let suggestions = vec![suggestion("plan.md", "2024 goals text", 0.9, Some(link)), suggestion("gone.md", "Gone", 0.5, None)];
+        let tags = HashMap::from([("plan.md".to_string(), vec!["#work".to_string()])]);
+
+        let rows = rows(suggestions, &tags, 0);
+
+        assert_eq!(rows[0], SemanticRow { path: "Projects/plan.md".to_string(), heading: "2024".to_string(), score: 0.9, tags: vec!["#work".to_string()] });
+        assert_eq!(rows[1], SemanticRow { path: "gone.md".to_string(), heading: "Gone".to_string(), score: 0.5, tags: Vec::new() });
+    }
+
+    #[test]
+    fn rows_are_limited() {
+        let suggestions = vec![suggestion("a.md", "A", 0.9, None), suggestion("b.md", "B", 0.8, None)];
+
+        assert_eq!(rows(suggestions, &HashMap::new(), 1).len(), 1);
+    }
+}
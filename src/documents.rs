@@ -0,0 +1,90 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+
+use crate::SemanticSearchError;
+use crate::input::InputRecord;
+use crate::pipeline::PipelineReport;
+
+/// Content handed to the plugin directly instead of through input.csv.
+/// `id` is stored as the record's note name, so it replaces earlier documents with the same id.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Document {
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+    pub text: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DocumentsReport {
+    pub documents: usize,
+    /// Stored records dropped because a document with the same id was embedded again
+    pub records_replaced: usize,
+    pub report: PipelineReport,
+}
+
+pub fn from_js(value: &JsValue) -> Result<Vec<Document>, SemanticSearchError> {
+    serde_wasm_bindgen::from_value(value.clone())
+        .map_err(|e| SemanticSearchError::InvalidArgument(format!("Expected a list of {{id, title, text}} documents: {}", e)))
+}
+
+/// One record per document, with the title as its header and as the first line of its body
+/// like a section's heading. Ids must be unique and non-empty.
+pub fn input_records(documents: Vec<Document>) -> Result<Vec<InputRecord>, SemanticSearchError> {
+    let mut ids = HashSet::new();
+    let mut records = Vec::with_capacity(documents.len());
+    for document in documents {
+        let id = document.id.trim().to_string();
+        if id.is_empty() {
+            return Err(SemanticSearchError::InvalidArgument("Document ids cannot be empty".to_string()));
+        }
+        if !ids.insert(id.clone()) {
+            return Err(SemanticSearchError::InvalidArgument(format!("Duplicate document id '{}'", id)));
+        }
+        let title = document.title.trim();
+        let text = document.text.trim();
+        if title.is_empty() && text.is_empty() {
+            return Err(SemanticSearchError::InvalidArgument(format!("Document '{}' has no title or text", id)));
+        }
+        let (header, body) = match (title, text) {
+            ("", text) => (id.clone(), text.to_string()),
+            (title, "") => (title.to_string(), title.to_string()),
+            (title, text) => (title.to_string(), format!("{}\n{}", title, text)),
+        };
+        records.push(InputRecord::new(id, header, body));
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{EmbedContent, EmbedText};
+
+    fn document(id: &str, title: &str, text: &str) -> Document {
+        Document { id: id.to_string(), title: title.to_string(), text: text.to_string() }
+    }
+
+    #[test]
+    fn records_from_documents() {
+        let documents: Vec<Document> = serde_json::from_str(
+            r#"[{"id": "readwise/1", "title": "Highlight", "text": "Some text"}, {"id": "readwise/2", "text": "Untitled"}]"#).unwrap();
+
+        let records = input_records(documents).unwrap();
+        let text = |content| EmbedText { content, template: String::new() };
+
+        assert_eq!(records[0], InputRecord::new("readwise/1".to_string(), "Highlight".to_string(), "Highlight\nSome text".to_string()));
+        assert_eq!(records[0].text_to_embed(&text(EmbedContent::Body)), "Some text");
+        assert_eq!(records[1].header, "readwise/2");
+        assert_eq!(records[1].text_to_embed(&text(EmbedContent::HeadingAndBody)), "Untitled");
+    }
+
+    #[test]
+    fn invalid_documents() {
+        assert!(input_records(vec![document(" ", "Title", "text")]).is_err());
+        assert!(input_records(vec![document("a", "", " ")]).is_err());
+        assert!(input_records(vec![document("a", "A", "one"), document("a", "B", "two")]).is_err());
+    }
+}
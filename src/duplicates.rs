@@ -0,0 +1,195 @@
+use serde::Serialize;
+
+use crate::math;
+
+/// Pairs compared by `find_duplicates` before it stops, enough to compare about 10,000 sections with each other
+pub const MAX_COMPARISONS: usize = 50_000_000;
+/// Pairs compared between yields to the UI thread
+pub const SLICE_COMPARISONS: usize = 100_000;
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateMember {
+    pub name: String,
+    pub header: String,
+    /// Highest similarity to any other chunk in the cluster
+    pub score: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateCluster {
+    pub members: Vec<DuplicateMember>,
+}
+
+/// What `find_duplicates` returns
+#[derive(Debug, Serialize)]
+pub struct DuplicateReport {
+    pub clusters: Vec<DuplicateCluster>,
+    /// Whether the scan stopped at `MAX_COMPARISONS`, so later sections were only compared with earlier ones
+    pub truncated: bool,
+}
+
+/// Groups chunks whose pairwise similarity is at least `threshold`, comparing every pair a
+/// slice at a time so large stores don't block the UI thread for the whole scan.
+///
+/// Pairs are linked transitively, so a cluster may contain two chunks that are
+/// only similar through a third one.
+pub struct DuplicateScan<'a> {
+    rows: &'a [(String, String, Vec<f32>)],
+    threshold: f32,
+    norms: Vec<f32>,
+    parents: Vec<usize>,
+    scores: Vec<f32>,
+    /// First row not yet compared with the rows after it
+    next: usize,
+    comparisons: usize,
+}
+
+impl<'a> DuplicateScan<'a> {
+    pub fn new(rows: &'a [(String, String, Vec<f32>)], threshold: f32) -> Self {
+        Self {
+            rows,
+            threshold,
+            norms: rows.iter().map(|(_, _, embedding)| math::dot(embedding, embedding).sqrt()).collect(),
+            parents: (0..rows.len()).collect(),
+            scores: vec![f32::MIN; rows.len()],
+            next: 0,
+            comparisons: 0,
+        }
+    }
+
+    /// Compares rows with every row after them until at least `max_comparisons` pairs
+    /// were compared, and returns whether rows are left
+    pub fn step(&mut self, max_comparisons: usize) -> bool {
+        let mut compared = 0;
+        while self.next < self.rows.len() && compared < max_comparisons {
+            let i = self.next;
+            for j in (i + 1)..self.rows.len() {
+                let score = math::dot(&self.rows[i].2, &self.rows[j].2) / (self.norms[i] * self.norms[j]);
+                if score.is_nan() || score < self.threshold {
+                    continue;
+                }
+                self.scores[i] = self.scores[i].max(score);
+                self.scores[j] = self.scores[j].max(score);
+                let (root_i, root_j) = (find(&mut self.parents, i), find(&mut self.parents, j));
+                if root_i != root_j {
+                    self.parents[root_j] = root_i;
+                }
+            }
+            compared += self.rows.len() - i - 1;
+            self.next += 1;
+        }
+        self.comparisons += compared;
+        self.next < self.rows.len()
+    }
+
+    /// Pairs compared so far
+    pub fn comparisons(&self) -> usize {
+        self.comparisons
+    }
+
+    /// Groups the chunks linked so far into clusters ordered by their best score
+    pub fn finish(mut self) -> Vec<DuplicateCluster> {
+        let rows = self.rows;
+        let mut groups: Vec<Vec<usize>> = vec![Vec::new(); rows.len()];
+        for i in 0..rows.len() {
+            let root = find(&mut self.parents, i);
+            groups[root].push(i);
+        }
+
+        let scores = self.scores;
+        let mut clusters: Vec<DuplicateCluster> = groups.into_iter()
+            .filter(|group| group.len() > 1)
+            .map(|group| {
+                let mut members: Vec<DuplicateMember> = group.into_iter().map(|i| DuplicateMember {
+                    name: rows[i].0.clone(),
+                    header: rows[i].1.clone(),
+                    score: scores[i],
+                }).collect();
+                members.sort_by(|a, b| b.score.total_cmp(&a.score));
+                DuplicateCluster { members }
+            })
+            .collect();
+        clusters.sort_by(|a, b| b.members[0].score.total_cmp(&a.members[0].score));
+        clusters
+    }
+}
+
+fn find(parents: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    // path compression
+    let mut node = i;
+    while parents[node] != root {
+        let next = parents[node];
+        parents[node] = root;
+        node = next;
+    }
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(name: &str, embedding: Vec<f32>) -> (String, String, Vec<f32>) {
+        (name.to_string(), "Header".to_string(), embedding)
+    }
+
+    fn find_duplicate_clusters(rows: &[(String, String, Vec<f32>)], threshold: f32) -> Vec<DuplicateCluster> {
+        let mut scan = DuplicateScan::new(rows, threshold);
+        while scan.step(usize::MAX) {}
+        scan.finish()
+    }
+
+    #[test]
+    fn clusters_similar_rows() {
+        let rows = vec![
+            row("a", vec![1.0, 0.0]),
+            row("b", vec![0.0, 1.0]),
+            row("c", vec![0.99, 0.01]),
+            row("d", vec![0.01, 0.99]),
+            row("e", vec![-1.0, 0.0]),
+        ];
+
+        let res = find_duplicate_clusters(&rows, 0.95);
+
+        assert_eq!(res.len(), 2);
+        let mut names: Vec<Vec<&str>> = res.iter().map(|cluster| {
+            let mut names: Vec<&str> = cluster.members.iter().map(|m| m.name.as_str()).collect();
+            names.sort();
+            names
+        }).collect();
+        names.sort();
+        assert_eq!(names, vec![vec!["a", "c"], vec!["b", "d"]]);
+    }
+
+    #[test]
+    fn scan_in_slices_matches_full_scan() {
+        let rows = vec![
+            row("a", vec![1.0, 0.0]),
+            row("b", vec![0.0, 1.0]),
+            row("c", vec![0.99, 0.01]),
+            row("d", vec![0.01, 0.99]),
+        ];
+
+        let mut scan = DuplicateScan::new(&rows, 0.95);
+        assert!(scan.step(1));
+        assert_eq!(scan.comparisons(), 3);
+        while scan.step(1) {}
+        assert_eq!(scan.comparisons(), 6);
+
+        let sliced = scan.finish();
+        let full = find_duplicate_clusters(&rows, 0.95);
+        assert_eq!(sliced.len(), full.len());
+        assert_eq!(sliced.iter().map(|cluster| cluster.members.len()).collect::<Vec<_>>(), full.iter().map(|cluster| cluster.members.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn no_duplicates() {
+        let rows = vec![row("a", vec![1.0, 0.0]), row("b", vec![0.0, 1.0])];
+
+        assert!(find_duplicate_clusters(&rows, 0.9).is_empty());
+    }
+}
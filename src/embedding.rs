@@ -70,6 +70,8 @@ pub struct Embedding {
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct EmbeddingUsage {
+    /// Not reported by Voyage
+    #[serde(default)]
     pub prompt_tokens: u32,
     pub total_tokens: u32,
 }
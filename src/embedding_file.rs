@@ -0,0 +1,665 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::Write;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::rc::Rc;
+
+use csv::{ReaderBuilder, StringRecord};
+use serde::{Deserialize, Serialize};
+
+use crate::SemanticSearchError;
+use crate::checksum;
+use crate::chunking::Granularity;
+use crate::error::MalformedRow;
+use crate::input::{BYTE_ORDER_MARK, LineRange, NoteTimes};
+use crate::matryoshka;
+use crate::provenance::Provenance;
+use crate::quantization::{Quantization, QuantizedVector};
+
+/// Version of the embedding file layout written by the embedding pipeline.
+///
+/// 1: `name,header,embedding` rows without metadata
+/// 2: a `# embedding_store: {..}` metadata line followed by a `name,header,embedding` header row
+/// 3: as 2, with per-record `model,provider,embedded_at,settings_hash` provenance columns
+/// 4: as 3, with embeddings optionally stored as `scale:int8,int8,...` when the metadata's `quantization` is `int8`
+/// 5: as 4, with `start_line,end_line` columns locating each record in its note when known
+/// 6: as 5, with `created_at,modified_at` columns holding the times of each record's note when known
+/// 7: as 6, with an embedding column of `=k` when the row has the same vector as the row `k` rows above it
+/// 8: as 7, with a `language` column holding the language each record's text was detected to be in
+/// 9: as 8, with a `checksum` column holding the CRC-32 of the row's other columns
+/// 10: as 9, with a `path` column before the checksum holding the vault path of each record's file when known
+pub const EMBEDDING_SCHEMA_VERSION: u32 = 10;
+const METADATA_PREFIX: &str = "# embedding_store:";
+/// Starts an embedding column that refers to an earlier row's vector
+const REFERENCE_PREFIX: char = '=';
+/// How embedding files are referred to in error messages
+const EMBEDDING_FILE_NAME: &str = "embedding file";
+const HEADER_ROW: &str = "name,header,embedding,model,provider,embedded_at,settings_hash,start_line,end_line,created_at,modified_at,language,path,checksum\n";
+/// Appended to the embedding file's path for the file rows that could not be read are moved to
+pub const CORRUPT_SUFFIX: &str = ".corrupt";
+/// Stored vectors are kept exactly as returned by the API
+pub const STORED_NORMALIZATION: &str = "none";
+
+/// Describes how the vectors in an embedding file were produced
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoreMetadata {
+    pub schema_version: u32,
+    pub model: String,
+    pub dimensions: usize,
+    pub normalization: String,
+    pub created_at: f64,
+    #[serde(default)]
+    pub quantization: Quantization,
+    /// How much of a note each record covers, decided when the input was generated
+    #[serde(default)]
+    pub granularity: Granularity,
+    /// Length of the vectors returned by the model when they were truncated to `dimensions`
+    #[serde(default)]
+    pub truncated_from: Option<usize>,
+}
+
+impl StoreMetadata {
+    pub fn new(model: &str, dimensions: usize, created_at: f64) -> Self {
+        Self {
+            schema_version: EMBEDDING_SCHEMA_VERSION,
+            model: model.to_string(),
+            dimensions,
+            normalization: STORED_NORMALIZATION.to_string(),
+            created_at,
+            quantization: Quantization::None,
+            granularity: Granularity::default(),
+            truncated_from: None,
+        }
+    }
+
+    pub fn with_quantization(self, quantization: Quantization) -> Self {
+        Self { quantization, ..self }
+    }
+
+    pub fn with_granularity(self, granularity: Granularity) -> Self {
+        Self { granularity, ..self }
+    }
+
+    pub fn with_truncated_from(self, truncated_from: Option<usize>) -> Self {
+        Self { truncated_from, ..self }
+    }
+
+    /// Metadata line and header row that start an embedding file
+    pub fn header(&self) -> Result<String, SemanticSearchError> {
+        let metadata = serde_json::to_string(self).map_err(|e| SemanticSearchError::ConversionError(Box::new(e)))?;
+        Ok(format!("{} {}\n{}", METADATA_PREFIX, metadata, HEADER_ROW))
+    }
+}
+
+/// A parsed embedding file. `metadata` is `None` for files written before version 2.
+#[derive(Debug)]
+pub struct EmbeddingFile {
+    pub metadata: Option<StoreMetadata>,
+    pub rows: Vec<(String, String, Vec<f32>)>,
+    /// Provenance of each row in `rows`. Version 2 files take the store's model and
+    /// creation time with an unknown provider, older files have none.
+    pub provenance: Vec<Option<Provenance>>,
+}
+
+impl EmbeddingFile {
+    /// Fails with `ReindexRequired` if queries embedded with `model` cannot be compared to this file
+    pub fn check_model(&self, model: &str) -> Result<(), SemanticSearchError> {
+        match &self.metadata {
+            Some(metadata) if metadata.model != model => Err(SemanticSearchError::ReindexRequired(format!(
+                "embeddings were created with '{}' but queries use '{}'", metadata.model, model))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Truncates a query vector the way the stored vectors were, so the two can be compared
+    pub fn fit_query(&self, mut embedding: Vec<f32>) -> Vec<f32> {
+        if let Some(metadata) = self.metadata.as_ref().filter(|metadata| metadata.truncated_from.is_some()) {
+            matryoshka::truncate(&mut embedding, metadata.dimensions);
+        }
+        embedding
+    }
+
+    /// Fails with `ReindexRequired` if a query vector has a different length from the stored ones
+    pub fn check_dimensions(&self, dimensions: usize) -> Result<(), SemanticSearchError> {
+        let stored = match &self.metadata {
+            Some(metadata) => Some(metadata.dimensions),
+            None => self.rows.first().map(|(_, _, embedding)| embedding.len()),
+        };
+        match stored {
+            Some(stored) if stored != dimensions => Err(SemanticSearchError::ReindexRequired(format!(
+                "stored embeddings have {} dimensions but the query has {}", stored, dimensions))),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl EmbeddingFile {
+    /// The whole file, as written to disk. Rows are written without metadata if there is none.
+    pub fn to_csv(&self) -> Result<String, SemanticSearchError> {
+        let header = match &self.metadata {
+            Some(metadata) => metadata.header()?,
+            None => String::new(),
+        };
+        let quantization = self.metadata.as_ref().map_or(Quantization::None, |metadata| metadata.quantization);
+        let rows = write_rows_with_provenance(self.rows.iter().zip(self.provenance.iter()).map(|((name, header, embedding), provenance)| {
+            (name.as_str(), header.as_str(), embedding.as_slice(), provenance.as_ref())
+        }), quantization)?;
+        Ok(header + &rows)
+    }
+}
+
+/// Formats rows that each carry their own provenance, leaving the columns empty where it is unknown.
+/// Rows with the same vector as an earlier row refer to it instead of repeating it.
+/// `quantization` must match the file's metadata.
+pub fn write_rows_with_provenance<'a>(rows: impl IntoIterator<Item = (&'a str, &'a str, &'a [f32], Option<&'a Provenance>)>, quantization: Quantization) -> Result<String, SemanticSearchError> {
+    let mut writer = RowWriter::new(quantization);
+    let mut written: HashMap<u64, Vec<(&[f32], usize)>> = HashMap::new();
+    for (name, header, embedding, provenance) in rows {
+        let same = written.entry(vector_hash(embedding)).or_default();
+        match same.iter().find(|(other, _)| *other == embedding) {
+            Some(&(_, row)) => {
+                writer.write_reference(name, header, row, provenance)?;
+            },
+            None => {
+                let row = writer.write(name, header, embedding, provenance)?;
+                same.push((embedding, row));
+            },
+        }
+    }
+    writer.take()
+}
+
+/// Hash of a vector's exact values, so identical vectors land in the same bucket
+fn vector_hash(embedding: &[f32]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for value in embedding {
+        value.to_bits().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Where a `RowWriter`'s CSV writer puts its output, shared so it can be drained between batches
+#[derive(Debug, Clone, Default)]
+struct RowBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl io::Write for RowBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Formats embedding file rows with one CSV writer for a whole run, so each batch only pays
+/// for its own rows. `quantization` must match the file's metadata.
+pub struct RowWriter {
+    wtr: csv::Writer<RowBuffer>,
+    buffer: RowBuffer,
+    quantization: Quantization,
+    /// The row's embedding column, reused from row to row
+    embedding: String,
+    /// Rows written so far, to tell how far back a reference points
+    rows: usize,
+}
+
+impl RowWriter {
+    pub fn new(quantization: Quantization) -> Self {
+        let buffer = RowBuffer::default();
+        Self { wtr: csv::Writer::from_writer(buffer.clone()), buffer, quantization, embedding: String::new(), rows: 0 }
+    }
+
+    /// Formats a row, leaving the provenance columns empty when it is unknown. Returns the
+    /// row's number among those written, to refer to it with `write_reference`.
+    pub fn write(&mut self, name: &str, header: &str, embedding: &[f32], provenance: Option<&Provenance>) -> Result<usize, SemanticSearchError> {
+        self.embedding.clear();
+        // Writing to a String cannot fail
+        let _ = match self.quantization {
+            Quantization::None => embedding.iter().enumerate().try_for_each(|(i, value)| match i {
+                0 => write!(self.embedding, "{}", value),
+                _ => write!(self.embedding, ",{}", value),
+            }),
+            Quantization::Int8 => write!(self.embedding, "{}", QuantizedVector::quantize(embedding)),
+        };
+        self.write_columns(name, header, provenance)
+    }
+
+    /// Formats a row with the same vector as the earlier row numbered `row`, without repeating it
+    pub fn write_reference(&mut self, name: &str, header: &str, row: usize, provenance: Option<&Provenance>) -> Result<usize, SemanticSearchError> {
+        self.embedding.clear();
+        let _ = write!(self.embedding, "{}{}", REFERENCE_PREFIX, self.rows - row);
+        self.write_columns(name, header, provenance)
+    }
+
+    fn write_columns(&mut self, name: &str, header: &str, provenance: Option<&Provenance>) -> Result<usize, SemanticSearchError> {
+        let columns = match provenance {
+            Some(provenance) => {
+                let (start_line, end_line) = match provenance.lines {
+                    Some(lines) => (lines.start.to_string(), lines.end.to_string()),
+                    None => (String::new(), String::new()),
+                };
+                let (created_at, modified_at) = match provenance.note_times {
+                    Some(times) => (times.created.to_string(), times.modified.to_string()),
+                    None => (String::new(), String::new()),
+                };
+                [provenance.model.clone(), provenance.provider.clone(), provenance.embedded_at.to_string(), provenance.settings_hash.clone(),
+                    start_line, end_line, created_at, modified_at, provenance.language.clone().unwrap_or_default(), provenance.path.clone().unwrap_or_default()]
+            },
+            None => Default::default(),
+        };
+        let leading = [name, header, self.embedding.as_str()];
+        let fields = leading.iter().copied().chain(columns.iter().map(String::as_str));
+        let sum = checksum::row_checksum(fields.clone());
+        self.wtr.write_record(fields.chain(std::iter::once(sum.as_str())))?;
+        self.rows += 1;
+        Ok(self.rows - 1)
+    }
+
+    /// The rows written since the last call
+    pub fn take(&mut self) -> Result<String, SemanticSearchError> {
+        self.wtr.flush().map_err(csv::Error::from)?;
+        let bytes = std::mem::take(&mut *self.buffer.0.borrow_mut());
+        Ok(String::from_utf8(bytes)?)
+    }
+}
+
+pub fn read_embedding_file(input: &str) -> Result<EmbeddingFile, SemanticSearchError> {
+    Ok(parse_embedding_file(input, false, false)?.0)
+}
+
+/// Like `read_embedding_file`, but skips the rows that cannot be read and returns them alongside the file,
+/// so one corrupted line does not stop querying. The metadata line must still be valid.
+pub fn read_embedding_file_lenient(input: &str) -> Result<(EmbeddingFile, Vec<MalformedRow>), SemanticSearchError> {
+    let (store, _, malformed) = parse_embedding_file(input, true, false)?;
+    Ok((store, malformed))
+}
+
+/// A parsed store, the int8 vectors of its rows if they were kept, and the rows that could not be read
+type ParsedStore = (EmbeddingFile, Option<Vec<QuantizedVector>>, Vec<MalformedRow>);
+
+/// Reads a store for searching. The vectors of int8 stores are returned as written, in the
+/// order of the rows, and the rows are left without their f32 embeddings, so the file is held
+/// in memory once and scored without dequantizing. Other stores are read as by `read_embedding_file`.
+pub fn read_searchable_store(input: &str, lenient: bool) -> Result<ParsedStore, SemanticSearchError> {
+    parse_embedding_file(input, lenient, true)
+}
+
+/// The schema version an embedding file was written with, 1 for files without metadata
+pub fn schema_version(input: &str) -> Result<u32, SemanticSearchError> {
+    Ok(split_metadata(input)?.0.map_or(1, |metadata| metadata.schema_version))
+}
+
+fn split_metadata(input: &str) -> Result<(Option<StoreMetadata>, &str), SemanticSearchError> {
+    let input = input.strip_prefix(BYTE_ORDER_MARK).unwrap_or(input);
+    match input.strip_prefix(METADATA_PREFIX) {
+        Some(rest) => {
+            let (metadata, data) = rest.split_once('\n').unwrap_or((rest, ""));
+            let metadata: StoreMetadata = serde_json::from_str(metadata.trim())
+                .map_err(|e| SemanticSearchError::UnsupportedSchema(format!("invalid embedding file metadata: {}", e)))?;
+            Ok((Some(metadata), data))
+        },
+        None => Ok((None, input)),
+    }
+}
+
+fn parse_embedding_file(input: &str, lenient: bool, keep_int8: bool) -> Result<ParsedStore, SemanticSearchError> {
+    let (metadata, data) = split_metadata(input)?;
+    if let Some(metadata) = &metadata {
+        if metadata.schema_version > EMBEDDING_SCHEMA_VERSION {
+            return Err(SemanticSearchError::UnsupportedSchema(format!(
+                "the embedding file has schema version {} but this version of the plugin supports up to {}, please update the plugin",
+                metadata.schema_version, EMBEDDING_SCHEMA_VERSION)));
+        }
+    }
+
+    // names and headers are kept as written, only the numbers in the embedding column are trimmed
+    let mut reader = ReaderBuilder::new().has_headers(metadata.is_some()).flexible(false)
+        .from_reader(data.as_bytes());
+    let skipped_lines = if metadata.is_some() { 1 } else { 0 };
+    let quantization = metadata.as_ref().map_or(Quantization::None, |metadata| metadata.quantization);
+    let mut rows: Vec<(String, String, Vec<f32>)> = Vec::new();
+    let mut quantized: Option<Vec<QuantizedVector>> = (keep_int8 && quantization == Quantization::Int8).then(Vec::new);
+    let mut provenance = Vec::new();
+    let mut malformed = Vec::new();
+    // where each record read so far ended up in `rows`, None for malformed ones
+    let mut parsed: Vec<Option<usize>> = Vec::new();
+    let mut record = StringRecord::new();
+    loop {
+        let start = reader.position().byte() as usize;
+        let row = match reader.read_record(&mut record) {
+            Ok(false) => break,
+            Ok(true) => parse_row(&record, metadata.as_ref(), quantization, skipped_lines, &|back| {
+                let record = parsed.len().checked_sub(back).filter(|_| back > 0)?;
+                let row = parsed[record]?;
+                Some(match &quantized {
+                    Some(quantized) => StoredVector::Int8(quantized[row].clone()),
+                    None => StoredVector::Floats(rows[row].2.clone()),
+                })
+            }),
+            Err(e) => Err(MalformedRow::from_csv(EMBEDDING_FILE_NAME, skipped_lines, e)),
+        };
+        // kept as written, so a malformed row can be set aside without losing it
+        let row = row.map_err(|malformed| malformed.with_raw(data.get(start..reader.position().byte() as usize).unwrap_or_default()));
+        match row {
+            Ok(((name, header, vector), row_provenance)) => {
+                parsed.push(Some(rows.len()));
+                let embedding = match (vector, &mut quantized) {
+                    (StoredVector::Int8(vector), Some(quantized)) => {
+                        quantized.push(vector);
+                        Vec::new()
+                    },
+                    (StoredVector::Int8(vector), None) => vector.dequantize(),
+                    (StoredVector::Floats(embedding), _) => embedding,
+                };
+                rows.push((name, header, embedding));
+                provenance.push(row_provenance);
+            },
+            Err(row) if lenient => {
+                parsed.push(None);
+                malformed.push(row);
+            },
+            Err(row) => return Err(row.into()),
+        }
+    }
+    Ok((EmbeddingFile { metadata, rows, provenance }, quantized, malformed))
+}
+
+/// Rows set aside for the `.corrupt` file, each as written after a comment saying why it could not be read
+pub fn quarantined_rows(malformed: &[MalformedRow]) -> String {
+    let mut data = String::new();
+    for row in malformed {
+        let _ = writeln!(data, "# {}", row);
+        if let Some(raw) = &row.raw {
+            data.push_str(raw);
+            if !raw.ends_with('\n') {
+                data.push('\n');
+            }
+        }
+    }
+    data
+}
+
+/// A vector as read from the embedding column
+enum StoredVector {
+    Floats(Vec<f32>),
+    Int8(QuantizedVector),
+}
+
+/// A stored row as (name, header, vector), with its provenance
+type ParsedRow = ((String, String, StoredVector), Option<Provenance>);
+
+/// Parses a record, looking up the vector of the record `k` records above with `earlier(k)`
+/// when the embedding column refers to one
+fn parse_row(record: &StringRecord, metadata: Option<&StoreMetadata>, quantization: Quantization, skipped_lines: u64, earlier: &dyn Fn(usize) -> Option<StoredVector>) -> Result<ParsedRow, MalformedRow> {
+    let line = record.position().map(|position| position.line() + skipped_lines);
+    if matches!(metadata, Some(metadata) if metadata.schema_version >= 9) {
+        let fields: Vec<&str> = record.iter().collect();
+        if let Some((stored, fields)) = fields.split_last() {
+            // rows added by hand may leave the checksum out
+            if !stored.is_empty() && *stored != checksum::row_checksum(fields.iter().copied()) {
+                return Err(MalformedRow::new(EMBEDDING_FILE_NAME, line, "checksum mismatch, the row was changed or damaged after it was written"));
+            }
+        }
+    }
+    let embedding = record.get(2).unwrap_or_default();
+    let embedding = match quantization {
+        _ if embedding.starts_with(REFERENCE_PREFIX) => embedding[1..].trim().parse().ok()
+            .and_then(earlier)
+            .ok_or_else(|| MalformedRow::new(EMBEDDING_FILE_NAME, line, format!("embedding refers to a missing or unreadable row: {}", embedding)))?,
+        Quantization::None => StoredVector::Floats(embedding.split(',')
+            .map(|value| value.trim().parse::<f32>())
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|e| MalformedRow::new(EMBEDDING_FILE_NAME, line, format!("invalid embedding value: {}", e)))?),
+        Quantization::Int8 => StoredVector::Int8(QuantizedVector::parse(embedding)
+            .map_err(|_| MalformedRow::new(EMBEDDING_FILE_NAME, line, "invalid quantized embedding"))?),
+    };
+    let provenance = match metadata {
+        Some(metadata) if metadata.schema_version >= 3 && record.get(3).unwrap_or_default().is_empty() => None,
+        Some(metadata) if metadata.schema_version >= 3 => Some(Provenance {
+            model: record.get(3).unwrap_or_default().to_string(),
+            provider: record.get(4).unwrap_or_default().to_string(),
+            embedded_at: record.get(5).unwrap_or_default().parse().unwrap_or(metadata.created_at),
+            settings_hash: record.get(6).unwrap_or_default().to_string(),
+            lines: match (record.get(7).and_then(|start| start.parse().ok()), record.get(8).and_then(|end| end.parse().ok())) {
+                (Some(start), Some(end)) => Some(LineRange { start, end }),
+                _ => None,
+            },
+            note_times: match (record.get(9).and_then(|created| created.parse().ok()), record.get(10).and_then(|modified| modified.parse().ok())) {
+                (Some(created), Some(modified)) => Some(NoteTimes { created, modified }),
+                _ => None,
+            },
+            language: record.get(11).filter(|language| !language.is_empty()).map(str::to_string),
+            // found by the column count, since stores rewritten under older metadata get the column too
+            path: match record.len() > 13 {
+                true => record.get(12).filter(|path| !path.is_empty()).map(str::to_string),
+                false => None,
+            },
+        }),
+        Some(metadata) => Some(Provenance {
+            model: metadata.model.clone(),
+            provider: String::new(),
+            embedded_at: metadata.created_at,
+            settings_hash: String::new(),
+            lines: None,
+            note_times: None,
+            language: None,
+            path: None,
+        }),
+        None => None,
+    };
+    let row = (record.get(0).unwrap_or_default().to_string(), record.get(1).unwrap_or_default().to_string(), embedding);
+    Ok((row, provenance))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let metadata = StoreMetadata::new("text-embedding-3-small", 2, 1.0);
+        let embedding = [0.5, -1.0];
+        let provenance = Provenance { model: "text-embedding-3-small".to_string(), provider: "https://api.openai.com/v1".to_string(), embedded_at: 2.0, settings_hash: "abc".to_string(), lines: Some(LineRange { start: 4, end: 9 }), note_times: Some(NoteTimes { created: 1.0, modified: 1.5 }), language: Some("en".to_string()), path: Some("Notes/a.md".to_string()) };
+        let data = metadata.header().unwrap() + &write_rows_with_provenance(vec![("note.md", "Header, with comma", &embedding[..], Some(&provenance))], Quantization::None).unwrap();
+
+        let res = read_embedding_file(&data).unwrap();
+
+        assert_eq!(res.metadata, Some(metadata));
+        assert_eq!(res.rows, vec![("note.md".to_string(), "Header, with comma".to_string(), vec![0.5, -1.0])]);
+        assert_eq!(res.provenance, vec![Some(provenance)]);
+    }
+
+    #[test]
+    fn pathological_names_round_trip() {
+        let metadata = StoreMetadata::new("m", 2, 1.0);
+        let rows = vec![
+            ("Notes, \"quoted\"/Café ☕.md".to_string(), " Heading, \"with\"\r\nnewlines\n".to_string(), vec![0.5, -1.0]),
+            ("日本語.md".to_string(), String::new(), vec![1e-8, f32::MAX]),
+        ];
+        let file = EmbeddingFile { metadata: Some(metadata), rows: rows.clone(), provenance: vec![None, None] };
+
+        let res = read_embedding_file(&file.to_csv().unwrap()).unwrap();
+
+        assert_eq!(res.rows, rows);
+    }
+
+    #[test]
+    fn repeated_vectors_are_written_once() {
+        let metadata = StoreMetadata::new("m", 2, 1.0);
+        let rows = vec![
+            ("a.md".to_string(), "A".to_string(), vec![0.5, -1.0]),
+            ("b.md".to_string(), "B".to_string(), vec![1.0, 0.0]),
+            ("c.md".to_string(), "C".to_string(), vec![0.5, -1.0]),
+        ];
+        let file = EmbeddingFile { metadata: Some(metadata), rows: rows.clone(), provenance: vec![None, None, None] };
+
+        let data = file.to_csv().unwrap();
+
+        assert!(data.contains("c.md,C,=2,,,,,,,,,"), "{}", data);
+        assert_eq!(read_embedding_file(&data).unwrap().rows, rows);
+    }
+
+    #[test]
+    fn references_to_unreadable_rows_are_malformed() {
+        let data = StoreMetadata::new("m", 2, 1.0).header().unwrap()
+            + "a.md,A,\"1,x\",,,,,,,,,,,\nb.md,B,\"0,1\",,,,,,,,,,,\nc.md,C,=2,,,,,,,,,,,\nd.md,D,=2,,,,,,,,,,,\ne.md,E,=9,,,,,,,,,,,\n";
+
+        let (file, malformed) = read_embedding_file_lenient(&data).unwrap();
+
+        assert_eq!(file.rows.iter().map(|(name, _, embedding)| (name.as_str(), embedding.clone())).collect::<Vec<_>>(), vec![("b.md", vec![0.0, 1.0]), ("d.md", vec![0.0, 1.0])]);
+        assert_eq!(malformed.iter().map(|row| row.line).collect::<Vec<_>>(), vec![Some(3), Some(5), Some(7)]);
+    }
+
+    #[test]
+    fn queries_are_truncated_like_the_store() {
+        let metadata = StoreMetadata::new("text-embedding-3-small", 2, 1.0).with_truncated_from(Some(4));
+        let data = metadata.header().unwrap() + &write_rows_with_provenance(vec![("a.md", "A", &[1.0, 0.0][..], None)], Quantization::None).unwrap();
+
+        let res = read_embedding_file(&data).unwrap();
+
+        assert_eq!(res.metadata.as_ref().unwrap().truncated_from, Some(4));
+        assert_eq!(res.fit_query(vec![0.6, 0.0, 0.8, 0.0]), vec![1.0, 0.0]);
+        let whole = EmbeddingFile { metadata: Some(StoreMetadata::new("m", 2, 1.0)), ..res };
+        assert_eq!(whole.fit_query(vec![0.6, 0.0, 0.8, 0.0]).len(), 4);
+    }
+
+    #[test]
+    fn malformed_rows_report_their_line() {
+        let header = StoreMetadata::new("m", 2, 1.0).header().unwrap();
+
+        let short_row = read_embedding_file(&format!("{}a.md,\"Two\nlines\",\"1,0\",,,,,,,,,,,\nb.md,B\n", header)).unwrap_err();
+        let bad_value = read_embedding_file(&format!("{}a.md,A,\"1,0\",,,,,,,,,,,\nb.md,B,\"1,x\",,,,,,,,,,,\n", header)).unwrap_err();
+
+        assert_eq!(short_row.code(), crate::error::ErrorCode::MalformedCsv);
+        assert!(short_row.to_string().contains("embedding file line 5: expected 14 fields but found 2"), "{}", short_row);
+        assert!(bad_value.to_string().contains("embedding file line 4: invalid embedding value"), "{}", bad_value);
+    }
+
+    #[test]
+    fn damaged_rows_fail_their_checksum() {
+        let metadata = StoreMetadata::new("m", 2, 1.0);
+        let data = metadata.header().unwrap() + &write_rows_with_provenance(vec![("a.md", "A", &[0.5, -1.0][..], None), ("b.md", "B", &[1.0, 0.0][..], None)], Quantization::None).unwrap();
+        let damaged = data.replace("0.5,-1", "0.5,-7");
+
+        let (file, malformed) = read_embedding_file_lenient(&damaged).unwrap();
+
+        assert_eq!(read_embedding_file(&data).unwrap().rows.len(), 2);
+        assert_eq!(file.rows.iter().map(|(name, _, _)| name.as_str()).collect::<Vec<_>>(), vec!["b.md"]);
+        assert!(malformed[0].reason.contains("checksum mismatch"), "{}", malformed[0]);
+        let quarantined = quarantined_rows(&malformed);
+        assert!(quarantined.starts_with("# embedding file line 3: checksum mismatch"), "{}", quarantined);
+        assert!(quarantined.contains("\na.md,A,\"0.5,-7\","), "{}", quarantined);
+    }
+
+    #[test]
+    fn lenient_read_skips_malformed_rows() {
+        let data = StoreMetadata::new("m", 2, 1.0).header().unwrap()
+            + "a.md,A,\"1,0\",,,,,,,,,,,\nb.md,B,\"1,x\",,,,,,,,,,,\nc.md,C\nd.md,D,\"0,1\",m,p,3,h,1,2,,,,,\n";
+
+        let (file, malformed) = read_embedding_file_lenient(&data).unwrap();
+
+        assert_eq!(file.rows.iter().map(|(name, _, _)| name.as_str()).collect::<Vec<_>>(), vec!["a.md", "d.md"]);
+        assert_eq!(file.provenance.len(), 2);
+        assert_eq!(file.provenance[1].as_ref().unwrap().model, "m");
+        assert_eq!(malformed.iter().map(|row| row.line).collect::<Vec<_>>(), vec![Some(4), Some(5)]);
+        assert!(read_embedding_file(&data).is_err());
+    }
+
+    #[test]
+    fn row_writer_continues_across_batches() {
+        let provenance = Provenance { model: "m".to_string(), provider: "p".to_string(), embedded_at: 2.0, settings_hash: "h".to_string(), lines: Some(LineRange { start: 1, end: 3 }), note_times: None, language: None, path: None };
+        let rows = [("a.md", "A, with comma", vec![0.5, -1.0]), ("b.md", "B", vec![0.25, 0.0]), ("c.md", "C \"quoted\"", vec![1.0, 2.0])];
+        let mut writer = RowWriter::new(Quantization::None);
+
+        let mut batches = String::new();
+        for batch in rows.chunks(2) {
+            for (name, header, embedding) in batch {
+                writer.write(name, header, embedding, Some(&provenance)).unwrap();
+            }
+            batches.push_str(&writer.take().unwrap());
+        }
+
+        let whole = write_rows_with_provenance(rows.iter().map(|(name, header, embedding)| (*name, *header, embedding.as_slice(), Some(&provenance))), Quantization::None).unwrap();
+        assert_eq!(batches, whole);
+        assert!(batches.starts_with("a.md,\"A, with comma\",\"0.5,-1\",m,p,2,h,1,3,,,,"), "{}", batches);
+        assert!(writer.take().unwrap().is_empty());
+    }
+
+    #[test]
+    fn quantized_round_trip() {
+        let metadata = StoreMetadata::new("m", 3, 1.0).with_quantization(Quantization::Int8);
+        let embedding = [0.5, -1.0, 0.25];
+        let data = metadata.header().unwrap() + &write_rows_with_provenance(vec![("a.md", "A", &embedding[..], None)], Quantization::Int8).unwrap();
+
+        let res = read_embedding_file(&data).unwrap();
+
+        assert!(data.contains("\"0.007874016:64,-127,32\""));
+        assert_eq!(res.metadata.unwrap().quantization, Quantization::Int8);
+        for (original, restored) in embedding.iter().zip(&res.rows[0].2) {
+            assert!((original - restored).abs() < 0.01);
+        }
+
+        let (searchable, quantized, _) = read_searchable_store(&data, false).unwrap();
+        assert!(searchable.rows[0].2.is_empty());
+        assert_eq!(quantized.unwrap(), vec![QuantizedVector::quantize(&embedding)]);
+    }
+
+    #[test]
+    fn unknown_provenance_round_trips() {
+        let metadata = StoreMetadata::new("m", 1, 1.0);
+        let data = metadata.header().unwrap() + &write_rows_with_provenance(vec![("a.md", "A", &[1.0][..], None)], Quantization::None).unwrap();
+
+        let res = read_embedding_file(&data).unwrap();
+
+        assert_eq!(res.rows.len(), 1);
+        assert_eq!(res.provenance, vec![None]);
+    }
+
+    #[test]
+    fn version_4_has_no_line_offsets() {
+        let res = read_embedding_file("# embedding_store: {\"schema_version\":4,\"model\":\"m\",\"dimensions\":1,\"normalization\":\"none\",\"created_at\":7}\nname,header,embedding,model,provider,embedded_at,settings_hash\na.md,A,1,m,p,8,h\n").unwrap();
+
+        assert_eq!(res.provenance[0].as_ref().unwrap().lines, None);
+        assert_eq!(res.provenance[0].as_ref().unwrap().embedded_at, 8.0);
+    }
+
+    #[test]
+    fn version_2_provenance_comes_from_metadata() {
+        let res = read_embedding_file("# embedding_store: {\"schema_version\":2,\"model\":\"m\",\"dimensions\":2,\"normalization\":\"none\",\"created_at\":7}\nname,header,embedding\na.md,A,\"1,0\"\n").unwrap();
+
+        assert_eq!(res.rows.len(), 1);
+        let provenance = res.provenance[0].as_ref().unwrap();
+        assert_eq!((provenance.model.as_str(), provenance.provider.as_str(), provenance.embedded_at), ("m", "", 7.0));
+    }
+
+    #[test]
+    fn legacy_file_reads_every_row() {
+        let res = read_embedding_file("a.md,A,\"1,0\"\nb.md,B,\"0,1\"\n").unwrap();
+
+        assert!(res.metadata.is_none());
+        assert_eq!(res.rows.len(), 2);
+        assert_eq!(res.provenance, vec![None, None]);
+        assert!(res.check_model("anything").is_ok());
+        assert!(res.check_dimensions(3).is_err());
+    }
+
+    #[test]
+    fn model_mismatch() {
+        let file = EmbeddingFile { metadata: Some(StoreMetadata::new("text-embedding-ada-002", 2, 0.0)), rows: vec![], provenance: vec![] };
+
+        assert!(file.check_model("text-embedding-ada-002").is_ok());
+        let err = file.check_model("text-embedding-3-small").unwrap_err();
+        assert_eq!(err.code(), crate::error::ErrorCode::ReindexRequired);
+    }
+
+    #[test]
+    fn newer_schema() {
+        let res = read_embedding_file("# embedding_store: {\"schema_version\":99,\"model\":\"m\",\"dimensions\":1,\"normalization\":\"none\",\"created_at\":0}\nname,header,embedding\n");
+
+        assert!(matches!(res, Err(SemanticSearchError::UnsupportedSchema(_))));
+    }
+}
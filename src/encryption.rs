@@ -0,0 +1,206 @@
+//! Encrypting embedding files at rest, for vaults synced to services that shouldn't be able to
+//! read what the notes are about.
+//!
+//! Each write to an encrypted file becomes one line, `<MARKER><salt>:<iv>:<ciphertext>` in
+//! base64, so a running pipeline can keep appending without reading the file back. Lines are
+//! AES-256-GCM encrypted, with a key derived from the passphrase and the line's salt by
+//! PBKDF2-SHA256, through the Web Crypto API. A salt is drawn once per session, so keys are
+//! derived once per session that wrote to a file. Lines without the marker are read as they
+//! are, so a file written to before encryption was turned on still reads.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use js_sys::{Array, Object, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+use crate::SemanticSearchError;
+use crate::settings::Settings;
+
+const MARKER: &str = "semantic-search-encrypted:v1:";
+const PBKDF2_ITERATIONS: u32 = 600_000;
+const SALT_LEN: u32 = 16;
+const IV_LEN: u32 = 12;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = crypto, js_name = getRandomValues)]
+    fn get_random_values(array: &Uint8Array);
+    #[wasm_bindgen(js_namespace = ["crypto", "subtle"], js_name = importKey, catch)]
+    async fn import_key(format: &str, key_data: &Uint8Array, algorithm: &str, extractable: bool, usages: &Array) -> Result<JsValue, JsValue>;
+    #[wasm_bindgen(js_namespace = ["crypto", "subtle"], js_name = deriveKey, catch)]
+    async fn derive_key(algorithm: &Object, base_key: &JsValue, derived_key_type: &Object, extractable: bool, usages: &Array) -> Result<JsValue, JsValue>;
+    #[wasm_bindgen(js_namespace = ["crypto", "subtle"], js_name = encrypt, catch)]
+    async fn subtle_encrypt(algorithm: &Object, key: &JsValue, data: &Uint8Array) -> Result<JsValue, JsValue>;
+    #[wasm_bindgen(js_namespace = ["crypto", "subtle"], js_name = decrypt, catch)]
+    async fn subtle_decrypt(algorithm: &Object, key: &JsValue, data: &Uint8Array) -> Result<JsValue, JsValue>;
+}
+
+struct Encryption {
+    passphrase: String,
+    /// Salt of the lines written this session
+    salt: Vec<u8>,
+    /// Files written encrypted, empty when only reading is possible
+    paths: Vec<String>,
+}
+
+thread_local! {
+    static ENCRYPTION: RefCell<Option<Encryption>> = const { RefCell::new(None) };
+    /// Keys derived from the passphrase this session, by salt
+    static KEYS: RefCell<HashMap<Vec<u8>, JsValue>> = RefCell::new(HashMap::new());
+}
+
+/// Applies the encryption settings. With a passphrase, encrypted files can be read; with
+/// encryption also turned on, the embedding files and the files derived from them are
+/// encrypted as they are written.
+pub fn configure(settings: &Settings) {
+    let passphrase = settings.encryption_passphrase.clone();
+    let paths = match settings.encrypt_embeddings {
+        true => encrypted_paths(settings),
+        false => Vec::new(),
+    };
+    ENCRYPTION.with(|encryption| {
+        let mut encryption = encryption.borrow_mut();
+        match encryption.as_mut() {
+            _ if passphrase.is_empty() => *encryption = None,
+            Some(current) if current.passphrase == passphrase => current.paths = paths,
+            _ => {
+                KEYS.with(|keys| keys.borrow_mut().clear());
+                *encryption = Some(Encryption { passphrase, salt: random_bytes(SALT_LEN), paths });
+            },
+        }
+    });
+}
+
+fn encrypted_paths(settings: &Settings) -> Vec<String> {
+    let mut paths = vec![settings.embedding_path()];
+    paths.extend(settings.language_routes().iter().map(|route| settings.language_embedding_path(&route.language)));
+    paths
+}
+
+/// Whether `path` is one of `paths` or a file kept next to one, such as its staging file, backups
+/// or set aside rows. Lock files only hold a time and are left readable.
+fn is_encrypted_path(paths: &[String], path: &str) -> bool {
+    !path.ends_with(".lock") && paths.iter().any(|encrypted| match path.strip_prefix(encrypted.as_str()) {
+        Some(rest) => rest.is_empty() || rest.starts_with('.'),
+        None => false,
+    })
+}
+
+/// Whether data written to `path` is encrypted
+pub fn encrypts(path: &str) -> bool {
+    ENCRYPTION.with(|encryption| matches!(encryption.borrow().as_ref(), Some(encryption) if is_encrypted_path(&encryption.paths, path)))
+}
+
+/// `data` as one encrypted line
+pub async fn encrypt(data: &str) -> Result<String, SemanticSearchError> {
+    let (passphrase, salt) = ENCRYPTION.with(|encryption| encryption.borrow().as_ref().map(|encryption| (encryption.passphrase.clone(), encryption.salt.clone())))
+        .ok_or_else(|| SemanticSearchError::DecryptionFailed("no encryption passphrase is set".to_string()))?;
+    let key = key(&passphrase, &salt).await?;
+    let iv = random_bytes(IV_LEN);
+    let ciphertext = subtle_encrypt(&aes_gcm(&iv), &key, &Uint8Array::from(data.as_bytes())).await
+        .map_err(|e| SemanticSearchError::DecryptionFailed(format!("could not encrypt: {:?}", e)))?;
+    Ok(format_line(&salt, &iv, &Uint8Array::new(&ciphertext).to_vec()))
+}
+
+/// The contents of the file at `path` with its encrypted lines decrypted
+pub async fn decrypt(path: &str, data: String) -> Result<String, SemanticSearchError> {
+    if !data.contains(MARKER) {
+        return Ok(data);
+    }
+    let passphrase = ENCRYPTION.with(|encryption| encryption.borrow().as_ref().map(|encryption| encryption.passphrase.clone()))
+        .ok_or_else(|| SemanticSearchError::DecryptionFailed(format!("'{}' is encrypted, enter its passphrase under Encryption passphrase", path)))?;
+    let mut plaintext = String::with_capacity(data.len());
+    for (number, line) in data.split_inclusive('\n').enumerate() {
+        if !line.starts_with(MARKER) {
+            plaintext.push_str(line);
+            continue;
+        }
+        let (salt, iv, ciphertext) = parse_line(line)
+            .ok_or_else(|| SemanticSearchError::DecryptionFailed(format!("'{}' line {} is not a valid encrypted block", path, number + 1)))?;
+        let key = key(&passphrase, &salt).await?;
+        let decrypted = subtle_decrypt(&aes_gcm(&iv), &key, &Uint8Array::from(ciphertext.as_slice())).await
+            .map_err(|_| SemanticSearchError::DecryptionFailed(format!("could not decrypt '{}', check the encryption passphrase", path)))?;
+        plaintext.push_str(&String::from_utf8(Uint8Array::new(&decrypted).to_vec())?);
+    }
+    Ok(plaintext)
+}
+
+fn format_line(salt: &[u8], iv: &[u8], ciphertext: &[u8]) -> String {
+    format!("{}{}:{}:{}\n", MARKER, STANDARD.encode(salt), STANDARD.encode(iv), STANDARD.encode(ciphertext))
+}
+
+/// The salt, iv and ciphertext of an encrypted line
+fn parse_line(line: &str) -> Option<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    let mut parts = line.strip_prefix(MARKER)?.trim_end().split(':');
+    let mut next = || parts.next().and_then(|part| STANDARD.decode(part).ok());
+    let parsed = (next()?, next()?, next()?);
+    match parts.next() {
+        Some(_) => None,
+        None => Some(parsed),
+    }
+}
+
+async fn key(passphrase: &str, salt: &[u8]) -> Result<JsValue, SemanticSearchError> {
+    if let Some(key) = KEYS.with(|keys| keys.borrow().get(salt).cloned()) {
+        return Ok(key);
+    }
+    let failed = |e: JsValue| SemanticSearchError::DecryptionFailed(format!("could not derive the encryption key: {:?}", e));
+    let usages = Array::of1(&"deriveKey".into());
+    let base_key = import_key("raw", &Uint8Array::from(passphrase.as_bytes()), "PBKDF2", false, &usages).await.map_err(failed)?;
+    let pbkdf2 = object(&[("name", "PBKDF2".into()), ("salt", Uint8Array::from(salt).into()), ("iterations", PBKDF2_ITERATIONS.into()), ("hash", "SHA-256".into())]);
+    let aes = object(&[("name", "AES-GCM".into()), ("length", 256.into())]);
+    let usages = Array::of2(&"encrypt".into(), &"decrypt".into());
+    let key = derive_key(&pbkdf2, &base_key, &aes, false, &usages).await.map_err(failed)?;
+    KEYS.with(|keys| keys.borrow_mut().insert(salt.to_vec(), key.clone()));
+    Ok(key)
+}
+
+fn aes_gcm(iv: &[u8]) -> Object {
+    object(&[("name", "AES-GCM".into()), ("iv", Uint8Array::from(iv).into())])
+}
+
+fn object(entries: &[(&str, JsValue)]) -> Object {
+    let object = Object::new();
+    for (name, value) in entries {
+        // setting a property of a plain object cannot fail
+        let _ = Reflect::set(&object, &(*name).into(), value);
+    }
+    object
+}
+
+fn random_bytes(len: u32) -> Vec<u8> {
+    let bytes = Uint8Array::new_with_length(len);
+    get_random_values(&bytes);
+    bytes.to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_round_trip() {
+        let line = format_line(&[1, 2], &[3], &[4, 5, 6]);
+
+        assert!(line.starts_with(MARKER) && line.ends_with('\n'), "{}", line);
+        assert_eq!(parse_line(&line), Some((vec![1, 2], vec![3], vec![4, 5, 6])));
+        assert_eq!(parse_line(&format!("{}AQI=:Aw==", MARKER)), None);
+        assert_eq!(parse_line(&format!("{}AQI=:Aw==:BAUG:BAUG", MARKER)), None);
+    }
+
+    #[test]
+    fn files_next_to_the_store_are_encrypted() {
+        let paths = vec!["storage/embedding.csv".to_string()];
+
+        assert!(is_encrypted_path(&paths, "storage/embedding.csv"));
+        assert!(is_encrypted_path(&paths, "storage/embedding.csv.reembed"));
+        assert!(is_encrypted_path(&paths, "storage/embedding.csv.tmp"));
+        assert!(is_encrypted_path(&paths, "storage/embedding.csv.v8.bak"));
+        assert!(!is_encrypted_path(&paths, "storage/embedding.csv.lock"));
+        assert!(!is_encrypted_path(&paths, "storage/embedding-ja.csv"));
+        assert!(!is_encrypted_path(&paths, "storage/input.csv"));
+    }
+}
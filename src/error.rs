@@ -1,7 +1,9 @@
 use std::error::Error;
 use crate::EmbeddingRequestBuilderError;
+use crate::redact;
 use csv::Writer;
 use serde::Deserialize;
+use serde::Serialize;
 use wasm_bindgen::JsValue;
 
 /// Wrapper to deserialize the error object nested in "error" JSON key
@@ -17,35 +19,219 @@ pub struct ApiError {
     pub r#type: String,
     pub param: Option<serde_json::Value>,
     pub code: Option<serde_json::Value>,
+    /// HTTP status of the failed response, filled in by the client
+    #[serde(skip)]
+    pub status: u16,
+}
+
+/// Stable error codes surfaced to the plugin so it can react to specific failures
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ErrorCode {
+    AuthFailed,
+    RateLimited,
+    QuotaExceeded,
+    InvalidRequest,
+    ApiError,
+    NetworkError,
+    MalformedCsv,
+    MalformedResponse,
+    MissingInputFile,
+    MissingEmbeddingFile,
+    FileNotFound,
+    InvalidArgument,
+    InvalidSettings,
+    Cancelled,
+    StoreLocked,
+    SchemaMismatch,
+    ReindexRequired,
+    DimensionMismatch,
+    BudgetExceeded,
+    Timeout,
+    DeadlineExceeded,
+    DecryptionFailed,
+    ObsidianError,
+    InternalError,
+}
+
+impl ErrorCode {
+    /// Whether the same request may succeed if tried again later
+    pub fn is_retriable(self) -> bool {
+        matches!(self, ErrorCode::RateLimited | ErrorCode::ApiError | ErrorCode::NetworkError | ErrorCode::Timeout)
+    }
+}
+
+/// Error object handed to JS: `{ code, message, retriable }`
+#[derive(Debug, Serialize)]
+pub struct JsErrorPayload {
+    pub code: ErrorCode,
+    pub message: String,
+    pub retriable: bool,
+}
+
+impl JsErrorPayload {
+    pub fn new(e: &SemanticSearchError) -> Self {
+        Self { code: e.code(), message: redact::redact(&e.to_string()), retriable: e.code().is_retriable() }
+    }
+}
+
+/// A row of input.csv or an embedding file that could not be read
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MalformedRow {
+    pub file: String,
+    /// Counted from 1 over the whole file, when known
+    pub line: Option<u64>,
+    pub reason: String,
+    /// The row as written, when it could be located
+    #[serde(skip)]
+    pub raw: Option<String>,
+}
+
+impl MalformedRow {
+    pub fn new(file: &str, line: Option<u64>, reason: impl std::fmt::Display) -> Self {
+        Self { file: file.to_string(), line, reason: reason.to_string(), raw: None }
+    }
+
+    pub fn with_raw(self, raw: &str) -> Self {
+        Self { raw: Some(raw.to_string()), ..self }
+    }
+
+    /// Locates a csv reader error in `file`, whose first `skipped_lines` lines were not given to the reader
+    pub fn from_csv(file: &str, skipped_lines: u64, e: csv::Error) -> Self {
+        let line = e.position().map(|position| position.line() + skipped_lines);
+        let reason = match e.kind() {
+            csv::ErrorKind::UnequalLengths { expected_len, len, .. } => format!("expected {} fields but found {}", expected_len, len),
+            csv::ErrorKind::Utf8 { err, .. } => format!("invalid UTF-8 in field {}", err.field() + 1),
+            csv::ErrorKind::Deserialize { err, .. } => match err.field() {
+                Some(field) => format!("field {}: {}", field + 1, err.kind()),
+                None => err.kind().to_string(),
+            },
+            _ => e.to_string(),
+        };
+        Self::new(file, line, reason)
+    }
+}
+
+impl std::fmt::Display for MalformedRow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{} line {}: {}", self.file, line, self.reason),
+            None => write!(f, "{}: {}", self.file, self.reason),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum SemanticSearchError {
     ObsidianError(JsValue),
     WriteError(csv::Error),
+    MalformedCsv(MalformedRow),
     ConversionError(Box<dyn std::error::Error>),
     ReqwestError(reqwest::Error),
     JSONDeserialize(serde_json::Error),
     ApiError(ApiError),
     InvalidArgument(String),
     GetEmbeddingsError(String),
+    FileNotFound(String),
+    MissingInputFile(String),
+    MissingEmbeddingFile(String),
+    InvalidSettings(String),
+    Cancelled,
+    Locked(String),
+    UnsupportedSchema(String),
+    ReindexRequired(String),
+    DimensionMismatch(String),
+    BudgetExceeded(String),
+    Timeout(String),
+    DeadlineExceeded(String),
+    DecryptionFailed(String),
+}
+
+impl SemanticSearchError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            SemanticSearchError::ObsidianError(_) => ErrorCode::ObsidianError,
+            SemanticSearchError::WriteError(_) => ErrorCode::MalformedCsv,
+            SemanticSearchError::MalformedCsv(_) => ErrorCode::MalformedCsv,
+            SemanticSearchError::ConversionError(_) => ErrorCode::InternalError,
+            SemanticSearchError::ReqwestError(_) => ErrorCode::NetworkError,
+            SemanticSearchError::JSONDeserialize(_) => ErrorCode::MalformedResponse,
+            SemanticSearchError::ApiError(e) => match e.status {
+                401 | 403 => ErrorCode::AuthFailed,
+                429 if e.r#type == "insufficient_quota" => ErrorCode::QuotaExceeded,
+                429 => ErrorCode::RateLimited,
+                400 | 404 | 422 => ErrorCode::InvalidRequest,
+                _ => ErrorCode::ApiError,
+            },
+            SemanticSearchError::InvalidArgument(_) => ErrorCode::InvalidArgument,
+            SemanticSearchError::GetEmbeddingsError(_) => ErrorCode::MalformedResponse,
+            SemanticSearchError::FileNotFound(_) => ErrorCode::FileNotFound,
+            SemanticSearchError::MissingInputFile(_) => ErrorCode::MissingInputFile,
+            SemanticSearchError::MissingEmbeddingFile(_) => ErrorCode::MissingEmbeddingFile,
+            SemanticSearchError::InvalidSettings(_) => ErrorCode::InvalidSettings,
+            SemanticSearchError::Cancelled => ErrorCode::Cancelled,
+            SemanticSearchError::Locked(_) => ErrorCode::StoreLocked,
+            SemanticSearchError::UnsupportedSchema(_) => ErrorCode::SchemaMismatch,
+            SemanticSearchError::ReindexRequired(_) => ErrorCode::ReindexRequired,
+            SemanticSearchError::DimensionMismatch(_) => ErrorCode::DimensionMismatch,
+            SemanticSearchError::BudgetExceeded(_) => ErrorCode::BudgetExceeded,
+            SemanticSearchError::Timeout(_) => ErrorCode::Timeout,
+            SemanticSearchError::DeadlineExceeded(_) => ErrorCode::DeadlineExceeded,
+            SemanticSearchError::DecryptionFailed(_) => ErrorCode::DecryptionFailed,
+        }
+    }
+
+    /// Reclassifies a missing file as the input file
+    pub fn missing_input(self) -> Self {
+        match self {
+            SemanticSearchError::FileNotFound(path) => SemanticSearchError::MissingInputFile(path),
+            e => e,
+        }
+    }
+
+    /// Reclassifies a missing file as the embedding file
+    pub fn missing_embedding(self) -> Self {
+        match self {
+            SemanticSearchError::FileNotFound(path) => SemanticSearchError::MissingEmbeddingFile(path),
+            e => e,
+        }
+    }
 }
 
 impl std::fmt::Display for SemanticSearchError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            SemanticSearchError::ObsidianError(e) => write!(f, "obsidian error; {}", e.as_string().unwrap()),
+            SemanticSearchError::ObsidianError(e) => write!(f, "obsidian error; {}", e.as_string().unwrap_or_else(|| format!("{:?}", e))),
             SemanticSearchError::WriteError(e) => write!(f, "write error; {:?}", e.source()),
+            SemanticSearchError::MalformedCsv(e) => write!(f, "Malformed CSV: {}", e),
             SemanticSearchError::ConversionError(e) => write!(f, "conversion error; {:?}", e.source()),
             SemanticSearchError::ReqwestError(e) => write!(f, "reqwest error; {}", e),
             SemanticSearchError::JSONDeserialize(e) => write!(f, "JSONDeserialize error: {:?}", e),
             SemanticSearchError::ApiError(e) => write!(f, "API error: {}: {}", e.r#type, e.message),
             SemanticSearchError::InvalidArgument(e) => write!(f, "Invalid argument: {}", e),
             SemanticSearchError::GetEmbeddingsError(e) => write!(f, "GetEmbeddingsError: {}", e),
+            SemanticSearchError::FileNotFound(path) => write!(f, "File not found: {}", path),
+            SemanticSearchError::MissingInputFile(path) => write!(f, "Input file '{}' not found, run the Generate Input command first", path),
+            SemanticSearchError::MissingEmbeddingFile(path) => write!(f, "Embedding file '{}' not found, run the Generate Embeddings command first", path),
+            SemanticSearchError::InvalidSettings(e) => write!(f, "Invalid settings: {}", e),
+            SemanticSearchError::Cancelled => write!(f, "Cancelled"),
+            SemanticSearchError::Locked(path) => write!(f, "'{}' is locked by another running command", path),
+            SemanticSearchError::UnsupportedSchema(e) => write!(f, "Unsupported schema: {}", e),
+            SemanticSearchError::ReindexRequired(e) => write!(f, "Re-index required: {}", e),
+            SemanticSearchError::DimensionMismatch(e) => write!(f, "Unexpected embedding size: {}", e),
+            SemanticSearchError::BudgetExceeded(e) => write!(f, "Over budget: {}", e),
+            SemanticSearchError::Timeout(e) => write!(f, "Timed out: {}", e),
+            SemanticSearchError::DeadlineExceeded(e) => write!(f, "Deadline exceeded: {}", e),
+            SemanticSearchError::DecryptionFailed(e) => write!(f, "Encryption error: {}", e),
         }
     }
 }
 
+impl From<MalformedRow> for SemanticSearchError {
+    fn from(value: MalformedRow) -> Self {
+        Self::MalformedCsv(value)
+    }
+}
+
 impl From<csv::Error> for SemanticSearchError {
     fn from(value: csv::Error) -> Self {
         Self::WriteError(value)
@@ -82,20 +268,21 @@ impl From<EmbeddingRequestBuilderError> for SemanticSearchError {
     }
 }
 
+impl From<serde_wasm_bindgen::Error> for SemanticSearchError {
+    fn from(value: serde_wasm_bindgen::Error) -> Self {
+        Self::ConversionError(Box::new(value))
+    }
+}
+
 impl std::error::Error for SemanticSearchError {
 }
 
 impl Into<wasm_bindgen::JsValue> for SemanticSearchError {
     fn into(self) -> wasm_bindgen::JsValue {
-        match self {
-            SemanticSearchError::ObsidianError(e) => e,
-            SemanticSearchError::WriteError(e) => JsValue::from_str(&format!("{:?}", e)),
-            SemanticSearchError::ConversionError(e) => JsValue::from_str(&format!("{:?}", e)),
-            SemanticSearchError::ReqwestError(e) => JsValue::from_str(&format!("{:?}", e)),
-            SemanticSearchError::JSONDeserialize(e) => JsValue::from_str(&format!("{:?}", e)),
-            SemanticSearchError::ApiError(e) => JsValue::from_str(&format!("{:?}", e)),
-            SemanticSearchError::InvalidArgument(e) => JsValue::from_str(&format!("{:?}", e)),
-            SemanticSearchError::GetEmbeddingsError(e) => JsValue::from_str(&format!("{:?}", e)),
+        let payload = JsErrorPayload::new(&self);
+        match serde_wasm_bindgen::to_value(&payload) {
+            Ok(value) => value,
+            Err(_) => JsValue::from_str(&payload.message),
         }
     }
 }
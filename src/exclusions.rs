@@ -0,0 +1,69 @@
+use log::debug;
+use regex::Regex;
+
+/// Obsidian's "Excluded files" setting, stored as `userIgnoreFilters` in `.obsidian/app.json`
+pub const USER_IGNORE_FILTERS_KEY: &str = "userIgnoreFilters";
+
+/// Paths excluded from the index the same way Obsidian excludes them from core search.
+/// Filters written as `/pattern/` are regexes matched anywhere in the path; any other filter
+/// excludes the paths that start with it, so `Archive/` covers the whole folder.
+#[derive(Debug, Clone, Default)]
+pub struct ExcludedFiles {
+    prefixes: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl ExcludedFiles {
+    pub fn new(filters: &[String]) -> Self {
+        let mut excluded = Self::default();
+        for filter in filters.iter().map(|filter| filter.trim()).filter(|filter| !filter.is_empty()) {
+            match filter.strip_prefix('/').and_then(|rest| rest.strip_suffix('/')) {
+                Some(pattern) if !pattern.is_empty() => match Regex::new(pattern) {
+                    Ok(re) => excluded.patterns.push(re),
+                    Err(e) => debug!("Ignoring invalid excluded files pattern {}: {}", filter, e),
+                },
+                _ => excluded.prefixes.push(filter.to_string()),
+            }
+        }
+        excluded
+    }
+
+    pub fn is_excluded(&self, path: &str) -> bool {
+        self.prefixes.iter().any(|prefix| path.starts_with(prefix.as_str()))
+            || self.patterns.iter().any(|re| re.is_match(path))
+    }
+
+    /// Whether everything in `folder` is excluded, so it does not need to be walked
+    pub fn is_folder_excluded(&self, folder: &str) -> bool {
+        self.is_excluded(&format!("{}/", folder.trim_end_matches('/')))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn excluded(filters: &[&str]) -> ExcludedFiles {
+        ExcludedFiles::new(&filters.iter().map(|filter| filter.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn prefixes_and_patterns() {
+        let excluded = excluded(&["Archive/", "Templates", r"/\.excalidraw\.md$/", "/[/", " "]);
+
+        assert!(excluded.is_excluded("Archive/2021/old.md"));
+        assert!(excluded.is_folder_excluded("Archive"));
+        assert!(excluded.is_excluded("Templates/Daily.md"));
+        assert!(excluded.is_excluded("Drawings/sketch.excalidraw.md"));
+        assert!(!excluded.is_excluded("Notes/Archive/kept.md"));
+        assert!(!excluded.is_folder_excluded("Drawings"));
+        assert!(!excluded.is_excluded("Notes/idea.md"));
+    }
+
+    #[test]
+    fn no_filters() {
+        let excluded = excluded(&[]);
+
+        assert!(!excluded.is_excluded("any.md"));
+    }
+}
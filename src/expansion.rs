@@ -0,0 +1,60 @@
+use crate::chat::{ChatMessage, ChatRequest};
+
+/// Asks `model` for `count` alternative phrasings of `query`, one per line
+pub fn reformulation_request(model: &str, query: &str, count: usize) -> ChatRequest {
+    let instructions = format!(
+        "You help search a personal knowledge base. Rewrite the user's search query in {} different ways that could match relevant notes, \
+         using synonyms, related terms or a more specific phrasing. Reply with one query per line and nothing else.", count);
+    ChatRequest {
+        model: model.to_string(),
+        messages: vec![
+            ChatMessage { role: "system".to_string(), content: instructions },
+            ChatMessage { role: "user".to_string(), content: query.to_string() },
+        ],
+        temperature: 0.7,
+    }
+}
+
+/// Extracts up to `count` reformulations from a chat reply, dropping list markers,
+/// quotes, blank lines, duplicates and repeats of the original query
+pub fn parse_reformulations(reply: &str, query: &str, count: usize) -> Vec<String> {
+    let mut reformulations: Vec<String> = Vec::new();
+    for line in reply.lines() {
+        let line = line.trim()
+            .trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c == ')' || c == '-' || c == '*')
+            .trim()
+            .trim_matches('"')
+            .trim();
+        if line.is_empty() || line.eq_ignore_ascii_case(query.trim()) || reformulations.iter().any(|existing| existing.eq_ignore_ascii_case(line)) {
+            continue;
+        }
+        reformulations.push(line.to_string());
+        if reformulations.len() == count {
+            break;
+        }
+    }
+    reformulations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_numbered_reply() {
+        let reply = "1. \"distributed consensus\"\n2) Raft leader election\n\n- Paxos\n* raft leader election\nconsensus algorithms";
+
+        let res = parse_reformulations(reply, "Consensus algorithms", 3);
+
+        assert_eq!(res, vec!["distributed consensus", "Raft leader election", "Paxos"]);
+    }
+
+    #[test]
+    fn request_includes_query() {
+        let request = reformulation_request("gpt-4o-mini", "raft", 2);
+
+        assert_eq!(request.messages.len(), 2);
+        assert_eq!(request.messages[1].content, "raft");
+        assert!(request.messages[0].content.contains("2 different ways"));
+    }
+}
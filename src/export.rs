@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::SemanticSearchError;
+use crate::embedding_file::EmbeddingFile;
+use crate::fusion::ScoredCandidate;
+use crate::input::InputRecord;
+use crate::provenance;
+use crate::rerank;
+
+/// Formats the embedding store can be written out in for use outside Obsidian
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    /// One `{id, name, header, text, vector}` object per line
+    Jsonl,
+    /// A float32 `.npy` matrix with one row per record, plus a JSON manifest describing the rows
+    Npy,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ExportReport {
+    pub records: usize,
+    pub dimensions: usize,
+    /// Files written, relative to the vault root
+    pub paths: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct JsonlRecord<'a> {
+    id: String,
+    name: &'a str,
+    header: &'a str,
+    text: &'a str,
+    vector: &'a [f32],
+}
+
+#[derive(Serialize)]
+struct ManifestRow<'a> {
+    id: String,
+    name: &'a str,
+    header: &'a str,
+    text: &'a str,
+}
+
+/// Describes the rows of the `.npy` matrix, in order
+#[derive(Serialize)]
+struct NpyManifest<'a> {
+    model: Option<&'a str>,
+    dimensions: usize,
+    count: usize,
+    rows: Vec<ManifestRow<'a>>,
+}
+
+/// Where an export of the store at `embedding_path` is written: next to the store, with the format's extensions
+pub fn export_paths(embedding_path: &str, format: ExportFormat) -> Vec<String> {
+    let base = embedding_path.strip_suffix(".csv").unwrap_or(embedding_path);
+    match format {
+        ExportFormat::Jsonl => vec![format!("{}.jsonl", base)],
+        ExportFormat::Npy => vec![format!("{}.npy", base), format!("{}.manifest.json", base)],
+    }
+}
+
+/// Identifies a record by its note and a hash of its section text, so the id splits
+/// cleanly on the first `#` and stays unique when a note repeats a heading
+fn record_id(name: &str, text: &str) -> String {
+    format!("{}#{}", name, provenance::fingerprint(text))
+}
+
+/// The section text of every stored record, kept in its header column
+pub fn record_texts(store: &EmbeddingFile) -> Vec<String> {
+    let candidates: Vec<ScoredCandidate> = store.rows.iter()
+        .map(|(name, header, _)| ScoredCandidate { name: name.clone(), header: header.clone(), score: 0.0, source: String::new(), embedding: Vec::new() })
+        .collect();
+    rerank::candidate_texts(&candidates)
+}
+
+/// The section heading of every stored record, looked up in the input records by note and
+/// section text; records no longer in the input get an empty heading
+pub fn record_headings(store: &EmbeddingFile, records: &[InputRecord]) -> Vec<String> {
+    let headings: HashMap<(&str, &str), &str> = records.iter()
+        .map(|record| ((record.name.as_str(), record.body.as_str()), record.header.as_str()))
+        .collect();
+    store.rows.iter()
+        .map(|(name, text, _)| headings.get(&(name.as_str(), text.as_str())).map_or_else(String::new, |heading| heading.to_string()))
+        .collect()
+}
+
+pub fn to_jsonl(store: &EmbeddingFile, headings: &[String], texts: &[String]) -> Result<String, SemanticSearchError> {
+    let mut output = String::new();
+    for (((name, _, vector), header), text) in store.rows.iter().zip(headings).zip(texts) {
+        let record = JsonlRecord { id: record_id(name, text), name, header, text, vector };
+        output.push_str(&serde_json::to_string(&record).map_err(|e| SemanticSearchError::ConversionError(Box::new(e)))?);
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+/// A version 1.0 `.npy` file holding the store's vectors as a little-endian float32 matrix
+pub fn to_npy(store: &EmbeddingFile) -> Result<Vec<u8>, SemanticSearchError> {
+    let dimensions = dimensions(store);
+    if let Some((name, header, _)) = store.rows.iter().find(|(_, _, vector)| vector.len() != dimensions) {
+        return Err(SemanticSearchError::InvalidArgument(format!(
+            "'{}' in {} does not have {} dimensions like the other records, a matrix needs equal sized vectors", header, name, dimensions)));
+    }
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}", store.rows.len(), dimensions);
+    // magic, version and header length take 10 bytes; the header is padded so the data starts 64-byte aligned
+    let padding = 63 - (10 + header.len()) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    let mut output = Vec::with_capacity(10 + header.len() + store.rows.len() * dimensions * 4);
+    output.extend_from_slice(b"\x93NUMPY\x01\x00");
+    output.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    output.extend_from_slice(header.as_bytes());
+    for (_, _, vector) in &store.rows {
+        for value in vector {
+            output.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    Ok(output)
+}
+
+pub fn npy_manifest(store: &EmbeddingFile, headings: &[String], texts: &[String]) -> Result<String, SemanticSearchError> {
+    let manifest = NpyManifest {
+        model: store.metadata.as_ref().map(|metadata| metadata.model.as_str()),
+        dimensions: dimensions(store),
+        count: store.rows.len(),
+        rows: store.rows.iter().zip(headings).zip(texts)
+            .map(|(((name, _, _), header), text)| ManifestRow { id: record_id(name, text), name, header, text })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&manifest).map_err(|e| SemanticSearchError::ConversionError(Box::new(e)))
+}
+
+pub fn dimensions(store: &EmbeddingFile) -> usize {
+    store.rows.first().map_or(0, |(_, _, vector)| vector.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> EmbeddingFile {
+        EmbeddingFile {
+            metadata: None,
+            rows: vec![
+                ("a.md".to_string(), "A".to_string(), vec![1.0, -0.5]),
+                ("b.md".to_string(), "B".to_string(), vec![0.25, 2.0]),
+            ],
+            provenance: vec![None, None],
+        }
+    }
+
+    #[test]
+    fn jsonl_lines() {
+        let mut store = store();
+        store.rows[0].1 = "Intro # of C# notes".to_string();
+        let records = vec![InputRecord::new("a.md".to_string(), "Intro".to_string(), "Intro # of C# notes".to_string())];
+        let headings = record_headings(&store, &records);
+        let texts = record_texts(&store);
+
+        let res = to_jsonl(&store, &headings, &texts).unwrap();
+
+        let lines: Vec<serde_json::Value> = res.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["id"], format!("a.md#{}", provenance::fingerprint("Intro # of C# notes")));
+        assert_eq!(lines[0]["header"], "Intro");
+        assert_eq!(lines[0]["text"], "Intro # of C# notes");
+        assert_eq!(lines[1]["header"], "");
+        assert_eq!(lines[0]["vector"], serde_json::json!([1.0, -0.5]));
+        assert_eq!(lines[1]["text"], "B");
+    }
+
+    #[test]
+    fn npy_layout() {
+        let res = to_npy(&store()).unwrap();
+
+        assert_eq!(&res[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([res[8], res[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        let header = std::str::from_utf8(&res[10..10 + header_len]).unwrap();
+        assert!(header.contains("'shape': (2, 2)"));
+        assert!(header.ends_with('\n'));
+        let data = &res[10 + header_len..];
+        assert_eq!(data.len(), 16);
+        assert_eq!(f32::from_le_bytes([data[4], data[5], data[6], data[7]]), -0.5);
+        assert_eq!(f32::from_le_bytes([data[8], data[9], data[10], data[11]]), 0.25);
+    }
+
+    #[test]
+    fn npy_needs_equal_dimensions() {
+        let mut store = store();
+        store.rows[1].2.push(0.0);
+
+        assert!(to_npy(&store).is_err());
+    }
+
+    #[test]
+    fn paths_next_to_store() {
+        assert_eq!(export_paths("semantic-search/embedding-work.csv", ExportFormat::Jsonl), vec!["semantic-search/embedding-work.jsonl"]);
+        assert_eq!(export_paths("embedding.csv", ExportFormat::Npy), vec!["embedding.npy", "embedding.manifest.json"]);
+    }
+}
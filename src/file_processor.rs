@@ -1,12 +1,47 @@
-use log::debug;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::future::Future;
+
+use log::{debug, error, warn};
 use wasm_bindgen::JsCast;
 use wasm_bindgen::prelude::*;
 
+use crate::obsidian::DataAdapter;
 use crate::obsidian::TFile;
 use crate::SemanticSearchError;
+use crate::encryption;
+use crate::exclusions::{ExcludedFiles, USER_IGNORE_FILTERS_KEY};
+use crate::rate_limit;
 use crate::obsidian::TFolder;
 use crate::obsidian::Vault;
 
+/// Minimum time between automatic flushes of queued writes
+const FLUSH_INTERVAL_MS: f64 = 2000.0;
+/// Locks older than this are assumed to be left behind by a crashed run
+const STALE_LOCK_MS: f64 = 30.0 * 60.0 * 1000.0;
+/// Reads of a file that keeps changing while it is read give up after this many attempts
+const READ_ATTEMPTS: usize = 5;
+/// Wait between attempts at reading a file being replaced
+const READ_RETRY_MS: f64 = 200.0;
+
+/// The version of a file as of one `stat`, which every write changes: its modification time and size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Generation {
+    modified: f64,
+    size: f64,
+}
+
+/// Appends queued by every FileProcessor, waiting to be written to the vault in one go
+#[derive(Default)]
+struct PendingWrites {
+    writes: BTreeMap<String, String>,
+    last_flush: f64,
+}
+
+thread_local! {
+    static PENDING_WRITES: RefCell<PendingWrites> = RefCell::new(PendingWrites::default());
+}
+
 #[wasm_bindgen]
 pub struct FileProcessor {
     vault: Vault,
@@ -17,10 +52,122 @@ impl FileProcessor {
         Self {vault}
     }
 
+    // Generated files are accessed through the adapter so they can live in hidden
+    // folders like .obsidian, which the Vault API does not index.
+    fn adapter(&self) -> DataAdapter {
+        self.vault.adapter()
+    }
+
     pub async fn read_from_path(&self, path: &str) -> Result<String, SemanticSearchError> {
-        let file: TFile = self.vault.getAbstractFileByPath(path.to_string()).unchecked_into();
-        let input = self.vault.cachedRead(file).await?.as_string().expect("file contents is not a string");
-        Ok(input)
+        self.flush_path(path).await?;
+        if !self.exists(path).await? {
+            return Err(SemanticSearchError::FileNotFound(path.to_string()));
+        }
+        let input = self.adapter().read(path.to_string()).await?.as_string().expect("file contents is not a string");
+        encryption::decrypt(path, input).await
+    }
+
+    pub async fn read_binary_from_path(&self, path: &str) -> Result<Vec<u8>, SemanticSearchError> {
+        if !self.exists(path).await? {
+            return Err(SemanticSearchError::FileNotFound(path.to_string()));
+        }
+        let buffer = self.adapter().readBinary(path.to_string()).await?;
+        Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+    }
+
+    /// Last modification time of `path` in milliseconds since the epoch, `None` if it does not exist.
+    /// Queued appends are written first so they count as a modification.
+    pub async fn modified_at(&self, path: &str) -> Result<Option<f64>, SemanticSearchError> {
+        self.flush_path(path).await?;
+        let stat = self.adapter().stat(path.to_string()).await?;
+        if stat.is_null() || stat.is_undefined() {
+            return Ok(None);
+        }
+        Ok(js_sys::Reflect::get(&stat, &JsValue::from_str("mtime"))?.as_f64())
+    }
+
+    /// Size of `path` in bytes, `None` if it does not exist
+    pub async fn size_of(&self, path: &str) -> Result<Option<f64>, SemanticSearchError> {
+        self.flush_path(path).await?;
+        let stat = self.adapter().stat(path.to_string()).await?;
+        if stat.is_null() || stat.is_undefined() {
+            return Ok(None);
+        }
+        Ok(js_sys::Reflect::get(&stat, &JsValue::from_str("size"))?.as_f64())
+    }
+
+    /// The `Generation` of `path`, `None` if it does not exist. Queued appends are written first.
+    pub async fn generation(&self, path: &str) -> Result<Option<Generation>, SemanticSearchError> {
+        self.flush_path(path).await?;
+        let stat = self.adapter().stat(path.to_string()).await?;
+        if stat.is_null() || stat.is_undefined() {
+            return Ok(None);
+        }
+        let field = |name: &str| js_sys::Reflect::get(&stat, &JsValue::from_str(name)).ok().and_then(|value| value.as_f64()).unwrap_or_default();
+        Ok(Some(Generation { modified: field("mtime"), size: field("size") }))
+    }
+
+    /// Reads `path` like `read_from_path`, but never half written: the read is repeated until
+    /// the file's `Generation` is the same before and after it, and while a replacement is being
+    /// swapped in by `swap_in`. If a crash interrupted a swap, the previous file is moved back.
+    pub async fn read_consistent(&self, path: &str) -> Result<String, SemanticSearchError> {
+        let previous_path = previous_path(path);
+        for attempt in 0..READ_ATTEMPTS {
+            if attempt > 0 {
+                rate_limit::sleep(READ_RETRY_MS).await;
+            }
+            let before = match self.generation(path).await? {
+                Some(generation) => generation,
+                None if self.exists(&previous_path).await? => continue,
+                None => return Err(SemanticSearchError::FileNotFound(path.to_string())),
+            };
+            let data = match self.read_from_path(path).await {
+                Ok(data) => data,
+                // removed by a swap after it was looked at
+                Err(_) if !self.exists(path).await? => continue,
+                Err(e) => return Err(e),
+            };
+            if self.generation(path).await? == Some(before) {
+                return Ok(data);
+            }
+            debug!("{} changed while it was read, reading it again", path);
+        }
+        if !self.exists(path).await? && self.exists(&previous_path).await? {
+            warn!("Restoring {} from {}, left behind by an interrupted update", path, previous_path);
+            self.rename_path(&previous_path, path).await?;
+            return self.read_from_path(path).await;
+        }
+        Err(SemanticSearchError::Locked(path.to_string()))
+    }
+
+    /// Replaces the contents of `path` with `data` so that readers see either the old or the
+    /// new contents in full: `data` is written to `<path>.tmp` first, then swapped in
+    pub async fn replace_file(&self, path: &str, data: &str) -> Result<(), SemanticSearchError> {
+        let temp_path = temp_path(path);
+        self.delete_file_at_path(&temp_path).await?;
+        self.write_to_path(&temp_path, data).await?;
+        self.swap_in(path).await
+    }
+
+    /// Moves the finished `<path>.tmp` over `path`. The adapter cannot rename onto an existing
+    /// file, so the old one is moved to `<path>.prev` first and removed once the new one is in
+    /// place; `read_consistent` waits for that, or restores `<path>.prev` after a crash.
+    pub async fn swap_in(&self, path: &str) -> Result<(), SemanticSearchError> {
+        let (temp_path, previous_path) = (temp_path(path), previous_path(path));
+        self.flush_path(&temp_path).await?;
+        self.delete_file_at_path(&previous_path).await?;
+        if self.exists(path).await? {
+            self.rename_path(path, &previous_path).await?;
+        }
+        self.rename_path(&temp_path, path).await?;
+        self.delete_file_at_path(&previous_path).await
+    }
+
+    /// Moves the file at `path` to `new_path`, which must not exist
+    pub async fn rename_path(&self, path: &str, new_path: &str) -> Result<(), SemanticSearchError> {
+        self.flush_path(path).await?;
+        self.adapter().rename(path.to_string(), new_path.to_string()).await?;
+        Ok(())
     }
 
     pub async fn read_from_file(&self, file: TFile) -> Result<String, SemanticSearchError> {
@@ -28,57 +175,384 @@ impl FileProcessor {
         Ok(input)
     }
 
+    pub async fn read_binary_from_file(&self, file: TFile) -> Result<Vec<u8>, SemanticSearchError> {
+        let buffer = self.vault.readBinary(file).await?;
+        Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+    }
+
+    /// Creates the file at `path` with `data`, or appends `data` to it, encrypting it first if
+    /// the file is encrypted at rest
     pub async fn write_to_path(&self, path: &str, data: &str) -> Result<(), SemanticSearchError> {
-        let file: TFile = self.vault.getAbstractFileByPath(path.to_string()).unchecked_into();
-        if file.is_null() {
+        let encrypted;
+        let data = match encryption::encrypts(path) {
+            true => {
+                encrypted = encryption::encrypt(data).await?;
+                encrypted.as_str()
+            },
+            false => data,
+        };
+        if !self.exists(path).await? {
             debug!("File: {} does not exist. Creating it now.", path);
-            self.vault.create(path.to_string(), data.to_string()).await?;
+            self.create_parent_folders(path).await?;
+            self.adapter().write(path.to_string(), data.to_string()).await?;
             return Ok(());
         }
-        self.vault.append(file, data.to_string()).await?;
+        self.adapter().append(path.to_string(), data.to_string()).await?;
+        Ok(())
+    }
+
+    /// Replaces whatever is at `path` with `data`
+    pub async fn write_binary_to_path(&self, path: &str, data: &[u8]) -> Result<(), SemanticSearchError> {
+        self.delete_file_at_path(path).await?;
+        self.create_parent_folders(path).await?;
+        self.adapter().writeBinary(path.to_string(), js_sys::Uint8Array::from(data).buffer()).await?;
+        Ok(())
+    }
+
+    async fn create_parent_folders(&self, path: &str) -> Result<(), SemanticSearchError> {
+        for folder in parent_folders(path) {
+            if !self.exists(&folder).await? {
+                debug!("Creating folder {}", folder);
+                self.adapter().mkdir(folder).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool, SemanticSearchError> {
+        Ok(self.adapter().exists(path.to_string()).await?.is_truthy())
+    }
+
+    /// Buffers an append to `path`, writing all buffered data once the flush interval has passed
+    pub async fn queue_append(&self, path: &str, data: &str) -> Result<(), SemanticSearchError> {
+        let should_flush = PENDING_WRITES.with(|pending| {
+            let mut pending = pending.borrow_mut();
+            pending.writes.entry(path.to_string()).or_default().push_str(data);
+            js_sys::Date::now() - pending.last_flush >= FLUSH_INTERVAL_MS
+        });
+        if should_flush {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Writes out every buffered append
+    pub async fn flush(&self) -> Result<(), SemanticSearchError> {
+        let writes = PENDING_WRITES.with(|pending| {
+            let mut pending = pending.borrow_mut();
+            pending.last_flush = js_sys::Date::now();
+            std::mem::take(&mut pending.writes)
+        });
+        if !writes.is_empty() {
+            debug!("Flushing queued writes to {} files", writes.len());
+        }
+        let mut writes = writes.into_iter();
+        while let Some((path, data)) = writes.next() {
+            if let Err(e) = self.write_to_path(&path, &data).await {
+                // put back whatever was not written so a later flush can retry it
+                PENDING_WRITES.with(|pending| {
+                    let mut pending = pending.borrow_mut();
+                    for (path, data) in std::iter::once((path, data)).chain(writes) {
+                        let queued = pending.writes.entry(path).or_default();
+                        queued.insert_str(0, &data);
+                    }
+                });
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn flush_path(&self, path: &str) -> Result<(), SemanticSearchError> {
+        let data = PENDING_WRITES.with(|pending| pending.borrow_mut().writes.remove(path));
+        if let Some(data) = data {
+            self.write_to_path(path, &data).await?;
+        }
         Ok(())
     }
 
     pub async fn delete_file_at_path(&self, path: &str) -> Result<(), SemanticSearchError> {
-        let file: TFile = self.vault.getAbstractFileByPath(path.to_string()).unchecked_into();
-        self.vault.delete(file).await?;
+        PENDING_WRITES.with(|pending| pending.borrow_mut().writes.remove(path));
+        if self.exists(path).await? {
+            self.adapter().remove(path.to_string()).await?;
+        }
         Ok(())
     }
 
-    pub async fn check_file_exists_at_path(&self, path: &str) -> Result<bool, SemanticSearchError> {
-        let file = self.vault.getAbstractFileByPath(path.to_string());
-        if file.is_null() {
-            return Ok(false);
+    /// Creates `<path>.lock`, failing if another command holds a lock that is not stale
+    pub async fn acquire_lock(&self, path: &str) -> Result<(), SemanticSearchError> {
+        let lock_path = lock_path(path);
+        if self.check_file_exists_at_path(&lock_path).await? {
+            let locked_at = self.read_from_path(&lock_path).await?.trim().parse::<f64>().unwrap_or(0.0);
+            if js_sys::Date::now() - locked_at < STALE_LOCK_MS {
+                return Err(SemanticSearchError::Locked(path.to_string()));
+            }
+            debug!("Removing stale lock {}", lock_path);
+            self.delete_file_at_path(&lock_path).await?;
         }
-        Ok(true)
+        self.write_to_path(&lock_path, &js_sys::Date::now().to_string()).await
     }
 
-    pub fn get_vault_markdown_files(&self, ignored_folders_setting: String) -> Vec<TFile> {
-        let root = self.vault.getRoot();
-        let ignored_folders: Vec<String> = ignored_folders_setting.split("\n").map(|x| x.to_string()).collect();
-        debug!("Ignored folders: {:?}", &ignored_folders);
-    
-        return self.search_for_markdown_files(root, &ignored_folders);
+    pub async fn release_lock(&self, path: &str) -> Result<(), SemanticSearchError> {
+        let lock_path = lock_path(path);
+        if self.check_file_exists_at_path(&lock_path).await? {
+            self.delete_file_at_path(&lock_path).await?;
+        }
+        Ok(())
     }
 
-    fn search_for_markdown_files(&self, root: TFolder, ignored_folders: &Vec<String>) -> Vec<TFile> {
-        let mut markdown_files: Vec<TFile> = Vec::new();
+    /// Releases the lock on `path` once a command is done with it, logging a failure rather than
+    /// returning it so it never takes the place of the command's own result
+    pub async fn release_lock_or_log(&self, path: &str) {
+        if let Err(e) = self.release_lock(path).await {
+            error!("Failed to release the lock on {}: {}", path, e);
+        }
+    }
 
-        for child in root.children() {
-            if child.has_type::<TFolder>() {
-                let folder = child.dyn_into::<TFolder>().expect("Folder should have TFolder type");
-                if ignored_folders.contains(&folder.path()) {
-                    continue;
+    pub async fn check_file_exists_at_path(&self, path: &str) -> Result<bool, SemanticSearchError> {
+        if PENDING_WRITES.with(|pending| pending.borrow().writes.contains_key(path)) {
+            return Ok(true);
+        }
+        self.exists(path).await
+    }
+
+    /// Every file matching `filter`, walking the vault from `filter.folder`
+    pub async fn list_files(&self, filter: &FileFilter) -> Vec<TFile> {
+        let root = match filter.folder() {
+            "" => self.vault.getRoot(),
+            folder => {
+                let folder = self.vault.getAbstractFileByPath(folder.to_string());
+                if !folder.has_type::<TFolder>() {
+                    return Vec::new();
                 }
-                markdown_files.extend(self.search_for_markdown_files(folder, &ignored_folders));
-            } else {
-                let file = child.dyn_into::<TFile>().expect("File should have TFile type");
-                if file.extension() == "md" {
-                    markdown_files.push(file);
+                folder.unchecked_into::<TFolder>()
+            }
+        };
+        let excluded = if filter.respect_excluded_files { self.excluded_files() } else { ExcludedFiles::default() };
+        debug!("Listing files matching {:?}", filter);
+
+        let mut files = Vec::new();
+        let mut pending = vec![root];
+        while let Some(folder) = pending.pop() {
+            for child in folder.children() {
+                if child.has_type::<TFolder>() {
+                    let folder = child.unchecked_into::<TFolder>();
+                    if !filter.skips_folder(&folder.path(), &excluded) {
+                        pending.push(folder);
+                    }
+                } else {
+                    let file = child.unchecked_into::<TFile>();
+                    if filter.includes_file(&file.path(), &file.extension(), &excluded) {
+                        files.push(file);
+                    }
                 }
             }
         }
+        files.sort_by_key(|file| file.path());
+        files
+    }
+
+    /// The file at `path`, if there is one
+    pub fn file_at_path(&self, path: &str) -> Option<TFile> {
+        let file = self.vault.getAbstractFileByPath(path.to_string());
+        file.has_type::<TFile>().then(|| file.unchecked_into::<TFile>())
+    }
+
+    /// Whether `list_files(filter)` lists `path`, without walking the vault. The file does not need to exist.
+    pub fn is_listed(&self, path: &str, filter: &FileFilter) -> bool {
+        let excluded = if filter.respect_excluded_files { self.excluded_files() } else { ExcludedFiles::default() };
+        filter.includes_path(path, &excluded)
+    }
+
+    /// Markdown notes matching `filter`, along with files of any extension it lists
+    pub async fn list_markdown_files(&self, filter: &FileFilter) -> Vec<TFile> {
+        let filter = filter.clone().with_extensions(&[MARKDOWN_EXTENSION]);
+        self.list_files(&filter).await
+    }
+
+    /// The "Excluded files" configured in Obsidian's Files and links settings
+    pub fn excluded_files(&self) -> ExcludedFiles {
+        let filters = serde_wasm_bindgen::from_value::<Option<Vec<String>>>(self.vault.getConfig(USER_IGNORE_FILTERS_KEY))
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        debug!("Excluded files: {:?}", &filters);
+        ExcludedFiles::new(&filters)
+    }
+}
+
+/// The writes the embedding pipeline makes to its store: to the vault through `FileProcessor`,
+/// or to memory when the pipeline runs natively in `headless`
+pub trait StoreFiles {
+    /// Appends `data` to the file at `path`, possibly holding it back until `flush`
+    fn queue_append(&self, path: &str, data: &str) -> impl Future<Output = Result<(), SemanticSearchError>>;
+
+    /// Writes out everything `queue_append` held back
+    fn flush(&self) -> impl Future<Output = Result<(), SemanticSearchError>>;
+}
+
+impl StoreFiles for FileProcessor {
+    async fn queue_append(&self, path: &str, data: &str) -> Result<(), SemanticSearchError> {
+        FileProcessor::queue_append(self, path, data).await
+    }
+
+    async fn flush(&self) -> Result<(), SemanticSearchError> {
+        FileProcessor::flush(self).await
+    }
+}
+
+pub const MARKDOWN_EXTENSION: &str = "md";
+
+/// Which vault files `FileProcessor::list_files` returns
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileFilter {
+    /// Extensions to list, without the dot. Empty lists files of every type.
+    pub extensions: Vec<String>,
+    /// Only list files below this vault relative folder, empty for the whole vault
+    pub folder: String,
+    /// Folders skipped along with everything below them
+    pub ignored_folders: Vec<String>,
+    /// Also skip the files excluded in Obsidian's settings
+    pub respect_excluded_files: bool,
+}
+
+impl FileFilter {
+    /// Files that get indexed: outside Obsidian's excluded files and the `ignoredFolders`
+    /// setting, which lists one vault relative folder per line
+    pub fn indexed(ignored_folders_setting: &str) -> Self {
+        let ignored_folders = ignored_folders_setting.lines()
+            .map(|folder| folder.trim().trim_matches('/').to_string())
+            .filter(|folder| !folder.is_empty())
+            .collect();
+        Self { ignored_folders, respect_excluded_files: true, ..Default::default() }
+    }
+
+    /// Every file below `folder`, whether it is indexed or not
+    pub fn in_folder(folder: &str) -> Self {
+        Self { folder: folder.to_string(), ..Default::default() }
+    }
+
+    pub fn with_extensions(mut self, extensions: &[&str]) -> Self {
+        for extension in extensions {
+            if !self.extensions.iter().any(|existing| existing == extension) {
+                self.extensions.push(extension.to_string());
+            }
+        }
+        self
+    }
+
+    fn folder(&self) -> &str {
+        self.folder.trim().trim_matches('/')
+    }
+
+    fn skips_folder(&self, path: &str, excluded: &ExcludedFiles) -> bool {
+        self.ignored_folders.iter().any(|folder| folder == path) || excluded.is_folder_excluded(path)
+    }
+
+    fn includes_file(&self, path: &str, extension: &str, excluded: &ExcludedFiles) -> bool {
+        (self.extensions.is_empty() || self.extensions.iter().any(|allowed| allowed == extension))
+            && !excluded.is_excluded(path)
+    }
+
+    /// Checks a file and every folder above it the way `FileProcessor::list_files` walks them
+    fn includes_path(&self, path: &str, excluded: &ExcludedFiles) -> bool {
+        let folder = self.folder();
+        if !folder.is_empty() && !path.starts_with(&format!("{}/", folder)) {
+            return false;
+        }
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        let extension = file_name.rsplit_once('.').map_or("", |(_, extension)| extension);
+        path.match_indices('/')
+            .map(|(end, _)| &path[..end])
+            .filter(|parent| parent.len() > folder.len())
+            .all(|parent| !self.skips_folder(parent, excluded))
+            && self.includes_file(path, extension, excluded)
+    }
+}
+
+/// Buffers an append to `path` without writing it, for callers that cannot wait such as the
+/// logger. Returns whether the flush interval has passed, so the caller can schedule a flush.
+pub fn queue_append_nowait(path: &str, data: &str) -> bool {
+    PENDING_WRITES.with(|pending| {
+        let mut pending = pending.borrow_mut();
+        pending.writes.entry(path.to_string()).or_default().push_str(data);
+        js_sys::Date::now() - pending.last_flush >= FLUSH_INTERVAL_MS
+    })
+}
+
+fn lock_path(path: &str) -> String {
+    format!("{}.lock", path)
+}
+
+/// Where a replacement of `path` is written before `FileProcessor::swap_in` moves it over `path`
+pub fn temp_path(path: &str) -> String {
+    format!("{}.tmp", path)
+}
+
+/// Where `path` is kept while a replacement is swapped in
+fn previous_path(path: &str) -> String {
+    format!("{}.prev", path)
+}
+
+/// Every ancestor folder of `path`, outermost first
+fn parent_folders(path: &str) -> Vec<String> {
+    let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+    (1..parts.len()).map(|i| parts[..i].join("/")).collect()
+}
+
+/// Joins a vault relative folder and file name, ignoring surrounding slashes
+pub fn storage_path(folder: &str, file_name: &str) -> String {
+    let folder = folder.trim().trim_matches('/');
+    if folder.is_empty() {
+        file_name.to_string()
+    } else {
+        format!("{}/{}", folder, file_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parent_folders_of_nested_path() {
+        assert_eq!(parent_folders(".obsidian/plugins/semantic-search/embedding.csv"), vec![".obsidian", ".obsidian/plugins", ".obsidian/plugins/semantic-search"]);
+        assert!(parent_folders("embedding.csv").is_empty());
+    }
+
+    #[test]
+    fn file_filter() {
+        let excluded = ExcludedFiles::new(&["Templates/".to_string()]);
+        let filter = FileFilter::indexed("Archive/\n\n Daily ").with_extensions(&["canvas", MARKDOWN_EXTENSION, "canvas"]);
+
+        assert_eq!(filter.ignored_folders, vec!["Archive", "Daily"]);
+        assert_eq!(filter.extensions, vec!["canvas", "md"]);
+        assert!(filter.skips_folder("Archive", &excluded));
+        assert!(filter.skips_folder("Templates", &excluded));
+        assert!(!filter.skips_folder("Notes/Archive", &excluded));
+        assert!(filter.includes_file("Notes/a.md", "md", &excluded));
+        assert!(!filter.includes_file("Notes/a.pdf", "pdf", &excluded));
+        assert!(FileFilter::in_folder("Notes/").includes_file("Notes/a.pdf", "pdf", &excluded));
+        assert_eq!(FileFilter::in_folder(" /Notes/ ").folder(), "Notes");
+    }
+
+    #[test]
+    fn single_paths() {
+        let excluded = ExcludedFiles::new(&["Templates/".to_string()]);
+        let filter = FileFilter::indexed("Archive").with_extensions(&[MARKDOWN_EXTENSION]);
+
+        assert!(filter.includes_path("a.md", &excluded));
+        assert!(filter.includes_path("Notes/Archive/a.md", &excluded));
+        assert!(!filter.includes_path("Archive/2021/a.md", &excluded));
+        assert!(!filter.includes_path("Templates/Daily.md", &excluded));
+        assert!(!filter.includes_path("Notes/input.csv", &excluded));
+        assert!(FileFilter::in_folder("Notes").includes_path("Notes/Archive/a.pdf", &excluded));
+        assert!(!FileFilter::in_folder("Notes").includes_path("Notes.md", &excluded));
+    }
 
-        return markdown_files;
+    #[test]
+    fn storage_path_joins() {
+        assert_eq!(storage_path("", "input.csv"), "input.csv");
+        assert_eq!(storage_path(" /Semantic Search/ ", "input.csv"), "Semantic Search/input.csv");
     }
 }
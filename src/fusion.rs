@@ -0,0 +1,124 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// A ranked chunk along with the source (store, model, modality) that scored it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoredCandidate {
+    pub name: String,
+    pub header: String,
+    pub score: f32,
+    pub source: String,
+    pub embedding: Vec<f32>,
+}
+
+/// How raw scores are rescaled per source so they can be compared after merging
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ScoreNormalization {
+    None,
+    #[default]
+    MinMax,
+    ZScore,
+}
+
+/// Damping constant for reciprocal rank fusion, the value from the original paper
+pub const RRF_K: f32 = 60.0;
+
+/// Merges several rankings of the same chunks by summing `1 / (k + rank)` over the rankings
+/// each chunk appears in. Only ranks are used, so raw scores need not be comparable.
+pub fn reciprocal_rank_fusion(rankings: Vec<Vec<ScoredCandidate>>, k: f32) -> Vec<ScoredCandidate> {
+    let mut merged: Vec<ScoredCandidate> = Vec::new();
+    let mut positions: HashMap<(String, String), usize> = HashMap::new();
+    for ranking in rankings {
+        for (rank, candidate) in ranking.into_iter().enumerate() {
+            let score = 1.0 / (k + rank as f32 + 1.0);
+            match positions.entry((candidate.name.clone(), candidate.header.clone())) {
+                Entry::Occupied(position) => merged[*position.get()].score += score,
+                Entry::Vacant(position) => {
+                    position.insert(merged.len());
+                    merged.push(ScoredCandidate { score, ..candidate });
+                },
+            }
+        }
+    }
+    merged.sort_by(|a, b| b.score.total_cmp(&a.score));
+    merged
+}
+
+pub fn normalize(candidates: &mut [ScoredCandidate], normalization: ScoreNormalization) {
+    let mut scores: Vec<f32> = candidates.iter().map(|c| c.score).collect();
+    normalize_scores(&mut scores, normalization);
+    for (candidate, score) in candidates.iter_mut().zip(scores) {
+        candidate.score = score;
+    }
+}
+
+/// Rescales raw scores in place, e.g. before only the best of them are turned into candidates
+pub fn normalize_scores(scores: &mut [f32], normalization: ScoreNormalization) {
+    if scores.is_empty() {
+        return;
+    }
+    let n = scores.len() as f32;
+    match normalization {
+        ScoreNormalization::None => (),
+        ScoreNormalization::MinMax => {
+            let min = scores.iter().copied().fold(f32::INFINITY, f32::min);
+            let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            let range = max - min;
+            for score in scores.iter_mut() {
+                *score = if range > 0.0 { (*score - min) / range } else { 1.0 };
+            }
+        },
+        ScoreNormalization::ZScore => {
+            let mean = scores.iter().sum::<f32>() / n;
+            let variance = scores.iter().map(|score| (score - mean).powi(2)).sum::<f32>() / n;
+            let std_dev = variance.sqrt();
+            for score in scores.iter_mut() {
+                *score = if std_dev > 0.0 { (*score - mean) / std_dev } else { 0.0 };
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(name: &str, score: f32, source: &str) -> ScoredCandidate {
+        ScoredCandidate { name: name.to_string(), header: "Header".to_string(), score, source: source.to_string(), embedding: vec![] }
+    }
+
+    #[test]
+    fn min_max() {
+        let mut candidates = vec![candidate("c", 0.40, "s"), candidate("d", 0.30, "s"), candidate("e", 0.35, "s")];
+
+        normalize(&mut candidates, ScoreNormalization::MinMax);
+
+        assert_eq!(candidates[0].score, 1.0);
+        assert_eq!(candidates[1].score, 0.0);
+        assert!((candidates[2].score - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn z_score() {
+        let mut candidates = vec![candidate("a", 1.0, "s"), candidate("b", 3.0, "s")];
+
+        normalize(&mut candidates, ScoreNormalization::ZScore);
+
+        assert_eq!(candidates[0].score, -1.0);
+        assert_eq!(candidates[1].score, 1.0);
+    }
+
+    #[test]
+    fn rrf_rewards_agreement() {
+        let first = vec![candidate("a", 0.9, "q1"), candidate("b", 0.8, "q1"), candidate("c", 0.7, "q1")];
+        let second = vec![candidate("b", 0.5, "q2"), candidate("c", 0.4, "q2"), candidate("d", 0.3, "q2")];
+
+        let res = reciprocal_rank_fusion(vec![first, second], RRF_K);
+
+        assert_eq!(res.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["b", "c", "a", "d"]);
+        assert!((res[0].score - (1.0 / 62.0 + 1.0 / 61.0)).abs() < 1e-6);
+    }
+}
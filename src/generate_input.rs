@@ -6,69 +6,329 @@ use wasm_bindgen::prelude::*;
 use lazy_static::lazy_static;
 
 use crate::FileProcessor;
+use crate::file_processor::{FileFilter, MARKDOWN_EXTENSION};
+use crate::attachments::{self, AttachmentKind};
+use crate::canvas::{self, CANVAS_EXTENSION};
 use crate::SemanticSearchError;
 use crate::Notice;
-use crate::DATA_FILE_PATH;
+use crate::chunking::{Chunking, Granularity};
+use crate::input::{InputRecord, LineRange, NoteTimes, note_title, write_input};
+use crate::jobs::JobHandle;
+use crate::manifest::{self, RunRecorder};
 use crate::obsidian;
 use crate::obsidian::App;
-use crate::obsidian::semanticSearchSettings;
+use crate::reindex::{Progress, ReindexStage};
+use crate::settings::Settings;
 
 #[wasm_bindgen]
 pub struct GenerateInputCommand {
     file_processor: FileProcessor,
+    input_path: String,
+    manifest_path: String,
     ignored_folders: String,
     section_delimeter_regex: String,
+    attachment_kinds: Vec<AttachmentKind>,
+    cleaning: MarkdownCleaning,
+    chunking: Chunking,
+    min_section_chars: usize,
 }
 
 #[wasm_bindgen]
 impl GenerateInputCommand {
     #[wasm_bindgen(constructor)]
-    pub fn new(app: App, settings: semanticSearchSettings) -> GenerateInputCommand {
+    pub fn new(app: App, settings: JsValue) -> Result<GenerateInputCommand, SemanticSearchError> {
+        let settings = Settings::from_js(&settings)?;
         let file_processor = FileProcessor::new(app.vault());
-        let ignored_folders = settings.ignoredFolders();
-        let section_delimeter_regex = settings.sectionDelimeterRegex();
+        let input_path = settings.input_path();
+        let manifest_path = settings.manifest_path();
+        let cleaning = MarkdownCleaning::from_settings(&settings);
+        let chunking = Chunking::from_settings(&settings);
+        let ignored_folders = settings.ignored_folders;
+        let section_delimeter_regex = settings.section_delimeter_regex;
+        let attachment_kinds = attachments::parse_attachment_kinds(&settings.attachment_types);
+        let min_section_chars = settings.min_section_chars;
+
+        Ok(GenerateInputCommand { file_processor, input_path, manifest_path, ignored_folders, section_delimeter_regex, attachment_kinds, cleaning, chunking, min_section_chars })
+    }
 
-        GenerateInputCommand { file_processor, ignored_folders, section_delimeter_regex}
+    pub async fn callback(&self) -> Result<(), SemanticSearchError> {
+        let (_, skipped) = self.write_input(&Progress::default()).await?;
+        match skipped {
+            0 => Notice::new(&format!("Successfully created {}", self.input_path)),
+            skipped => Notice::new(&format!("Successfully created {}, skipped {} empty sections", self.input_path, skipped)),
+        };
+        Ok(())
     }
+}
 
-    pub async fn callback(&self) {
-        let data = self.generate_input().await.expect("failed to generate input.csv");
-        match self.file_processor.delete_file_at_path(DATA_FILE_PATH).await {
-            Ok(()) => (),
-            Err(e) => error!("{:?}", e),
+impl GenerateInputCommand {
+    /// Writes input.csv, reporting each file read to `progress`, and returns the number of
+    /// sections written and of sections left out for being too short
+    pub(crate) async fn write_input(&self, progress: &Progress) -> Result<(usize, usize), SemanticSearchError> {
+        let job = JobHandle::start("generate input");
+        let mut recorder = RunRecorder::start("generate input");
+        let result = self.generate_input(&job, &mut recorder, progress).await;
+        if let Ok((data, _, _)) = &result {
+            match self.file_processor.delete_file_at_path(&self.input_path).await {
+                Ok(()) => (),
+                Err(e) => error!("{:?}", e),
+            }
+            match self.file_processor.write_to_path(&self.input_path, data).await {
+                Ok(()) => (),
+                Err(e) => {
+                    error!("{:?}", e);
+                    recorder.warn(format!("Failed to write {}: {}", self.input_path, e));
+                }
+            }
+            recorder.stage("write input", data.len());
         }
-        match self.file_processor.write_to_path(DATA_FILE_PATH, &data).await {
-            Ok(()) => (),
-            Err(e) => error!("{:?}", e),
+        let manifest = recorder.finish(&result);
+        if let Err(e) = manifest::save(&self.file_processor, &self.manifest_path, &manifest).await {
+            error!("Failed to save run manifest: {}", e);
         }
-
-        Notice::new("Successfully created input.csv");
+        let (_, records, skipped) = result?;
+        Ok((records, skipped))
     }
 
-    async fn generate_input(&self) -> Result<String, SemanticSearchError> {
-        let files = self.file_processor.get_vault_markdown_files(self.ignored_folders.clone());
-        let mut wtr = csv::Writer::from_writer(vec![]);
-        for file in files {
-            let extracted = self.process_file(file).await.unwrap();
-            for (file_name, header, body) in extracted {
-                wtr.write_record(&[&file_name, &header, &body])?;
+    /// The contents of input.csv, the number of sections in it and the number of sections left
+    /// out for being too short
+    async fn generate_input(&self, job: &JobHandle, recorder: &mut RunRecorder, progress: &Progress) -> Result<(String, usize, usize), SemanticSearchError> {
+        let files = self.file_processor.list_files(&self.file_filter()).await;
+        recorder.stage("collect files", files.len());
+        let total = files.len();
+        let mut records = Vec::new();
+        let mut skipped = 0;
+        for (done, file) in files.into_iter().enumerate() {
+            job.check()?;
+            progress.report(ReindexStage::Input, done, total);
+            let path = file.path();
+            let mut extracted = match self.process_file(file).await {
+                Ok(extracted) => extracted,
+                Err(e) => {
+                    recorder.skip(&path, e.to_string());
+                    continue;
+                }
+            };
+            let found = extracted.len();
+            skipped += drop_short_records(&mut extracted, self.min_section_chars);
+            if extracted.is_empty() {
+                let reason = if found == 0 { "no sections found" } else { "all sections are empty" };
+                recorder.skip(&path, reason.to_string());
             }
+            records.extend(extracted);
+        }
+        recorder.stage("extract sections", records.len());
+        if skipped > 0 {
+            recorder.warn(format!("Skipped {} sections with fewer than {} characters", skipped, self.min_section_chars));
         }
-        let data = String::from_utf8(wtr.into_inner()?)?;
-        Ok(data)
+        progress.report(ReindexStage::Input, total, total);
+        Ok((write_input(&records, self.chunking.granularity)?, records.len(), skipped))
     }
 
-    async fn process_file(&self, file: obsidian::TFile) -> Result<Vec<(String, String, String)>, SemanticSearchError> {
+    /// The records of `file`, each stamped with the file's vault path and creation and modification times
+    async fn process_file(&self, file: obsidian::TFile) -> Result<Vec<InputRecord>, SemanticSearchError> {
+        let stat = file.stat();
+        let times = NoteTimes { created: stat.ctime(), modified: stat.mtime() };
+        let path = file.path();
+        let records = self.file_sections(file).await?;
+        Ok(records.into_iter().map(|record| record.with_times(times).with_path(&path)).collect())
+    }
+
+    async fn file_sections(&self, file: obsidian::TFile) -> Result<Vec<InputRecord>, SemanticSearchError> {
         let name = file.name();
-        let text = self.file_processor.read_from_file(file).await?;
-        let sections = extract_sections(&name, &text, &self.section_delimeter_regex)?;
-        Ok(sections)
+        if file.extension() == CANVAS_EXTENSION {
+            let data = self.file_processor.read_from_file(file).await?;
+            return Ok(into_records(canvas::canvas_sections(&name, &data)?));
+        }
+        let sections = match AttachmentKind::from_extension(&file.extension()) {
+            Some(AttachmentKind::Pdf) => {
+                let data = self.file_processor.read_binary_from_file(file).await?;
+                attachments::pdf_sections(&name, &data).await
+            }
+            Some(kind) => {
+                let text = self.file_processor.read_from_file(file).await?;
+                attachments::text_sections(kind, &name, &text, &self.section_delimeter_regex)
+            }
+            None => {
+                let text = self.file_processor.read_from_file(file).await?;
+                let text = self.cleaning.clean(&text);
+                return Ok(extract_section_records(&name, &text, &self.section_delimeter_regex, self.chunking));
+            }
+        };
+        Ok(into_records(sections?))
     }
+
+    /// How much of a markdown note each record covers
+    pub(crate) fn granularity(&self) -> Granularity {
+        self.chunking.granularity
+    }
+
+    /// The notes and attachments that go into input.csv
+    pub(crate) fn file_filter(&self) -> FileFilter {
+        let mut extensions = vec![MARKDOWN_EXTENSION, CANVAS_EXTENSION];
+        extensions.extend(self.attachment_kinds.iter().map(AttachmentKind::extension));
+        FileFilter::indexed(&self.ignored_folders).with_extensions(&extensions)
+    }
+
+    /// The records Generate Input would write for `file`, for updating the index one file at a time
+    pub(crate) async fn file_records(&self, file: obsidian::TFile) -> Result<Vec<InputRecord>, SemanticSearchError> {
+        let mut records = self.process_file(file).await?;
+        drop_short_records(&mut records, self.min_section_chars);
+        Ok(records)
+    }
+}
+
+/// Drops records with fewer than `min_chars` characters besides whitespace, which would
+/// waste tokens or be rejected by the API, and returns how many were dropped
+pub(crate) fn drop_short_records(records: &mut Vec<InputRecord>, min_chars: usize) -> usize {
+    let before = records.len();
+    records.retain(|record| record.body.chars().filter(|c| !c.is_whitespace()).count() >= min_chars);
+    before - records.len()
 }
 
-fn extract_sections(name: &str, text: &str, delimeter: &str) -> Result<Vec<(String, String, String)>, SemanticSearchError> {
-    let mut header_to_content: Vec<(String, String, String)> = Vec::new();
-    let mut lines = text.lines().peekable();
+/// Records for sections that are not lines of a note, such as PDF pages and canvas cards
+fn into_records(sections: Vec<(String, String, String)>) -> Vec<InputRecord> {
+    sections.into_iter().map(|(name, header, body)| InputRecord::new(name, header, body)).collect()
+}
+
+/// Markdown that is removed from notes before they are split into sections
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct MarkdownCleaning {
+    pub code_blocks: bool,
+    pub inline_code: bool,
+    pub math: bool,
+    /// Dataview and DataviewJS blocks and inline `= queries`
+    pub dataview: bool,
+}
+
+impl MarkdownCleaning {
+    pub(crate) fn from_settings(settings: &Settings) -> Self {
+        Self {
+            code_blocks: settings.strip_code_blocks,
+            inline_code: settings.strip_inline_code,
+            math: settings.strip_math,
+            dataview: settings.strip_dataview,
+        }
+    }
+
+    pub(crate) fn clean(&self, text: &str) -> String {
+        lazy_static! {
+            static ref INLINE_DATAVIEW: Regex = Regex::new(r"`\$?=[^`\n]*`").unwrap();
+            static ref INLINE_CODE: Regex = Regex::new(r"`[^`\n]+`").unwrap();
+            static ref DISPLAY_MATH: Regex = Regex::new(r"(?s)\$\$.*?\$\$").unwrap();
+            // Obsidian only treats $..$ as math without whitespace inside the dollars, so prices survive
+            static ref INLINE_MATH: Regex = Regex::new(r"\$[^\s$](?:[^$\n]*[^\s$])?\$").unwrap();
+        }
+        let mut text = self.remove_fenced_blocks(text);
+        if self.dataview {
+            text = INLINE_DATAVIEW.replace_all(&text, "").to_string();
+        }
+        if self.inline_code {
+            text = INLINE_CODE.replace_all(&text, "").to_string();
+        }
+        if self.math {
+            // keep the line breaks so line numbers still match the note
+            text = DISPLAY_MATH.replace_all(&text, |caps: &regex::Captures| "\n".repeat(caps[0].matches('\n').count())).to_string();
+            text = INLINE_MATH.replace_all(&text, "").to_string();
+        }
+        text
+    }
+
+    /// Blanks out fenced code blocks, or only dataview blocks, including their fences.
+    /// The lines are kept empty rather than removed so line numbers still match the note.
+    fn remove_fenced_blocks(&self, text: &str) -> String {
+        if !self.code_blocks && !self.dataview {
+            return text.to_string();
+        }
+        let mut kept = Vec::new();
+        let mut fence: Option<(String, bool)> = None;
+        for line in text.lines() {
+            let trimmed = line.trim_start();
+            match &fence {
+                Some((marker, strip)) => {
+                    let strip = *strip;
+                    if trimmed.starts_with(marker.as_str()) && trimmed.trim_start_matches(marker.chars().next().unwrap()).trim().is_empty() {
+                        fence = None;
+                    }
+                    kept.push(if strip { "" } else { line });
+                }
+                None => {
+                    let marker: String = trimmed.chars().take_while(|c| *c == '`' || *c == '~').collect();
+                    if marker.len() >= 3 && marker.chars().all(|c| c == marker.chars().next().unwrap()) {
+                        let language = trimmed[marker.len()..].trim().to_lowercase();
+                        let strip = self.code_blocks || (self.dataview && language.starts_with("dataview"));
+                        fence = Some((marker, strip));
+                        kept.push(if strip { "" } else { line });
+                    } else {
+                        kept.push(line);
+                    }
+                }
+            }
+        }
+        kept.join("\n")
+    }
+}
+
+/// A run of lines starting at a delimiter match, or at the top of the note
+struct LineSection {
+    /// The delimiter line, or the first line for text above the first delimiter
+    header: String,
+    /// Whether the section starts with a line matching the delimiter
+    delimited: bool,
+    lines: LineRange,
+    /// Markdown headings the section is nested under, including its own heading
+    headings: Vec<String>,
+    /// Cleaned non-empty lines after the delimiter line, with their line numbers
+    content: Vec<(usize, String)>,
+}
+
+impl LineSection {
+    /// The whole note as one section headed by its title
+    fn note(title: &str, sections: Vec<LineSection>) -> Option<LineSection> {
+        let lines = LineRange { start: sections.first()?.lines.start, end: sections.last()?.lines.end };
+        let mut content = Vec::new();
+        for section in sections {
+            if section.delimited {
+                content.push((section.lines.start, clean_text(&section.header)));
+            }
+            content.extend(section.content);
+        }
+        Some(LineSection { header: title.to_string(), delimited: false, lines, headings: Vec::new(), content })
+    }
+
+    /// Splits the section at blank lines, each paragraph keeping the section's heading
+    fn paragraphs(self) -> Vec<LineSection> {
+        let mut groups: Vec<Vec<(usize, String)>> = Vec::new();
+        for (number, line) in self.content.iter().cloned() {
+            match groups.last_mut() {
+                Some(group) if group.last().is_some_and(|(previous, _)| previous + 1 == number) => group.push((number, line)),
+                _ => groups.push(vec![(number, line)]),
+            }
+        }
+        if groups.len() <= 1 {
+            return vec![self];
+        }
+        groups.into_iter().enumerate().map(|(i, content)| LineSection {
+            header: self.header.clone(),
+            delimited: self.delimited,
+            lines: LineRange {
+                start: if i == 0 { self.lines.start } else { content[0].0 },
+                end: content[content.len() - 1].0,
+            },
+            headings: self.headings.clone(),
+            content,
+        }).collect()
+    }
+
+    /// The section text, starting with the delimiter line as written
+    fn body(&self) -> String {
+        let heading = if self.delimited { Some(self.header.as_str()) } else { None };
+        let body: Vec<&str> = heading.into_iter().chain(self.content.iter().map(|(_, line)| line.as_str())).collect();
+        clean_text(&body.join(" "))
+    }
+}
+
+fn split_sections(text: &str, delimeter: &str) -> Vec<LineSection> {
     let re = match Regex::new(delimeter) {
         Ok(r) => r,
         Err(_) => {
@@ -76,32 +336,110 @@ fn extract_sections(name: &str, text: &str, delimeter: &str) -> Result<Vec<(Stri
             Regex::new(".").unwrap()
         },
     };
-    let mut section_header = "".to_string();
-    let mut body = Vec::new();
-    while let Some(line) = lines.next() {
-        if re.is_match(&line) {
-            if body.len() != 0 || section_header != "" {
-                header_to_content.push((name.to_string(), clean_text(&section_header), clean_text(&body.join(" "))));
-            }
-            section_header = line.to_string();
-            body = vec![line.to_string()];
-        } else {
-            if section_header == "" {
-                section_header = line.to_string();
-            }
-            let cleaned_line = clean_text(line);
-            if cleaned_line != "" {
-                body.push(cleaned_line);
-            }
+    let mut sections = Vec::new();
+    let mut current: Option<LineSection> = None;
+    let mut headings = HeadingStack::default();
+    for (number, line) in text.lines().enumerate() {
+        headings.update(line);
+        if re.is_match(line) {
+            sections.extend(current.take());
+            current = Some(LineSection { header: line.to_string(), delimited: true, lines: LineRange { start: number, end: number }, headings: headings.path(), content: Vec::new() });
+            continue;
+        }
+        let section = current.get_or_insert_with(|| LineSection { header: String::new(), delimited: false, lines: LineRange { start: number, end: number }, headings: headings.path(), content: Vec::new() });
+        if section.header.is_empty() {
+            section.header = line.to_string();
+            section.lines.start = number;
+        }
+        let cleaned_line = clean_text(line);
+        if !cleaned_line.is_empty() {
+            section.content.push((number, cleaned_line));
+            section.lines.end = number;
+        }
+    }
+    sections.extend(current);
+    sections.retain(|section| section.delimited || !section.header.is_empty() || !section.content.is_empty());
+    sections
+}
+
+/// Markdown headings open at the current line, skipping `#` lines inside code fences
+#[derive(Default)]
+struct HeadingStack {
+    headings: Vec<(usize, String)>,
+    in_fence: bool,
+}
+
+impl HeadingStack {
+    fn update(&mut self, line: &str) {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            self.in_fence = !self.in_fence;
+            return;
+        }
+        if self.in_fence {
+            return;
         }
-        if lines.peek().is_none() && (section_header != "" || body.len() != 0) {
-            header_to_content.push((name.to_string(), clean_text(&section_header), clean_text(&body.join(" "))));
+        let level = line.chars().take_while(|c| *c == '#').count();
+        let heading = &line[level..];
+        if level == 0 || level > 6 || !(heading.is_empty() || heading.starts_with(char::is_whitespace)) {
+            return;
         }
+        while matches!(self.headings.last(), Some((open, _)) if *open >= level) {
+            self.headings.pop();
+        }
+        self.headings.push((level, clean_text(heading)));
+    }
+
+    fn path(&self) -> Vec<String> {
+        self.headings.iter().map(|(_, heading)| heading.clone()).filter(|heading| !heading.is_empty()).collect()
     }
-    Ok(header_to_content)
 }
 
-fn clean_text(text: &str) -> String {
+pub(crate) fn extract_sections(name: &str, text: &str, delimeter: &str) -> Result<Vec<(String, String, String)>, SemanticSearchError> {
+    Ok(split_sections(text, delimeter).iter()
+        .map(|section| (name.to_string(), clean_text(&section.header), section.body()))
+        .collect())
+}
+
+/// Sections of a markdown note with the lines they cover, merged or split further to the
+/// configured granularity and into overlapping chunks of `chunking.size` words when they
+/// are longer than that
+pub(crate) fn extract_section_records(name: &str, text: &str, delimeter: &str, chunking: Chunking) -> Vec<InputRecord> {
+    let sections = split_sections(text, delimeter);
+    let sections = match chunking.granularity {
+        Granularity::Note => LineSection::note(note_title(name), sections).into_iter().collect(),
+        Granularity::Heading => sections,
+        Granularity::Paragraph => sections.into_iter().flat_map(LineSection::paragraphs).collect(),
+    };
+    let mut records = Vec::new();
+    for section in sections {
+        let header = clean_text(&section.header);
+        let words: Vec<(usize, &str)> = section.content.iter()
+            .flat_map(|(number, line)| line.split_whitespace().map(move |word| (*number, word)))
+            .collect();
+        let windows = chunking.windows(words.len());
+        if windows.len() <= 1 {
+            records.push(InputRecord::new(name.to_string(), header, section.body()).with_lines(section.lines).with_headings(&section.headings));
+            continue;
+        }
+        for (i, (start, end)) in windows.into_iter().enumerate() {
+            let window: Vec<&str> = words[start..end].iter().map(|(_, word)| *word).collect();
+            // every chunk repeats the heading so it is embedded with its context
+            let body = match section.delimited {
+                true => clean_text(&format!("{} {}", section.header, window.join(" "))),
+                false => clean_text(&window.join(" ")),
+            };
+            let lines = LineRange {
+                start: if i == 0 { section.lines.start } else { words[start].0 },
+                end: words[end - 1].0,
+            };
+            records.push(InputRecord::new(name.to_string(), header.clone(), body).with_lines(lines).with_headings(&section.headings));
+        }
+    }
+    records
+}
+
+pub(crate) fn clean_text(text: &str) -> String {
     const MAX_TOKEN_LENGTH: usize = 8191;
     let mut input = remove_hashtags(text);
     input = remove_links(&input);
@@ -297,6 +635,23 @@ Guarantees reliability only if sender is correct
 - BEB3. No creation: No message delivered unless broadcast");
     }
 
+    #[test]
+    fn clean_code_and_math() {
+        let text = "## Setup\nRun `cargo build` first.\n```rust\nfn main() {}\n```\nEnergy $E = mc^2$ costs $5 and $10.\n$$\n\\int x\\,dx\n$$\nDone";
+        let cleaning = MarkdownCleaning { code_blocks: true, inline_code: true, math: true, dataview: false };
+
+        assert_eq!(cleaning.clean(text), "## Setup\nRun  first.\n\n\n\nEnergy  costs $5 and $10.\n\n\n\nDone");
+        assert_eq!(MarkdownCleaning::default().clean(text), text);
+    }
+
+    #[test]
+    fn clean_dataview_only() {
+        let text = "Tasks\n```dataview\nTABLE file.name\n```\n```js\nlet a = 1;\n```\nDue `= this.due` today, see `code`.";
+        let cleaning = MarkdownCleaning { dataview: true, ..Default::default() };
+
+        assert_eq!(cleaning.clean(text), "Tasks\n\n\n\n```js\nlet a = 1;\n```\nDue  today, see `code`.");
+    }
+
     #[test]
     fn no_delimeter() {
         let text = "## Test\n![Pasted image 20220415211535](Pics/Pasted%20image%2020220415211535.png)\n### Test2\n![Pasted image 20220415211535](Pics/Pasted%20image%2020220415211535.png)";
@@ -319,4 +674,96 @@ Guarantees reliability only if sender is correct
         assert_eq!(res.get(3).unwrap().1, "");
         assert_eq!(res.get(3).unwrap().2, "");
     }
+
+    #[test]
+    fn section_line_offsets() {
+        let text = "Intro line\n\n## First\nsome text\n\nmore text\n## Second\nlast";
+
+        let res = extract_section_records(NAME, text, "^## ", Chunking::default());
+
+        let sections: Vec<(&str, Option<LineRange>)> = res.iter().map(|record| (record.header.as_str(), record.lines())).collect();
+        assert_eq!(sections, vec![
+            ("Intro line", Some(LineRange { start: 0, end: 0 })),
+            ("First", Some(LineRange { start: 2, end: 5 })),
+            ("Second", Some(LineRange { start: 6, end: 7 })),
+        ]);
+        let bodies: Vec<String> = res.into_iter().map(|record| record.body).collect();
+        let expected: Vec<String> = extract_sections(NAME, text, "^## ").unwrap().into_iter().map(|(_, _, body)| body).collect();
+        assert_eq!(bodies, expected);
+    }
+
+    #[test]
+    fn overlapping_chunks() {
+        let text = "## Long\none two three\nfour five\nsix seven";
+        let chunking = Chunking { size: 3, overlap: 1, ..Default::default() };
+
+        let res = extract_section_records(NAME, text, "^## ", chunking);
+
+        let chunks: Vec<(&str, Option<LineRange>)> = res.iter().map(|record| (record.body.as_str(), record.lines())).collect();
+        assert_eq!(chunks, vec![
+            ("Long one two three", Some(LineRange { start: 0, end: 1 })),
+            ("Long three four five", Some(LineRange { start: 1, end: 2 })),
+            ("Long five six seven", Some(LineRange { start: 2, end: 3 })),
+        ]);
+        assert!(res.iter().all(|record| record.header == "Long"));
+    }
+
+    #[test]
+    fn granularity() {
+        let text = "Intro line\n\n## First\nsome text\n\nmore text\n## Second\nlast";
+        let at = |granularity| Chunking { granularity, ..Default::default() };
+
+        let note = extract_section_records("folder/Note.md", text, "^## ", at(Granularity::Note));
+        let paragraphs = extract_section_records(NAME, text, "^## ", at(Granularity::Paragraph));
+
+        assert_eq!(note.len(), 1);
+        assert_eq!((note[0].header.as_str(), note[0].body.as_str()), ("Note", "Intro line First some text more text Second last"));
+        assert_eq!(note[0].lines(), Some(LineRange { start: 0, end: 7 }));
+        let paragraphs: Vec<(&str, Option<LineRange>)> = paragraphs.iter().map(|record| (record.body.as_str(), record.lines())).collect();
+        assert_eq!(paragraphs, vec![
+            ("Intro line", Some(LineRange { start: 0, end: 0 })),
+            ("First some text", Some(LineRange { start: 2, end: 3 })),
+            ("First more text", Some(LineRange { start: 5, end: 5 })),
+            ("Second last", Some(LineRange { start: 6, end: 7 })),
+        ]);
+    }
+
+    #[test]
+    fn short_records_dropped() {
+        let mut records = vec![
+            InputRecord::new(NAME.to_string(), "".to_string(), " \t ".to_string()),
+            InputRecord::new(NAME.to_string(), "A".to_string(), "A".to_string()),
+            InputRecord::new(NAME.to_string(), "B".to_string(), "B with a body".to_string()),
+        ];
+
+        assert_eq!(drop_short_records(&mut records.clone(), 1), 1);
+        assert_eq!(drop_short_records(&mut records, 5), 2);
+        assert_eq!(records[0].header, "B");
+    }
+
+    #[test]
+    fn heading_paths() {
+        let text = "# Roadmap\nintro\n## Tasks\nTODO: fix it\n```\n# not a heading\n```\n### Later\nsoon\n## Done\nshipped";
+
+        let res = extract_section_records(NAME, text, "^#+ [A-Z]", Chunking::default());
+
+        let paths: Vec<(&str, &str)> = res.iter().map(|record| (record.header.as_str(), record.headings.as_str())).collect();
+        assert_eq!(paths, vec![
+            ("Roadmap", "Roadmap"),
+            ("Tasks", "Roadmap > Tasks"),
+            ("Later", "Roadmap > Tasks > Later"),
+            ("Done", "Roadmap > Done"),
+        ]);
+    }
+
+    #[test]
+    fn cleaning_keeps_line_numbers() {
+        let text = "## A\n```\ncode\n```\nafter\n$$\nx\n$$\nend";
+        let cleaning = MarkdownCleaning { code_blocks: true, math: true, ..Default::default() };
+
+        let res = extract_section_records(NAME, &cleaning.clean(text), "^## ", Chunking::default());
+
+        assert_eq!(res[0].lines(), Some(LineRange { start: 0, end: 8 }));
+        assert_eq!(res[0].body, "A after end");
+    }
 }
@@ -0,0 +1,84 @@
+use regex::Regex;
+
+use crate::SemanticSearchError;
+
+/// A vault path pattern such as `Projects/**`, `**/meetings/*.md` or plain `Projects`.
+///
+/// `*` and `?` match within one folder or file name, `**` matches across folders, and `**/`
+/// also matches no folder at all. A pattern without wildcards matches that path and everything below it.
+#[derive(Debug, Clone)]
+pub struct PathPattern {
+    regex: Regex,
+}
+
+impl PathPattern {
+    pub fn new(pattern: &str) -> Result<Self, SemanticSearchError> {
+        let pattern = pattern.trim().trim_start_matches('/');
+        let mut source = String::from("^");
+        let mut rest = pattern;
+        while let Some(c) = rest.chars().next() {
+            if let Some(after) = rest.strip_prefix("**/") {
+                source.push_str("(?:.*/)?");
+                rest = after;
+                continue;
+            }
+            if let Some(after) = rest.strip_prefix("**") {
+                source.push_str(".*");
+                rest = after;
+                continue;
+            }
+            match c {
+                '*' => source.push_str("[^/]*"),
+                '?' => source.push_str("[^/]"),
+                c => source.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+        if !pattern.contains(['*', '?']) {
+            // A folder or file, and everything below it
+            let trimmed = source.trim_end_matches('/').len();
+            source.truncate(trimmed);
+            source.push_str("(?:/.*)?");
+        }
+        source.push('$');
+        let regex = Regex::new(&source)
+            .map_err(|e| SemanticSearchError::InvalidArgument(format!("Invalid path pattern {}: {}", pattern, e)))?;
+        Ok(Self { regex })
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        self.regex.is_match(path.trim_start_matches('/'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        PathPattern::new(pattern).unwrap().matches(path)
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("Projects/**", "Projects/plan.md"));
+        assert!(matches("Projects/**", "Projects/2023/plan.md"));
+        assert!(!matches("Projects/**", "ProjectsArchive/plan.md"));
+        assert!(matches("Projects/*.md", "Projects/plan.md"));
+        assert!(!matches("Projects/*.md", "Projects/2023/plan.md"));
+        assert!(matches("**/meetings/*.md", "meetings/monday.md"));
+        assert!(matches("**/meetings/*.md", "Work/meetings/monday.md"));
+        assert!(!matches("**/meetings/*.md", "Work/meetings/notes.txt"));
+        assert!(matches("Daily/2024-0?-*.md", "Daily/2024-03-01.md"));
+        assert!(matches("**", "anything/at/all.md"));
+    }
+
+    #[test]
+    fn plain_prefixes() {
+        assert!(matches("Projects", "Projects/plan.md"));
+        assert!(matches("/Projects/", "Projects/2023/plan.md"));
+        assert!(matches("Projects/plan.md", "Projects/plan.md"));
+        assert!(!matches("Projects", "ProjectsArchive/plan.md"));
+        assert!(matches("notes (old)", "notes (old)/a.md"));
+    }
+}
@@ -0,0 +1,551 @@
+//! Native, headless use of the indexing pipeline against a folder of notes on disk.
+//!
+//! Only built with the `headless` feature. Inside Obsidian notes are read through the
+//! vault API, which only exists in wasm; `FolderVault` reads them from the filesystem
+//! instead, and `HeadlessIndex` runs them through the plugin's own `EmbeddingPipeline`,
+//! writing the store to memory rather than the vault, and ranks queries the same way the
+//! plugin's commands do. `examples/headless.rs` walks through a build, a query and an
+//! incremental update.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub use crate::mock::MockEmbeddingProvider;
+pub use crate::settings::Settings;
+
+use crate::Client;
+use crate::SemanticSearchError;
+use crate::canvas::{self, CANVAS_EXTENSION};
+use crate::embedding::{Embedding, EmbeddingInput, EmbeddingResponse, EmbeddingUsage};
+use crate::embedding_file::{read_embedding_file, EmbeddingFile};
+use crate::exclusions::{ExcludedFiles, USER_IGNORE_FILTERS_KEY};
+use crate::file_processor::StoreFiles;
+use crate::fusion::{self, ScoredCandidate};
+use crate::chunking::Chunking;
+use crate::generate_input::{drop_short_records, extract_section_records, MarkdownCleaning};
+use crate::input::{InputRecord, NoteTimes};
+use crate::maintenance::{self, NoteSet};
+use crate::manifest::RunRecorder;
+use crate::math;
+use crate::mock;
+use crate::pipeline::{EmbeddingPipeline, PipelineRecord};
+use crate::provenance::{fingerprint, fnv1a};
+use crate::providers::EmbeddingProvider;
+use crate::ranking;
+use crate::rate_limit::RateLimit;
+use crate::truncation::TruncationHook;
+
+/// Provider recorded in the provenance of records embedded headlessly
+pub const HEADLESS_PROVIDER: &str = "headless";
+
+/// Where the pipeline writes the records of an update before they are merged into the index
+const STAGING_PATH: &str = "embedding.csv.staging";
+
+/// A note read from disk
+#[derive(Debug, Clone)]
+pub struct VaultNote {
+    /// Path relative to the vault root, with `/` separators
+    pub path: String,
+    pub content: String,
+    /// Creation and modification times of the file, when the filesystem reports them
+    pub times: Option<NoteTimes>,
+}
+
+impl VaultNote {
+    /// The file name, which is how the plugin names stored sections
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+}
+
+/// Lists the markdown notes and canvases below a folder, standing in for Obsidian's vault
+pub struct FolderVault {
+    root: PathBuf,
+}
+
+impl FolderVault {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Reads every note, sorted by path. Hidden folders such as `.obsidian` are skipped,
+    /// as are the folders in the `ignored_folders` setting (one vault relative path per line)
+    /// and the files excluded in the vault's own settings.
+    pub fn read_notes(&self, ignored_folders: &str) -> io::Result<Vec<VaultNote>> {
+        let ignored: Vec<&str> = ignored_folders.lines().map(|folder| folder.trim().trim_matches('/')).filter(|folder| !folder.is_empty()).collect();
+        let excluded = self.excluded_files()?;
+        let mut notes = Vec::new();
+        self.collect(&self.root, &ignored, &excluded, &mut notes)?;
+        notes.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(notes)
+    }
+
+    /// The "Excluded files" saved in `.obsidian/app.json`, if the folder is an Obsidian vault
+    pub fn excluded_files(&self) -> io::Result<ExcludedFiles> {
+        let path = self.root.join(".obsidian").join("app.json");
+        if !path.is_file() {
+            return Ok(ExcludedFiles::default());
+        }
+        let config: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let filters: Vec<String> = config.get(USER_IGNORE_FILTERS_KEY)
+            .and_then(|filters| serde_json::from_value(filters.clone()).ok())
+            .unwrap_or_default();
+        Ok(ExcludedFiles::new(&filters))
+    }
+
+    fn collect(&self, dir: &Path, ignored: &[&str], excluded: &ExcludedFiles, notes: &mut Vec<VaultNote>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.')) {
+                continue;
+            }
+            let relative = path.strip_prefix(&self.root).unwrap_or(&path)
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if path.is_dir() {
+                if !ignored.contains(&relative.as_str()) && !excluded.is_folder_excluded(&relative) {
+                    self.collect(&path, ignored, excluded, notes)?;
+                }
+            } else if excluded.is_excluded(&relative) {
+                continue;
+            } else if path.extension().is_some_and(|extension| extension == "md" || extension == CANVAS_EXTENSION) {
+                let metadata = fs::metadata(&path)?;
+                let times = match (metadata.created().ok(), metadata.modified().ok()) {
+                    (Some(created), Some(modified)) => Some(NoteTimes { created: system_ms(created), modified: system_ms(modified) }),
+                    (None, Some(modified)) => Some(NoteTimes { created: system_ms(modified), modified: system_ms(modified) }),
+                    _ => None,
+                };
+                notes.push(VaultNote { path: relative, content: fs::read_to_string(&path)?, times });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Splits a note into input records the same way Generate Input does
+pub fn note_records(note: &VaultNote, settings: &Settings) -> Result<Vec<InputRecord>, SemanticSearchError> {
+    let name = note.name();
+    let mut records = if note.path.ends_with(&format!(".{}", CANVAS_EXTENSION)) {
+        let sections = canvas::canvas_sections(name, &note.content)?;
+        sections.into_iter().map(|(name, header, body)| InputRecord::new(name, header, body)).collect()
+    } else {
+        let text = MarkdownCleaning::from_settings(settings).clean(&note.content);
+        extract_section_records(name, &text, &settings.section_delimeter_regex, Chunking::from_settings(settings))
+    };
+    drop_short_records(&mut records, settings.min_section_chars);
+    records = records.into_iter().map(|record| record.with_path(&note.path)).collect();
+    if let Some(times) = note.times {
+        records = records.into_iter().map(|record| record.with_times(times)).collect();
+    }
+    Ok(records)
+}
+
+/// Turns texts into vectors, one per text in the same order
+pub trait Embedder {
+    fn model(&self) -> &str;
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, SemanticSearchError>;
+}
+
+/// Deterministic bag-of-words embedder that hashes each lowercased word into one of
+/// `dimensions` buckets. Only shared words make texts similar, but it needs no network,
+/// so the whole flow can run in tests. Implement `Embedder` over an HTTP client to use a real model.
+pub struct HashingEmbedder {
+    dimensions: usize,
+    model: String,
+}
+
+impl HashingEmbedder {
+    pub fn new(dimensions: usize) -> Self {
+        let dimensions = dimensions.max(1);
+        Self { dimensions, model: format!("hashing-{}", dimensions) }
+    }
+}
+
+impl Embedder for HashingEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, SemanticSearchError> {
+        Ok(texts.iter().map(|text| {
+            let mut embedding = vec![0.0; self.dimensions];
+            for word in text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()) {
+                embedding[(fnv1a(&word.to_lowercase()) % self.dimensions as u64) as usize] += 1.0;
+            }
+            embedding
+        }).collect())
+    }
+}
+
+/// The plugin's offline mock provider, so the pipeline can be run with the same vectors the
+/// plugin stores when the mock provider is picked
+impl Embedder for MockEmbeddingProvider {
+    fn model(&self) -> &str {
+        mock::MOCK_MODEL
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, SemanticSearchError> {
+        Ok(texts.iter().map(|text| mock::embed(text, mock::MOCK_DIMENSIONS)).collect())
+    }
+}
+
+/// Serves an `Embedder` as an offline provider, so the plugin's `Client` embeds with it
+struct EmbedderProvider(Box<dyn Embedder>);
+
+impl fmt::Debug for EmbedderProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("EmbedderProvider").field(&self.0.model()).finish()
+    }
+}
+
+impl EmbeddingProvider for EmbedderProvider {
+    fn name(&self) -> &str {
+        HEADLESS_PROVIDER
+    }
+
+    fn label(&self) -> &str {
+        "Headless"
+    }
+
+    fn api_base(&self) -> &str {
+        HEADLESS_PROVIDER
+    }
+
+    fn models(&self) -> Vec<String> {
+        vec![self.0.model().to_string()]
+    }
+
+    fn embed_offline(&self, model: &str, input: &EmbeddingInput) -> Option<Result<EmbeddingResponse, SemanticSearchError>> {
+        let EmbeddingInput::StringArray(texts) = input;
+        Some(self.0.embed(texts).map(|embeddings| EmbeddingResponse {
+            object: "list".to_string(),
+            model: model.to_string(),
+            data: embeddings.into_iter().enumerate()
+                .map(|(index, embedding)| Embedding { index: index as u32, object: "embedding".to_string(), embedding })
+                .collect(),
+            usage: EmbeddingUsage { prompt_tokens: 0, total_tokens: 0 },
+        }))
+    }
+}
+
+/// A client for `embedder`, recording `HEADLESS_PROVIDER` as the provider of what it embeds
+fn headless_client(embedder: impl Embedder + 'static) -> Client {
+    Client {
+        api_key: String::new(),
+        api_base: HEADLESS_PROVIDER.to_string(),
+        org_id: String::new(),
+        project_id: String::new(),
+        model: embedder.model().to_string(),
+        provider: Rc::new(EmbedderProvider(Box::new(embedder))),
+        rate_limit: RateLimit::default(),
+        request_timeout_ms: 0.0,
+    }
+}
+
+/// Keeps the files the pipeline writes in memory
+#[derive(Default)]
+struct MemoryFiles {
+    files: RefCell<HashMap<String, String>>,
+}
+
+impl MemoryFiles {
+    fn take(&self, path: &str) -> String {
+        self.files.borrow_mut().remove(path).unwrap_or_default()
+    }
+}
+
+impl StoreFiles for MemoryFiles {
+    async fn queue_append(&self, path: &str, data: &str) -> Result<(), SemanticSearchError> {
+        self.files.borrow_mut().entry(path.to_string()).or_default().push_str(data);
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), SemanticSearchError> {
+        Ok(())
+    }
+}
+
+/// What an update embedded, by note name
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UpdateReport {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+    pub records_embedded: usize,
+}
+
+/// An embedding store kept in memory and updated from the notes of a vault.
+///
+/// Like the plugin, sections are named by their note's file name, so notes with
+/// the same name in different folders are treated as one.
+pub struct HeadlessIndex {
+    settings: Settings,
+    client: Client,
+    store: EmbeddingFile,
+    /// Fingerprint of each note's content when it was last embedded
+    note_hashes: HashMap<String, String>,
+}
+
+impl HeadlessIndex {
+    pub fn new(settings: Settings, embedder: impl Embedder + 'static) -> Self {
+        Self { settings, client: headless_client(embedder), store: empty_store(), note_hashes: HashMap::new() }
+    }
+
+    /// Embeds every note into a new index
+    pub fn build(settings: Settings, notes: &[VaultNote], embedder: impl Embedder + 'static) -> Result<(Self, UpdateReport), SemanticSearchError> {
+        let mut index = Self::new(settings, embedder);
+        let report = index.update(notes)?;
+        Ok((index, report))
+    }
+
+    /// Embeds updates and queries with `embedder` from now on. If its model differs from the one
+    /// the index was built with, queries fail until the next update has re-embedded every note.
+    pub fn set_embedder(&mut self, embedder: impl Embedder + 'static) {
+        self.client = headless_client(embedder);
+    }
+
+    /// Re-embeds new and edited notes and drops deleted ones, keeping the records of unchanged notes.
+    /// Every note is re-embedded if the embedder's model differs from the one the index was built with.
+    pub fn update(&mut self, notes: &[VaultNote]) -> Result<UpdateReport, SemanticSearchError> {
+        let model = self.client.model().to_string();
+        let model_changed = self.store.metadata.as_ref().is_some_and(|metadata| metadata.model != model);
+        let mut report = UpdateReport::default();
+        let mut note_hashes = HashMap::new();
+        let mut records = Vec::new();
+        for note in notes {
+            let name = note.name().to_string();
+            let hash = fingerprint(&note.content);
+            let needs_embedding = match self.note_hashes.get(&name) {
+                Some(previous) if *previous == hash && !model_changed => false,
+                Some(_) => {
+                    report.changed.push(name.clone());
+                    true
+                }
+                None => {
+                    report.added.push(name.clone());
+                    true
+                }
+            };
+            if needs_embedding {
+                records.extend(note_records(note, &self.settings)?);
+            }
+            note_hashes.insert(name, hash);
+        }
+        report.removed = self.note_hashes.keys().filter(|name| !note_hashes.contains_key(*name)).cloned().collect();
+        report.removed.sort();
+
+        let expected_dimensions = self.store.metadata.as_ref().filter(|_| !model_changed).map(|metadata| metadata.dimensions);
+        let replacement = self.embed(records, expected_dimensions)?;
+        report.records_embedded = replacement.rows.len();
+
+        let replaced: Vec<String> = report.added.iter().chain(&report.changed).chain(&report.removed).cloned().collect();
+        let store = std::mem::replace(&mut self.store, empty_store());
+        self.store = maintenance::merge(store, &NoteSet::names(&replaced), replacement);
+        self.note_hashes = note_hashes;
+        Ok(report)
+    }
+
+    /// Runs `records` through the plugin's embedding pipeline, configured from the settings
+    /// like Generate Embeddings, and returns the store it wrote
+    fn embed(&self, records: Vec<InputRecord>, expected_dimensions: Option<usize>) -> Result<EmbeddingFile, SemanticSearchError> {
+        if records.is_empty() {
+            return Ok(empty_store());
+        }
+        let embed_text = self.settings.embed_text();
+        let records: Vec<PipelineRecord> = records.into_iter().map(|record| PipelineRecord::from_input(record, &embed_text)).collect();
+        let files = MemoryFiles::default();
+        let settings_hash = self.settings.embedding_settings_hash();
+        let mut pipeline = EmbeddingPipeline::new(&self.client, &files, STAGING_PATH, self.settings.num_batches, self.settings.max_tokens_per_request, embed_text, &settings_hash);
+        pipeline.clock(now_ms);
+        pipeline.quantize(self.settings.embedding_quantization);
+        pipeline.granularity(self.settings.embedding_granularity);
+        pipeline.truncate_dimensions(self.settings.embedding_dimensions);
+        if self.settings.max_tokens_per_record > 0 {
+            pipeline.add_hook(Box::new(TruncationHook::new(self.settings.max_tokens_per_record)));
+        }
+        if let Some(dimensions) = expected_dimensions {
+            pipeline.expect_dimensions(dimensions);
+        }
+        let mut recorder = RunRecorder::with_clock("headless update", now_ms);
+        block_on(pipeline.run_records(records, &mut recorder))?;
+        match files.take(STAGING_PATH) {
+            output if output.is_empty() => Ok(empty_store()),
+            output => read_embedding_file(&output),
+        }
+    }
+
+    /// Ranks the stored sections against `query`, best first, scoring and picking them like
+    /// the plugin's searches
+    pub fn query(&self, query: &str, limit: usize) -> Result<Vec<ScoredCandidate>, SemanticSearchError> {
+        self.store.check_model(self.client.model())?;
+        let query_embedding = block_on(self.client.embed_query(query))?;
+        let query_embedding = self.store.fit_query(query_embedding);
+        self.store.check_dimensions(query_embedding.len())?;
+        // vectors of texts without any words have no direction
+        let kept: Vec<usize> = (0..self.store.rows.len()).filter(|&i| !math::is_degenerate(&self.store.rows[i].2)).collect();
+        let mut scores: Vec<f32> = kept.iter().map(|&i| crate::cosine_similarity(&query_embedding, &self.store.rows[i].2)).collect();
+        fusion::normalize_scores(&mut scores, self.settings.score_normalization);
+        Ok(ranking::top_k(&scores, limit, |_| true).into_iter().map(|j| {
+            let (name, header, embedding) = &self.store.rows[kept[j]];
+            ScoredCandidate { name: name.clone(), header: header.clone(), score: scores[j], source: HEADLESS_PROVIDER.to_string(), embedding: embedding.clone() }
+        }).collect())
+    }
+
+    /// Number of stored sections
+    pub fn len(&self) -> usize {
+        self.store.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.rows.is_empty()
+    }
+
+    /// The index in the plugin's embedding file format, ready to be saved as `embedding.csv`
+    pub fn to_csv(&self) -> Result<String, SemanticSearchError> {
+        self.store.to_csv()
+    }
+}
+
+fn empty_store() -> EmbeddingFile {
+    EmbeddingFile { metadata: None, rows: Vec::new(), provenance: Vec::new() }
+}
+
+fn now_ms() -> f64 {
+    system_ms(SystemTime::now())
+}
+
+/// Runs `future` to completion on the current thread. Headless runs embed and write in memory,
+/// so the pipeline's futures never wait on anything and are ready the first time they are polled.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
+
+fn system_ms(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH).map_or(0.0, |elapsed| elapsed.as_millis() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(path: &str, content: &str) -> VaultNote {
+        VaultNote { path: path.to_string(), content: content.to_string(), times: None }
+    }
+
+    fn vault() -> Vec<VaultNote> {
+        vec![
+            note("garden/Soil.md", "# Soil\nCompost and mulch keep the soil healthy\n# Watering\nWater seedlings in the morning"),
+            note("code/Rust.md", "# Borrowing\nThe borrow checker enforces lifetimes"),
+        ]
+    }
+
+    fn settings() -> Settings {
+        Settings { section_delimeter_regex: "^#".to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn build_and_query() {
+        let (index, report) = HeadlessIndex::build(settings(), &vault(), HashingEmbedder::new(64)).unwrap();
+
+        assert_eq!(report.added, vec!["Soil.md", "Rust.md"]);
+        assert_eq!(index.len(), 3);
+        let results = index.query("compost mulch", 2).unwrap();
+        assert!(results[0].header.starts_with("Soil"));
+        assert_eq!(results.len(), 2);
+    }
+
+    #[test]
+    fn update_only_embeds_changes() {
+        let (mut index, _) = HeadlessIndex::build(settings(), &vault(), HashingEmbedder::new(64)).unwrap();
+
+        let unchanged = index.update(&vault()).unwrap();
+        assert_eq!(unchanged, UpdateReport::default());
+
+        let edited = vec![note("garden/Soil.md", "# Soil\nClay soil drains slowly"), note("Ideas.md", "# Ideas\nA greenhouse")];
+        let report = index.update(&edited).unwrap();
+        assert_eq!((report.added, report.changed, report.removed), (vec!["Ideas.md".to_string()], vec!["Soil.md".to_string()], vec!["Rust.md".to_string()]));
+        assert_eq!(report.records_embedded, 2);
+        assert_eq!(index.len(), 2);
+        assert!(index.query("clay", 1).unwrap()[0].header.starts_with("Soil"));
+    }
+
+    #[test]
+    fn model_change_reembeds_everything() {
+        let (mut index, _) = HeadlessIndex::build(settings(), &vault(), HashingEmbedder::new(64)).unwrap();
+
+        index.set_embedder(HashingEmbedder::new(32));
+        assert!(index.query("soil", 1).is_err());
+        let report = index.update(&vault()).unwrap();
+
+        assert_eq!(report.changed.len(), 2);
+        assert!(index.query("soil", 1).is_ok());
+        index.set_embedder(HashingEmbedder::new(64));
+        assert!(index.query("soil", 1).is_err());
+    }
+
+    #[test]
+    fn runs_the_pipeline_hooks() {
+        let (whole, _) = HeadlessIndex::build(settings(), &vault(), HashingEmbedder::new(64)).unwrap();
+        let (truncated, _) = HeadlessIndex::build(Settings { max_tokens_per_record: 3, ..settings() }, &vault(), HashingEmbedder::new(64)).unwrap();
+
+        // the truncation hook shortens the text sent for long sections, the stored text stays whole
+        assert_eq!(whole.store.rows[0].1, truncated.store.rows[0].1);
+        assert_ne!(whole.store.rows[0].2, truncated.store.rows[0].2);
+    }
+
+    #[test]
+    fn csv_round_trips() {
+        let (index, _) = HeadlessIndex::build(settings(), &vault(), HashingEmbedder::new(8)).unwrap();
+
+        let store = read_embedding_file(&index.to_csv().unwrap()).unwrap();
+
+        assert_eq!(store.rows.len(), 3);
+        assert_eq!(store.metadata.unwrap().model, "hashing-8");
+        assert_eq!(store.provenance[0].as_ref().unwrap().provider, HEADLESS_PROVIDER);
+    }
+
+    #[test]
+    fn mock_provider_builds_the_same_store_every_time() {
+        let (index, _) = HeadlessIndex::build(settings(), &vault(), MockEmbeddingProvider).unwrap();
+        let (again, _) = HeadlessIndex::build(settings(), &vault(), MockEmbeddingProvider).unwrap();
+
+        let (store, rebuilt) = (read_embedding_file(&index.to_csv().unwrap()).unwrap(), read_embedding_file(&again.to_csv().unwrap()).unwrap());
+        assert_eq!(store.rows.iter().map(|(name, header, _)| (name, header)).collect::<Vec<_>>(), rebuilt.rows.iter().map(|(name, header, _)| (name, header)).collect::<Vec<_>>());
+        assert_eq!(store.metadata.unwrap().model, mock::MOCK_MODEL);
+        assert!(index.query("watering seedlings", 1).unwrap()[0].header.starts_with("Watering"));
+    }
+
+    #[test]
+    fn folder_vault_reads_notes() {
+        let root = std::env::temp_dir().join(format!("semantic-search-headless-{}", std::process::id()));
+        for (path, content) in [("a.md", "# A"), ("sub/b.md", "# B"), ("sub/c.txt", "C"), ("archive/d.md", "# D"), (".obsidian/e.md", "# E"),
+            (".obsidian/app.json", r#"{"userIgnoreFilters": ["Templates/", "/\\.draft\\.md$/"]}"#), ("Templates/f.md", "# F"), ("sub/g.draft.md", "# G")] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        let notes = FolderVault::new(&root).read_notes("archive\n").unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(notes.iter().map(|note| note.path.as_str()).collect::<Vec<_>>(), vec!["a.md", "sub/b.md"]);
+        assert_eq!(notes[1].name(), "b.md");
+    }
+}
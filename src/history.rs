@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+
+use crate::FileProcessor;
+use crate::SemanticSearchError;
+
+/// Name of the file holding past and saved searches, stored next to input.csv
+pub const SEARCH_HISTORY_FILE_NAME: &str = "searches.json";
+
+/// Past queries kept, oldest are dropped first
+pub const MAX_HISTORY_ENTRIES: usize = 100;
+
+/// Results remembered for each past query
+pub const MAX_RESULTS_PER_ENTRY: usize = 5;
+
+/// A result shown for a past query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryResult {
+    pub name: String,
+    pub header: String,
+    pub score: f32,
+}
+
+/// A query that was searched, with when and what it found
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub query: String,
+    pub searched_at: f64,
+    pub results: Vec<HistoryResult>,
+}
+
+/// A query pinned under a name so it can be searched again
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedSearch {
+    pub name: String,
+    pub query: String,
+    pub saved_at: f64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchHistory {
+    /// Most recent first
+    #[serde(default)]
+    pub history: Vec<HistoryEntry>,
+    /// Sorted by name
+    #[serde(default)]
+    pub saved: Vec<SavedSearch>,
+}
+
+impl SearchHistory {
+    pub async fn load(file_processor: &FileProcessor, path: &str) -> Result<Self, SemanticSearchError> {
+        if !file_processor.check_file_exists_at_path(path).await? {
+            return Ok(Self::default());
+        }
+        let data = file_processor.read_from_path(path).await?;
+        serde_json::from_str(&data).map_err(SemanticSearchError::JSONDeserialize)
+    }
+
+    pub async fn save(&self, file_processor: &FileProcessor, path: &str) -> Result<(), SemanticSearchError> {
+        let data = serde_json::to_string_pretty(self).map_err(|e| SemanticSearchError::ConversionError(Box::new(e)))?;
+        file_processor.delete_file_at_path(path).await?;
+        file_processor.write_to_path(path, &data).await
+    }
+
+    /// Puts `entry` first, replacing an earlier search for the same query, and keeps its
+    /// top `MAX_RESULTS_PER_ENTRY` results. Blank queries are not recorded.
+    pub fn record(&mut self, mut entry: HistoryEntry) {
+        entry.query = entry.query.trim().to_string();
+        if entry.query.is_empty() {
+            return;
+        }
+        entry.results.truncate(MAX_RESULTS_PER_ENTRY);
+        self.history.retain(|past| past.query != entry.query);
+        self.history.insert(0, entry);
+        self.history.truncate(MAX_HISTORY_ENTRIES);
+    }
+
+    /// Saves `search`, replacing any saved search with the same name
+    pub fn save_search(&mut self, search: SavedSearch) -> Result<(), SemanticSearchError> {
+        if search.name.trim().is_empty() || search.query.trim().is_empty() {
+            return Err(SemanticSearchError::InvalidSettings("a saved search needs a name and a query".to_string()));
+        }
+        match self.saved.iter_mut().find(|saved| saved.name == search.name) {
+            Some(saved) => *saved = search,
+            None => self.saved.push(search),
+        }
+        self.saved.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(())
+    }
+
+    /// Removes the saved search called `name`, returning whether there was one
+    pub fn remove_search(&mut self, name: &str) -> bool {
+        let before = self.saved.len();
+        self.saved.retain(|saved| saved.name != name);
+        self.saved.len() != before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(query: &str, searched_at: f64, results: usize) -> HistoryEntry {
+        let results = (0..results).map(|i| HistoryResult { name: format!("note{}.md", i), header: "Header".to_string(), score: 1.0 - i as f32 / 10.0 }).collect();
+        HistoryEntry { query: query.to_string(), searched_at, results }
+    }
+
+    #[test]
+    fn record_keeps_latest_first() {
+        let mut history = SearchHistory::default();
+        history.record(entry("gardening", 1.0, 8));
+        history.record(entry("  ", 2.0, 1));
+        history.record(entry("recipes", 3.0, 0));
+        history.record(entry(" gardening ", 4.0, 1));
+
+        assert_eq!(history.history.iter().map(|e| (e.query.as_str(), e.searched_at)).collect::<Vec<_>>(), vec![("gardening", 4.0), ("recipes", 3.0)]);
+        assert_eq!(history.history[0].results.len(), 1);
+
+        for i in 0..MAX_HISTORY_ENTRIES + 5 {
+            history.record(entry(&format!("query {}", i), i as f64, MAX_RESULTS_PER_ENTRY + 1));
+        }
+        assert_eq!(history.history.len(), MAX_HISTORY_ENTRIES);
+        assert_eq!(history.history[0].results.len(), MAX_RESULTS_PER_ENTRY);
+    }
+
+    #[test]
+    fn saved_searches_by_name() {
+        let mut history = SearchHistory::default();
+        history.save_search(SavedSearch { name: "work".to_string(), query: "meeting notes".to_string(), saved_at: 1.0 }).unwrap();
+        history.save_search(SavedSearch { name: "books".to_string(), query: "reading list".to_string(), saved_at: 2.0 }).unwrap();
+        history.save_search(SavedSearch { name: "work".to_string(), query: "project plans".to_string(), saved_at: 3.0 }).unwrap();
+
+        assert_eq!(history.saved.iter().map(|s| (s.name.as_str(), s.query.as_str())).collect::<Vec<_>>(), vec![("books", "reading list"), ("work", "project plans")]);
+        assert!(history.save_search(SavedSearch { name: String::new(), query: "anything".to_string(), saved_at: 4.0 }).is_err());
+        assert!(history.remove_search("books"));
+        assert!(!history.remove_search("books"));
+        assert_eq!(history.saved.len(), 1);
+    }
+
+    #[test]
+    fn loads_files_without_saved_searches() {
+        let history: SearchHistory = serde_json::from_str(r#"{"history": [{"query": "q", "searchedAt": 1.0, "results": []}]}"#).unwrap();
+
+        assert_eq!(history.history.len(), 1);
+        assert!(history.saved.is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+use std::cell::RefCell;
+
+use js_sys::{Function, Promise};
+use log::{debug, error};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+use crate::embedding_file::EmbeddingFile;
+use crate::SemanticSearchError;
+
+thread_local! {
+    static POST_BUILD_HOOKS: RefCell<Vec<Function>> = RefCell::new(Vec::new());
+}
+
+/// A single stored chunk handed to post-build hooks
+#[wasm_bindgen]
+pub struct IndexRecord {
+    name: String,
+    header: String,
+    embedding: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl IndexRecord {
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn header(&self) -> String {
+        self.header.clone()
+    }
+
+    /// Returns a copy of the embedding, so hooks cannot modify the store
+    #[wasm_bindgen(getter)]
+    pub fn embedding(&self) -> Vec<f32> {
+        self.embedding.clone()
+    }
+}
+
+/// Read-only cursor over the records of a freshly built store.
+/// `next()` returns `undefined` once every record has been visited.
+#[wasm_bindgen]
+pub struct IndexRecordIterator {
+    model: Option<String>,
+    rows: Vec<(String, String, Vec<f32>)>,
+    position: usize,
+}
+
+impl IndexRecordIterator {
+    pub fn new(store: EmbeddingFile) -> Self {
+        Self {
+            model: store.metadata.map(|metadata| metadata.model),
+            rows: store.rows,
+            position: 0,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl IndexRecordIterator {
+    #[wasm_bindgen(js_name = next)]
+    pub fn next_record(&mut self) -> Option<IndexRecord> {
+        let (name, header, embedding) = self.rows.get(self.position)?.clone();
+        self.position += 1;
+        Some(IndexRecord { name, header, embedding })
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Model the store was built with, if the file records it
+    #[wasm_bindgen(getter)]
+    pub fn model(&self) -> Option<String> {
+        self.model.clone()
+    }
+}
+
+/// Registers `callback` to be called with an `IndexRecordIterator` after every successful build.
+/// The callback may return a promise, which is awaited before the next hook runs.
+#[wasm_bindgen(js_name = registerPostBuildHook)]
+pub fn register_post_build_hook(callback: Function) {
+    POST_BUILD_HOOKS.with(|hooks| hooks.borrow_mut().push(callback));
+}
+
+#[wasm_bindgen(js_name = unregisterPostBuildHook)]
+pub fn unregister_post_build_hook(callback: &Function) {
+    POST_BUILD_HOOKS.with(|hooks| hooks.borrow_mut().retain(|hook| hook != callback));
+}
+
+pub fn clear_post_build_hooks() {
+    POST_BUILD_HOOKS.with(|hooks| hooks.borrow_mut().clear());
+}
+
+/// Runs every registered hook against `store`, returning a warning for each hook that failed.
+/// A failing hook never fails the build that triggered it.
+pub async fn run_post_build_hooks(store: &EmbeddingFile) -> Vec<String> {
+    let hooks = POST_BUILD_HOOKS.with(|hooks| hooks.borrow().clone());
+    let mut warnings = Vec::new();
+    for (index, hook) in hooks.iter().enumerate() {
+        let iterator = IndexRecordIterator::new(EmbeddingFile { metadata: store.metadata.clone(), rows: store.rows.clone(), provenance: store.provenance.clone() });
+        if let Err(e) = call_hook(hook, iterator).await {
+            error!("Post-build hook {} failed: {}", index, e);
+            warnings.push(format!("post-build hook {} failed; {}", index, e));
+        }
+    }
+    debug!("Ran {} post-build hooks", hooks.len());
+    warnings
+}
+
+pub fn has_post_build_hooks() -> bool {
+    POST_BUILD_HOOKS.with(|hooks| !hooks.borrow().is_empty())
+}
+
+async fn call_hook(hook: &Function, iterator: IndexRecordIterator) -> Result<(), SemanticSearchError> {
+    let result = hook.call1(&JsValue::NULL, &iterator.into())?;
+    if let Some(promise) = result.dyn_ref::<Promise>() {
+        JsFuture::from(promise.clone()).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding_file::StoreMetadata;
+
+    #[test]
+    fn iterator_visits_each_record_once() {
+        let store = EmbeddingFile {
+            metadata: Some(StoreMetadata::new("test-model", 2, 0.0)),
+            rows: vec![
+                ("a.md".to_string(), "a".to_string(), vec![1.0, 0.0]),
+                ("b.md".to_string(), "b".to_string(), vec![0.0, 1.0]),
+            ],
+            provenance: vec![None, None],
+        };
+        let mut iterator = IndexRecordIterator::new(store);
+        assert_eq!(iterator.length(), 2);
+        assert_eq!(iterator.model().as_deref(), Some("test-model"));
+
+        let first = iterator.next_record().unwrap();
+        assert_eq!((first.name(), first.header(), first.embedding()), ("a.md".to_string(), "a".to_string(), vec![1.0, 0.0]));
+        assert_eq!(iterator.next_record().unwrap().name(), "b.md");
+        assert!(iterator.next_record().is_none());
+    }
+}
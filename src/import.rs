@@ -0,0 +1,317 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::SemanticSearchError;
+
+/// Index formats of other semantic search tools that can be converted into an embedding file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportFormat {
+    /// Smart Connections' `.smart-connections/embeddings-2.json`
+    SmartConnections,
+    /// khoj entries exported as JSON lines, one entry with its embedding per line
+    Khoj,
+    /// Embeddings computed elsewhere as JSON lines of `{name, header, vector}` or `{id, text, vector}`,
+    /// as written by the JSON lines export
+    Jsonl,
+    /// A float32 or float64 `.npy` matrix with a `.manifest.json` next to it naming each row,
+    /// as written by the NumPy export
+    Npy,
+}
+
+impl ImportFormat {
+    /// Model the format's default index is built with, if it is fixed
+    pub fn default_model(&self) -> Option<&'static str> {
+        match self {
+            ImportFormat::SmartConnections => Some("text-embedding-ada-002"),
+            ImportFormat::Khoj | ImportFormat::Jsonl | ImportFormat::Npy => None,
+        }
+    }
+}
+
+/// A chunk read from another tool's index
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedChunk {
+    pub name: String,
+    pub header: String,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub imported: usize,
+    /// Entries without a name or vector, or with a vector of the wrong size
+    pub skipped: usize,
+    /// Stored records dropped because an imported note replaced them, when merging
+    pub replaced: usize,
+    pub path: String,
+}
+
+#[derive(Deserialize)]
+struct SmartConnectionsEntry {
+    vec: Option<Vec<f32>>,
+    meta: SmartConnectionsMeta,
+}
+
+#[derive(Deserialize)]
+struct SmartConnectionsMeta {
+    /// `folder/note.md` for whole notes or `folder/note.md#Heading#Subheading` for blocks
+    path: String,
+}
+
+/// Subset of khoj's `Entry` model
+#[derive(Deserialize)]
+struct KhojEntry {
+    #[serde(alias = "file")]
+    file_path: String,
+    #[serde(default)]
+    heading: Option<String>,
+    #[serde(default)]
+    compiled: String,
+    #[serde(alias = "embedding")]
+    embeddings: Option<Vec<f32>>,
+}
+
+/// A line of a JSON lines import. Records are named by `name`, or by an `id` of the form
+/// `name#...` as written by the export, and stored under their `text`, falling back to `header`
+/// and then the rest of the id when the tool does not keep the text.
+#[derive(Deserialize)]
+struct JsonlEntry {
+    #[serde(flatten)]
+    key: EntryKey,
+    #[serde(alias = "embedding")]
+    vector: Option<Vec<f32>>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct EntryKey {
+    id: Option<String>,
+    name: Option<String>,
+    header: Option<String>,
+    text: Option<String>,
+}
+
+impl EntryKey {
+    /// The note name and header the entry is stored under; an empty name means the entry cannot be stored
+    fn resolve(self) -> (String, String) {
+        let (id_name, id_header) = match &self.id {
+            Some(id) => match id.split_once('#') {
+                Some((name, header)) => (Some(name.to_string()), Some(header.to_string())),
+                None => (Some(id.clone()), None),
+            },
+            None => (None, None),
+        };
+        let name = self.name.or(id_name).unwrap_or_default();
+        let header = self.text.or(self.header).or(id_header).unwrap_or_default();
+        (name.trim().to_string(), header)
+    }
+}
+
+/// Describes the rows of an `.npy` import, in order
+#[derive(Deserialize)]
+struct NpyManifest {
+    #[serde(default)]
+    model: Option<String>,
+    rows: Vec<EntryKey>,
+}
+
+/// The manifest read alongside the `.npy` file at `path`
+pub fn npy_manifest_path(path: &str) -> String {
+    format!("{}.manifest.json", path.strip_suffix(".npy").unwrap_or(path))
+}
+
+/// Parses another tool's index, returning the usable chunks and the number of skipped entries.
+///
+/// Chunks are keyed like this plugin's own store: the note's file name, and the
+/// section text (or heading when the tool does not keep the text) as the header.
+pub fn parse(format: ImportFormat, data: &str) -> Result<(Vec<ImportedChunk>, usize), SemanticSearchError> {
+    let entries: Vec<(String, String, Option<Vec<f32>>)> = match format {
+        ImportFormat::SmartConnections => {
+            let index: BTreeMap<String, SmartConnectionsEntry> = serde_json::from_str(data).map_err(SemanticSearchError::JSONDeserialize)?;
+            index.into_values().map(|entry| {
+                let (path, heading) = entry.meta.path.split_once('#').unwrap_or((&entry.meta.path, ""));
+                let heading = heading.rsplit('#').next().unwrap_or_default().to_string();
+                (file_name(path), heading, entry.vec)
+            }).collect()
+        }
+        ImportFormat::Khoj => data.lines().filter(|line| !line.trim().is_empty()).map(|line| {
+            let entry: KhojEntry = serde_json::from_str(line).map_err(SemanticSearchError::JSONDeserialize)?;
+            let header = if entry.compiled.trim().is_empty() { entry.heading.unwrap_or_default() } else { entry.compiled };
+            Ok((file_name(&entry.file_path), header, entry.embeddings))
+        }).collect::<Result<_, SemanticSearchError>>()?,
+        ImportFormat::Jsonl => data.lines().filter(|line| !line.trim().is_empty()).map(|line| {
+            let entry: JsonlEntry = serde_json::from_str(line).map_err(SemanticSearchError::JSONDeserialize)?;
+            let (name, header) = entry.key.resolve();
+            Ok((name, header, entry.vector))
+        }).collect::<Result<_, SemanticSearchError>>()?,
+        ImportFormat::Npy => return Err(SemanticSearchError::InvalidArgument("NumPy matrices are binary, read them with parse_npy".to_string())),
+    };
+    Ok(usable_chunks(entries))
+}
+
+/// Parses an `.npy` matrix and the manifest naming its rows, returning the chunks and the model
+/// the manifest says they were embedded with
+pub fn parse_npy(data: &[u8], manifest: &str) -> Result<(Vec<ImportedChunk>, usize, Option<String>), SemanticSearchError> {
+    let invalid = |reason: &str| SemanticSearchError::InvalidArgument(format!("Invalid .npy file: {}", reason));
+    if data.len() < 10 || &data[..6] != b"\x93NUMPY" {
+        return Err(invalid("missing the NUMPY magic string"));
+    }
+    let (header_len, start) = match data[6] {
+        1 => (u16::from_le_bytes([data[8], data[9]]) as usize, 10),
+        2 | 3 if data.len() >= 12 => (u32::from_le_bytes([data[8], data[9], data[10], data[11]]) as usize, 12),
+        version => return Err(invalid(&format!("unsupported format version {}", version))),
+    };
+    let header = data.get(start..start + header_len).and_then(|header| std::str::from_utf8(header).ok())
+        .ok_or_else(|| invalid("truncated header"))?;
+    let width = match npy_field(header, "descr") {
+        Some(descr) if descr.starts_with("'<f4'") => 4,
+        Some(descr) if descr.starts_with("'<f8'") => 8,
+        _ => return Err(invalid("only little-endian float32 and float64 matrices are supported")),
+    };
+    if !npy_field(header, "fortran_order").is_some_and(|order| order.starts_with("False")) {
+        return Err(invalid("Fortran ordered matrices are not supported"));
+    }
+    let shape: Vec<usize> = npy_field(header, "shape")
+        .and_then(|shape| shape.strip_prefix('('))
+        .and_then(|shape| shape.split(')').next())
+        .map(|shape| shape.split(',').map(str::trim).filter(|size| !size.is_empty()).filter_map(|size| size.parse().ok()).collect())
+        .unwrap_or_default();
+    let (rows, dimensions) = match shape[..] {
+        [rows, dimensions] if dimensions > 0 => (rows, dimensions),
+        _ => return Err(invalid("expected a matrix with one row per embedding")),
+    };
+    let values = &data[start + header_len..];
+    if values.len() != rows * dimensions * width {
+        return Err(invalid(&format!("expected {} bytes of data for a {}x{} matrix but found {}", rows * dimensions * width, rows, dimensions, values.len())));
+    }
+
+    let manifest: NpyManifest = serde_json::from_str(manifest).map_err(SemanticSearchError::JSONDeserialize)?;
+    if manifest.rows.len() != rows {
+        return Err(SemanticSearchError::InvalidArgument(format!("The manifest lists {} rows but the matrix has {}", manifest.rows.len(), rows)));
+    }
+    let entries = manifest.rows.into_iter().zip(values.chunks_exact(dimensions * width)).map(|(key, row)| {
+        let embedding = row.chunks_exact(width).map(|value| match value {
+            [a, b, c, d] => f32::from_le_bytes([*a, *b, *c, *d]),
+            [a, b, c, d, e, f, g, h] => f64::from_le_bytes([*a, *b, *c, *d, *e, *f, *g, *h]) as f32,
+            _ => unreachable!("chunks are 4 or 8 bytes"),
+        }).collect();
+        let (name, header) = key.resolve();
+        (name, header, Some(embedding))
+    }).collect();
+    let (chunks, skipped) = usable_chunks(entries);
+    Ok((chunks, skipped, manifest.model))
+}
+
+/// The text following `'key':` in an `.npy` header dictionary
+fn npy_field<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let (_, rest) = header.split_once(&format!("'{}':", key))?;
+    Some(rest.trim_start())
+}
+
+/// Keeps the entries with a note name and a vector of the same size as the first, counting the rest as skipped
+fn usable_chunks(entries: Vec<(String, String, Option<Vec<f32>>)>) -> (Vec<ImportedChunk>, usize) {
+    let dimensions = entries.iter().find_map(|(_, _, embedding)| embedding.as_ref().map(|embedding| embedding.len()));
+    let total = entries.len();
+    let chunks: Vec<ImportedChunk> = entries.into_iter().filter_map(|(name, header, embedding)| match embedding {
+        Some(embedding) if !name.is_empty() && !embedding.is_empty() && Some(embedding.len()) == dimensions => Some(ImportedChunk { name, header, embedding }),
+        _ => None,
+    }).collect();
+    let skipped = total - chunks.len();
+    (chunks, skipped)
+}
+
+fn file_name(path: &str) -> String {
+    path.rsplit(['/', '\\']).next().unwrap_or(path).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smart_connections() {
+        let data = r#"{
+            "a1": {"vec": [0.1, 0.2], "meta": {"path": "Projects/plan.md", "mtime": 1}},
+            "b2": {"vec": [0.3, 0.4], "meta": {"path": "Projects/plan.md#Goals#Q3", "len": 10}},
+            "c3": {"meta": {"path": "empty.md"}}
+        }"#;
+
+        let (chunks, skipped) = parse(ImportFormat::SmartConnections, data).unwrap();
+
+        assert_eq!(skipped, 1);
+        assert_eq!(chunks, vec![
+            ImportedChunk { name: "plan.md".to_string(), header: "".to_string(), embedding: vec![0.1, 0.2] },
+            ImportedChunk { name: "plan.md".to_string(), header: "Q3".to_string(), embedding: vec![0.3, 0.4] },
+        ]);
+    }
+
+    #[test]
+    fn khoj_jsonl() {
+        let data = "{\"file_path\": \"/vault/notes/idea.md\", \"heading\": \"# Idea\", \"compiled\": \"Idea. Something new\", \"embeddings\": [1.0, 0.0]}\n\
+                    \n\
+                    {\"file\": \"other.md\", \"heading\": \"# Other\", \"embedding\": [0.0, 1.0]}\n\
+                    {\"file\": \"wrong.md\", \"embedding\": [0.0, 1.0, 2.0]}\n";
+
+        let (chunks, skipped) = parse(ImportFormat::Khoj, data).unwrap();
+
+        assert_eq!(skipped, 1);
+        assert_eq!(chunks[0].name, "idea.md");
+        assert_eq!(chunks[0].header, "Idea. Something new");
+        assert_eq!(chunks[1].header, "# Other");
+    }
+
+    #[test]
+    fn jsonl_keys() {
+        let data = "{\"name\": \"idea.md\", \"header\": \"Idea\", \"vector\": [1.0, 0.0]}\n\
+                    {\"id\": \"plan.md#Goals\", \"text\": \"Goals and more\", \"vector\": [0.0, 1.0]}\n\
+                    {\"id\": \"readwise/1\", \"text\": \"A highlight\", \"embedding\": [0.5, 0.5]}\n\
+                    {\"text\": \"no name\", \"vector\": [0.5, 0.5]}\n";
+
+        let (chunks, skipped) = parse(ImportFormat::Jsonl, data).unwrap();
+
+        assert_eq!(skipped, 1);
+        assert_eq!((chunks[0].name.as_str(), chunks[0].header.as_str()), ("idea.md", "Idea"));
+        assert_eq!((chunks[1].name.as_str(), chunks[1].header.as_str()), ("plan.md", "Goals and more"));
+        assert_eq!((chunks[2].name.as_str(), chunks[2].header.as_str()), ("readwise/1", "A highlight"));
+    }
+
+    fn npy(descr: &str, shape: &str, values: &[u8]) -> Vec<u8> {
+        let header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}\n", descr, shape);
+        let mut data = b"\x93NUMPY\x01\x00".to_vec();
+        data.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        data.extend_from_slice(header.as_bytes());
+        data.extend_from_slice(values);
+        data
+    }
+
+    #[test]
+    fn npy_matrix() {
+        let values: Vec<u8> = [1.0f32, 0.0, 0.25, -1.0].iter().flat_map(|value| value.to_le_bytes()).collect();
+        let manifest = r#"{"model": "nomic-embed-text", "dimensions": 2, "count": 2, "rows": [{"id": "a.md#A", "name": "a.md", "header": "A"}, {"name": "b.md", "header": "B"}]}"#;
+
+        let (chunks, skipped, model) = parse_npy(&npy("<f4", "(2, 2)", &values), manifest).unwrap();
+
+        assert_eq!(skipped, 0);
+        assert_eq!(model.as_deref(), Some("nomic-embed-text"));
+        assert_eq!(chunks[1], ImportedChunk { name: "b.md".to_string(), header: "B".to_string(), embedding: vec![0.25, -1.0] });
+
+        let doubles: Vec<u8> = [0.5f64, 2.0].iter().flat_map(|value| value.to_le_bytes()).collect();
+        let (chunks, _, _) = parse_npy(&npy("<f8", "(1, 2)", &doubles), r#"{"rows": [{"name": "c.md"}]}"#).unwrap();
+        assert_eq!(chunks[0].embedding, vec![0.5, 2.0]);
+    }
+
+    #[test]
+    fn invalid_npy() {
+        let values = [0u8; 16];
+        let manifest = r#"{"rows": [{"name": "a.md"}, {"name": "b.md"}]}"#;
+
+        assert!(parse_npy(&npy("<f4", "(2, 2)", &values), manifest).is_ok());
+        assert!(parse_npy(&npy("<f4", "(2, 2)", &values[..12]), manifest).is_err());
+        assert!(parse_npy(&npy("<i4", "(2, 2)", &values), manifest).is_err());
+        assert!(parse_npy(&npy("<f4", "(4,)", &values), manifest).is_err());
+        assert!(parse_npy(&npy("<f4", "(2, 2)", &values), r#"{"rows": [{"name": "a.md"}]}"#).is_err());
+        assert!(parse_npy(b"not numpy", manifest).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use js_sys::JsString;
+use log::debug;
+use wasm_bindgen::prelude::*;
+
+use crate::{GenerateEmbeddingsCommand, QueryCommand};
+use crate::SemanticSearchError;
+use crate::embedding_file::EmbeddingFile;
+use crate::generate_input::GenerateInputCommand;
+use crate::negative;
+use crate::obsidian;
+use crate::quantization::QuantizedVector;
+use crate::reindex::{Progress, ReindexReport, ReindexStage};
+use crate::session::{QueryFilters, QuerySession};
+use crate::settings::Settings;
+
+/// A parsed embedding file and the modification time it was read at
+struct ResidentStore {
+    modified_at: Option<f64>,
+    store: Rc<EmbeddingFile>,
+    /// The stored vectors of int8 stores as written, scored without dequantizing. The rows of
+    /// such stores have no f32 embeddings.
+    quantized: Option<Rc<Vec<QuantizedVector>>>,
+}
+
+/// Embeddings kept in memory between searches, so repeated queries skip reading and parsing the embedding file.
+///
+/// The plugin creates one on load. `query` loads the configured store on first use and
+/// re-reads it whenever it changed on disk, so a finished Generate Embeddings run is
+/// picked up by the next search. Queries in a language embedded with its own model search
+/// that language's store, which is kept alongside. The last query's embeddings are cached
+/// as in `QuerySession`.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct SemanticIndex {
+    /// Resident stores by path
+    resident: RefCell<HashMap<String, ResidentStore>>,
+    session: QuerySession,
+}
+
+#[wasm_bindgen]
+impl SemanticIndex {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> SemanticIndex {
+        Self::default()
+    }
+
+    /// Reads and parses the configured embedding file, returning the number of stored chunks
+    pub async fn load(&self, app: &obsidian::App, settings: JsValue) -> Result<usize, SemanticSearchError> {
+        let settings = Settings::from_js(&settings)?;
+        let query_cmd = QueryCommand::new(app, &settings);
+        self.forget_unconfigured(&settings);
+        Ok(self.load_store(&query_cmd).await?.store.rows.len())
+    }
+
+    /// Re-reads the embedding file if it changed since it was loaded or another store is configured.
+    /// Returns whether it was re-read.
+    pub async fn refresh(&self, app: &obsidian::App, settings: JsValue) -> Result<bool, SemanticSearchError> {
+        let settings = Settings::from_js(&settings)?;
+        let query_cmd = QueryCommand::new(app, &settings);
+        self.forget_unconfigured(&settings);
+        Ok(self.refresh_store(&query_cmd).await?.1)
+    }
+
+    /// Returns suggestions for `query` among the chunks matching `filters`, using the resident embeddings
+    pub async fn query(&self, app: &obsidian::App, settings: JsValue, query: JsString, filters: JsValue) -> Result<JsValue, SemanticSearchError> {
+        let settings = Settings::from_js(&settings)?;
+        let mut filters = QueryFilters::from_js(&filters)?;
+        let query = query.as_string().unwrap_or_default();
+        let mut query_cmd = QueryCommand::new(app, &settings);
+        self.forget_unconfigured(&settings);
+        query_cmd.route_language(&negative::split_query(&query).0).await?;
+        filters.resolve(&query_cmd.file_processor).await?;
+
+        let (resident, _) = self.refresh_store(&query_cmd).await?;
+        resident.store.check_model(query_cmd.client.model())?;
+        let quantized = resident.quantized.as_deref().map(Vec::as_slice);
+        let ranked_suggestions = self.session.search_store(&query_cmd, &resident.store, quantized, &query, &filters).await?;
+        query_cmd.suggestions_value(&ranked_suggestions)
+    }
+
+    /// Rebuilds the index from the vault: generates input.csv, embeds it and loads the result into
+    /// memory, returning a `ReindexReport`. `progress`, if given, is called with a `ReindexProgress`
+    /// as each file is read and before each batch is sent. The embeddings are always generated
+    /// right away, also when the batch API is enabled. The first stage to fail stops the reindex
+    /// with its error, leaving the files of the stages before it in place.
+    pub async fn reindex_vault(&self, app: &obsidian::App, settings: JsValue, progress: Option<js_sys::Function>) -> Result<JsValue, SemanticSearchError> {
+        let progress = Progress::new(progress);
+        let input_cmd = GenerateInputCommand::new(app.clone(), settings.clone())?;
+        let (input_records, skipped_sections) = input_cmd.write_input(&progress).await?;
+
+        let mut embeddings_cmd = GenerateEmbeddingsCommand::new(app.clone(), settings.clone())?;
+        embeddings_cmd.report_progress(progress.clone());
+        let embeddings = embeddings_cmd.embed().await?;
+
+        let settings = Settings::from_js(&settings)?;
+        let query_cmd = QueryCommand::new(app, &settings);
+        progress.report(ReindexStage::Index, 0, embeddings.records_embedded);
+        let indexed = self.load_store(&query_cmd).await?.store.rows.len();
+        progress.report(ReindexStage::Index, indexed, indexed);
+        debug!("Reindexed {} sections into {} chunks", input_records, indexed);
+        Ok(serde_wasm_bindgen::to_value(&ReindexReport { input_records, skipped_sections, embeddings, indexed })?)
+    }
+
+    /// Number of chunks held in memory, 0 if nothing is loaded
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.resident.borrow().values().map(|resident| resident.store.rows.len()).sum()
+    }
+
+    /// Frees the resident embeddings until the next load or query
+    pub fn clear(&self) {
+        self.resident.borrow_mut().clear();
+    }
+}
+
+/// What a search needs from the resident store, cloned out so no borrow is held across an await
+struct ResidentView {
+    store: Rc<EmbeddingFile>,
+    quantized: Option<Rc<Vec<QuantizedVector>>>,
+}
+
+impl SemanticIndex {
+    async fn load_store(&self, query_cmd: &QueryCommand) -> Result<ResidentView, SemanticSearchError> {
+        let modified_at = query_cmd.file_processor.modified_at(&query_cmd.embedding_path).await?;
+        let (store, quantized) = query_cmd.load_searchable_store().await?;
+        let (store, quantized) = (Rc::new(store), quantized.map(Rc::new));
+        debug!("Loaded {} embeddings from {} into memory", store.rows.len(), query_cmd.embedding_path);
+        self.resident.borrow_mut().insert(query_cmd.embedding_path.clone(), ResidentStore { modified_at, store: store.clone(), quantized: quantized.clone() });
+        Ok(ResidentView { store, quantized })
+    }
+
+    /// Frees the stores no search can be routed to with `settings` any more, such as those of
+    /// a language whose model was removed
+    fn forget_unconfigured(&self, settings: &Settings) {
+        let configured: HashSet<String> = std::iter::once(settings.embedding_path())
+            .chain(settings.language_routes().iter().map(|route| settings.language_embedding_path(&route.language)))
+            .collect();
+        self.resident.borrow_mut().retain(|path, _| configured.contains(path));
+    }
+
+    /// The resident store, re-read first if it is stale. The flag is set if it was re-read.
+    async fn refresh_store(&self, query_cmd: &QueryCommand) -> Result<(ResidentView, bool), SemanticSearchError> {
+        let modified_at = query_cmd.file_processor.modified_at(&query_cmd.embedding_path).await?;
+        let current = self.resident.borrow().get(&query_cmd.embedding_path)
+            .filter(|resident| modified_at.is_some() && resident.modified_at == modified_at)
+            .map(|resident| ResidentView { store: resident.store.clone(), quantized: resident.quantized.clone() });
+        match current {
+            Some(view) => Ok((view, false)),
+            None => Ok((self.load_store(query_cmd).await?, true)),
+        }
+    }
+}
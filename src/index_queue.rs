@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::FileProcessor;
+use crate::SemanticSearchError;
+
+/// Name of the file holding changes not yet auto-indexed, stored next to input.csv
+pub const INDEX_QUEUE_FILE_NAME: &str = "index_queue.json";
+
+/// What happened to a queued path, the latest event winning
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Change {
+    /// Created or modified, so the file is read again
+    Modified,
+    Deleted,
+}
+
+/// Vault paths changed since the last auto-index update, one entry per path.
+///
+/// The queue is saved after every change and only cleared once an update has gone through,
+/// so changes interrupted by a crash, a closed vault or a failed request are picked up on the next load.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IndexQueue {
+    changes: BTreeMap<String, Change>,
+    /// When the last change came in, so updates wait for the vault to settle
+    #[serde(default)]
+    last_change_at: f64,
+}
+
+impl IndexQueue {
+    pub const fn new() -> Self {
+        Self { changes: BTreeMap::new(), last_change_at: 0.0 }
+    }
+
+    pub async fn load(file_processor: &FileProcessor, path: &str) -> Result<Self, SemanticSearchError> {
+        if !file_processor.check_file_exists_at_path(path).await? {
+            return Ok(Self::default());
+        }
+        let data = file_processor.read_from_path(path).await?;
+        serde_json::from_str(&data).map_err(SemanticSearchError::JSONDeserialize)
+    }
+
+    pub async fn save(&self, file_processor: &FileProcessor, path: &str) -> Result<(), SemanticSearchError> {
+        file_processor.delete_file_at_path(path).await?;
+        if self.changes.is_empty() {
+            return Ok(());
+        }
+        let data = serde_json::to_string_pretty(self).map_err(|e| SemanticSearchError::ConversionError(Box::new(e)))?;
+        file_processor.write_to_path(path, &data).await
+    }
+
+    pub fn record(&mut self, path: &str, change: Change, now: f64) {
+        self.changes.insert(path.to_string(), change);
+        self.last_change_at = now;
+    }
+
+    /// Milliseconds from `now` until no change has come in for `delay_ms`
+    pub fn wait(&self, now: f64, delay_ms: f64) -> f64 {
+        (self.last_change_at + delay_ms - now).max(0.0)
+    }
+
+    pub fn take(&mut self) -> BTreeMap<String, Change> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Takes the first `count` changes by path, leaving the rest queued
+    pub fn take_up_to(&mut self, count: usize) -> BTreeMap<String, Change> {
+        let rest = match self.changes.keys().nth(count).cloned() {
+            Some(first_left) => self.changes.split_off(&first_left),
+            None => BTreeMap::new(),
+        };
+        std::mem::replace(&mut self.changes, rest)
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Adds changes that could not be applied, or were read back from disk, unless the path changed again since
+    pub fn requeue(&mut self, changes: BTreeMap<String, Change>, now: f64) {
+        for (path, change) in changes {
+            self.changes.entry(path).or_insert(change);
+        }
+        self.last_change_at = self.last_change_at.max(now);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// The queue as it is saved while `in_flight` changes are being applied: they stay in the
+    /// saved queue until the update succeeds, unless the path changed again since
+    pub fn with_in_flight(&self, in_flight: &BTreeMap<String, Change>) -> Self {
+        let mut changes = self.changes.clone();
+        for (path, change) in in_flight {
+            changes.entry(path.clone()).or_insert(*change);
+        }
+        Self { changes, last_change_at: self.last_change_at }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_the_last_change() {
+        let mut queue = IndexQueue::default();
+        queue.record("a.md", Change::Modified, 1000.0);
+        queue.record("b.md", Change::Modified, 4000.0);
+        queue.record("a.md", Change::Deleted, 5000.0);
+
+        assert_eq!(queue.wait(6000.0, 10_000.0), 9000.0);
+        assert_eq!(queue.wait(20_000.0, 10_000.0), 0.0);
+        let changes = queue.take();
+        assert_eq!(changes.into_iter().collect::<Vec<_>>(), vec![("a.md".to_string(), Change::Deleted), ("b.md".to_string(), Change::Modified)]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn requeue_keeps_newer_changes() {
+        let mut queue = IndexQueue::default();
+        queue.record("a.md", Change::Modified, 0.0);
+        queue.record("b.md", Change::Modified, 0.0);
+        let changes = queue.take();
+        queue.record("a.md", Change::Deleted, 100.0);
+
+        queue.requeue(changes, 50.0);
+
+        assert_eq!(queue.wait(100.0, 10.0), 10.0);
+        assert_eq!(queue.take().into_iter().collect::<Vec<_>>(), vec![("a.md".to_string(), Change::Deleted), ("b.md".to_string(), Change::Modified)]);
+    }
+
+    #[test]
+    fn takes_a_slice() {
+        let mut queue = IndexQueue::default();
+        for path in ["c.md", "a.md", "b.md"] {
+            queue.record(path, Change::Modified, 0.0);
+        }
+
+        assert_eq!(queue.take_up_to(2).into_keys().collect::<Vec<_>>(), vec!["a.md", "b.md"]);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.take_up_to(2).len(), 1);
+        assert!(queue.is_empty() && queue.take_up_to(2).is_empty());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut queue = IndexQueue::default();
+        queue.record("Notes/a.md", Change::Modified, 1.0);
+        queue.record("b.md", Change::Deleted, 2.0);
+
+        let data = serde_json::to_string(&queue).unwrap();
+        let mut res: IndexQueue = serde_json::from_str(&data).unwrap();
+
+        assert_eq!(data, r#"{"changes":{"Notes/a.md":"modified","b.md":"deleted"},"last_change_at":2.0}"#);
+        assert_eq!(res.take(), queue.take());
+    }
+
+    #[test]
+    fn in_flight_changes_stay_saved() {
+        let mut queue = IndexQueue::default();
+        queue.record("a.md", Change::Modified, 0.0);
+        queue.record("b.md", Change::Modified, 0.0);
+        let in_flight = queue.take();
+        queue.record("b.md", Change::Deleted, 1.0);
+        queue.record("c.md", Change::Modified, 1.0);
+
+        let saved = queue.with_in_flight(&in_flight).take();
+
+        assert_eq!(saved.into_iter().collect::<Vec<_>>(), vec![("a.md".to_string(), Change::Modified), ("b.md".to_string(), Change::Deleted), ("c.md".to_string(), Change::Modified)]);
+        assert_eq!(queue.take().len(), 2);
+    }
+}
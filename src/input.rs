@@ -0,0 +1,377 @@
+use csv::ReaderBuilder;
+use serde::{Deserialize, Serialize};
+
+use crate::SemanticSearchError;
+use crate::chunking::Granularity;
+use crate::error::MalformedRow;
+
+/// Version of the input.csv layout written by generate_input.
+///
+/// 1: `name,header,body` rows without a header row
+/// 2: a `# schema_version: 2` line followed by a `name,header,body` header row
+/// 3: as 2, with `start_line,end_line` columns, empty for records that are not lines of a note
+/// 4: as 3, with a `headings` column holding the markdown headings above the record
+/// 5: as 4, with `created_at,modified_at` columns holding the times of the record's file, when known
+/// 6: as 5, with a `# granularity:` line after the version line naming the granularity the records were extracted at
+/// 7: as 6, with a `path` column holding the vault path of the record's file, when it came from one
+pub const INPUT_SCHEMA_VERSION: u32 = 7;
+const VERSION_PREFIX: &str = "# schema_version:";
+const GRANULARITY_PREFIX: &str = "# granularity:";
+const HEADING_SEPARATOR: &str = " > ";
+/// How input files are referred to in error messages
+const INPUT_FILE_NAME: &str = "input.csv";
+pub(crate) const BYTE_ORDER_MARK: char = '\u{feff}';
+
+/// Lines of a note a record was taken from, counted from 0, both ends included
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// When the file a record was taken from was created and last modified, in ms since the epoch
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NoteTimes {
+    pub created: f64,
+    pub modified: f64,
+}
+
+/// A section extracted from a note. `body` is the section text including its heading line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputRecord {
+    pub name: String,
+    pub header: String,
+    pub body: String,
+    /// Set for sections and chunks of markdown notes, so results can point at the exact lines
+    #[serde(default)]
+    pub start_line: Option<usize>,
+    #[serde(default)]
+    pub end_line: Option<usize>,
+    /// Markdown headings the record is nested under, outermost first and joined with " > "
+    #[serde(default)]
+    pub headings: String,
+    /// Times of the file the record was taken from, for filtering searches by date
+    #[serde(default)]
+    pub created_at: Option<f64>,
+    #[serde(default)]
+    pub modified_at: Option<f64>,
+    /// Vault path of the file the record was taken from; `name` is only its file name
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+/// Which part of a section is sent to the embedding API
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EmbedContent {
+    Heading,
+    Body,
+    #[default]
+    HeadingAndBody,
+}
+
+/// How the text sent to the embedding API is built from a record
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EmbedText {
+    pub content: EmbedContent,
+    /// Wraps the chosen text with its context, empty embeds the text alone. Supports
+    /// `{title}`, `{headings}`, `{path}` (the title followed by the headings) and `{text}`.
+    pub template: String,
+}
+
+impl InputRecord {
+    /// A record without line offsets, such as a PDF page or canvas card
+    pub fn new(name: String, header: String, body: String) -> Self {
+        Self { name, header, body, start_line: None, end_line: None, headings: String::new(), created_at: None, modified_at: None, path: None }
+    }
+
+    pub fn with_lines(self, lines: LineRange) -> Self {
+        Self { start_line: Some(lines.start), end_line: Some(lines.end), ..self }
+    }
+
+    pub fn with_headings(self, headings: &[String]) -> Self {
+        Self { headings: headings.join(HEADING_SEPARATOR), ..self }
+    }
+
+    pub fn with_times(self, times: NoteTimes) -> Self {
+        Self { created_at: Some(times.created), modified_at: Some(times.modified), ..self }
+    }
+
+    pub fn with_path(self, path: &str) -> Self {
+        Self { path: Some(path.to_string()), ..self }
+    }
+
+    pub fn title(&self) -> &str {
+        note_title(&self.name)
+    }
+
+    pub fn lines(&self) -> Option<LineRange> {
+        match (self.start_line, self.end_line) {
+            (Some(start), Some(end)) => Some(LineRange { start, end }),
+            _ => None,
+        }
+    }
+
+    /// Headings the record is nested under, outermost first
+    pub fn heading_path(&self) -> Vec<&str> {
+        self.headings.split(HEADING_SEPARATOR).filter(|heading| !heading.is_empty()).collect()
+    }
+
+    pub fn times(&self) -> Option<NoteTimes> {
+        match (self.created_at, self.modified_at) {
+            (Some(created), Some(modified)) => Some(NoteTimes { created, modified }),
+            _ => None,
+        }
+    }
+
+    pub fn text_to_embed(&self, text: &EmbedText) -> String {
+        let content = self.content_to_embed(text.content);
+        if text.template.trim().is_empty() {
+            return content;
+        }
+        let path: Vec<&str> = std::iter::once(self.title())
+            .chain(self.heading_path())
+            .collect();
+        text.template
+            .replace("{title}", self.title())
+            .replace("{headings}", &self.headings)
+            .replace("{path}", &path.join(HEADING_SEPARATOR))
+            .replace("{text}", &content)
+    }
+
+    fn content_to_embed(&self, content: EmbedContent) -> String {
+        match content {
+            EmbedContent::Heading => self.header.clone(),
+            EmbedContent::Body => {
+                let without_heading = self.body.strip_prefix(self.header.as_str()).unwrap_or(&self.body).trim();
+                // sections that are only a heading would otherwise embed an empty string
+                if without_heading.is_empty() { self.body.clone() } else { without_heading.to_string() }
+            },
+            EmbedContent::HeadingAndBody => self.body.clone(),
+        }
+    }
+}
+
+/// A note's file name without folders or the markdown extension
+pub fn note_title(name: &str) -> &str {
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    file_name.strip_suffix(".md").unwrap_or(file_name)
+}
+
+/// `records` as input.csv, noting the `granularity` they were extracted at
+pub fn write_input(records: &[InputRecord], granularity: Granularity) -> Result<String, SemanticSearchError> {
+    let mut wtr = csv::Writer::from_writer(vec![]);
+    for record in records {
+        wtr.serialize(record)?;
+    }
+    let data = String::from_utf8(wtr.into_inner()?)?;
+    Ok(format!("{} {}\n{} {}\n{}", VERSION_PREFIX, INPUT_SCHEMA_VERSION, GRANULARITY_PREFIX, granularity.name(), data))
+}
+
+pub fn read_input(input: &str) -> Result<Vec<InputRecord>, SemanticSearchError> {
+    Ok(parse_input(input, false)?.0)
+}
+
+/// Like `read_input`, but skips the rows that cannot be read and returns them alongside the records,
+/// so one corrupted line does not make the whole file unusable
+pub fn read_input_lenient(input: &str) -> Result<(Vec<InputRecord>, Vec<MalformedRow>), SemanticSearchError> {
+    parse_input(input, true)
+}
+
+/// The schema version input.csv was written with, 1 for files without a version line
+pub fn schema_version(input: &str) -> Result<u32, SemanticSearchError> {
+    Ok(split_version(input)?.0)
+}
+
+/// The granularity input.csv was extracted at. Files written before it was recorded were split at headings.
+pub fn granularity(input: &str) -> Result<Granularity, SemanticSearchError> {
+    let (_, data) = split_version(input)?;
+    Ok(split_granularity(data)?.0.unwrap_or_default())
+}
+
+fn split_granularity(data: &str) -> Result<(Option<Granularity>, &str), SemanticSearchError> {
+    match data.strip_prefix(GRANULARITY_PREFIX) {
+        Some(rest) => {
+            let (granularity, data) = rest.split_once('\n').unwrap_or((rest, ""));
+            let granularity = Granularity::from_name(granularity.trim())
+                .ok_or_else(|| SemanticSearchError::UnsupportedSchema(format!("invalid input.csv granularity '{}'", granularity.trim())))?;
+            Ok((Some(granularity), data))
+        },
+        None => Ok((None, data)),
+    }
+}
+
+fn split_version(input: &str) -> Result<(u32, &str), SemanticSearchError> {
+    // editors that re-save the file as UTF-8 may add a byte order mark
+    let input = input.strip_prefix(BYTE_ORDER_MARK).unwrap_or(input);
+    match input.strip_prefix(VERSION_PREFIX) {
+        Some(rest) => {
+            let (version, data) = rest.split_once('\n').unwrap_or((rest, ""));
+            let version = version.trim().parse::<u32>()
+                .map_err(|_| SemanticSearchError::UnsupportedSchema(format!("invalid input.csv schema version '{}'", version.trim())))?;
+            Ok((version, data))
+        },
+        None => Ok((1, input)),
+    }
+}
+
+fn parse_input(input: &str, lenient: bool) -> Result<(Vec<InputRecord>, Vec<MalformedRow>), SemanticSearchError> {
+    let (version, data) = split_version(input)?;
+    if version > INPUT_SCHEMA_VERSION {
+        return Err(SemanticSearchError::UnsupportedSchema(format!(
+            "input.csv has schema version {} but this version of the plugin supports up to {}, please update the plugin", version, INPUT_SCHEMA_VERSION)));
+    }
+
+    let (granularity, data) = split_granularity(data)?;
+
+    // fields are kept as written, so headings and names with surrounding spaces read back unchanged
+    let mut reader = ReaderBuilder::new().has_headers(version > 1).flexible(false)
+        .from_reader(data.as_bytes());
+    let skipped_lines = u64::from(version > 1) + u64::from(granularity.is_some());
+    let mut records = Vec::new();
+    let mut malformed = Vec::new();
+    for record in reader.deserialize() {
+        match record {
+            Ok(record) => records.push(record),
+            Err(e) if lenient => malformed.push(MalformedRow::from_csv(INPUT_FILE_NAME, skipped_lines, e)),
+            Err(e) => return Err(MalformedRow::from_csv(INPUT_FILE_NAME, skipped_lines, e).into()),
+        }
+    }
+    Ok((records, malformed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(header: &str, body: &str) -> InputRecord {
+        InputRecord::new("note.md".to_string(), header.to_string(), body.to_string())
+    }
+
+    #[test]
+    fn round_trip() {
+        let records = vec![record("Test", "Test content, with comma"), record("Other", "Other \"quoted\"").with_times(NoteTimes { created: 1.7e12, modified: 1.75e12 }).with_path("Notes/note.md")];
+
+        let res = read_input(&write_input(&records, Granularity::default()).unwrap()).unwrap();
+
+        assert_eq!(res, records);
+        assert_eq!(res[1].times(), Some(NoteTimes { created: 1.7e12, modified: 1.75e12 }));
+    }
+
+    #[test]
+    fn granularity_round_trips() {
+        let input = write_input(&[record("Test", "Test content")], Granularity::Paragraph).unwrap();
+
+        assert_eq!(granularity(&input).unwrap(), Granularity::Paragraph);
+        assert_eq!(read_input(&input).unwrap(), vec![record("Test", "Test content")]);
+        assert_eq!(granularity("# schema_version: 5\nname,header,body\n").unwrap(), Granularity::Heading);
+        assert!(granularity("# schema_version: 6\n# granularity: words\n").is_err());
+        let err = read_input("# schema_version: 6\n# granularity: note\nname,header,body\na.md,A\n").unwrap_err();
+        assert!(err.to_string().contains("input.csv line 4"), "{}", err);
+    }
+
+    #[test]
+    fn version_4_has_no_times() {
+        let res = read_input("# schema_version: 4\nname,header,body,start_line,end_line,headings\na.md,A,text,1,2,\n").unwrap();
+
+        assert_eq!(res[0].lines(), Some(LineRange { start: 1, end: 2 }));
+        assert_eq!(res[0].times(), None);
+    }
+
+    #[test]
+    fn pathological_text_round_trips() {
+        let records = vec![
+            InputRecord::new("Notes, \"quoted\"/Café ☕.md".to_string(), " Heading, with \"quotes\" ".to_string(), "Line one\r\nline \"two\", three\n\n".to_string())
+                .with_headings(&["Top, level".to_string(), "\"Nested\"\nheading".to_string()]),
+            record("", ""),
+        ];
+
+        let res = read_input(&write_input(&records, Granularity::default()).unwrap()).unwrap();
+
+        assert_eq!(res, records);
+    }
+
+    #[test]
+    fn malformed_row_reports_its_line() {
+        let res = read_input("# schema_version: 4\nname,header,body,start_line,end_line,headings\na.md,A,\"multi\nline\",,,\nb.md,B,text,x,,\n");
+
+        let err = res.unwrap_err();
+        assert_eq!(err.code(), crate::error::ErrorCode::MalformedCsv);
+        assert!(err.to_string().contains("input.csv line 5: field 4"), "{}", err);
+    }
+
+    #[test]
+    fn lenient_read_skips_malformed_rows() {
+        let input = "# schema_version: 4\nname,header,body,start_line,end_line,headings\na.md,A,text,,,\nb.md,B,text,x,,\nc.md,C\nd.md,D,text,1,2,\n";
+
+        let (records, malformed) = read_input_lenient(input).unwrap();
+
+        assert_eq!(records.iter().map(|record| record.name.as_str()).collect::<Vec<_>>(), vec!["a.md", "d.md"]);
+        assert_eq!(malformed.iter().map(|row| row.line).collect::<Vec<_>>(), vec![Some(4), Some(5)]);
+        assert!(read_input(input).is_err());
+    }
+
+    #[test]
+    fn byte_order_mark_ignored() {
+        let res = read_input("\u{feff}# schema_version: 2\nname,header,body\nnote.md,Test,Test content\n").unwrap();
+
+        assert_eq!(res, vec![record("Test", "Test content")]);
+    }
+
+    #[test]
+    fn line_offsets_round_trip() {
+        let records = vec![record("Test", "Test content").with_lines(LineRange { start: 3, end: 7 }), record("Page 1", "Page 1 text")];
+
+        let res = read_input(&write_input(&records, Granularity::default()).unwrap()).unwrap();
+
+        assert_eq!(res, records);
+        assert_eq!(res[0].lines(), Some(LineRange { start: 3, end: 7 }));
+        assert_eq!(res[1].lines(), None);
+    }
+
+    #[test]
+    fn version_2_has_no_offsets() {
+        let res = read_input("# schema_version: 2\nname,header,body\nnote.md,Test,Test content\n").unwrap();
+
+        assert_eq!(res, vec![record("Test", "Test content")]);
+    }
+
+    #[test]
+    fn legacy_without_header_row() {
+        let res = read_input("note.md,Test,Test content\nnote.md,Other,Other content\n").unwrap();
+
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0], record("Test", "Test content"));
+    }
+
+    #[test]
+    fn newer_schema_rejected() {
+        let res = read_input("# schema_version: 99\nname,header,body\n");
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn embed_content() {
+        let record = record("Test", "Test content");
+
+        let text = |content| EmbedText { content, template: String::new() };
+
+        assert_eq!(record.text_to_embed(&text(EmbedContent::Heading)), "Test");
+        assert_eq!(record.text_to_embed(&text(EmbedContent::Body)), "content");
+        assert_eq!(record.text_to_embed(&text(EmbedContent::HeadingAndBody)), "Test content");
+    }
+
+    #[test]
+    fn heading_path_template() {
+        let nested = InputRecord::new("projects/Plugin.md".to_string(), "Tasks".to_string(), "Tasks TODO: fix it".to_string())
+            .with_headings(&["Roadmap".to_string(), "Tasks".to_string()]);
+        let top = record("Intro", "Intro text");
+        let text = EmbedText { content: EmbedContent::Body, template: "{path}: {text}".to_string() };
+
+        assert_eq!(nested.text_to_embed(&text), "Plugin > Roadmap > Tasks: TODO: fix it");
+        assert_eq!(top.text_to_embed(&text), "note: text");
+        let text = EmbedText { template: "{title} ({headings}) {text}".to_string(), ..text };
+        assert_eq!(nested.text_to_embed(&text), "Plugin (Roadmap > Tasks) TODO: fix it");
+    }
+}
@@ -0,0 +1,123 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use log::debug;
+
+use crate::SemanticSearchError;
+use crate::pipeline::{PipelineHook, PipelineRecord};
+
+struct Job {
+    id: u32,
+    name: String,
+    cancelled: Rc<Cell<bool>>,
+    locks: Vec<String>,
+}
+
+thread_local! {
+    static JOBS: RefCell<Vec<Job>> = const { RefCell::new(Vec::new()) };
+    static NEXT_JOB_ID: Cell<u32> = const { Cell::new(1) };
+}
+
+/// Registration of a long running command so it can be cancelled on unload.
+/// The job is unregistered when the handle is dropped.
+pub struct JobHandle {
+    id: u32,
+    cancelled: Rc<Cell<bool>>,
+}
+
+impl JobHandle {
+    pub fn start(name: &str) -> Self {
+        let id = NEXT_JOB_ID.with(|next| {
+            let id = next.get();
+            next.set(id + 1);
+            id
+        });
+        let cancelled = Rc::new(Cell::new(false));
+        JOBS.with(|jobs| jobs.borrow_mut().push(Job { id, name: name.to_string(), cancelled: cancelled.clone(), locks: Vec::new() }));
+        debug!("Started job {}: {}", id, name);
+        Self { id, cancelled }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.get()
+    }
+
+    pub fn check(&self) -> Result<(), SemanticSearchError> {
+        if self.is_cancelled() {
+            return Err(SemanticSearchError::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Records that this job holds the lock on `path`, so unload can release it
+    pub fn hold_lock(&self, path: &str) {
+        self.with_job(|job| job.locks.push(path.to_string()));
+    }
+
+    pub fn release_lock(&self, path: &str) {
+        self.with_job(|job| job.locks.retain(|lock| lock != path));
+    }
+
+    /// Pipeline hook that aborts the run before the next batch once the job is cancelled
+    pub fn cancellation_hook(&self) -> Box<dyn PipelineHook> {
+        Box::new(CancellationHook { cancelled: self.cancelled.clone() })
+    }
+
+    fn with_job(&self, f: impl FnOnce(&mut Job)) {
+        JOBS.with(|jobs| {
+            if let Some(job) = jobs.borrow_mut().iter_mut().find(|job| job.id == self.id) {
+                f(job);
+            }
+        });
+    }
+}
+
+impl Drop for JobHandle {
+    fn drop(&mut self) {
+        JOBS.with(|jobs| jobs.borrow_mut().retain(|job| job.id != self.id));
+    }
+}
+
+/// Cancels every running job, returning the lock files they still hold
+pub fn cancel_all() -> Vec<String> {
+    JOBS.with(|jobs| {
+        let mut jobs = jobs.borrow_mut();
+        let mut locks = Vec::new();
+        for job in jobs.iter_mut() {
+            debug!("Cancelling job {}: {}", job.id, job.name);
+            job.cancelled.set(true);
+            locks.append(&mut job.locks);
+        }
+        locks
+    })
+}
+
+struct CancellationHook {
+    cancelled: Rc<Cell<bool>>,
+}
+
+impl PipelineHook for CancellationHook {
+    fn before_embed(&mut self, _batch: &[PipelineRecord]) -> Result<(), SemanticSearchError> {
+        if self.cancelled.get() {
+            return Err(SemanticSearchError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancel_releases_locks() {
+        let job = JobHandle::start("test");
+        job.hold_lock("embedding.csv");
+
+        let locks = cancel_all();
+
+        assert_eq!(locks, vec!["embedding.csv".to_string()]);
+        assert!(job.check().is_err());
+        assert!(job.cancellation_hook().before_embed(&[]).is_err());
+    }
+}
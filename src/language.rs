@@ -0,0 +1,226 @@
+//! Language detection for chunks and queries, so vaults written in several languages can embed
+//! some of them with a model suited to them.
+//!
+//! Detection is deliberately simple: the script most letters are written in decides most
+//! languages, and Latin-script text is told apart by its most common words. Text too short or
+//! too mixed to tell is left without a language.
+
+use std::collections::HashSet;
+
+use crate::SemanticSearchError;
+use crate::pipeline::{PipelineHook, PipelineRecord};
+
+/// Languages `detect` can return, as ISO 639-1 codes
+pub const LANGUAGES: [&str; 17] = ["en", "de", "fr", "es", "it", "pt", "nl", "ru", "uk", "el", "ar", "he", "hi", "th", "ko", "ja", "zh"];
+
+/// Letters a script needs before text written in it is given a language
+const MIN_SCRIPT_LETTERS: usize = 3;
+/// Common words Latin-script text needs before it is given a language
+const MIN_STOP_WORDS: usize = 2;
+
+/// The most common words of each Latin-script language, which rarely appear in the others
+const STOP_WORDS: [(&str, &[&str]); 7] = [
+    ("en", &["the", "and", "is", "of", "to", "in", "that", "it", "with", "for", "are", "this"]),
+    ("de", &["der", "die", "das", "und", "ist", "nicht", "ein", "eine", "mit", "auf", "ich", "zu"]),
+    ("fr", &["le", "la", "les", "et", "est", "des", "une", "du", "pas", "pour", "dans", "qui"]),
+    ("es", &["el", "los", "las", "y", "es", "del", "una", "por", "para", "con", "se", "como"]),
+    ("it", &["il", "di", "che", "e", "è", "della", "per", "non", "sono", "gli", "un", "nel"]),
+    ("pt", &["o", "os", "do", "da", "não", "uma", "em", "com", "é", "dos", "mais", "ao"]),
+    ("nl", &["het", "een", "en", "van", "niet", "dat", "op", "te", "zijn", "ik", "voor", "ook"]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Latin,
+    Cyrillic,
+    Greek,
+    Arabic,
+    Hebrew,
+    Devanagari,
+    Thai,
+    Hangul,
+    Kana,
+    Han,
+}
+
+const SCRIPTS: [Script; 10] = [Script::Latin, Script::Cyrillic, Script::Greek, Script::Arabic, Script::Hebrew, Script::Devanagari, Script::Thai, Script::Hangul, Script::Kana, Script::Han];
+
+fn script(c: char) -> Option<Script> {
+    match c {
+        'a'..='z' | 'A'..='Z' | '\u{00C0}'..='\u{024F}' if c.is_alphabetic() => Some(Script::Latin),
+        '\u{0370}'..='\u{03FF}' => Some(Script::Greek),
+        '\u{0400}'..='\u{04FF}' => Some(Script::Cyrillic),
+        '\u{0590}'..='\u{05FF}' => Some(Script::Hebrew),
+        '\u{0600}'..='\u{06FF}' => Some(Script::Arabic),
+        '\u{0900}'..='\u{097F}' => Some(Script::Devanagari),
+        '\u{0E00}'..='\u{0E7F}' => Some(Script::Thai),
+        '\u{1100}'..='\u{11FF}' | '\u{3130}'..='\u{318F}' | '\u{AC00}'..='\u{D7AF}' => Some(Script::Hangul),
+        '\u{3040}'..='\u{30FF}' => Some(Script::Kana),
+        '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' => Some(Script::Han),
+        _ => None,
+    }
+}
+
+/// The language most of `text` is written in, as an ISO 639-1 code from `LANGUAGES`, or `None`
+/// when it is too short or too mixed to tell
+pub fn detect(text: &str) -> Option<&'static str> {
+    let mut counts = [0usize; SCRIPTS.len()];
+    for script in text.chars().filter_map(script) {
+        counts[script as usize] += 1;
+    }
+    let letters: usize = counts.iter().sum();
+    // Japanese mixes kana into Han text, so the two are counted as one script
+    let kana = counts[Script::Kana as usize];
+    counts[Script::Han as usize] += kana;
+    counts[Script::Kana as usize] = 0;
+    let (dominant, most) = SCRIPTS.iter().zip(counts).max_by_key(|&(_, count)| count)?;
+    if most < MIN_SCRIPT_LETTERS || most * 2 <= letters {
+        return None;
+    }
+    match dominant {
+        Script::Latin => latin_language(text),
+        Script::Cyrillic if text.chars().any(|c| "іїєґІЇЄҐ".contains(c)) => Some("uk"),
+        Script::Cyrillic => Some("ru"),
+        Script::Greek => Some("el"),
+        Script::Arabic => Some("ar"),
+        Script::Hebrew => Some("he"),
+        Script::Devanagari => Some("hi"),
+        Script::Thai => Some("th"),
+        Script::Hangul => Some("ko"),
+        Script::Han | Script::Kana if kana > 0 => Some("ja"),
+        Script::Han | Script::Kana => Some("zh"),
+    }
+}
+
+/// The Latin-script language with the most common words in `text`, if it clearly has the most
+fn latin_language(text: &str) -> Option<&'static str> {
+    let words: Vec<String> = text.split(|c: char| !c.is_alphabetic()).filter(|word| !word.is_empty()).map(str::to_lowercase).collect();
+    let mut best: Option<(&'static str, usize)> = None;
+    let mut runner_up = 0;
+    for (language, stop_words) in STOP_WORDS {
+        let hits = words.iter().filter(|word| stop_words.contains(&word.as_str())).count();
+        match best {
+            Some((_, most)) if hits <= most => runner_up = runner_up.max(hits),
+            _ => {
+                runner_up = best.map_or(0, |(_, most)| most);
+                best = Some((language, hits));
+            },
+        }
+    }
+    best.filter(|&(_, hits)| hits >= MIN_STOP_WORDS && hits > runner_up).map(|(language, _)| language)
+}
+
+/// A language embedded with its own model, into its own store
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanguageRoute {
+    pub language: String,
+    pub model: String,
+}
+
+/// Parses one `language model` pair per line, e.g. `ja intfloat/multilingual-e5-large`, also
+/// accepting `language: model`. Blank lines are skipped, and lines that cannot be read, name a
+/// language `detect` does not know or repeat a language are returned as errors.
+pub fn parse_language_models(text: &str) -> (Vec<LanguageRoute>, Vec<String>) {
+    let mut routes: Vec<LanguageRoute> = Vec::new();
+    let mut errors = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let parsed = line.split_once(|c: char| c.is_whitespace() || c == ':')
+            .map(|(language, model)| (language.trim().to_lowercase(), model.trim().trim_start_matches(':').trim().to_string()))
+            .filter(|(_, model)| !model.is_empty());
+        match parsed {
+            Some((language, _)) if !LANGUAGES.contains(&language.as_str()) => {
+                errors.push(format!("'{}' is not a language that can be detected, use one of {}", language, LANGUAGES.join(", ")));
+            },
+            Some((language, _)) if routes.iter().any(|route| route.language == language) => {
+                errors.push(format!("'{}' is given a model more than once", language));
+            },
+            Some((language, model)) => routes.push(LanguageRoute { language, model }),
+            None => errors.push(format!("'{}' should be a language code followed by a model, e.g. ja intfloat/multilingual-e5-large", line)),
+        }
+    }
+    (routes, errors)
+}
+
+/// Index in `languages` of the language `query` is detected to be in, when it is one of them
+pub fn route<'a>(query: &str, languages: impl IntoIterator<Item = &'a str>) -> Option<usize> {
+    let language = detect(query)?;
+    languages.into_iter().position(|routed| routed == language)
+}
+
+/// Only lets the records in some languages, or all but some languages, through to be embedded
+pub struct LanguageHook {
+    languages: HashSet<String>,
+    /// Whether records in `languages` are kept, rather than left out
+    keep: bool,
+}
+
+impl LanguageHook {
+    pub fn only(language: &str) -> Self {
+        Self { languages: HashSet::from([language.to_string()]), keep: true }
+    }
+
+    pub fn excluding(languages: &[String]) -> Self {
+        Self { languages: languages.iter().cloned().collect(), keep: false }
+    }
+}
+
+impl PipelineHook for LanguageHook {
+    fn preprocess(&mut self, records: Vec<PipelineRecord>) -> Result<Vec<PipelineRecord>, SemanticSearchError> {
+        Ok(records.into_iter()
+            .filter(|record| detect(&record.text).is_some_and(|language| self.languages.contains(language)) == self.keep)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+
+    #[test]
+    fn detects_languages() {
+        assert_eq!(detect("The leader is elected when the timeout of a follower expires and it starts an election."), Some("en"));
+        assert_eq!(detect("Der Leader wird gewählt, wenn die Zeit abläuft und der Knoten eine Wahl startet."), Some("de"));
+        assert_eq!(detect("Le leader est élu quand le délai expire et la élection commence."), Some("fr"));
+        assert_eq!(detect("El líder se elige cuando expira el tiempo y los nodos votan por una opción."), Some("es"));
+        assert_eq!(detect("Лидер выбирается голосованием узлов."), Some("ru"));
+        assert_eq!(detect("Лідер обирається голосуванням вузлів."), Some("uk"));
+        assert_eq!(detect("リーダーは選挙で選ばれる"), Some("ja"));
+        assert_eq!(detect("领导者通过选举产生"), Some("zh"));
+        assert_eq!(detect("리더는 선거로 선출된다"), Some("ko"));
+    }
+
+    #[test]
+    fn leaves_unclear_text_undetected() {
+        assert_eq!(detect("raft"), None);
+        assert_eq!(detect("Raft Paxos Zookeeper"), None);
+        assert_eq!(detect("12:30 - 13:00"), None);
+        assert_eq!(detect(""), None);
+    }
+
+    #[test]
+    fn routes_queries_to_their_language_store() {
+        let settings = Settings { language_models: "ja intfloat/multilingual-e5-large\nde nomic-embed-text".to_string(), ..Settings::default() };
+        let routes = settings.language_routes();
+        let routed = |query: &str| route(query, routes.iter().map(|route| route.language.as_str()))
+            .map(|index| settings.language_embedding_path(&routes[index].language));
+
+        assert_eq!(routed("リーダーは選挙で選ばれる"), Some(settings.language_embedding_path("ja")));
+        assert_eq!(routed("Der Leader wird gewählt, wenn die Zeit abläuft und der Knoten eine Wahl startet."), Some(settings.language_embedding_path("de")));
+        assert_eq!(routed("The leader is elected when the timeout of a follower expires and it starts an election."), None);
+        assert_eq!(routed("raft"), None);
+    }
+
+    #[test]
+    fn parses_language_models() {
+        let (routes, errors) = parse_language_models("ja intfloat/multilingual-e5-large\n\nDE: nomic-embed-text:latest\nxx model\nja other\nzh");
+
+        assert_eq!(routes, vec![
+            LanguageRoute { language: "ja".to_string(), model: "intfloat/multilingual-e5-large".to_string() },
+            LanguageRoute { language: "de".to_string(), model: "nomic-embed-text:latest".to_string() },
+        ]);
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].starts_with("'xx' is not a language"));
+        assert!(errors[1].starts_with("'ja' is given a model more than once"));
+    }
+}
@@ -1,143 +1,698 @@
 mod obsidian;
+mod attachments;
+mod auto_index;
+mod batch_api;
+mod budget;
+mod canvas;
+mod chat;
+mod checksum;
+mod chunking;
+mod dataview;
+mod documents;
 mod embedding;
+pub mod embedding_file;
+mod encryption;
+mod export;
 mod file_processor;
 mod error;
+mod exclusions;
+mod expansion;
 mod generate_input;
+mod glob;
+mod fusion;
+#[cfg(feature = "headless")]
+pub mod headless;
+mod history;
+mod hooks;
+mod clustering;
+mod compaction;
+mod context;
+mod coverage;
+mod duplicates;
+mod import;
+mod index_queue;
+mod index;
+mod input;
+mod jobs;
+mod language;
+mod links;
+mod logging;
+mod maintenance;
+mod manifest;
+mod matryoshka;
+mod migrations;
+mod mentions;
+mod moc;
+mod mock;
+pub mod math;
+mod models;
+mod negative;
+mod pipeline;
+mod pricing;
+pub mod provenance;
+mod providers;
+pub mod quantization;
+mod ranking;
+mod rate_limit;
+mod redact;
+mod reindex;
+mod rpc;
+mod rerank;
+mod results_note;
+mod session;
+mod seeding;
+mod settings;
+mod snippets;
+mod stats;
+mod stores;
+#[cfg(feature = "test-vault")]
+pub mod test_vault;
+mod timeouts;
+mod truncation;
+
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
 
 use crate::embedding::EmbeddingRequestBuilderError;
-use crate::embedding::EmbeddingRequestBuilder;
 use crate::obsidian::Notice;
 
-use csv::{ReaderBuilder, StringRecord};
-use embedding::EmbeddingRequest;
+use batch_api::{Batch, BatchJob, FileObject};
 use embedding::EmbeddingResponse;
+use documents::DocumentsReport;
+use embedding_file::{EmbeddingFile, StoreMetadata};
+use compaction::CompactionReport;
+use import::{ImportFormat, ImportReport};
+use export::{ExportFormat, ExportReport};
+use error::ErrorCode;
+use error::JsErrorPayload;
+use error::MalformedRow;
 use error::SemanticSearchError;
 use error::WrappedError;
-use file_processor::FileProcessor;
+use chat::{Answer, ChatRequest, ChatResponse};
+use file_processor::{FileFilter, FileProcessor};
+use fusion::{ScoreNormalization, ScoredCandidate};
+use input::{EmbedText, InputRecord, LineRange};
+use jobs::JobHandle;
+use language::LanguageHook;
+use maintenance::{NoteFilterHook, NoteSet, ReembedFilter, ReembedReport};
+use manifest::RunRecorder;
+use migrations::{Migration, MigrationReport, Refusal, StoreKind};
+use session::QueryFilters;
 use js_sys::JsString;
 use log::debug;
-use ndarray::Array1;
+use log::error;
+use log::info;
+use log::warn;
+use models::ModelListResponse;
+use math::cosine_similarity;
 use obsidian::App;
-use obsidian::semanticSearchSettings;
-use reqwest::header::HeaderMap;
+use pipeline::EmbeddingPipeline;
+use pipeline::PipelineHook;
+use pipeline::PipelineRecord;
+use pipeline::PipelineReport;
+use provenance::{Provenance, ProvenanceFilter};
+use providers::{EmbeddingProvider, InputType};
+use std::rc::Rc;
+use quantization::{Quantization, QuantizedVector};
+use ranking::{FileGrouping, RecencyDecay, ScoreAdjustment, Stage, TagBoost, TagBoosting};
+use rate_limit::RateLimit;
+use reindex::{Progress, ProgressHook};
+use chunking::Granularity;
+use rerank::Reranker;
+use reqwest::header::{HeaderMap, HeaderValue};
+use seeding::SeedMode;
+use snippets::Snippet;
+use links::{LinkCandidate, SectionLink};
+use moc::MocSource;
+use context::{ContextSection, RagContext};
+use settings::Settings;
+use stores::{StoreEntry, StoreRegistry};
+use history::{HistoryEntry, HistoryResult, SavedSearch, SearchHistory};
+use truncation::TruncationHook;
+use budget::BudgetHook;
+use timeouts::DeadlineHook;
 use serde::Deserialize;
 use serde::Serialize;
-use tiktoken_rs::cl100k_base;
 use wasm_bindgen::prelude::*;
 
 use crate::embedding::EmbeddingInput;
 
-const DATA_FILE_PATH: &str = "input.csv";
-const EMBEDDING_FILE_PATH: &str = "embedding.csv";
+const INPUT_FILE_NAME: &str = "input.csv";
+const EMBEDDING_FILE_NAME: &str = "embedding.csv";
+const NUM_SUGGESTIONS: usize = 10;
+/// Number of top candidates considered when re-ranking with MMR
+const MMR_POOL_SIZE: usize = 50;
+/// Number of best scoring chunks kept per query for fusion, grouping, reranking and MMR
+const RANK_POOL_SIZE: usize = 200;
 
 #[wasm_bindgen]
 pub struct GenerateEmbeddingsCommand {
     file_processor: FileProcessor,
     client: Client,
+    input_path: String,
+    embedding_path: String,
+    manifest_path: String,
+    store_registry_path: String,
+    batch_job_path: String,
+    store: String,
     num_batches: u32,
+    max_tokens_per_request: usize,
+    max_tokens_per_record: usize,
+    /// USD a single run may cost at most, 0 for no cap
+    max_cost_per_run: f32,
+    /// Minutes a run may take before it stops sending batches, 0 for no deadline
+    run_deadline: u32,
+    use_batch_api: bool,
+    embed_text: EmbedText,
+    settings_hash: String,
+    quantization: Quantization,
+    /// Dimensions stored embeddings are truncated to, 0 keeps them whole
+    embedding_dimensions: usize,
+    language_stores: Vec<LanguageStore>,
+    /// Told about each batch before it is sent, when the run is part of a reindex
+    progress: Option<Progress>,
+}
+
+/// A language embedded with its own model: the language, a client for its model and its store's path
+type LanguageStore = (String, Client, String);
+
+fn language_stores(settings: &Settings) -> Vec<LanguageStore> {
+    settings.language_routes().into_iter()
+        .map(|route| {
+            let path = settings.language_embedding_path(&route.language);
+            (route.language, Client::new(&settings.with_model(&route.model)), path)
+        })
+        .collect()
 }
 
 #[wasm_bindgen]
 impl GenerateEmbeddingsCommand {
     #[wasm_bindgen(constructor)]
-    pub fn new(app: App, settings: semanticSearchSettings) -> GenerateEmbeddingsCommand {
+    pub fn new(app: App, settings: JsValue) -> Result<GenerateEmbeddingsCommand, SemanticSearchError> {
+        let settings = Settings::from_js(&settings)?;
         let file_processor = FileProcessor::new(app.vault());
-        let client = Client::new(settings.apiKey());
-        let num_batches = settings.numBatches();
-        GenerateEmbeddingsCommand { file_processor, client, num_batches }
-    }
-
-    pub async fn get_embeddings(&self) -> Result<(), SemanticSearchError> {
-        self.file_processor.delete_file_at_path(EMBEDDING_FILE_PATH).await?;
-        let input = self.file_processor.read_from_path(DATA_FILE_PATH).await?;
-        let string_records = self.get_content_to_embed(input.clone())?;
-
-        let mut num_processed = 0;
-        let num_batches = self.num_batches;
-        let mut batch = 1;
-        let num_records = string_records.len();
-        debug!("Found {} records.", num_records);
-        let batch_size = (num_records as f64 / num_batches as f64).ceil() as usize;
-
-        while num_processed < num_records {
-            let num_to_process = if batch == num_batches {
-                num_records - num_processed
-            } else {
-                batch_size
-            };
+        let client = Client::new(&settings);
+        let input_path = settings.input_path();
+        let embedding_path = settings.embedding_path();
+        let manifest_path = settings.manifest_path();
+        let store_registry_path = settings.store_registry_path();
+        let batch_job_path = settings.batch_job_path();
+        let store = settings.embedding_store.trim().to_string();
+        let num_batches = settings.num_batches;
+        let max_tokens_per_request = settings.max_tokens_per_request;
+        let max_tokens_per_record = settings.max_tokens_per_record;
+        let max_cost_per_run = settings.max_cost_per_run;
+        let run_deadline = settings.run_deadline;
+        let use_batch_api = settings.use_batch_api;
+        let embed_text = settings.embed_text();
+        let settings_hash = settings.embedding_settings_hash();
+        let quantization = settings.embedding_quantization;
+        let embedding_dimensions = settings.embedding_dimensions;
+        let language_stores = language_stores(&settings);
+        Ok(GenerateEmbeddingsCommand { file_processor, client, input_path, embedding_path, manifest_path, store_registry_path, batch_job_path, store, num_batches, max_tokens_per_request, max_tokens_per_record, max_cost_per_run, run_deadline, use_batch_api, embed_text, settings_hash, quantization, embedding_dimensions, language_stores, progress: None })
+    }
 
-            let records = &string_records[num_processed..num_processed + num_to_process];
-            debug!("Processing batch {}: {} to {}", batch, num_processed, num_processed + num_to_process);
-
-            let request = self.client.create_embedding_request(records.into())?;
-            let response = self.client.post_embedding_request(&request).await?;
-            debug!("Sucessfully obtained {} embeddings", response.data.len());
-
-            let filename_body = self.get_filename_body(input.clone())?;
-            let mut wtr = csv::Writer::from_writer(vec![]);
-            match request.input {
-                EmbeddingInput::StringArray(arr) => {
-                    for (i, _) in arr.iter().enumerate() {
-                        let record_idx = num_processed + i;
-                        let filename_header = match filename_body.get(record_idx) {
-                            None => return Err(SemanticSearchError::GetEmbeddingsError(format!("Cannot find matching filename and header for input index {}", i)).into()),
-                            Some(filename_header) => filename_header
-                        };
-                        let filename = &filename_header.0;
-                        let header = &filename_header.1;
-                        let embedding = match &response.data.get(i) {
-                            None => return Err(SemanticSearchError::GetEmbeddingsError(format!("Cannot find matching embedding for filename: {}, header: {}", filename, header)).into()),
-                            Some(embedding) => {
-                                let vec: Vec<String> = embedding.embedding.clone().into_iter().map(|f| f.to_string()).collect();
-                                vec.join(",")
-                            }
-                        };
-                        wtr.write_record(&[filename, header, &embedding])?;
-                    }
-                }
+    /// Lifts the cost cap for this command, once the user has confirmed a run that exceeds it
+    pub fn allow_over_budget(&mut self) {
+        self.max_cost_per_run = 0.0;
+    }
+
+    /// Embeds input.csv and returns a `PipelineReport` describing the run
+    pub async fn get_embeddings(&self) -> Result<JsValue, SemanticSearchError> {
+        Ok(serde_wasm_bindgen::to_value(&self.embed().await?)?)
+    }
+
+    /// Reports each batch of the run to `progress` before it is sent
+    fn report_progress(&mut self, progress: Progress) {
+        self.progress = Some(progress);
+    }
+
+    /// Embeds input.csv, holding the lock on the embedding file, then runs the post-build hooks
+    /// and saves the run manifest
+    async fn embed(&self) -> Result<PipelineReport, SemanticSearchError> {
+        let job = JobHandle::start("generate embeddings");
+        let mut recorder = RunRecorder::start("generate embeddings");
+        self.file_processor.acquire_lock(&self.embedding_path).await?;
+        job.hold_lock(&self.embedding_path);
+
+        let result = self.run_pipeline(&job, &mut recorder).await;
+
+        self.file_processor.release_lock_or_log(&self.embedding_path).await;
+        job.release_lock(&self.embedding_path);
+        if result.is_ok() {
+            self.run_post_build_hooks(&mut recorder).await;
+        }
+        let manifest = recorder.finish(&result);
+        if let Err(e) = manifest::save(&self.file_processor, &self.manifest_path, &manifest).await {
+            error!("Failed to save run manifest: {}", e);
+        }
+        result
+    }
+
+    /// Embeds input.csv into the embedding file, and the records in each language embedded with
+    /// its own model into that language's store.
+    ///
+    /// Every store is written to `<store>.tmp` and swapped in once all of them are complete, so
+    /// searches keep using the previous index during the run and after a failed one.
+    async fn run_pipeline(&self, job: &JobHandle, recorder: &mut RunRecorder) -> Result<PipelineReport, SemanticSearchError> {
+        let paths: Vec<&str> = std::iter::once(self.embedding_path.as_str()).chain(self.language_stores.iter().map(|(_, _, path)| path.as_str())).collect();
+        let result = self.embed_stores(job, recorder).await;
+        for path in &paths {
+            let temp_path = file_processor::temp_path(path);
+            if result.is_ok() {
+                self.file_processor.swap_in(path).await?;
+            } else if let Err(e) = self.file_processor.delete_file_at_path(&temp_path).await {
+                error!("Failed to remove {}: {}", temp_path, e);
             }
+        }
+        let (report, records_embedded) = result?;
+        recorder.set_report(&report);
+        self.register_store(records_embedded).await?;
+        Ok(report)
+    }
 
-            let data = String::from_utf8(wtr.into_inner()?)?;
-            self.file_processor.write_to_path(EMBEDDING_FILE_PATH, &data).await?;
-            num_processed += num_to_process;
-            batch += 1;
+    /// Embeds input.csv into the temporary files of the embedding file and the language stores,
+    /// returning the report of the run and the number of records in the embedding file
+    async fn embed_stores(&self, job: &JobHandle, recorder: &mut RunRecorder) -> Result<(PipelineReport, usize), SemanticSearchError> {
+        let input = self.file_processor.read_from_path(&self.input_path).await.map_err(SemanticSearchError::missing_input)?;
+        recorder.stage("read input", input.len());
+        let routed: Vec<String> = self.language_stores.iter().map(|(language, _, _)| language.clone()).collect();
+        let language_hook = if routed.is_empty() { None } else { Some(LanguageHook::excluding(&routed)) };
+        let temp_path = file_processor::temp_path(&self.embedding_path);
+        self.file_processor.delete_file_at_path(&temp_path).await?;
+        let mut report = self.embed_input(&input, &self.client, &temp_path, language_hook, job, recorder).await?;
+        debug!("Saved embeddings to {}", temp_path);
+        let records_embedded = report.records_embedded;
+        for (language, client, path) in &self.language_stores {
+            let temp_path = file_processor::temp_path(path);
+            self.file_processor.delete_file_at_path(&temp_path).await?;
+            let language_report = self.embed_input(&input, client, &temp_path, Some(LanguageHook::only(language)), job, recorder).await?;
+            debug!("Saved {} embeddings in {} to {}", language_report.records_embedded, language, temp_path);
+            report.merge(language_report);
         }
-        
-        debug!("Saved embeddings to {}", EMBEDDING_FILE_PATH);
-        Ok(())
+        Ok((report, records_embedded))
     }
 
-    pub async fn get_input_cost_estimate(&self) -> Result<f32, SemanticSearchError> {
-        let input = self.file_processor.read_from_path(DATA_FILE_PATH).await?;
-        let string_records = self.get_content_to_embed(input)?;
-        let combined_string = string_records.join("");
-        let estimate = get_query_cost_estimate(&combined_string);
-        Ok(estimate)
+    async fn embed_input(&self, input: &str, client: &Client, path: &str, language_hook: Option<LanguageHook>, job: &JobHandle, recorder: &mut RunRecorder) -> Result<PipelineReport, SemanticSearchError> {
+        let mut pipeline = EmbeddingPipeline::new(client, &self.file_processor, path, self.num_batches, self.max_tokens_per_request, self.embed_text.clone(), &self.settings_hash);
+        if let Some(language_hook) = language_hook {
+            pipeline.add_hook(Box::new(language_hook));
+        }
+        self.configure_pipeline(&mut pipeline, client, job);
+        if let Some(progress) = &self.progress {
+            pipeline.add_hook(Box::new(ProgressHook::new(progress.clone())));
+        }
+        pipeline.run(input, recorder).await
+    }
+
+    /// Applies what every run of this command shares to `pipeline`, which embeds with `client`:
+    /// the stored vector format, cancellation through `job`, and the record size, cost and time limits
+    fn configure_pipeline(&self, pipeline: &mut EmbeddingPipeline, client: &Client, job: &JobHandle) {
+        pipeline.quantize(self.quantization);
+        pipeline.truncate_dimensions(self.embedding_dimensions);
+        pipeline.add_hook(job.cancellation_hook());
+        if self.max_tokens_per_record > 0 {
+            pipeline.add_hook(Box::new(TruncationHook::new(self.max_tokens_per_record)));
+        }
+        if self.max_cost_per_run > 0.0 {
+            pipeline.add_hook(Box::new(BudgetHook::new(client.model(), client.dollars_per_million_tokens(), self.max_cost_per_run)));
+        }
+        if self.run_deadline > 0 {
+            pipeline.add_hook(Box::new(DeadlineHook::start(self.run_deadline)));
+        }
+    }
+
+    /// Re-embeds the notes whose stored records match `filter` (a `ReembedFilter`) from the
+    /// current input.csv, keeping every other record, and returns a `ReembedReport`
+    pub async fn reembed(&self, filter: JsValue) -> Result<JsValue, SemanticSearchError> {
+        let filter = ReembedFilter::from_js(&filter)?;
+        let job = JobHandle::start("re-embed");
+        let mut recorder = RunRecorder::start("re-embed");
+        self.file_processor.acquire_lock(&self.embedding_path).await?;
+        job.hold_lock(&self.embedding_path);
+
+        let result = self.run_reembed(&job, &mut recorder, filter).await;
+
+        self.file_processor.release_lock_or_log(&self.embedding_path).await;
+        job.release_lock(&self.embedding_path);
+        let manifest = recorder.finish(&result);
+        if let Err(e) = manifest::save(&self.file_processor, &self.manifest_path, &manifest).await {
+            error!("Failed to save run manifest: {}", e);
+        }
+        Ok(serde_wasm_bindgen::to_value(&result?)?)
+    }
+
+    async fn run_reembed(&self, job: &JobHandle, recorder: &mut RunRecorder, filter: ReembedFilter) -> Result<ReembedReport, SemanticSearchError> {
+        let store = self.load_store().await.map_err(SemanticSearchError::missing_embedding)?;
+        let mut scope = QueryFilters::for_folder(&filter.folder);
+        scope.resolve(&self.file_processor).await?;
+        let notes = maintenance::select_notes(&store, &filter.provenance, &scope);
+        recorder.stage("select notes", notes.len());
+        if notes.is_empty() {
+            return Ok(ReembedReport::default());
+        }
+
+        let input = self.file_processor.read_from_path(&self.input_path).await.map_err(SemanticSearchError::missing_input)?;
+        let (notes, missing) = maintenance::split_missing(notes, &input::read_input_lenient(&input)?.0);
+        if !missing.is_empty() {
+            recorder.warn(format!("{} selected notes are not in input.csv and were left as they are, run Generate Input to pick them up (first: {})", missing.len(), missing[0]));
+        }
+        if notes.is_empty() {
+            return Ok(ReembedReport { missing, ..ReembedReport::default() });
+        }
+        let selected = NoteSet::names(&notes);
+        maintenance::check_model_change(&store, self.client.model(), &selected)?;
+
+        let staging_path = maintenance::staging_path(&self.embedding_path);
+        self.file_processor.delete_file_at_path(&staging_path).await?;
+        let mut pipeline = EmbeddingPipeline::new(&self.client, &self.file_processor, &staging_path, self.num_batches, self.max_tokens_per_request, self.embed_text.clone(), &self.settings_hash);
+        pipeline.add_hook(Box::new(NoteFilterHook::new(&notes)));
+        self.configure_pipeline(&mut pipeline, &self.client, job);
+        if let Some(metadata) = store.metadata.as_ref().filter(|metadata| metadata.model == self.client.model()) {
+            pipeline.expect_dimensions(metadata.dimensions);
+        }
+        let report = pipeline.run(&input, recorder).await?;
+        recorder.set_report(&report);
+
+        let records_replaced = self.merge_staged(store, &selected, &staging_path, input::granularity(&input)?, recorder).await?;
+        Ok(ReembedReport { notes: notes.len(), records_replaced, missing, report })
+    }
+
+    /// Embeds `{id, title, text}` documents handed over by other plugins or scripts, bypassing
+    /// input.csv, and returns a `DocumentsReport`. Each document is stored as one record named
+    /// by its id; records from an earlier call with the same id are replaced, everything else is kept.
+    pub async fn embed_documents(&self, documents: JsValue) -> Result<JsValue, SemanticSearchError> {
+        let documents = documents::from_js(&documents)?;
+        let job = JobHandle::start("embed documents");
+        let mut recorder = RunRecorder::start("embed documents");
+        self.file_processor.acquire_lock(&self.embedding_path).await?;
+        job.hold_lock(&self.embedding_path);
+
+        let result = self.run_embed_documents(&job, &mut recorder, documents).await;
+
+        self.file_processor.release_lock_or_log(&self.embedding_path).await;
+        job.release_lock(&self.embedding_path);
+        let manifest = recorder.finish(&result);
+        if let Err(e) = manifest::save(&self.file_processor, &self.manifest_path, &manifest).await {
+            error!("Failed to save run manifest: {}", e);
+        }
+        Ok(serde_wasm_bindgen::to_value(&result?)?)
+    }
+
+    async fn run_embed_documents(&self, job: &JobHandle, recorder: &mut RunRecorder, documents: Vec<documents::Document>) -> Result<DocumentsReport, SemanticSearchError> {
+        let records = documents::input_records(documents)?;
+        if records.is_empty() {
+            return Ok(DocumentsReport::default());
+        }
+        let ids: Vec<String> = records.iter().map(|record| record.name.clone()).collect();
+        // each document is stored as a single record, like a note embedded whole
+        let (records_replaced, report) = self.replace_records(job, recorder, records, &NoteSet::names(&ids), Granularity::Note).await?;
+        Ok(DocumentsReport { documents: ids.len(), records_replaced, report })
+    }
+
+    /// Embeds `records` in place of every stored record of `notes`, keeping the rest of the store.
+    /// Returns how many stored records were dropped along with the pipeline report.
+    async fn replace_records(&self, job: &JobHandle, recorder: &mut RunRecorder, records: Vec<InputRecord>, notes: &NoteSet, granularity: Granularity) -> Result<(usize, PipelineReport), SemanticSearchError> {
+        let store = match self.file_processor.check_file_exists_at_path(&self.embedding_path).await? {
+            true => self.load_store().await?,
+            false => EmbeddingFile { metadata: None, rows: Vec::new(), provenance: Vec::new() },
+        };
+        let staging_path = maintenance::staging_path(&self.embedding_path);
+        self.file_processor.delete_file_at_path(&staging_path).await?;
+        // removing records never mixes models, so only new embeddings need a matching store
+        if records.is_empty() {
+            if !(0..store.rows.len()).any(|i| notes.holds(&store, i)) {
+                return Ok((0, PipelineReport::default()));
+            }
+            let records_replaced = self.merge_staged(store, notes, &staging_path, granularity, recorder).await?;
+            return Ok((records_replaced, PipelineReport::default()));
+        }
+        maintenance::check_model_change(&store, self.client.model(), notes)?;
+
+        let mut pipeline = EmbeddingPipeline::new(&self.client, &self.file_processor, &staging_path, self.num_batches, self.max_tokens_per_request, self.embed_text.clone(), &self.settings_hash);
+        self.configure_pipeline(&mut pipeline, &self.client, job);
+        pipeline.granularity(granularity);
+        if let Some(metadata) = store.metadata.as_ref().filter(|metadata| metadata.model == self.client.model()) {
+            pipeline.expect_dimensions(metadata.dimensions);
+        }
+        let records = records.into_iter().map(|record| PipelineRecord::from_input(record, &self.embed_text)).collect();
+        let report = pipeline.run_records(records, recorder).await?;
+        recorder.set_report(&report);
+
+        let records_replaced = self.merge_staged(store, notes, &staging_path, granularity, recorder).await?;
+        Ok((records_replaced, report))
+    }
+
+    /// Replaces the records of `notes` in `store` with the embeddings written to `staging_path`,
+    /// saves the result over the embedding file and returns how many stored records were dropped.
+    /// A store without metadata gets metadata recording `granularity`.
+    async fn merge_staged(&self, store: EmbeddingFile, notes: &NoteSet, staging_path: &str, granularity: Granularity, recorder: &mut RunRecorder) -> Result<usize, SemanticSearchError> {
+        let replacement = match self.file_processor.check_file_exists_at_path(staging_path).await? {
+            true => embedding_file::read_embedding_file(&self.file_processor.read_from_path(staging_path).await?)?,
+            false => EmbeddingFile { metadata: None, rows: Vec::new(), provenance: Vec::new() },
+        };
+        let records_replaced = (0..store.rows.len()).filter(|&i| notes.holds(&store, i)).count();
+        let mut merged = maintenance::merge(store, notes, replacement);
+        if merged.metadata.is_none() {
+            merged.metadata = Some(maintenance::metadata_for(&merged, self.client.model(), js_sys::Date::now()).with_quantization(self.quantization).with_granularity(granularity));
+        }
+        self.file_processor.replace_file(&self.embedding_path, &merged.to_csv()?).await?;
+        self.file_processor.delete_file_at_path(staging_path).await?;
+        recorder.stage("merge", merged.rows.len());
+        self.register_store(merged.rows.len()).await?;
+        Ok(records_replaced)
+    }
+
+    async fn run_post_build_hooks(&self, recorder: &mut RunRecorder) {
+        if !hooks::has_post_build_hooks() {
+            return;
+        }
+        let store = match self.load_store().await {
+            Ok(store) => store,
+            Err(e) => {
+                recorder.warn(format!("post-build hooks skipped; {}", e));
+                return;
+            }
+        };
+        for warning in hooks::run_post_build_hooks(&store).await {
+            recorder.warn(warning);
+        }
+        recorder.stage("post-build hooks", store.rows.len());
+    }
+
+    /// Rewrites the embedding file without rows of notes that have left the vault, exact
+    /// duplicate rows and unreadable rows, and returns a `CompactionReport`. Unreadable rows
+    /// are moved to `<embedding file>.corrupt` like `QueryCommand` does.
+    pub async fn compact_store(&self) -> Result<JsValue, SemanticSearchError> {
+        self.file_processor.acquire_lock(&self.embedding_path).await?;
+        let result = self.run_compaction().await;
+        self.file_processor.release_lock_or_log(&self.embedding_path).await;
+        Ok(serde_wasm_bindgen::to_value(&result?)?)
+    }
+
+    async fn run_compaction(&self) -> Result<CompactionReport, SemanticSearchError> {
+        let input = self.file_processor.read_from_path(&self.embedding_path).await.map_err(SemanticSearchError::missing_embedding)?;
+        let (store, malformed) = embedding_file::read_embedding_file_lenient(&input)?;
+        let vault_names: HashSet<String> = self.file_processor.list_files(&FileFilter::default()).await.iter().map(|file| file.name()).collect();
+        let (compacted, mut report) = compaction::compact(store, &vault_names);
+        let output = compacted.to_csv()?;
+        if !malformed.is_empty() {
+            self.file_processor.write_to_path(&corrupt_path(&self.embedding_path), &embedding_file::quarantined_rows(&malformed)).await?;
+        }
+        report.malformed_removed = malformed.len();
+        report.bytes_before = input.len();
+        report.bytes_after = output.len();
+        report.bytes_reclaimed = input.len().saturating_sub(output.len());
+        self.file_processor.replace_file(&self.embedding_path, &output).await?;
+        self.register_store(compacted.rows.len()).await?;
+        debug!("Compacted {} from {} to {} rows", self.embedding_path, report.rows_before, report.rows_after);
+        Ok(report)
+    }
+
+    async fn load_store(&self) -> Result<EmbeddingFile, SemanticSearchError> {
+        let input = self.file_processor.read_from_path(&self.embedding_path).await?;
+        embedding_file::read_embedding_file(&input)
+    }
+
+    async fn register_store(&self, records: usize) -> Result<(), SemanticSearchError> {
+        stores::register(&self.file_processor, &self.store_registry_path, StoreEntry {
+            name: self.store.clone(),
+            model: self.client.model().to_string(),
+            path: self.embedding_path.clone(),
+            records,
+            updated_at: js_sys::Date::now(),
+        }).await
+    }
+
+    /// Returns a `CostEstimate` of `{ model, tokens, requests, dollars }` for the current input.csv
+    pub async fn get_input_cost_estimate(&self) -> Result<JsValue, SemanticSearchError> {
+        let input = self.file_processor.read_from_path(&self.input_path).await.map_err(SemanticSearchError::missing_input)?;
+        let records = pipeline::collect(&input, &self.embed_text)?;
+        let (batches, _) = pipeline::dedup(pipeline::batch(records, self.num_batches, self.max_tokens_per_request));
+        let requests = batches.len();
+        let texts: Vec<String> = batches.into_iter().flatten().map(|record| record.text).collect();
+        let estimate = pricing::estimate_records(self.client.model(), self.client.dollars_per_million_tokens(), &texts, requests).discounted(self.price_factor());
+        Ok(serde_wasm_bindgen::to_value(&estimate)?)
+    }
+
+    /// Returns an `EmbeddingPlan` breaking the cost of the current input.csv down per note,
+    /// after truncation, without calling the API
+    pub async fn plan_embeddings(&self) -> Result<JsValue, SemanticSearchError> {
+        let input = self.file_processor.read_from_path(&self.input_path).await.map_err(SemanticSearchError::missing_input)?;
+        let mut records = pipeline::collect(&input, &self.embed_text)?;
+        if self.max_tokens_per_record > 0 {
+            records = TruncationHook::new(self.max_tokens_per_record).preprocess(records)?;
+        }
+        let requests = pipeline::batch(records.clone(), self.num_batches, self.max_tokens_per_request).len();
+        let plan = pricing::plan_records(self.client.model(), self.client.dollars_per_million_tokens(), &records, requests).discounted(self.price_factor());
+        Ok(serde_wasm_bindgen::to_value(&plan)?)
+    }
+
+    /// Submits input.csv to the batch API instead of embedding it right away and returns a
+    /// `BatchJobStatus`. The embedding file is left alone until `poll_batch_status` finds the batch completed.
+    pub async fn submit_batch_job(&self) -> Result<JsValue, SemanticSearchError> {
+        if self.client.provider.name() != providers::DEFAULT_PROVIDER {
+            return Err(SemanticSearchError::InvalidSettings("the batch API is only available from OpenAI".to_string()));
+        }
+        if let Some(job) = BatchJob::load(&self.file_processor, &self.batch_job_path).await? {
+            if !job.batch.is_finished() {
+                return Err(SemanticSearchError::InvalidArgument(format!(
+                    "batch job {} is still {}, wait for it to finish before submitting another", job.batch.id, job.batch.status)));
+            }
+        }
+        let input = self.file_processor.read_from_path(&self.input_path).await.map_err(SemanticSearchError::missing_input)?;
+        let mut pipeline = EmbeddingPipeline::new(&self.client, &self.file_processor, &self.embedding_path, self.num_batches, self.max_tokens_per_request, self.embed_text.clone(), &self.settings_hash);
+        if self.max_tokens_per_record > 0 {
+            pipeline.add_hook(Box::new(TruncationHook::new(self.max_tokens_per_record)));
+        }
+        if self.max_cost_per_run > 0.0 {
+            pipeline.add_hook(Box::new(BudgetHook::new(self.client.model(), self.client.dollars_per_million_tokens(), self.max_cost_per_run).price_factor(batch_api::BATCH_PRICE_FACTOR)));
+        }
+        let requests = pipeline.prepare(pipeline::collect(&input, &self.embed_text)?)?;
+        if requests.is_empty() {
+            return Err(SemanticSearchError::InvalidArgument("input.csv has no sections to embed".to_string()));
+        }
+        let file = self.client.upload_batch_file(batch_api::request_lines(self.client.model(), &requests)?).await?;
+        let batch = self.client.create_batch(&file.id).await?;
+        info!("Submitted batch job {} with {} requests", batch.id, requests.len());
+        let job = BatchJob { batch, model: self.client.model().to_string(), submitted_at: js_sys::Date::now(), requests, granularity: input::granularity(&input)? };
+        job.save(&self.file_processor, &self.batch_job_path).await?;
+        Ok(serde_wasm_bindgen::to_value(&job.status())?)
+    }
+
+    /// Checks on the submitted batch job, returning its `BatchJobStatus` or null when there is none.
+    /// Once the batch has completed its results replace the embedding file and the job is cleared;
+    /// meant to be called periodically.
+    pub async fn poll_batch_status(&self) -> Result<JsValue, SemanticSearchError> {
+        let mut job = match BatchJob::load(&self.file_processor, &self.batch_job_path).await? {
+            Some(job) => job,
+            None => return Ok(JsValue::NULL),
+        };
+        if !job.batch.is_finished() {
+            job.batch = self.client.retrieve_batch(&job.batch.id).await?;
+            debug!("Batch job {} is {}", job.batch.id, job.batch.status);
+            job.save(&self.file_processor, &self.batch_job_path).await?;
+        }
+        let mut status = job.status();
+        if !status.finished {
+            return Ok(serde_wasm_bindgen::to_value(&status)?);
+        }
+        if job.batch.status == "completed" {
+            status.report = Some(self.apply_batch_job(job).await?);
+        } else {
+            warn!("Batch job {} {} without results", job.batch.id, job.batch.status);
+        }
+        self.file_processor.delete_file_at_path(&self.batch_job_path).await?;
+        Ok(serde_wasm_bindgen::to_value(&status)?)
     }
 
     pub async fn check_embedding_file_exists(&self) -> Result<bool, SemanticSearchError> {
-        let exists = self.file_processor.check_file_exists_at_path(EMBEDDING_FILE_PATH).await?;
+        let exists = self.file_processor.check_file_exists_at_path(&self.embedding_path).await?;
         Ok(exists)
     }
+}
+
+impl GenerateEmbeddingsCommand {
+    /// Share of the regular price paid for the embeddings this command generates
+    fn price_factor(&self) -> f32 {
+        if self.use_batch_api { batch_api::BATCH_PRICE_FACTOR } else { 1.0 }
+    }
 
-    fn get_content_to_embed(&self, input: String) -> Result<Vec<String>, SemanticSearchError> {
-        let mut reader = ReaderBuilder::new().trim(csv::Trim::All).flexible(false)
-            .from_reader(input.as_bytes());
-        let records = reader.records().collect::<Result<Vec<StringRecord>, csv::Error>>()?;
-        let string_records = records.iter().map(|record| {
-            record.get(2).unwrap().to_string()
-        }).collect();
-        Ok(string_records)
+    /// Downloads the results of a completed batch job and writes them over the embedding file
+    async fn apply_batch_job(&self, job: BatchJob) -> Result<PipelineReport, SemanticSearchError> {
+        if job.model != self.client.model() {
+            return Err(SemanticSearchError::InvalidSettings(format!(
+                "batch job {} was embedded with {} but the model is now {}, switch back to apply its results", job.batch.id, job.model, self.client.model())));
+        }
+        let output = match &job.batch.output_file_id {
+            Some(id) => self.client.file_content(id).await?,
+            None => String::new(),
+        };
+        let output = batch_api::parse_output(&output, job.requests.len())?;
+        let granularity = job.granularity;
+        let responses = job.requests.into_iter().zip(output.responses)
+            .filter_map(|(records, response)| Some((records, response?)))
+            .collect();
+
+        let task = JobHandle::start("batch results");
+        let mut recorder = RunRecorder::start("batch results");
+        for failure in output.failures {
+            recorder.warn(format!("batch request failed; {}", failure));
+        }
+        self.file_processor.acquire_lock(&self.embedding_path).await?;
+        task.hold_lock(&self.embedding_path);
+
+        let result = self.write_batch_responses(responses, granularity, &task, &mut recorder).await;
+
+        self.file_processor.release_lock_or_log(&self.embedding_path).await;
+        task.release_lock(&self.embedding_path);
+        if result.is_ok() {
+            self.run_post_build_hooks(&mut recorder).await;
+        }
+        let manifest = recorder.finish(&result);
+        if let Err(e) = manifest::save(&self.file_processor, &self.manifest_path, &manifest).await {
+            error!("Failed to save run manifest: {}", e);
+        }
+        result
     }
 
-    fn get_filename_body(&self, input: String) -> Result<Vec<(String, String)>, SemanticSearchError> {
-        let mut reader = ReaderBuilder::new().trim(csv::Trim::All).flexible(false)
-            .from_reader(input.as_bytes());
-        let records = reader.records().collect::<Result<Vec<StringRecord>, csv::Error>>()?;
-        let filename_body = records.iter().map(|record| 
-                           (record.get(0).unwrap().to_string(), record.get(2).unwrap().to_string())
-                          ).collect();
-        Ok(filename_body)
+    /// Writes the embeddings of a finished batch job, whose records were extracted at `granularity`, to
+    /// `<embedding file>.tmp` and swaps it in, leaving the embedding file as it was if they cannot be written
+    async fn write_batch_responses(&self, responses: Vec<(Vec<PipelineRecord>, EmbeddingResponse)>, granularity: Granularity, job: &JobHandle, recorder: &mut RunRecorder) -> Result<PipelineReport, SemanticSearchError> {
+        let temp_path = file_processor::temp_path(&self.embedding_path);
+        self.file_processor.delete_file_at_path(&temp_path).await?;
+        let mut pipeline = EmbeddingPipeline::new(&self.client, &self.file_processor, &temp_path, self.num_batches, self.max_tokens_per_request, self.embed_text.clone(), &self.settings_hash);
+        self.configure_pipeline(&mut pipeline, &self.client, job);
+        pipeline.granularity(granularity);
+        let report = match pipeline.run_responses(responses, recorder).await {
+            Ok(report) => report,
+            Err(e) => {
+                self.file_processor.delete_file_at_path(&temp_path).await?;
+                return Err(e);
+            },
+        };
+        self.file_processor.swap_in(&self.embedding_path).await?;
+        debug!("Saved batch embeddings to {}", self.embedding_path);
+        recorder.set_report(&report);
+        self.register_store(report.records_embedded).await?;
+        Ok(report)
+    }
+
+    /// Re-embeds the notes changed in the vault: `records`, extracted at `granularity`, replace every
+    /// stored record of `notes`, so notes without records are removed from the store. Takes the store lock like the other
+    /// commands but leaves the last run report alone, since it runs in the background.
+    pub(crate) async fn update_notes(&self, records: Vec<InputRecord>, notes: &NoteSet, granularity: Granularity) -> Result<ReembedReport, SemanticSearchError> {
+        let job = JobHandle::start("auto-index");
+        let mut recorder = RunRecorder::start("auto-index");
+        self.file_processor.acquire_lock(&self.embedding_path).await?;
+        job.hold_lock(&self.embedding_path);
+
+        let result = self.replace_records(&job, &mut recorder, records, notes, granularity).await;
+
+        self.file_processor.release_lock_or_log(&self.embedding_path).await;
+        job.release_lock(&self.embedding_path);
+        let (records_replaced, report) = result?;
+        Ok(ReembedReport { notes: notes.count(), records_replaced, missing: Vec::new(), report })
     }
 }
 
@@ -145,78 +700,1064 @@ impl GenerateEmbeddingsCommand {
 pub struct QueryCommand {
     file_processor: FileProcessor,
     client: Client,
+    embedding_path: String,
+    normalization: ScoreNormalization,
+    grouping: FileGrouping,
+    stages: Vec<Stage>,
+    show_snippets: bool,
+    /// Chat model and number of reformulations, when query expansion is enabled
+    expansion: Option<(String, usize)>,
+    reranker: Option<Reranker>,
+    input_path: String,
+    skip_malformed_rows: bool,
+    language_stores: Vec<LanguageStore>,
+    /// Stored chunks the last ranking left out because their embeddings cannot be scored
+    degenerate_rows: Cell<usize>,
+    /// Rows the last load of the embedding file could not read, moved to `.corrupt` or skipped
+    malformed_rows: Cell<usize>,
+    app: obsidian::App,
 }
 
 #[wasm_bindgen]
 impl QueryCommand {
-    async fn get_similarity(&self, query: String) -> Result<Vec<Suggestions>, SemanticSearchError> {
-        let mut rows = self.get_embedding_rows().await?;
-        let response = self.client.get_embedding(query.into()).await?;
-        debug!("Sucessfully obtained {} embeddings", response.data.len());
-        let query_embedding = response.data[0].clone().embedding;
-        rows.sort_unstable_by(|row1, row2| cosine_similarity(query_embedding.clone(), row1.clone().2).partial_cmp(&cosine_similarity(query_embedding.to_owned(), row2.clone().2)).unwrap());
-        rows.reverse();
-        let ranked = rows.iter().map(|(name, header, _)| Suggestions { name: name.to_string(), header: header.to_string() }).collect();
-        Ok(ranked)
+    fn new(app: &obsidian::App, settings: &Settings) -> Self {
+        let file_processor = FileProcessor::new(app.vault());
+        let client = Client::new(settings);
+        QueryCommand {
+            file_processor,
+            client,
+            embedding_path: settings.embedding_path(),
+            normalization: settings.score_normalization,
+            grouping: settings.group_by_file,
+            stages: settings.ranking_stages(),
+            show_snippets: settings.show_snippets,
+            expansion: match settings.query_expansion_model.trim() {
+                "" => None,
+                model => Some((model.to_string(), settings.query_expansion_count.max(1))),
+            },
+            reranker: Reranker::from_settings(settings),
+            input_path: settings.input_path(),
+            skip_malformed_rows: settings.skip_malformed_rows,
+            language_stores: language_stores(settings),
+            degenerate_rows: Cell::new(0),
+            malformed_rows: Cell::new(0),
+            app: app.clone(),
+        }
+    }
+
+    /// Changes to candidate scores for a boost stage: tag boosts, reading each note's tags from
+    /// Obsidian's metadata cache, then recency decay, reading modification times from `store`
+    fn score_adjustments<'a>(&self, tag_boosts: &'a [TagBoost], recency_half_life_days: Option<f64>, candidates: &[ScoredCandidate], store: &EmbeddingFile) -> Vec<Box<dyn ScoreAdjustment + 'a>> {
+        let mut adjustments: Vec<Box<dyn ScoreAdjustment>> = Vec::new();
+        if !tag_boosts.is_empty() {
+            adjustments.push(Box::new(TagBoosting { boosts: tag_boosts, tags: self.note_tags(candidates.iter().map(|candidate| candidate.name.as_str()).collect()) }));
+        }
+        if let Some(half_life_days) = recency_half_life_days {
+            let modified = store.rows.iter().zip(&store.provenance)
+                .filter_map(|((name, _, _), provenance)| Some((name.clone(), provenance.as_ref()?.note_times?.modified)))
+                .collect();
+            adjustments.push(Box::new(RecencyDecay { half_life_days, now: js_sys::Date::now(), modified }));
+        }
+        adjustments
+    }
+
+    /// Tags of the notes named in `names` by file name, from Obsidian's metadata cache
+    fn note_tags(&self, names: HashSet<&str>) -> HashMap<String, Vec<String>> {
+        let metadata_cache = self.app.metadataCache();
+        self.app.vault().getMarkdownFiles().into_iter()
+            .filter(|file| names.contains(file.name().as_str()))
+            .filter_map(|file| {
+                let cache = metadata_cache.getFileCache(&file);
+                if cache.is_null() || cache.is_undefined() {
+                    return None;
+                }
+                let tags: Vec<String> = serde_wasm_bindgen::from_value(obsidian::getAllTags(&cache)).ok()?;
+                Some((file.name(), tags))
+            })
+            .collect()
+    }
+
+    /// Runs ranked candidates through the configured ranking stages, see `Settings::ranking_stages`,
+    /// and returns the suggestions to show. Reranking is skipped without a `query`.
+    /// Each suggestion gets the note lines its record in `store` was taken from, if known, a link
+    /// to its section in the note at the path its record was stored with, or else the note with its
+    /// file name, using the headings from input.csv, and a snippet of its text around the
+    /// sentence best matching `query`, if enabled.
+    async fn run_stages(&self, query: Option<&str>, mut candidates: Vec<ScoredCandidate>, store: &EmbeddingFile) -> Vec<Suggestions> {
+        for stage in &self.stages {
+            candidates = match (stage, query) {
+                (Stage::Rerank, Some(query)) => self.rerank(query, candidates).await,
+                (Stage::Rerank, None) => candidates,
+                (Stage::Boost { tag_boosts, recency_half_life_days }, _) => {
+                    let adjustments = self.score_adjustments(tag_boosts, *recency_half_life_days, &candidates, store);
+                    ranking::adjust_scores(&mut candidates, &adjustments);
+                    candidates
+                },
+                (Stage::GroupByFile(grouping), _) => {
+                    let granularity = store.metadata.as_ref().map(|metadata| metadata.granularity).unwrap_or_default();
+                    ranking::group_by_file(candidates, granularity.file_grouping(*grouping))
+                },
+                (Stage::Diversify { lambda, count, pool_size }, _) => ranking::mmr(candidates, *lambda, *count, *pool_size),
+                (Stage::Truncate { max_results, adaptive }, _) => ranking::truncate(candidates, *max_results, *adaptive),
+            };
+        }
+        let lines: HashMap<(&str, &str), LineRange> = store.rows.iter().zip(&store.provenance)
+            .filter_map(|((name, header, _), provenance)| Some(((name.as_str(), header.as_str()), provenance.as_ref()?.lines?)))
+            .collect();
+        let recorded_paths: HashMap<(&str, &str), &str> = store.rows.iter().zip(&store.provenance)
+            .filter_map(|((name, header, _), provenance)| Some(((name.as_str(), header.as_str()), provenance.as_ref()?.path.as_deref()?)))
+            .collect();
+        if candidates.is_empty() {
+            return Vec::new();
+        }
+        let records = self.read_section_texts().await;
+        let sections: HashMap<(&str, &str), &InputRecord> = records.iter()
+            .map(|record| ((record.name.as_str(), record.header.as_str()), record))
+            .collect();
+        let files = self.file_processor.list_files(&FileFilter::default()).await;
+        let vault_paths: HashSet<String> = files.iter().map(|file| file.path()).collect();
+        // for rows stored before their note's path was recorded, which can only be told apart by file name
+        let paths: HashMap<String, String> = files.iter()
+            .map(|file| (file.name(), file.path()))
+            .collect();
+        let vault = self.app.vault().getName();
+        candidates.into_iter().map(|candidate| {
+            let key = (candidate.name.as_str(), candidate.header.as_str());
+            let lines = lines.get(&key).copied();
+            let section = sections.get(&key);
+            let snippet = section.filter(|_| self.show_snippets).and_then(|section| snippets::snippet(&section.body, query));
+            let path = recorded_paths.get(&key).copied().filter(|path| vault_paths.contains(*path))
+                .or_else(|| paths.get(&candidate.name).map(String::as_str));
+            let link = path.map(|path| {
+                let headings = section.map(|section| section.heading_path().into_iter().map(str::to_string).collect()).unwrap_or_default();
+                SectionLink::new(&vault, path, headings, lines.map(|lines| lines.start))
+            });
+            Suggestions { lines, snippet, link, ..Suggestions::from(candidate) }
+        }).collect()
+    }
+
+    /// Suggestions for `query`, which may end in negative terms, among the chunks matching the
+    /// `QueryFilters` in `filters`
+    async fn suggest(&mut self, query: &str, filters: &JsValue) -> Result<Vec<Suggestions>, SemanticSearchError> {
+        let mut filters = QueryFilters::from_js(filters)?;
+        let (query, exclude) = negative::split_query(query);
+        self.route_language(&query).await?;
+        filters.resolve(&self.file_processor).await?;
+        let queries = self.expand_queries(vec![query.clone()]).await;
+        let (store, candidates) = self.get_similarity(queries, exclude, &filters).await?;
+        Ok(self.run_stages(Some(&query), candidates, &store).await)
+    }
+
+    /// Ranks the stored chunks matching `filters` against `queries`, steering away from chunks
+    /// like `exclude` if given, and returns the store along with them
+    async fn get_similarity(&self, mut queries: Vec<String>, exclude: Option<String>, filters: &QueryFilters) -> Result<(EmbeddingFile, Vec<ScoredCandidate>), SemanticSearchError> {
+        let (store, quantized) = self.load_comparable_store().await?;
+        let excluding = exclude.is_some();
+        queries.extend(exclude);
+        let mut query_embeddings: Vec<Vec<f32>> = self.client.embed_queries(&queries).await?
+            .into_iter().map(|query_embedding| store.fit_query(query_embedding)).collect();
+        for query_embedding in &query_embeddings {
+            store.check_dimensions(query_embedding.len())?;
+        }
+        let exclude_embedding = if excluding { query_embeddings.pop() } else { None };
+        let candidates = self.rank(&query_embeddings, exclude_embedding.as_deref(), &store.rows, quantized.as_deref(), &|i| filters.keeps(&store, i));
+        Ok((store, candidates))
+    }
+
+    /// Adds reformulations from the configured chat model to a single query.
+    ///
+    /// Expansion is best effort: if the chat request fails the original query is searched alone.
+    async fn expand_queries(&self, queries: Vec<String>) -> Vec<String> {
+        let (model, count) = match &self.expansion {
+            Some(expansion) if queries.len() == 1 => expansion,
+            _ => return queries,
+        };
+        match self.client.reformulate(model, &queries[0], *count).await {
+            Ok(reformulations) => {
+                debug!("Expanded query into {} reformulations", reformulations.len());
+                queries.into_iter().chain(reformulations).collect()
+            },
+            Err(e) => {
+                error!("Query expansion failed, searching the original query only: {}", e);
+                queries
+            },
+        }
+    }
+
+    /// Reorders the top candidates with the configured reranker, which reads their stored section text.
+    ///
+    /// Like query expansion this is best effort: if the reranker fails the vector ranking is kept.
+    async fn rerank(&self, query: &str, mut candidates: Vec<ScoredCandidate>) -> Vec<ScoredCandidate> {
+        let reranker = match &self.reranker {
+            Some(reranker) => reranker,
+            None => return candidates,
+        };
+        candidates.truncate(rerank::RERANK_CANDIDATES);
+        let documents = rerank::candidate_texts(&candidates);
+        match reranker.score(query, &documents).await {
+            Ok(scores) => rerank::reorder(candidates, &scores),
+            Err(e) => {
+                error!("Reranking failed, keeping the vector ranking: {}", e);
+                candidates
+            },
+        }
+    }
+
+    /// Ranks stored chunks against every query embedding, merging the rankings of
+    /// several queries with reciprocal rank fusion. Chunks similar to `exclude` are
+    /// penalized in every ranking. `quantized` holds the stored vectors of an int8 store, in the
+    /// same order, to score with instead; the rows of such stores have no f32 embeddings.
+    ///
+    /// Only the chunks whose index `keep` accepts are scored, so narrow filters make searches faster.
+    /// Chunks with degenerate embeddings, such as all zeros, are skipped and counted in `degenerate_rows`.
+    fn rank(&self, query_embeddings: &[Vec<f32>], exclude: Option<&[f32]>, rows: &[(String, String, Vec<f32>)], quantized: Option<&[QuantizedVector]>, keep: &dyn Fn(usize) -> bool) -> Vec<ScoredCandidate> {
+        let is_degenerate = |i: usize| match quantized {
+            Some(quantized) => quantized[i].is_degenerate(),
+            None => math::is_degenerate(&rows[i].2),
+        };
+        let (kept, degenerate): (Vec<usize>, Vec<usize>) = (0..rows.len()).filter(|&i| keep(i)).partition(|&i| !is_degenerate(i));
+        self.degenerate_rows.set(degenerate.len());
+        if kept.len() + degenerate.len() < rows.len() {
+            debug!("Scoring {} of {} chunks that match the filters", kept.len(), rows.len());
+        }
+        let excluded = exclude.map(|exclude| self.similarities(exclude, rows, &kept, quantized));
+        if let [query_embedding] = query_embeddings {
+            return self.score(query_embedding, excluded.as_deref(), rows, &kept, quantized);
+        }
+        let rankings = query_embeddings.iter().map(|query_embedding| self.score(query_embedding, excluded.as_deref(), rows, &kept, quantized)).collect();
+        let mut fused = fusion::reciprocal_rank_fusion(rankings, fusion::RRF_K);
+        fusion::normalize(&mut fused, self.normalization);
+        fused
+    }
+
+    /// Cosine similarity to `embedding` of each stored chunk in `kept`, in the same order
+    fn similarities(&self, embedding: &[f32], rows: &[(String, String, Vec<f32>)], kept: &[usize], quantized: Option<&[QuantizedVector]>) -> Vec<f32> {
+        let quantized_query = quantized.map(|_| QuantizedVector::quantize(embedding));
+        kept.iter().map(|&i| match (&quantized_query, quantized) {
+            (Some(query), Some(quantized)) => query.cosine(&quantized[i]),
+            _ => cosine_similarity(embedding, &rows[i].2),
+        }).collect()
+    }
+
+    /// Scores the stored chunks in `kept` against an embedded query and returns the best of them, ranked.
+    /// `excluded` holds each of their similarities to a negative query, which is taken off its score.
+    ///
+    /// Scores are normalized over every kept chunk before the best are picked, and only those are
+    /// turned into candidates. Grouping by mean score needs every chunk of a note, so it ranks them all.
+    fn score(&self, query_embedding: &[f32], excluded: Option<&[f32]>, rows: &[(String, String, Vec<f32>)], kept: &[usize], quantized: Option<&[QuantizedVector]>) -> Vec<ScoredCandidate> {
+        let mut scores = self.similarities(query_embedding, rows, kept, quantized);
+        if let Some(excluded) = excluded {
+            for (score, &excluded) in scores.iter_mut().zip(excluded) {
+                *score = negative::penalize(*score, excluded);
+            }
+        }
+        fusion::normalize_scores(&mut scores, self.normalization);
+        let pool = match self.grouping {
+            FileGrouping::Mean => kept.len(),
+            _ => RANK_POOL_SIZE,
+        };
+        let mut seen = HashSet::new();
+        ranking::top_k(&scores, pool, |_| true).into_iter()
+            .filter(|&j| seen.insert((&rows[kept[j]].0, &rows[kept[j]].1)))
+            .map(|j| {
+                let (name, header, embedding) = &rows[kept[j]];
+                let embedding = quantized.map_or_else(|| embedding.clone(), |quantized| quantized[kept[j]].dequantize());
+                ScoredCandidate { name: name.clone(), header: header.clone(), score: scores[j], source: self.embedding_path.clone(), embedding }
+            })
+            .collect()
+    }
+
+    /// `suggestions` as a JS array, whose `skipped` property is the number of stored chunks the
+    /// last ranking left out because their embeddings cannot be scored, and whose `malformed`
+    /// property is the number of rows the embedding file was read without
+    fn suggestions_value(&self, suggestions: &[Suggestions]) -> Result<JsValue, SemanticSearchError> {
+        let value = serde_wasm_bindgen::to_value(suggestions)?;
+        js_sys::Reflect::set(&value, &"skipped".into(), &JsValue::from(self.degenerate_rows.get() as u32))?;
+        js_sys::Reflect::set(&value, &"malformed".into(), &JsValue::from(self.malformed_rows.get() as u32))?;
+        Ok(value)
+    }
+
+    async fn load_embedding_file(&self) -> Result<EmbeddingFile, SemanticSearchError> {
+        Ok(self.load_store_file(false).await?.0)
+    }
+
+    /// Loads the stored chunks for searching: the vectors of int8 stores are returned as written,
+    /// see `embedding_file::read_searchable_store`
+    async fn load_searchable_store(&self) -> Result<(EmbeddingFile, Option<Vec<QuantizedVector>>), SemanticSearchError> {
+        self.load_store_file(true).await
+    }
+
+    async fn load_store_file(&self, searchable: bool) -> Result<(EmbeddingFile, Option<Vec<QuantizedVector>>), SemanticSearchError> {
+        let input = self.file_processor.read_consistent(&self.embedding_path).await.map_err(SemanticSearchError::missing_embedding)?;
+        self.malformed_rows.set(0);
+        let (store, quantized, malformed) = match searchable {
+            true => embedding_file::read_searchable_store(&input, self.skip_malformed_rows)?,
+            false if self.skip_malformed_rows => {
+                let (store, malformed) = embedding_file::read_embedding_file_lenient(&input)?;
+                (store, None, malformed)
+            },
+            false => (embedding_file::read_embedding_file(&input)?, None, Vec::new()),
+        };
+        self.malformed_rows.set(malformed.len());
+        // the rewritten file is read back with f32 embeddings, which are searched without the int8 copies until the next load
+        let (store, quantized) = match malformed.is_empty() {
+            true => (store, quantized),
+            false => match self.quarantine().await {
+                Ok(store) => (store, None),
+                Err(e) => {
+                    error!("Failed to move malformed rows out of {}: {}", self.embedding_path, e);
+                    warn_malformed(&self.embedding_path, &malformed);
+                    (store, quantized)
+                },
+            },
+        };
+        self.warn_degenerate(&store, quantized.as_deref());
+        Ok((store, quantized))
+    }
+
+    /// Warns once per load about stored chunks searches will skip. Only note names are logged,
+    /// since the section text is note content.
+    fn warn_degenerate(&self, store: &EmbeddingFile, quantized: Option<&[QuantizedVector]>) {
+        let mut degenerate = store.rows.iter().enumerate().filter(|(i, (_, _, embedding))| match quantized {
+            Some(quantized) => quantized[*i].is_degenerate(),
+            None => math::is_degenerate(embedding),
+        });
+        if let Some((_, (first, _, _))) = degenerate.next() {
+            warn!("Skipped {} chunks of {} whose embeddings are all zeros or not numbers, starting with a chunk of {}; regenerate embeddings to fix them",
+                degenerate.count() + 1, self.embedding_path, first);
+        }
+    }
+
+    /// Moves rows that could not be read to `<embedding file>.corrupt` and rewrites the embedding
+    /// file with the rest, so they are reported once rather than on every search. The file is read
+    /// again under the lock, so rows another command wrote since it was loaded are not lost.
+    /// Returns the readable rows.
+    async fn quarantine(&self) -> Result<EmbeddingFile, SemanticSearchError> {
+        let corrupt_path = corrupt_path(&self.embedding_path);
+        self.file_processor.acquire_lock(&self.embedding_path).await?;
+        let result = async {
+            let input = self.file_processor.read_consistent(&self.embedding_path).await?;
+            let (store, malformed) = embedding_file::read_embedding_file_lenient(&input)?;
+            if !malformed.is_empty() {
+                self.file_processor.write_to_path(&corrupt_path, &embedding_file::quarantined_rows(&malformed)).await?;
+                self.file_processor.replace_file(&self.embedding_path, &store.to_csv()?).await?;
+            }
+            Ok::<_, SemanticSearchError>((store, malformed))
+        }.await;
+        self.file_processor.release_lock_or_log(&self.embedding_path).await;
+        let (store, malformed) = result?;
+        if let Some(first) = malformed.first() {
+            let message = format!("Moved {} unreadable rows of {} to {}, starting with {}", malformed.len(), self.embedding_path, corrupt_path, first);
+            warn!("{}", message);
+            Notice::new(&message);
+        }
+        Ok(store)
+    }
+
+    /// Records from input.csv, whose bodies stand in for section texts. Best effort: without a
+    /// readable input.csv callers fall back to headers.
+    async fn read_section_texts(&self) -> Vec<InputRecord> {
+        let input = match self.file_processor.read_from_path(&self.input_path).await {
+            Ok(input) => input,
+            Err(_) => return Vec::new(),
+        };
+        let records = match self.skip_malformed_rows {
+            true => input::read_input_lenient(&input).map(|(records, malformed)| {
+                warn_malformed(&self.input_path, &malformed);
+                records
+            }),
+            false => input::read_input(&input),
+        };
+        records.unwrap_or_else(|e| {
+            warn!("Could not read section texts, using headers instead: {}", e);
+            Vec::new()
+        })
     }
 
     async fn get_embedding_rows(&self) -> Result<Vec<(String, String, Vec<f32>)>, SemanticSearchError> {
-        let input = self.file_processor.read_from_path(EMBEDDING_FILE_PATH).await?;
-        let mut reader = ReaderBuilder::new().trim(csv::Trim::All).flexible(false)
-            .from_reader(input.as_bytes());
-        let records = reader.records().collect::<Result<Vec<StringRecord>, csv::Error>>()?;
-        let rows = records.iter().map(|record| 
-                           (record.get(0).unwrap().to_string(), 
-                            record.get(1).unwrap().to_string(),
-                            record.get(2).unwrap().to_string().split(",").map(|s| s.parse::<f32>().unwrap()).collect())
-                          ).collect();
-        Ok(rows)
+        Ok(self.load_embedding_file().await?.rows)
+    }
+
+    /// Searches for `query` and packs the text of the best section of each matching note into
+    /// `token_budget` tokens, see `context::build_context`
+    async fn build_context(&mut self, query: &str, token_budget: usize) -> Result<RagContext, SemanticSearchError> {
+        self.route_language(query).await?;
+        let queries = self.expand_queries(vec![query.to_string()]).await;
+        let (_, candidates) = self.get_similarity(queries, None, &QueryFilters::default()).await?;
+        // the header column holds each section's text, which starts with its heading
+        let sections = candidates.into_iter().map(|candidate| {
+            let header = candidate.header.lines().next().unwrap_or_default().to_string();
+            ContextSection { name: candidate.name, header, score: candidate.score, text: candidate.header }
+        });
+        let context = context::build_context(sections, token_budget);
+        debug!("Built a context of {} tokens from {} sections", context.tokens, context.sources.len());
+        Ok(context)
+    }
+
+    /// Searches the store of `query`'s language instead of the embedding file, with its model,
+    /// when that language is embedded with its own model and its store has been built
+    async fn route_language(&mut self, query: &str) -> Result<(), SemanticSearchError> {
+        let index = match language::route(query, self.language_stores.iter().map(|(language, _, _)| language.as_str())) {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        if self.file_processor.check_file_exists_at_path(&self.language_stores[index].2).await? {
+            let (language, client, path) = self.language_stores.swap_remove(index);
+            debug!("Searching {} for a query in {}", path, language);
+            self.client = client;
+            self.embedding_path = path;
+        }
+        Ok(())
     }
+
+    /// Loads the stored chunks for searching, refusing to go on if queries from the configured model cannot be compared to them
+    async fn load_comparable_store(&self) -> Result<(EmbeddingFile, Option<Vec<QuantizedVector>>), SemanticSearchError> {
+        let (store, quantized) = self.load_searchable_store().await?;
+        store.check_model(self.client.model())?;
+        Ok((store, quantized))
+    }
+}
+
+/// Where rows of the embedding file at `path` that could not be read are moved
+fn corrupt_path(path: &str) -> String {
+    format!("{}{}", path, embedding_file::CORRUPT_SUFFIX)
 }
 
-fn cosine_similarity(left: Vec<f32>, right: Vec<f32>) -> f32 {
-    let a1  = Array1::from_vec(left);
-    let a2 = Array1::from_vec(right);
-    a1.dot(&a2) / a1.dot(&a1).sqrt() * a2.dot(&a2).sqrt()
+fn warn_malformed(path: &str, malformed: &[MalformedRow]) {
+    if let Some(first) = malformed.first() {
+        warn!("Skipped {} malformed rows of {}, starting with {}", malformed.len(), path, first);
+    }
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct Suggestions {
     name: String,
     header: String,
+    score: f32,
+    /// Lines of the note the suggestion was embedded from, for deep links
+    lines: Option<LineRange>,
+    /// Excerpt of the suggestion's text, when snippets are enabled and its section is in input.csv
+    snippet: Option<Snippet>,
+    /// Where to open the suggestion, when it is from a note still in the vault
+    link: Option<SectionLink>,
+}
+
+impl From<ScoredCandidate> for Suggestions {
+    fn from(candidate: ScoredCandidate) -> Self {
+        Suggestions { name: candidate.name, header: candidate.header, score: candidate.score, lines: None, snippet: None, link: None }
+    }
+}
+
+/// Returns suggestions for `query` among the chunks matching the optional `QueryFilters`,
+/// e.g. `{ path: "Projects/**" }`. The array's `skipped` property counts the stored chunks left
+/// out because their embeddings are all zeros or not numbers.
+#[wasm_bindgen]
+pub async fn get_suggestions(app: &obsidian::App, settings: JsValue, query: JsString, filters: JsValue) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let mut query_cmd = QueryCommand::new(app, &settings);
+    let ranked_suggestions = query_cmd.suggest(&query.as_string().unwrap(), &filters).await?;
+    query_cmd.suggestions_value(&ranked_suggestions)
+}
+
+/// Searches several phrasings of the same question at once and merges their rankings
+/// with reciprocal rank fusion. A single query is expanded first if query expansion is configured.
+/// Only chunks matching the optional `QueryFilters` are ranked, and chunks similar to its
+/// `exclude` text are penalized in every ranking.
+#[wasm_bindgen]
+pub async fn get_suggestions_for_queries(app: &obsidian::App, settings: JsValue, queries: JsValue, filters: JsValue) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let mut filters = QueryFilters::from_js(&filters)?;
+    let queries: Vec<String> = serde_wasm_bindgen::from_value(queries)
+        .map_err(|e| SemanticSearchError::InvalidArgument(format!("Expected a list of queries: {}", e)))?;
+    let queries: Vec<String> = queries.into_iter().map(|query| query.trim().to_string()).filter(|query| !query.is_empty()).collect();
+    if queries.is_empty() {
+        return Err(SemanticSearchError::InvalidArgument("No queries given".to_string()));
+    }
+    let mut query_cmd = QueryCommand::new(app, &settings);
+    let query = queries[0].clone();
+    query_cmd.route_language(&query).await?;
+    let queries = query_cmd.expand_queries(queries).await;
+    filters.resolve(&query_cmd.file_processor).await?;
+    let exclude = Some(filters.exclude.trim().to_string()).filter(|exclude| !exclude.is_empty());
+    let (store, candidates) = query_cmd.get_similarity(queries, exclude, &filters).await?;
+    let ranked_suggestions = query_cmd.run_stages(Some(&query), candidates, &store).await;
+    query_cmd.suggestions_value(&ranked_suggestions)
+}
+
+/// Finds chunks related to an arbitrary passage, such as the current editor selection.
+///
+/// The passage is cleaned the same way as note sections, and the chunk it was
+/// taken from is left out of the results.
+#[wasm_bindgen]
+pub async fn get_suggestions_for_text(app: &obsidian::App, settings: JsValue, text: JsString) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let text = generate_input::clean_text(&text.as_string().unwrap_or_default());
+    if text.is_empty() {
+        return Err(SemanticSearchError::InvalidArgument("No text selected".to_string()));
+    }
+    let mut query_cmd = QueryCommand::new(app, &settings);
+    query_cmd.route_language(&text).await?;
+    let (store, mut candidates) = query_cmd.get_similarity(vec![text.clone()], None, &QueryFilters::default()).await?;
+    candidates.retain(|candidate| candidate.header != text);
+    let ranked_suggestions = query_cmd.run_stages(Some(&text), candidates, &store).await;
+    query_cmd.suggestions_value(&ranked_suggestions)
+}
+
+/// Returns `LinkCandidate`s for a paragraph being written in the note at `current_path`, most
+/// similar first, for inserting a wiki link to a related section. The current note is left out.
+#[wasm_bindgen]
+pub async fn suggest_links(app: &obsidian::App, settings: JsValue, paragraph_text: JsString, current_path: String) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let text = generate_input::clean_text(&paragraph_text.as_string().unwrap_or_default());
+    if text.is_empty() {
+        return Err(SemanticSearchError::InvalidArgument("The paragraph is empty".to_string()));
+    }
+    let current_name = current_path.rsplit('/').next().unwrap_or_default();
+    let mut query_cmd = QueryCommand::new(app, &settings);
+    query_cmd.route_language(&text).await?;
+    let (store, mut candidates) = query_cmd.get_similarity(vec![text.clone()], None, &QueryFilters::default()).await?;
+    candidates.retain(|candidate| candidate.name != current_name && candidate.name != current_path);
+    let ranked = query_cmd.run_stages(Some(&text), candidates, &store).await.into_iter()
+        .filter_map(|suggestion| Some((suggestion.link?, suggestion.score)));
+    let links: Vec<LinkCandidate> = links::link_candidates(ranked, &current_path);
+    Ok(serde_wasm_bindgen::to_value(&links)?)
+}
+
+/// Finds notes similar to a set of seed notes, identified by file name, leaving the seeds out.
+///
+/// Uses the stored vectors of the seeds, so no embedding request is made.
+#[wasm_bindgen]
+pub async fn get_similar_to_notes(app: &obsidian::App, settings: JsValue, notes: JsValue, mode: JsValue) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let seeds: Vec<String> = serde_wasm_bindgen::from_value(notes)
+        .map_err(|e| SemanticSearchError::InvalidArgument(format!("Expected a list of note names: {}", e)))?;
+    let mode: SeedMode = if mode.is_undefined() || mode.is_null() {
+        SeedMode::default()
+    } else {
+        serde_wasm_bindgen::from_value(mode).map_err(|e| SemanticSearchError::InvalidArgument(format!("Invalid seed mode: {}", e)))?
+    };
+    let query_cmd = QueryCommand::new(app, &settings);
+    let store = query_cmd.load_embedding_file().await?;
+    let scored = seeding::score_against_seeds(&store.rows, &seeds, mode)
+        .ok_or_else(|| SemanticSearchError::InvalidArgument("None of the selected notes have embeddings".to_string()))?;
+    if scored.is_empty() {
+        return Err(SemanticSearchError::InvalidArgument("Every indexed note is among the selected notes".to_string()));
+    }
+    let (indices, mut scores): (Vec<usize>, Vec<f32>) = scored.into_iter().unzip();
+    fusion::normalize_scores(&mut scores, query_cmd.normalization);
+    let mut seen = HashSet::new();
+    let candidates = ranking::top_k(&scores, RANK_POOL_SIZE, |_| true).into_iter()
+        .filter(|&j| seen.insert((&store.rows[indices[j]].0, &store.rows[indices[j]].1)))
+        .map(|j| {
+            let (name, header, embedding) = &store.rows[indices[j]];
+            ScoredCandidate { name: name.clone(), header: header.clone(), score: scores[j], source: query_cmd.embedding_path.clone(), embedding: embedding.clone() }
+        })
+        .collect();
+    let notes = ranking::group_by_file(candidates, FileGrouping::Max);
+    debug!("Found {} notes similar to {} seeds", notes.len(), seeds.len());
+    Ok(serde_wasm_bindgen::to_value(&query_cmd.run_stages(None, notes, &store).await)?)
 }
 
+/// Returns a `DuplicateReport` with the clusters of chunks whose embeddings are at least
+/// `threshold` similar. The scan yields to the UI thread between slices and stops after
+/// `duplicates::MAX_COMPARISONS` pairs.
 #[wasm_bindgen]
-pub async fn get_suggestions(app: &obsidian::App, api_key: JsString, query: JsString) -> Result<JsValue, JsError> {
-    let query_string = query.as_string().unwrap();
+pub async fn find_duplicates(app: &obsidian::App, settings: JsValue, threshold: f32) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let query_cmd = QueryCommand::new(app, &settings);
+    let rows = query_cmd.get_embedding_rows().await?;
+    let mut scan = duplicates::DuplicateScan::new(&rows, threshold);
+    let mut truncated = false;
+    while scan.step(duplicates::SLICE_COMPARISONS) {
+        if scan.comparisons() >= duplicates::MAX_COMPARISONS {
+            warn!("Stopped looking for duplicates after {} comparisons among {} chunks", scan.comparisons(), rows.len());
+            truncated = true;
+            break;
+        }
+        rate_limit::sleep(0.0).await;
+    }
+    let clusters = scan.finish();
+    debug!("Found {} duplicate clusters among {} chunks", clusters.len(), rows.len());
+    Ok(serde_wasm_bindgen::to_value(&duplicates::DuplicateReport { clusters, truncated })?)
+}
+
+/// Returns a `RagContext` for answering `query` with a language model: the text of the best
+/// matching section of each note, labelled `[n]` and packed into `token_budget` tokens, with
+/// the sources the labels refer to
+#[wasm_bindgen]
+pub async fn build_context(app: &obsidian::App, settings: JsValue, query: JsString, token_budget: usize) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let query = query.as_string().unwrap_or_default().trim().to_string();
+    if query.is_empty() {
+        return Err(SemanticSearchError::InvalidArgument("The query is empty".to_string()));
+    }
+    if token_budget == 0 {
+        return Err(SemanticSearchError::InvalidArgument("The token budget must be more than 0".to_string()));
+    }
+    let mut query_cmd = QueryCommand::new(app, &settings);
+    let context = query_cmd.build_context(&query, token_budget).await?;
+    Ok(serde_wasm_bindgen::to_value(&context)?)
+}
+
+/// Returns an `Answer` to `query` written by the configured answer model from the notes that
+/// best match it, with the notes it was given. Fails if no answer model is configured.
+#[wasm_bindgen]
+pub async fn answer_query(app: &obsidian::App, settings: JsValue, query: JsString) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let model = settings.answer_model.trim();
+    if model.is_empty() {
+        return Err(SemanticSearchError::InvalidSettings("set an answer model to answer questions".to_string()));
+    }
+    let query = query.as_string().unwrap_or_default().trim().to_string();
+    if query.is_empty() {
+        return Err(SemanticSearchError::InvalidArgument("The question is empty".to_string()));
+    }
+    let mut query_cmd = QueryCommand::new(app, &settings);
+    let context = query_cmd.build_context(&query, settings.answer_context_tokens.max(1)).await?;
+    if context.sources.is_empty() {
+        return Err(SemanticSearchError::InvalidArgument("No notes match the question".to_string()));
+    }
+    let answer = query_cmd.client.answer(model, &query, &context).await?;
+    Ok(serde_wasm_bindgen::to_value(&Answer { answer, model: model.to_string(), sources: context.sources })?)
+}
+
+/// Returns a `Moc` outlining the notes on a topic by sub-topic, for a `MocSource` of
+/// `{ query }` or `{ cluster, k }`. The outline is also written to a new note at `path` if
+/// given, refusing to replace an existing file.
+#[wasm_bindgen]
+pub async fn generate_moc(app: &obsidian::App, settings: JsValue, source: JsValue, path: Option<String>) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let source: MocSource = serde_wasm_bindgen::from_value(source)
+        .map_err(|e| SemanticSearchError::InvalidArgument(format!("Invalid map of content source: {}", e)))?;
+    source.validate()?;
+    let path = path.map(|path| path.trim().to_string()).filter(|path| !path.is_empty());
+    let query_cmd = QueryCommand::new(app, &settings);
+    if let Some(path) = &path {
+        if query_cmd.file_processor.check_file_exists_at_path(path).await? {
+            return Err(SemanticSearchError::InvalidArgument(format!("{} already exists", path)));
+        }
+    }
+    let (title, rows): (String, Vec<(String, String, Vec<f32>)>) = match (&source.query, source.cluster) {
+        (Some(query), _) => {
+            let (_, candidates) = query_cmd.get_similarity(vec![query.trim().to_string()], None, &QueryFilters::default()).await?;
+            let rows = candidates.into_iter().take(moc::MOC_SECTIONS).map(|candidate| (candidate.name, candidate.header, candidate.embedding)).collect();
+            (query.clone(), rows)
+        },
+        (None, cluster) => {
+            let rows = query_cmd.get_embedding_rows().await?;
+            let k = if source.k == 0 { clustering::default_k(rows.len()) } else { source.k };
+            let clusters = clustering::cluster_notes(&rows, k);
+            let cluster = cluster.and_then(|cluster| clusters.into_iter().nth(cluster))
+                .ok_or_else(|| SemanticSearchError::InvalidArgument(format!("There is no cluster {} of {}", cluster.unwrap_or_default(), k)))?;
+            let chunks: HashSet<(&str, &str)> = cluster.chunks.iter().map(|chunk| (chunk.name.as_str(), chunk.header.as_str())).collect();
+            let rows = rows.iter().filter(|(name, header, _)| chunks.contains(&(name.as_str(), header.as_str()))).cloned().collect();
+            (cluster.label.clone(), rows)
+        },
+    };
+    let paths: HashMap<String, String> = query_cmd.file_processor.list_files(&FileFilter::default()).await.iter()
+        .map(|file| (file.name(), file.path()))
+        .collect();
+    let mut moc = moc::outline(&title, &rows, &paths);
+    if let Some(path) = path {
+        query_cmd.file_processor.write_to_path(&path, &moc.markdown).await?;
+        moc.path = Some(path);
+    }
+    debug!("Outlined {} notes on {}", moc.notes, moc.title);
+    Ok(serde_wasm_bindgen::to_value(&moc)?)
+}
+
+/// Returns `UnlinkedMentions` of the note at `path`: passages of other notes at least
+/// `threshold` similar to one of its sections that don't link to it yet. Uses the stored
+/// vectors, so no embedding request is made.
+#[wasm_bindgen]
+pub async fn find_unlinked_mentions(app: &obsidian::App, settings: JsValue, path: String, threshold: f32) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let query_cmd = QueryCommand::new(app, &settings);
+    let store = query_cmd.load_embedding_file().await?;
+    let name = path.rsplit('/').next().unwrap_or_default();
+    if !store.rows.iter().any(|(row_name, _, _)| row_name == name || *row_name == path) {
+        return Err(SemanticSearchError::InvalidArgument(format!("{} has no embeddings", path)));
+    }
+    let lines: HashMap<(&str, &str), LineRange> = store.rows.iter().zip(&store.provenance)
+        .filter_map(|((name, header, _), provenance)| Some(((name.as_str(), header.as_str()), provenance.as_ref()?.lines?)))
+        .collect();
+    let found = mentions::find_unlinked_mentions(&store.rows, &lines, &path, threshold);
+    debug!("Found {} unlinked mentions of {}", found.mentions.len(), path);
+    Ok(serde_wasm_bindgen::to_value(&found)?)
+}
+
+/// Groups the stored chunks into `k` themes, picking `k` from the store size when it is 0
+#[wasm_bindgen]
+pub async fn cluster_notes(app: &obsidian::App, settings: JsValue, k: usize) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let query_cmd = QueryCommand::new(app, &settings);
+    let rows = query_cmd.get_embedding_rows().await?;
+    let k = if k == 0 { clustering::default_k(rows.len()) } else { k };
+    let clusters = clustering::cluster_notes(&rows, k);
+    debug!("Grouped {} chunks into {} clusters", rows.len(), clusters.len());
+    Ok(serde_wasm_bindgen::to_value(&clusters)?)
+}
+
+/// Converts another tool's index at `path` into the configured embedding store, returning an `ImportReport`.
+///
+/// `source_model` is the model the index was built with; it must match the
+/// configured model since the imported vectors are compared with new queries.
+/// With `merge`, the imported notes replace their stored records and every other note
+/// is kept, otherwise the import replaces the whole store.
+#[wasm_bindgen]
+pub async fn import_embeddings(app: &obsidian::App, settings: JsValue, format: JsValue, path: String, source_model: String, merge: bool) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let format: ImportFormat = serde_wasm_bindgen::from_value(format)
+        .map_err(|e| SemanticSearchError::InvalidArgument(format!("Unknown import format: {}", e)))?;
+    let client = Client::new(&settings);
+    let file_processor = FileProcessor::new(app.vault());
+    let (chunks, skipped, index_model) = match format {
+        ImportFormat::Npy => {
+            let data = file_processor.read_binary_from_path(&path).await?;
+            let manifest = file_processor.read_from_path(&import::npy_manifest_path(&path)).await?;
+            import::parse_npy(&data, &manifest)?
+        },
+        _ => {
+            let data = file_processor.read_from_path(&path).await?;
+            let (chunks, skipped) = import::parse(format, &data)?;
+            (chunks, skipped, None)
+        },
+    };
+    let source_model = match source_model.trim() {
+        "" => index_model.as_deref().or(format.default_model()).unwrap_or_default(),
+        model => model,
+    };
+    if source_model != client.model() {
+        return Err(SemanticSearchError::InvalidArgument(format!(
+            "the index was created with '{}' but the configured model is '{}', switch models or generate new embeddings instead", source_model, client.model())));
+    }
+
+    let dimensions = chunks.first().ok_or_else(|| SemanticSearchError::InvalidArgument(format!("No embeddings found in '{}'", path)))?.embedding.len();
+    let count = chunks.len();
+    let mut notes: Vec<String> = Vec::new();
+    for chunk in &chunks {
+        if !notes.contains(&chunk.name) {
+            notes.push(chunk.name.clone());
+        }
+    }
+    let imported_at = js_sys::Date::now();
+    let metadata = StoreMetadata::new(client.model(), dimensions, imported_at).with_quantization(settings.embedding_quantization);
+    let provenance = Provenance {
+        model: client.model().to_string(),
+        provider: format!("import:{}", path),
+        embedded_at: imported_at,
+        settings_hash: String::new(),
+        lines: None,
+        note_times: None,
+        language: None,
+        path: None,
+    };
+    let imported = EmbeddingFile {
+        metadata: Some(metadata),
+        rows: chunks.into_iter().map(|chunk| (chunk.name, chunk.header, chunk.embedding)).collect(),
+        provenance: vec![Some(provenance); count],
+    };
+
+    let embedding_path = settings.embedding_path();
+    file_processor.acquire_lock(&embedding_path).await?;
+    let written = async {
+        let mut replaced = 0;
+        let store = match merge && file_processor.check_file_exists_at_path(&embedding_path).await? {
+            true => {
+                let store = embedding_file::read_embedding_file(&file_processor.read_from_path(&embedding_path).await?)?;
+                let notes = NoteSet::names(&notes);
+                maintenance::check_model_change(&store, client.model(), &notes)?;
+                let stored_dimensions = store.rows.first().map(|(_, _, embedding)| embedding.len());
+                if stored_dimensions.is_some_and(|stored| stored != dimensions) && !(0..store.rows.len()).all(|i| notes.holds(&store, i)) {
+                    return Err(SemanticSearchError::DimensionMismatch(format!(
+                        "imported embeddings have {} dimensions but the store has {}", dimensions, stored_dimensions.unwrap_or_default())));
+                }
+                replaced = (0..store.rows.len()).filter(|&i| notes.holds(&store, i)).count();
+                maintenance::merge(store, &notes, imported)
+            },
+            false => imported,
+        };
+        file_processor.replace_file(&embedding_path, &store.to_csv()?).await?;
+        Ok((store.rows.len(), replaced))
+    }.await;
+    file_processor.release_lock_or_log(&embedding_path).await;
+    let (records, replaced) = written?;
+
+    stores::register(&file_processor, &settings.store_registry_path(), StoreEntry {
+        name: settings.embedding_store.trim().to_string(),
+        model: client.model().to_string(),
+        path: embedding_path.clone(),
+        records,
+        updated_at: js_sys::Date::now(),
+    }).await?;
+    debug!("Imported {} embeddings from {}, skipped {}, replaced {}", count, path, skipped, replaced);
+    Ok(serde_wasm_bindgen::to_value(&ImportReport { imported: count, skipped, replaced, path: embedding_path })?)
+}
+
+/// Writes the configured embedding store next to itself as JSON lines or as a `.npy` matrix
+/// with a JSON manifest, for use in Python or other vector databases, returning an `ExportReport`
+#[wasm_bindgen]
+pub async fn export_embeddings(app: &obsidian::App, settings: JsValue, format: JsValue) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let format: ExportFormat = serde_wasm_bindgen::from_value(format)
+        .map_err(|e| SemanticSearchError::InvalidArgument(format!("Unknown export format: {}", e)))?;
+    let query_cmd = QueryCommand::new(app, &settings);
+    let store = query_cmd.load_embedding_file().await?;
+    let headings = export::record_headings(&store, &query_cmd.read_section_texts().await);
+    let texts = export::record_texts(&store);
+
+    let paths = export::export_paths(&query_cmd.embedding_path, format);
+    match format {
+        ExportFormat::Jsonl => {
+            query_cmd.file_processor.delete_file_at_path(&paths[0]).await?;
+            query_cmd.file_processor.write_to_path(&paths[0], &export::to_jsonl(&store, &headings, &texts)?).await?;
+        },
+        ExportFormat::Npy => {
+            query_cmd.file_processor.write_binary_to_path(&paths[0], &export::to_npy(&store)?).await?;
+            query_cmd.file_processor.delete_file_at_path(&paths[1]).await?;
+            query_cmd.file_processor.write_to_path(&paths[1], &export::npy_manifest(&store, &headings, &texts)?).await?;
+        },
+    }
+    debug!("Exported {} embeddings to {}", store.rows.len(), paths.join(", "));
+    Ok(serde_wasm_bindgen::to_value(&ExportReport { records: store.rows.len(), dimensions: export::dimensions(&store), paths })?)
+}
+
+/// Returns a `ProvenanceReport`: how many stored records came from each model, provider and
+/// settings hash, and the records matching `filter` (a `ProvenanceFilter`, or null for all)
+#[wasm_bindgen]
+pub async fn query_provenance(app: &obsidian::App, settings: JsValue, filter: JsValue) -> Result<JsValue, SemanticSearchError> {
+    let filter: ProvenanceFilter = if filter.is_null() || filter.is_undefined() {
+        ProvenanceFilter::default()
+    } else {
+        serde_wasm_bindgen::from_value(filter).map_err(|e| SemanticSearchError::InvalidArgument(format!("Invalid provenance filter: {}", e)))?
+    };
+    let settings = Settings::from_js(&settings)?;
+    let query_cmd = QueryCommand::new(app, &settings);
+    let store = query_cmd.load_embedding_file().await?;
+    Ok(serde_wasm_bindgen::to_value(&provenance::query(&store, &filter))?)
+}
+
+/// Returns the `StoreEntry` of every embedding store that has been built
+#[wasm_bindgen]
+pub async fn list_embedding_stores(app: &obsidian::App, settings: JsValue) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
     let file_processor = FileProcessor::new(app.vault());
-    let client = Client::new(api_key.as_string().unwrap());
-    let query_cmd = QueryCommand { file_processor, client };
-    let mut ranked_suggestions = query_cmd.get_similarity(query_string).await?;
-    ranked_suggestions.truncate(10);
-    Ok(serde_wasm_bindgen::to_value(&ranked_suggestions)?)
+    let registry = StoreRegistry::load(&file_processor, &settings.store_registry_path()).await?;
+    Ok(serde_wasm_bindgen::to_value(&registry.stores)?)
+}
+
+/// Returns the `SearchHistory` of past and saved searches
+#[wasm_bindgen]
+pub async fn get_search_history(app: &obsidian::App, settings: JsValue) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let file_processor = FileProcessor::new(app.vault());
+    let history = SearchHistory::load(&file_processor, &settings.search_history_path()).await?;
+    Ok(serde_wasm_bindgen::to_value(&history)?)
+}
+
+/// Adds `query` to the search history with the suggestions it returned, most relevant first
+#[wasm_bindgen]
+pub async fn record_search(app: &obsidian::App, settings: JsValue, query: String, suggestions: JsValue) -> Result<(), SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let results: Vec<HistoryResult> = serde_wasm_bindgen::from_value(suggestions)?;
+    let file_processor = FileProcessor::new(app.vault());
+    let path = settings.search_history_path();
+    let mut history = SearchHistory::load(&file_processor, &path).await?;
+    history.record(HistoryEntry { query, searched_at: js_sys::Date::now(), results });
+    history.save(&file_processor, &path).await
+}
+
+/// Saves `query` as a search called `name`, replacing any saved search with that name
+#[wasm_bindgen]
+pub async fn save_search(app: &obsidian::App, settings: JsValue, name: String, query: String) -> Result<(), SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let file_processor = FileProcessor::new(app.vault());
+    let path = settings.search_history_path();
+    let mut history = SearchHistory::load(&file_processor, &path).await?;
+    history.save_search(SavedSearch { name, query, saved_at: js_sys::Date::now() })?;
+    history.save(&file_processor, &path).await
+}
+
+/// Removes the saved search called `name`, returning whether it existed
+#[wasm_bindgen]
+pub async fn delete_saved_search(app: &obsidian::App, settings: JsValue, name: String) -> Result<bool, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let file_processor = FileProcessor::new(app.vault());
+    let path = settings.search_history_path();
+    let mut history = SearchHistory::load(&file_processor, &path).await?;
+    if !history.remove_search(&name) {
+        return Ok(false);
+    }
+    history.save(&file_processor, &path).await?;
+    Ok(true)
+}
+
+/// Forgets every past search, keeping saved searches
+#[wasm_bindgen]
+pub async fn clear_search_history(app: &obsidian::App, settings: JsValue) -> Result<(), SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let file_processor = FileProcessor::new(app.vault());
+    let path = settings.search_history_path();
+    let mut history = SearchHistory::load(&file_processor, &path).await?;
+    history.history.clear();
+    history.save(&file_processor, &path).await
+}
+
+/// Returns the `RunManifest` saved by the last Generate Input or Generate Embeddings run, or null if there is none
+#[wasm_bindgen]
+pub async fn get_last_run_report(app: &obsidian::App, settings: JsValue) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let file_processor = FileProcessor::new(app.vault());
+    let path = settings.manifest_path();
+    if !file_processor.check_file_exists_at_path(&path).await? {
+        return Ok(JsValue::NULL);
+    }
+    let manifest = file_processor.read_from_path(&path).await?;
+    Ok(js_sys::JSON::parse(&manifest)?)
+}
+
+/// Rows of input.csv and the embedding file that cannot be read
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexFileCheck {
+    pub input_rows: usize,
+    pub embedding_rows: usize,
+    pub malformed: Vec<MalformedRow>,
+}
+
+/// Reads input.csv and the embedding file, returning an `IndexFileCheck` listing every row that
+/// searches skip or fail on. Missing files count as empty.
+#[wasm_bindgen]
+pub async fn check_index_files(app: &obsidian::App, settings: JsValue) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let file_processor = FileProcessor::new(app.vault());
+    let mut check = IndexFileCheck { input_rows: 0, embedding_rows: 0, malformed: Vec::new() };
+    let input_path = settings.input_path();
+    if file_processor.check_file_exists_at_path(&input_path).await? {
+        let (records, malformed) = input::read_input_lenient(&file_processor.read_from_path(&input_path).await?)?;
+        check.input_rows = records.len();
+        check.malformed.extend(malformed);
+    }
+    let embedding_path = settings.embedding_path();
+    if file_processor.check_file_exists_at_path(&embedding_path).await? {
+        let (store, malformed) = embedding_file::read_embedding_file_lenient(&file_processor.read_from_path(&embedding_path).await?)?;
+        check.embedding_rows = store.rows.len();
+        check.malformed.extend(malformed);
+    }
+    debug!("Found {} malformed rows in {} and {}", check.malformed.len(), input_path, embedding_path);
+    Ok(serde_wasm_bindgen::to_value(&check)?)
+}
+
+/// Estimates what embedding the vault as it is now would cost, returning a `VaultEstimate`
+/// broken down per folder. Notes are read and split like Generate Input would, respecting the
+/// ignored folders, attachment types and chunking settings, so input.csv need not exist or be fresh.
+#[wasm_bindgen]
+pub async fn estimate_vault_cost(app: &obsidian::App, settings: JsValue) -> Result<JsValue, SemanticSearchError> {
+    let input_cmd = generate_input::GenerateInputCommand::new(app.clone(), settings.clone())?;
+    let settings = Settings::from_js(&settings)?;
+    let file_processor = FileProcessor::new(app.vault());
+    let client = Client::new(&settings);
+    let embed_text = settings.embed_text();
+    let mut paths = Vec::new();
+    let mut records = Vec::new();
+    for file in file_processor.list_files(&input_cmd.file_filter()).await {
+        let path = file.path();
+        match input_cmd.file_records(file).await {
+            Ok(file_records) => for record in file_records {
+                paths.push(path.clone());
+                records.push(PipelineRecord::from_input(record, &embed_text));
+            },
+            Err(e) => warn!("Left {} out of the cost estimate: {}", path, e),
+        }
+    }
+    if settings.max_tokens_per_record > 0 {
+        records = TruncationHook::new(settings.max_tokens_per_record).preprocess(records)?;
+    }
+    let requests = pipeline::batch(records.clone(), settings.num_batches, settings.max_tokens_per_request).len();
+    let records: Vec<(String, PipelineRecord)> = paths.into_iter().zip(records).collect();
+    let price_factor = if settings.use_batch_api { batch_api::BATCH_PRICE_FACTOR } else { 1.0 };
+    let estimate = pricing::plan_folders(client.model(), client.dollars_per_million_tokens(), &records, requests).discounted(price_factor);
+    debug!("Estimated {} tokens in {} folders", estimate.estimate.tokens, estimate.folders.len());
+    Ok(serde_wasm_bindgen::to_value(&estimate)?)
+}
+
+/// Upgrades input.csv and the embedding files in use to the current schema version, keeping each
+/// file as it was next to it as `<path>.v<version>.bak`. Returns a `MigrationReport` of the files
+/// upgraded and those left as they are, such as files written by a newer version of the plugin.
+#[wasm_bindgen]
+pub async fn migrate_stores(app: &obsidian::App, settings: JsValue) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let file_processor = FileProcessor::new(app.vault());
+    let mut stores = vec![(StoreKind::Input, settings.input_path()), (StoreKind::Embeddings, settings.embedding_path())];
+    stores.extend(language_stores(&settings).into_iter().map(|(_, _, path)| (StoreKind::Embeddings, path)));
+    let mut report = MigrationReport::default();
+    for (kind, path) in stores {
+        if !file_processor.check_file_exists_at_path(&path).await? {
+            continue;
+        }
+        file_processor.acquire_lock(&path).await?;
+        let result = migrate_store(&file_processor, kind, &path).await;
+        file_processor.release_lock_or_log(&path).await;
+        match result {
+            Ok(Some(migration)) => {
+                info!("Upgraded {} from schema version {} to {}", path, migration.from, migration.to);
+                report.migrated.push(migration);
+            },
+            Ok(None) => {},
+            Err(e) => {
+                warn!("Left {} as it is: {}", path, e);
+                report.refused.push(Refusal { path, reason: e.to_string() });
+            },
+        }
+    }
+    Ok(serde_wasm_bindgen::to_value(&report)?)
+}
+
+async fn migrate_store(file_processor: &FileProcessor, kind: StoreKind, path: &str) -> Result<Option<Migration>, SemanticSearchError> {
+    let data = file_processor.read_from_path(path).await?;
+    let upgrade = match migrations::upgrade(kind, &data)? {
+        Some(upgrade) => upgrade,
+        None => return Ok(None),
+    };
+    let backup = migrations::backup_path(path, upgrade.from);
+    file_processor.delete_file_at_path(&backup).await?;
+    file_processor.rename_path(path, &backup).await?;
+    file_processor.write_to_path(path, &upgrade.data).await?;
+    Ok(Some(Migration { kind, path: path.to_string(), from: upgrade.from, to: upgrade.to, backup }))
+}
+
+/// Number of tokens in `text`, cheap enough to call on every keystroke
+#[wasm_bindgen]
+pub fn count_tokens(text: &str) -> usize {
+    pricing::count_tokens(text)
 }
 
 #[wasm_bindgen]
-pub fn get_query_cost_estimate(query: &str) -> f32 {
-    const TOKEN_COST: f32 = 0.0004 / 1000.0;
-    let tokens = cl100k_base().unwrap().encode_with_special_tokens(query); 
-    let tokens_length = tokens.len() as f32;
-    return TOKEN_COST * tokens_length;
+pub fn get_query_cost_estimate(query: &str, model: &str) -> f32 {
+    let tokens = pricing::count_tokens(query);
+    pricing::CostEstimate::from_tokens(model, tokens, 1).dollars
 }
 
 #[derive(Debug, Clone)]
-/// Client is a container for api key, base url, organization id
+/// Client is a container for api key, base url, organization and project ids
 pub struct Client {
     api_key: String,
     api_base: String,
     org_id: String,
+    project_id: String,
+    model: String,
+    provider: Rc<dyn EmbeddingProvider>,
+    rate_limit: RateLimit,
+    /// Milliseconds a request may take, 0 for no timeout
+    request_timeout_ms: f64,
 }
 
 /// Default v1 API base url
 pub const API_BASE: &str = "https://lai.rambhat.la/v1";
 /// Name for organization header
 pub const ORGANIZATION_HEADER: &str = "OpenAI-Organization";
+/// Name for project header
+pub const PROJECT_HEADER: &str = "OpenAI-Project";
 
 impl Client {
     pub fn api_base(&self) -> &str {
@@ -227,64 +1768,321 @@ impl Client {
         &self.api_key
     }
 
-    fn new(api_key: String) -> Self{
-        Self { api_key, api_base: API_BASE.to_string(), org_id: Default::default() }
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn new(settings: &Settings) -> Self {
+        // Settings from JS have their provider checked already
+        let provider = settings.provider().unwrap_or_else(|_| Rc::new(providers::BUILTIN_PROVIDERS[0].clone()));
+        let api_base = match settings.api_base.trim().trim_end_matches('/') {
+            "" => provider.api_base().to_string(),
+            api_base => api_base.to_string(),
+        };
+        let model = match settings.model.trim() {
+            "" => provider.models().into_iter().next().unwrap_or_default(),
+            model => model.to_string(),
+        };
+        Self {
+            api_key: settings.api_key.clone(),
+            api_base,
+            org_id: settings.organization_id.trim().to_string(),
+            project_id: settings.project_id.trim().to_string(),
+            model,
+            provider,
+            rate_limit: RateLimit::from_settings(settings),
+            request_timeout_ms: f64::from(settings.request_timeout) * 1000.0,
+        }
     }
 
-    fn headers(&self) -> HeaderMap {
+    /// USD per million tokens embedded, from the provider or else the model's published price
+    pub fn dollars_per_million_tokens(&self) -> f32 {
+        self.provider.dollars_per_million_tokens(&self.model)
+            .unwrap_or_else(|| pricing::pricing_for(&self.model).dollars_per_million_tokens)
+    }
+
+    fn headers(&self) -> Result<HeaderMap, SemanticSearchError> {
         let mut headers = HeaderMap::new();
-        if !self.org_id.is_empty() {
-            headers.insert(ORGANIZATION_HEADER, self.org_id.as_str().parse().unwrap());
+        for (name, value) in [(ORGANIZATION_HEADER, &self.org_id), (PROJECT_HEADER, &self.project_id)] {
+            if value.is_empty() {
+                continue;
+            }
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| SemanticSearchError::InvalidSettings(format!("'{}' is not a valid {} header", value, name)))?;
+            headers.insert(name, value);
         }
-        headers
+        Ok(headers)
     }
 
-    pub async fn get_embedding(&self, input: EmbeddingInput) -> Result<EmbeddingResponse, SemanticSearchError> {
-        let request = self.create_embedding_request(input)?;
+    pub async fn get_embedding(&self, input: EmbeddingInput, input_type: InputType) -> Result<EmbeddingResponse, SemanticSearchError> {
+        if let Some(response) = self.provider.embed_offline(&self.model, &input) {
+            let response = response?;
+            debug!("Embedded {} inputs offline with {}", response.data.len(), self.model);
+            return Ok(response);
+        }
+        if self.rate_limit.is_enabled() {
+            let tokens = match (&input, self.rate_limit.tokens_per_minute) {
+                (_, 0) => 0,
+                (EmbeddingInput::StringArray(texts), _) => pricing::count_tokens_in(texts),
+            };
+            rate_limit::acquire(self.rate_limit, tokens).await;
+        }
+        let request = self.create_embedding_request(input, input_type)?;
         let response = self.post_embedding_request(request).await?;
+        info!("Embedded {} inputs with {} ({} tokens)", response.data.len(), self.model, response.usage.total_tokens);
         Ok(response)
     }
 
-    fn create_embedding_request(&self, input: EmbeddingInput) -> Result<EmbeddingRequest, SemanticSearchError> {
-        let embedding_request = EmbeddingRequestBuilder::default()
-            .model("text-embedding-ada-002".to_string())
-            .input(input)
-            .user(None)
-            .build()?;
-        Ok(embedding_request)
+    /// Embeds a single query string
+    pub async fn embed_query(&self, query: &str) -> Result<Vec<f32>, SemanticSearchError> {
+        let response = self.get_embedding(query.to_string().into(), InputType::Query).await?;
+        debug!("Sucessfully obtained {} embeddings", response.data.len());
+        response.data.into_iter().next()
+            .map(|embedding| embedding.embedding)
+            .ok_or_else(|| SemanticSearchError::GetEmbeddingsError("No embedding returned for query".to_string()))
+    }
+
+    /// Embeds several queries in one request, in the order given
+    pub async fn embed_queries(&self, queries: &[String]) -> Result<Vec<Vec<f32>>, SemanticSearchError> {
+        let mut response = self.get_embedding(queries.into(), InputType::Query).await?;
+        if response.data.len() != queries.len() {
+            return Err(SemanticSearchError::GetEmbeddingsError(format!(
+                "Expected {} query embeddings but got {}", queries.len(), response.data.len())));
+        }
+        response.data.sort_by_key(|embedding| embedding.index);
+        Ok(response.data.into_iter().map(|embedding| embedding.embedding).collect())
+    }
+
+    /// Asks a chat model for up to `count` reformulations of `query`
+    pub async fn reformulate(&self, model: &str, query: &str, count: usize) -> Result<Vec<String>, SemanticSearchError> {
+        let response = self.post_chat_request(expansion::reformulation_request(model, query, count)).await?;
+        Ok(expansion::parse_reformulations(&response.reply(), query, count))
+    }
+
+    /// Asks `model` to answer `question` from the notes in `context`, citing them
+    pub async fn answer(&self, model: &str, question: &str, context: &RagContext) -> Result<String, SemanticSearchError> {
+        let response = self.post_chat_request(chat::answer_request(model, question, context)).await?;
+        Ok(response.reply().trim().to_string())
+    }
+
+    fn create_embedding_request(&self, input: EmbeddingInput, input_type: InputType) -> Result<serde_json::Value, SemanticSearchError> {
+        self.provider.request_body(&self.model, input, input_type)
     }
 
     async fn post_embedding_request<I: serde::ser::Serialize>(&self, request: I) -> Result<EmbeddingResponse, SemanticSearchError> {
-        let path = "/embeddings";
+        let path = self.provider.embeddings_path();
+
+        let request = reqwest::Client::new()
+            .post(format!("{}{path}", self.api_base()))
+            .headers(self.provider.headers(self.api_key())?)
+            .headers(self.headers()?)
+            .json(&request)
+            .build()?;
+
+        let body = self.execute_bytes(request).await?;
+        self.provider.parse_response(&body)
+    }
+
+    async fn post_chat_request(&self, request: ChatRequest) -> Result<ChatResponse, SemanticSearchError> {
+        let path = "/chat/completions";
 
         let request = reqwest::Client::new()
             .post(format!("{}{path}", self.api_base()))
             .bearer_auth(self.api_key())
-            .headers(self.headers())
+            .headers(self.headers()?)
             .json(&request)
             .build()?;
 
-        let reqwest_client = reqwest::Client::new();
-        let response = reqwest_client.execute(request).await?;
+        self.execute(request).await
+    }
+
+    /// Uploads the JSONL requests of a batch job
+    pub async fn upload_batch_file(&self, lines: String) -> Result<FileObject, SemanticSearchError> {
+        let path = "/files";
+        let file = reqwest::multipart::Part::text(lines).file_name("embeddings.jsonl");
+        let form = reqwest::multipart::Form::new().text("purpose", "batch").part("file", file);
+
+        let request = reqwest::Client::new()
+            .post(format!("{}{path}", self.api_base()))
+            .bearer_auth(self.api_key())
+            .headers(self.headers()?)
+            .multipart(form)
+            .build()?;
+
+        self.execute(request).await
+    }
+
+    pub async fn create_batch(&self, input_file_id: &str) -> Result<Batch, SemanticSearchError> {
+        let path = "/batches";
 
-        let status = response.status();
-        let bytes = response.bytes().await?;
+        let request = reqwest::Client::new()
+            .post(format!("{}{path}", self.api_base()))
+            .bearer_auth(self.api_key())
+            .headers(self.headers()?)
+            .json(&batch_api::CreateBatchRequest { input_file_id, endpoint: batch_api::BATCH_ENDPOINT, completion_window: "24h" })
+            .build()?;
+
+        self.execute(request).await
+    }
+
+    pub async fn retrieve_batch(&self, id: &str) -> Result<Batch, SemanticSearchError> {
+        let path = format!("/batches/{}", id);
+
+        let request = reqwest::Client::new()
+            .get(format!("{}{path}", self.api_base()))
+            .bearer_auth(self.api_key())
+            .headers(self.headers()?)
+            .build()?;
+
+        self.execute(request).await
+    }
+
+    /// Downloads the contents of an uploaded or generated file, such as a batch's output
+    pub async fn file_content(&self, id: &str) -> Result<String, SemanticSearchError> {
+        let path = format!("/files/{}/content", id);
+
+        let request = reqwest::Client::new()
+            .get(format!("{}{path}", self.api_base()))
+            .bearer_auth(self.api_key())
+            .headers(self.headers()?)
+            .build()?;
+
+        let bytes = self.execute_bytes(request).await?;
+        String::from_utf8(bytes).map_err(|e| SemanticSearchError::ConversionError(Box::new(e)))
+    }
+
+    pub async fn list_models(&self) -> Result<ModelListResponse, SemanticSearchError> {
+        let path = "/models";
+
+        let request = reqwest::Client::new()
+            .get(format!("{}{path}", self.api_base()))
+            .bearer_auth(self.api_key())
+            .headers(self.headers()?)
+            .build()?;
+
+        self.execute(request).await
+    }
+
+    async fn execute<O: serde::de::DeserializeOwned>(&self, request: reqwest::Request) -> Result<O, SemanticSearchError> {
+        let bytes = self.execute_bytes(request).await?;
+        let response: O =
+            serde_json::from_slice(&bytes).map_err(SemanticSearchError::JSONDeserialize)?;
+        Ok(response)
+    }
+
+    /// Sends `request` and returns the body of a successful response
+    async fn execute_bytes(&self, request: reqwest::Request) -> Result<Vec<u8>, SemanticSearchError> {
+        let reqwest_client = reqwest::Client::new();
+        let start = js_sys::Date::now();
+        let (method, path) = (request.method().clone(), request.url().path().to_string());
+        let timed_out = || SemanticSearchError::Timeout(format!("{} {} got no response within {:.0} seconds", method, path, self.request_timeout_ms / 1000.0));
+        // the timeout covers reading the body too, since a stalled body hangs the run just the same
+        let (status, bytes) = timeouts::with_timeout(async {
+            let response = reqwest_client.execute(request).await?;
+            let status = response.status();
+            Ok::<_, SemanticSearchError>((status, response.bytes().await?))
+        }, self.request_timeout_ms).await.ok_or_else(timed_out)??;
+        info!("{} {} returned {} in {:.0} ms ({} bytes)", method, path, status.as_u16(), js_sys::Date::now() - start, bytes.len());
 
         if !status.is_success() {
-            let wrapped_error: WrappedError =
+            let mut wrapped_error: WrappedError =
                 serde_json::from_slice(bytes.as_ref()).map_err(SemanticSearchError::JSONDeserialize)?;
+            wrapped_error.error.status = status.as_u16();
 
             return Err(SemanticSearchError::ApiError(wrapped_error.error));
         }
-
-        let response: EmbeddingResponse =
-            serde_json::from_slice(bytes.as_ref()).map_err(SemanticSearchError::JSONDeserialize)?;
-        Ok(response)
+        Ok(bytes.to_vec())
     }
 }
 
+#[derive(Serialize)]
+pub struct ConnectionTestResult {
+    success: bool,
+    api_base: String,
+    latency_ms: f64,
+    models: Vec<String>,
+    error: Option<JsErrorPayload>,
+}
+
+/// Checks the configured API key and base url with a cheap request.
+///
+/// Tries `/models` first and falls back to embedding a single word for
+/// OpenAI-compatible endpoints that do not implement it.
+#[wasm_bindgen]
+pub async fn test_api_connection(settings: JsValue) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let client = Client::new(&settings);
+    let start = js_sys::Date::now();
+
+    let ping = || client.get_embedding("ping".to_string().into(), InputType::Query);
+    // offline providers have no endpoint to ask
+    let result = if client.provider.is_offline() {
+        ping().await.map(|response| vec![response.model])
+    } else {
+        match client.list_models().await {
+            Err(e) if e.code() == ErrorCode::InvalidRequest => {
+                debug!("/models is unavailable, falling back to an embedding request: {}", e);
+                ping().await.map(|response| vec![response.model])
+            },
+            result => result.map(|response| {
+                let mut models: Vec<String> = response.data.into_iter().map(|model| model.id).collect();
+                models.sort();
+                models
+            }),
+        }
+    };
+
+    let latency_ms = js_sys::Date::now() - start;
+    let result = match result {
+        Ok(models) => ConnectionTestResult { success: true, api_base: client.api_base, latency_ms, models, error: None },
+        Err(e) => ConnectionTestResult {
+            success: false,
+            api_base: client.api_base,
+            latency_ms,
+            models: Vec::new(),
+            error: Some(JsErrorPayload::new(&e)),
+        },
+    };
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
 #[wasm_bindgen]
 pub fn onload(plugin: &obsidian::Plugin) {
-    console_log::init_with_level(log::Level::Debug).expect("");
+    let settings = Settings::from_js(&plugin.settings()).unwrap_or_default();
+    logging::configure(&plugin.app(), &settings);
     debug!("Semantic Search Loaded!");
 }
+
+/// Applies the log level and log file settings, rotating the log file once it has grown too large
+#[wasm_bindgen]
+pub async fn configure_logging(app: &obsidian::App, settings: JsValue) -> Result<(), SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    logging::configure(app, &settings);
+    if settings.log_to_file {
+        logging::rotate(&FileProcessor::new(app.vault()), &settings.log_file_path()).await?;
+    }
+    Ok(())
+}
+
+/// Applies the encryption settings, so embedding files are encrypted and decrypted as they are
+/// written and read
+#[wasm_bindgen]
+pub fn configure_encryption(settings: JsValue) -> Result<(), SemanticSearchError> {
+    encryption::configure(&Settings::from_js(&settings)?);
+    Ok(())
+}
+
+/// Cancels running jobs, flushes queued writes and releases their locks so that
+/// disabling or updating the plugin mid-run does not leave a corrupt store behind
+#[wasm_bindgen]
+pub async fn onunload(plugin: &obsidian::Plugin) -> Result<(), SemanticSearchError> {
+    let file_processor = FileProcessor::new(plugin.app().vault());
+    let locks = jobs::cancel_all();
+    hooks::clear_post_build_hooks();
+    file_processor.flush().await?;
+    for lock in locks {
+        file_processor.release_lock_or_log(&lock).await;
+    }
+    debug!("Semantic Search Unloaded!");
+    Ok(())
+}
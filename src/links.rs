@@ -0,0 +1,111 @@
+//! Where a search result's section is in the vault, so front ends can open the note at it.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+/// Characters of a heading that Obsidian drops or treats specially in link text
+const LINK_SPECIAL: [char; 5] = ['#', '|', '^', '[', ']'];
+
+/// A section's note and heading path, with ready-made links to it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionLink {
+    /// Vault path of the note
+    pub path: String,
+    /// Headings the section is nested under, outermost first and ending with its own
+    pub headings: Vec<String>,
+    /// First line of the section counted from 0, when known
+    pub line: Option<usize>,
+    /// Link text to open with `workspace.openLinkText`, e.g. `Projects/plan#Goals#2024`
+    pub link_text: String,
+    /// `obsidian://open` URL opening the note at the section from outside Obsidian
+    pub url: String,
+}
+
+impl SectionLink {
+    pub fn new(vault: &str, path: &str, headings: Vec<String>, line: Option<usize>) -> Self {
+        let note = path.strip_suffix(".md").unwrap_or(path);
+        let link_text = std::iter::once(note.to_string())
+            .chain(headings.iter().map(|heading| heading.replace(LINK_SPECIAL, " ").trim().to_string()))
+            .collect::<Vec<_>>()
+            .join("#");
+        let url = format!("obsidian://open?vault={}&file={}", encode_component(vault), encode_component(&link_text));
+        Self { path: path.to_string(), headings, line, link_text, url }
+    }
+}
+
+/// `[[...]]` link to the note at `path`, leaving out the extension of markdown notes
+pub fn note_wikilink(path: &str) -> String {
+    format!("[[{}]]", path.strip_suffix(".md").unwrap_or(path))
+}
+
+/// A note section worth linking to from a paragraph, with the wiki link to insert
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkCandidate {
+    /// `[[...]]` link to the section, e.g. `[[Projects/plan#Goals]]`
+    pub wikilink: String,
+    pub path: String,
+    pub headings: Vec<String>,
+    pub score: f32,
+}
+
+/// Wiki links to the sections of `ranked`, most similar first, leaving out the note at
+/// `current_path` and later sections with the same link text
+pub fn link_candidates(ranked: impl IntoIterator<Item = (SectionLink, f32)>, current_path: &str) -> Vec<LinkCandidate> {
+    let mut seen = HashSet::new();
+    ranked.into_iter()
+        .filter(|(link, _)| link.path != current_path && seen.insert(link.link_text.clone()))
+        .map(|(link, score)| LinkCandidate { wikilink: format!("[[{}]]", link.link_text), path: link.path, headings: link.headings, score })
+        .collect()
+}
+
+/// Percent-encodes `text` like JavaScript's `encodeURIComponent`
+fn encode_component(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'!' | b'~' | b'*' | b'\'' | b'(' | b')' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_to_nested_heading() {
+        let link = SectionLink::new("My Vault", "Projects/plan.md", vec!["Goals".to_string(), "Q1 #focus".to_string()], Some(12));
+
+        assert_eq!(link.link_text, "Projects/plan#Goals#Q1  focus");
+        assert_eq!(link.url, "obsidian://open?vault=My%20Vault&file=Projects%2Fplan%23Goals%23Q1%20%20focus");
+        assert_eq!(link.line, Some(12));
+    }
+
+    #[test]
+    fn links_to_note_without_headings() {
+        let link = SectionLink::new("notes", "Papers/raft.pdf", Vec::new(), None);
+
+        assert_eq!(link.link_text, "Papers/raft.pdf");
+        assert_eq!(link.url, "obsidian://open?vault=notes&file=Papers%2Fraft.pdf");
+        assert_eq!(encode_component("café & co"), "caf%C3%A9%20%26%20co");
+    }
+
+    #[test]
+    fn link_candidates_skip_the_current_note() {
+        let ranked = vec![
+            (SectionLink::new("v", "Daily/today.md", vec!["Log".to_string()], None), 0.9),
+            (SectionLink::new("v", "Projects/plan.md", vec!["Goals".to_string()], Some(3)), 0.8),
+            (SectionLink::new("v", "Projects/plan.md", vec!["Goals".to_string()], Some(3)), 0.7),
+            (SectionLink::new("v", "raft.md", Vec::new(), None), 0.6),
+        ];
+
+        let candidates = link_candidates(ranked, "Daily/today.md");
+
+        assert_eq!(candidates.iter().map(|candidate| (candidate.wikilink.as_str(), candidate.score)).collect::<Vec<_>>(), vec![("[[Projects/plan#Goals]]", 0.8), ("[[raft]]", 0.6)]);
+    }
+}
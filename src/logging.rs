@@ -0,0 +1,149 @@
+//! Routes `log` records to the developer console and, when enabled, to a log file in the
+//! vault, so logs can be attached to bug reports without opening the devtools.
+
+use std::cell::{Cell, RefCell};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::Deserialize;
+
+use crate::FileProcessor;
+use crate::SemanticSearchError;
+use crate::file_processor;
+use crate::obsidian::App;
+use crate::redact;
+use crate::settings::Settings;
+
+pub const LOG_FILE_NAME: &str = "semantic-search.log";
+/// Log files larger than this are moved to `<path>.1` when logging is configured
+const MAX_LOG_FILE_BYTES: f64 = 1024.0 * 1024.0;
+
+/// Most verbose level written to the console and the log file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub fn filter(self) -> LevelFilter {
+        match self {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+struct LogFile {
+    app: App,
+    path: String,
+}
+
+thread_local! {
+    static LOG_FILE: RefCell<Option<LogFile>> = const { RefCell::new(None) };
+    static FLUSH_SCHEDULED: Cell<bool> = const { Cell::new(false) };
+}
+
+struct PluginLogger;
+
+static LOGGER: PluginLogger = PluginLogger;
+
+impl Log for PluginLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        // records are redacted before they reach the console or the log file
+        let message = redact::redact(&record.args().to_string());
+        console_log::log(&Record::builder().level(record.level()).target(record.target()).args(format_args!("{}", message)).build());
+        LOG_FILE.with(|file| {
+            let file = file.borrow();
+            let file = match file.as_ref() {
+                Some(file) => file,
+                None => return,
+            };
+            let timestamp = String::from(js_sys::Date::new_0().to_iso_string());
+            let line = format_line(&timestamp, record.level(), record.target(), &message);
+            // log lines go out with the next flush of queued writes, scheduling one if it is due
+            if file_processor::queue_append_nowait(&file.path, &line) && !FLUSH_SCHEDULED.with(|scheduled| scheduled.replace(true)) {
+                let file_processor = FileProcessor::new(file.app.vault());
+                wasm_bindgen_futures::spawn_local(async move {
+                    FLUSH_SCHEDULED.with(|scheduled| scheduled.set(false));
+                    if let Err(e) = file_processor.flush().await {
+                        console_warn(&format!("Failed to write the log file: {}", e));
+                    }
+                });
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// Reports problems with the log file on the console only, since logging them would queue more lines
+fn console_warn(message: &str) {
+    console_log::log(&Record::builder().level(Level::Warn).target(module_path!()).args(format_args!("{}", message)).build());
+}
+
+/// Installs the logger on first use and applies the log level and log file settings
+pub fn configure(app: &App, settings: &Settings) {
+    // the logger can only be installed once, later calls just change its settings
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(settings.log_level.filter());
+    redact::configure(settings);
+    let log_file = match settings.log_to_file {
+        true => Some(LogFile { app: app.clone(), path: settings.log_file_path() }),
+        false => None,
+    };
+    LOG_FILE.with(|file| file.replace(log_file));
+}
+
+/// Moves the log file at `path` to `<path>.1` once it is too large, replacing the previous one
+pub async fn rotate(file_processor: &FileProcessor, path: &str) -> Result<(), SemanticSearchError> {
+    match file_processor.size_of(path).await? {
+        Some(size) if size > MAX_LOG_FILE_BYTES => {
+            let previous = format!("{}.1", path);
+            file_processor.delete_file_at_path(&previous).await?;
+            file_processor.rename_path(path, &previous).await
+        }
+        _ => Ok(()),
+    }
+}
+
+fn format_line(timestamp: &str, level: Level, target: &str, message: &str) -> String {
+    format!("{} {:<5} {}: {}\n", timestamp, level, target, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_lines() {
+        let line = format_line("2024-01-02T03:04:05.000Z", Level::Info, "obsidian_rust_plugin::pipeline", "Batch 1/2: 10 records embedded");
+
+        assert_eq!(line, "2024-01-02T03:04:05.000Z INFO  obsidian_rust_plugin::pipeline: Batch 1/2: 10 records embedded\n");
+    }
+
+    #[test]
+    fn levels() {
+        let settings: Settings = serde_json::from_str(r#"{"logLevel": "debug"}"#).unwrap();
+
+        assert_eq!(settings.log_level.filter(), LevelFilter::Debug);
+        assert_eq!(Settings::default().log_level.filter(), LevelFilter::Info);
+        assert!(!Settings::default().log_to_file);
+    }
+}
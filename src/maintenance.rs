@@ -0,0 +1,253 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+
+use crate::SemanticSearchError;
+use crate::auto_index::record_name;
+use crate::embedding_file::{EmbeddingFile, StoreMetadata};
+use crate::input::InputRecord;
+use crate::pipeline::{PipelineHook, PipelineRecord, PipelineReport};
+use crate::provenance::ProvenanceFilter;
+use crate::session::QueryFilters;
+
+/// Selects the notes to re-embed: a folder plus any provenance constraints
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ReembedFilter {
+    pub folder: String,
+    #[serde(flatten)]
+    pub provenance: ProvenanceFilter,
+}
+
+impl ReembedFilter {
+    pub fn from_js(value: &JsValue) -> Result<Self, SemanticSearchError> {
+        if value.is_undefined() || value.is_null() {
+            return Ok(Self::default());
+        }
+        serde_wasm_bindgen::from_value(value.clone())
+            .map_err(|e| SemanticSearchError::InvalidArgument(format!("Invalid re-embed filter: {}", e)))
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReembedReport {
+    /// Notes re-embedded: those with a record matching the filter that input.csv still lists
+    pub notes: usize,
+    /// Stored records dropped in favour of the new embeddings
+    pub records_replaced: usize,
+    /// Selected notes with no records in input.csv, whose stored records were kept as they are
+    pub missing: Vec<String>,
+    pub report: PipelineReport,
+}
+
+/// The notes whose stored records a merge replaces
+#[derive(Debug, Default)]
+pub struct NoteSet {
+    names: HashSet<String>,
+    /// Vault paths of the notes, when they are known
+    paths: Option<HashSet<String>>,
+}
+
+impl NoteSet {
+    /// Every record with one of these names
+    pub fn names(names: &[String]) -> Self {
+        Self { names: names.iter().cloned().collect(), paths: None }
+    }
+
+    /// The records of the files at these vault paths. Records stored without their path can
+    /// only be matched by file name, which also catches notes with the same name elsewhere.
+    pub fn paths(paths: &[String]) -> Self {
+        Self { names: paths.iter().map(|path| record_name(path).to_string()).collect(), paths: Some(paths.iter().cloned().collect()) }
+    }
+
+    /// Number of notes, counting each path separately when paths are known
+    pub fn count(&self) -> usize {
+        self.paths.as_ref().map_or(self.names.len(), HashSet::len)
+    }
+
+    /// Whether the record named `name`, taken from the file at `path` if known, belongs to one of the notes
+    pub fn contains(&self, name: &str, path: Option<&str>) -> bool {
+        match (&self.paths, path) {
+            (Some(paths), Some(path)) => paths.contains(path),
+            _ => self.names.contains(name),
+        }
+    }
+
+    /// Whether stored record `index` of `store` belongs to one of the notes
+    pub fn holds(&self, store: &EmbeddingFile, index: usize) -> bool {
+        let path = store.provenance.get(index).and_then(|provenance| provenance.as_ref()?.path.as_deref());
+        self.contains(&store.rows[index].0, path)
+    }
+}
+
+/// Where new embeddings are written before they are merged into the store
+pub fn staging_path(embedding_path: &str) -> String {
+    format!("{}.reembed", embedding_path)
+}
+
+/// Names of the notes with at least one stored record matching both filters, in store order
+pub fn select_notes(store: &EmbeddingFile, filter: &ProvenanceFilter, scope: &QueryFilters) -> Vec<String> {
+    let mut seen: HashSet<&str> = HashSet::new();
+    let mut notes: Vec<String> = Vec::new();
+    for ((name, _, _), provenance) in store.rows.iter().zip(store.provenance.iter()) {
+        let path = provenance.as_ref().and_then(|provenance| provenance.path.as_deref());
+        if scope.matches(name, path) && filter.matches(provenance.as_ref()) && seen.insert(name) {
+            notes.push(name.clone());
+        }
+    }
+    notes
+}
+
+/// Splits `notes` into those with records in input.csv and those without. Re-embedding a note
+/// that input.csv no longer lists would replace its stored records with nothing.
+pub fn split_missing(notes: Vec<String>, input_records: &[InputRecord]) -> (Vec<String>, Vec<String>) {
+    let listed: HashSet<&str> = input_records.iter().map(|record| record.name.as_str()).collect();
+    notes.into_iter().partition(|name| listed.contains(name.as_str()))
+}
+
+/// Vectors from different models cannot be compared, so switching models has to replace every note at once
+pub fn check_model_change(store: &EmbeddingFile, model: &str, notes: &NoteSet) -> Result<(), SemanticSearchError> {
+    let stored_model = match &store.metadata {
+        Some(metadata) if metadata.model != model => &metadata.model,
+        _ => return Ok(()),
+    };
+    let untouched = (0..store.rows.len()).filter(|&i| !notes.holds(store, i)).count();
+    if untouched > 0 {
+        return Err(SemanticSearchError::InvalidArgument(format!(
+            "the store was embedded with '{}' but the configured model is '{}'; re-embedding only part of it would mix incomparable vectors ({} records left out), select every note or run Generate Embeddings",
+            stored_model, model, untouched)));
+    }
+    Ok(())
+}
+
+/// Replaces every record of `notes` in `store` with the records in `replacement`.
+/// The replacement's metadata wins, since it describes the model now in use.
+pub fn merge(store: EmbeddingFile, notes: &NoteSet, replacement: EmbeddingFile) -> EmbeddingFile {
+    let metadata = replacement.metadata.or(store.metadata);
+    let (mut rows, mut provenance): (Vec<_>, Vec<_>) = store.rows.into_iter().zip(store.provenance)
+        .filter(|((name, _, _), provenance)| !notes.contains(name, provenance.as_ref().and_then(|provenance| provenance.path.as_deref())))
+        .unzip();
+    rows.extend(replacement.rows);
+    provenance.extend(replacement.provenance);
+    EmbeddingFile { metadata, rows, provenance }
+}
+
+/// Fallback metadata when neither the store nor the new embeddings have any
+pub fn metadata_for(store: &EmbeddingFile, model: &str, created_at: f64) -> StoreMetadata {
+    let dimensions = store.rows.first().map_or(0, |(_, _, embedding)| embedding.len());
+    StoreMetadata::new(model, dimensions, created_at)
+}
+
+/// Only lets the records of the selected notes through to be embedded
+pub struct NoteFilterHook {
+    notes: HashSet<String>,
+}
+
+impl NoteFilterHook {
+    pub fn new(notes: &[String]) -> Self {
+        Self { notes: notes.iter().cloned().collect() }
+    }
+}
+
+impl PipelineHook for NoteFilterHook {
+    fn preprocess(&mut self, records: Vec<PipelineRecord>) -> Result<Vec<PipelineRecord>, SemanticSearchError> {
+        Ok(records.into_iter().filter(|record| self.notes.contains(&record.name)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provenance::Provenance;
+
+    fn provenance(model: &str, embedded_at: f64) -> Option<Provenance> {
+        Some(Provenance { model: model.to_string(), provider: String::new(), embedded_at, settings_hash: String::new(), lines: None, note_times: None, language: None, path: None })
+    }
+
+    fn store(model: &str) -> EmbeddingFile {
+        EmbeddingFile {
+            metadata: Some(StoreMetadata::new(model, 1, 0.0)),
+            rows: vec![
+                ("a.md".to_string(), "A1".to_string(), vec![1.0]),
+                ("b.md".to_string(), "B".to_string(), vec![2.0]),
+                ("a.md".to_string(), "A2".to_string(), vec![3.0]),
+            ],
+            provenance: vec![provenance(model, 1.0), provenance(model, 5.0), provenance(model, 9.0)],
+        }
+    }
+
+    #[test]
+    fn filter_from_json() {
+        let filter: ReembedFilter = serde_json::from_str(r#"{"folder": "Projects", "exclude_model": "small"}"#).unwrap();
+
+        assert_eq!(filter.folder, "Projects");
+        assert_eq!(filter.provenance.exclude_model.as_deref(), Some("small"));
+    }
+
+    #[test]
+    fn selects_each_note_once() {
+        let old = ProvenanceFilter { embedded_before: Some(6.0), ..Default::default() };
+
+        assert_eq!(select_notes(&store("ada"), &old, &QueryFilters::default()), vec!["a.md", "b.md"]);
+        assert!(select_notes(&store("ada"), &ProvenanceFilter { model: Some("small".to_string()), ..Default::default() }, &QueryFilters::default()).is_empty());
+    }
+
+    #[test]
+    fn notes_missing_from_input_are_split_off() {
+        let input = vec![InputRecord::new("b.md".to_string(), "B".to_string(), "b".to_string())];
+
+        let (listed, missing) = split_missing(vec!["a.md".to_string(), "b.md".to_string()], &input);
+
+        assert_eq!(listed, vec!["b.md"]);
+        assert_eq!(missing, vec!["a.md"]);
+    }
+
+    #[test]
+    fn model_change_must_cover_store() {
+        let store = store("ada");
+        let all = vec!["a.md".to_string(), "b.md".to_string()];
+
+        assert!(check_model_change(&store, "ada", &NoteSet::names(&all[..1])).is_ok());
+        assert!(check_model_change(&store, "small", &NoteSet::names(&all)).is_ok());
+        assert!(check_model_change(&store, "small", &NoteSet::names(&all[..1])).is_err());
+    }
+
+    #[test]
+    fn merge_replaces_selected_notes() {
+        let replacement = EmbeddingFile {
+            metadata: Some(StoreMetadata::new("ada", 1, 10.0)),
+            rows: vec![("a.md".to_string(), "A".to_string(), vec![4.0])],
+            provenance: vec![provenance("ada", 10.0)],
+        };
+
+        let merged = merge(store("ada"), &NoteSet::names(&["a.md".to_string()]), replacement);
+
+        assert_eq!(merged.rows.iter().map(|(_, header, _)| header.as_str()).collect::<Vec<_>>(), vec!["B", "A"]);
+        assert_eq!(merged.provenance, vec![provenance("ada", 5.0), provenance("ada", 10.0)]);
+        assert_eq!(merged.metadata.unwrap().created_at, 10.0);
+    }
+
+    #[test]
+    fn merge_by_path_keeps_notes_with_the_same_name() {
+        let mut store = store("ada");
+        store.provenance[0].as_mut().unwrap().path = Some("Projects/a.md".to_string());
+        store.provenance[2].as_mut().unwrap().path = Some("Archive/a.md".to_string());
+        let notes = NoteSet::paths(&["Projects/a.md".to_string(), "Inbox/b.md".to_string()]);
+
+        let merged = merge(store, &notes, EmbeddingFile { metadata: None, rows: Vec::new(), provenance: Vec::new() });
+
+        // b.md was stored without its path, so it is matched by name
+        assert_eq!(merged.rows.iter().map(|(_, header, _)| header.as_str()).collect::<Vec<_>>(), vec!["A2"]);
+    }
+
+    #[test]
+    fn filter_hook_keeps_selected_notes() {
+        let record = |name: &str| PipelineRecord { name: name.to_string(), header: String::new(), body: String::new(), text: "text".to_string(), lines: None, times: None, path: None };
+        let mut hook = NoteFilterHook::new(&["a.md".to_string()]);
+
+        let kept = hook.preprocess(vec![record("a.md"), record("b.md")]).unwrap();
+
+        assert_eq!(kept, vec![record("a.md")]);
+    }
+}
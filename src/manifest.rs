@@ -0,0 +1,142 @@
+use serde::Serialize;
+
+use crate::FileProcessor;
+use crate::SemanticSearchError;
+use crate::error::JsErrorPayload;
+use crate::pipeline::PipelineReport;
+
+/// Name of the manifest describing the most recent build, stored next to input.csv
+pub const MANIFEST_FILE_NAME: &str = "last_run.json";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StageTiming {
+    pub name: String,
+    pub duration_ms: f64,
+    /// Number of files, records or bytes the stage produced
+    pub items: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+/// What happened during the last Generate Input or Generate Embeddings run
+#[derive(Debug, Serialize)]
+pub struct RunManifest {
+    pub command: String,
+    pub started_at: f64,
+    pub finished_at: f64,
+    pub success: bool,
+    pub error: Option<JsErrorPayload>,
+    pub stages: Vec<StageTiming>,
+    pub warnings: Vec<String>,
+    pub skipped: Vec<SkippedFile>,
+    pub report: Option<PipelineReport>,
+}
+
+/// Builds a `RunManifest` as a command progresses through its stages
+pub struct RunRecorder {
+    manifest: RunManifest,
+    stage_started_at: f64,
+    clock: fn() -> f64,
+}
+
+impl RunRecorder {
+    pub fn start(command: &str) -> Self {
+        Self::with_clock(command, js_sys::Date::now)
+    }
+
+    pub fn with_clock(command: &str, clock: fn() -> f64) -> Self {
+        let now = clock();
+        let manifest = RunManifest {
+            command: command.to_string(),
+            started_at: now,
+            finished_at: now,
+            success: false,
+            error: None,
+            stages: Vec::new(),
+            warnings: Vec::new(),
+            skipped: Vec::new(),
+            report: None,
+        };
+        Self { manifest, stage_started_at: now, clock }
+    }
+
+    /// Ends the current stage, timing it from the end of the previous one
+    pub fn stage(&mut self, name: &str, items: usize) {
+        let now = (self.clock)();
+        self.manifest.stages.push(StageTiming { name: name.to_string(), duration_ms: now - self.stage_started_at, items });
+        self.stage_started_at = now;
+    }
+
+    pub fn warn(&mut self, warning: String) {
+        self.manifest.warnings.push(warning);
+    }
+
+    pub fn skip(&mut self, path: &str, reason: String) {
+        self.manifest.skipped.push(SkippedFile { path: path.to_string(), reason });
+    }
+
+    pub fn set_report(&mut self, report: &PipelineReport) {
+        for event in &report.truncated {
+            self.warn(format!("Truncated {} > {} from {} to {} tokens", event.name, event.header, event.original_tokens, event.truncated_tokens));
+        }
+        self.manifest.report = Some(report.clone());
+    }
+
+    pub fn finish<T>(mut self, result: &Result<T, SemanticSearchError>) -> RunManifest {
+        self.manifest.finished_at = (self.clock)();
+        match result {
+            Ok(_) => self.manifest.success = true,
+            Err(e) => self.manifest.error = Some(JsErrorPayload::new(e)),
+        }
+        self.manifest
+    }
+}
+
+/// Replaces the manifest at `path`
+pub async fn save(file_processor: &FileProcessor, path: &str, manifest: &RunManifest) -> Result<(), SemanticSearchError> {
+    let data = serde_json::to_string_pretty(manifest).map_err(|e| SemanticSearchError::ConversionError(Box::new(e)))?;
+    file_processor.delete_file_at_path(path).await?;
+    file_processor.write_to_path(path, &data).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    thread_local! {
+        static NOW: Cell<f64> = const { Cell::new(0.0) };
+    }
+
+    fn advance(ms: f64) {
+        NOW.with(|now| now.set(now.get() + ms));
+    }
+
+    fn fake_clock() -> f64 {
+        NOW.with(|now| now.get())
+    }
+
+    #[test]
+    fn records_stages_and_failure() {
+        let mut recorder = RunRecorder::with_clock("generate input", fake_clock);
+        advance(5.0);
+        recorder.stage("collect files", 3);
+        advance(20.0);
+        recorder.stage("extract sections", 12);
+        recorder.skip("broken.md", "could not be read".to_string());
+
+        let manifest = recorder.finish::<()>(&Err(SemanticSearchError::Cancelled));
+
+        assert_eq!(manifest.stages.iter().map(|stage| (stage.name.as_str(), stage.duration_ms, stage.items)).collect::<Vec<_>>(),
+            vec![("collect files", 5.0, 3), ("extract sections", 20.0, 12)]);
+        assert_eq!(manifest.finished_at - manifest.started_at, 25.0);
+        assert!(!manifest.success);
+        assert_eq!(manifest.error.unwrap().code, crate::error::ErrorCode::Cancelled);
+        assert_eq!(manifest.skipped[0].path, "broken.md");
+    }
+}
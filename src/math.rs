@@ -0,0 +1,93 @@
+//! Vector arithmetic used to score stored embeddings.
+//!
+//! The wasm build enables `simd128` (see `.cargo/config.toml`), so `dot` multiplies four lanes
+//! per instruction there. Other targets use the same four-accumulator loop in scalar code,
+//! which sums in the same order and which the compiler can vectorize on its own.
+//!
+//! cargo run --release --example dot_product compares it with ndarray's dot product.
+
+/// Components processed per step, the width of a wasm `f32x4`
+const LANES: usize = 4;
+
+/// Dot product over the shorter of the two vectors
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+pub fn dot(left: &[f32], right: &[f32]) -> f32 {
+    use std::arch::wasm32::{f32x4_add, f32x4_extract_lane, f32x4_mul, f32x4_splat, v128, v128_load};
+
+    let n = left.len().min(right.len());
+    let chunks = n / LANES;
+    let mut sum = f32x4_splat(0.0);
+    for i in 0..chunks {
+        // Safety: both slices hold at least `(i + 1) * LANES` floats and `v128_load` allows unaligned reads
+        let (l, r) = unsafe {
+            (v128_load(left.as_ptr().add(i * LANES) as *const v128), v128_load(right.as_ptr().add(i * LANES) as *const v128))
+        };
+        sum = f32x4_add(sum, f32x4_mul(l, r));
+    }
+    let tail = chunks * LANES;
+    (f32x4_extract_lane::<0>(sum) + f32x4_extract_lane::<1>(sum))
+        + (f32x4_extract_lane::<2>(sum) + f32x4_extract_lane::<3>(sum))
+        + left[tail..n].iter().zip(&right[tail..n]).map(|(l, r)| l * r).sum::<f32>()
+}
+
+/// Dot product over the shorter of the two vectors
+#[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+pub fn dot(left: &[f32], right: &[f32]) -> f32 {
+    let n = left.len().min(right.len());
+    let (left, right) = (&left[..n], &right[..n]);
+    let mut sum = [0.0f32; LANES];
+    for (l, r) in left.chunks_exact(LANES).zip(right.chunks_exact(LANES)) {
+        for ((sum, l), r) in sum.iter_mut().zip(l).zip(r) {
+            *sum += l * r;
+        }
+    }
+    let tail = n - n % LANES;
+    (sum[0] + sum[1]) + (sum[2] + sum[3])
+        + left[tail..].iter().zip(&right[tail..]).map(|(l, r)| l * r).sum::<f32>()
+}
+
+/// Cosine similarity of two vectors, NaN when either is degenerate, see `is_degenerate`
+pub fn cosine_similarity(left: &[f32], right: &[f32]) -> f32 {
+    dot(left, right) / (dot(left, left).sqrt() * dot(right, right).sqrt())
+}
+
+/// Whether `vector` has no direction to compare: it is empty, all zeros, or has a NaN or infinite component
+pub fn is_degenerate(vector: &[f32]) -> bool {
+    vector.iter().all(|&x| x == 0.0) || vector.iter().any(|x| !x.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naive(left: &[f32], right: &[f32]) -> f32 {
+        left.iter().zip(right).map(|(l, r)| l * r).sum()
+    }
+
+    #[test]
+    fn matches_a_plain_sum() {
+        for n in [0, 1, 3, 4, 5, 8, 1536] {
+            let left: Vec<f32> = (0..n).map(|i| (i as f32 * 0.37).sin()).collect();
+            let right: Vec<f32> = (0..n).map(|i| (i as f32 * 0.11).cos()).collect();
+
+            assert!((dot(&left, &right) - naive(&left, &right)).abs() < 1e-3, "n = {}", n);
+        }
+        assert_eq!(dot(&[1.0, 2.0, 3.0], &[4.0, 5.0]), 14.0);
+    }
+
+    #[test]
+    fn cosine() {
+        assert!((cosine_similarity(&[1.0, 2.0, 3.0, 4.0, 5.0], &[2.0, 4.0, 6.0, 8.0, 10.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 0.0]).is_nan());
+    }
+
+    #[test]
+    fn degenerate_vectors() {
+        assert!(is_degenerate(&[]));
+        assert!(is_degenerate(&[0.0, -0.0, 0.0]));
+        assert!(is_degenerate(&[0.5, f32::NAN]));
+        assert!(is_degenerate(&[f32::INFINITY, 0.5]));
+        assert!(!is_degenerate(&[0.0, 1e-30]));
+    }
+}
@@ -0,0 +1,48 @@
+//! Matryoshka (MRL) truncation. Models trained with Matryoshka Representation Learning pack
+//! most of the meaning into the leading dimensions, so a prefix of each vector, renormalized,
+//! ranks almost as well as the whole vector while taking a fraction of the space.
+
+/// Models known to be trained for truncation, matched by prefix
+const MATRYOSHKA_MODELS: &[&str] = &["text-embedding-3-", "voyage-3", "jina-embeddings-v3", "nomic-embed-text-v1.5", "mxbai-embed-large"];
+
+pub fn supports_truncation(model: &str) -> bool {
+    MATRYOSHKA_MODELS.iter().any(|prefix| model.starts_with(prefix))
+}
+
+/// Keeps the first `dimensions` components of `embedding` and scales them back to unit length.
+/// Vectors that are not longer than `dimensions`, or a `dimensions` of 0, are left alone.
+pub fn truncate(embedding: &mut Vec<f32>, dimensions: usize) {
+    if dimensions == 0 || embedding.len() <= dimensions {
+        return;
+    }
+    embedding.truncate(dimensions);
+    let norm = embedding.iter().map(|value| value * value).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        embedding.iter_mut().for_each(|value| *value /= norm);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncates_and_renormalizes() {
+        let mut embedding = vec![0.6, 0.0, 0.8, 0.0];
+        truncate(&mut embedding, 2);
+        assert_eq!(embedding, vec![1.0, 0.0]);
+
+        let mut short = vec![0.6, 0.8];
+        truncate(&mut short, 4);
+        assert_eq!(short, vec![0.6, 0.8]);
+        truncate(&mut short, 0);
+        assert_eq!(short, vec![0.6, 0.8]);
+    }
+
+    #[test]
+    fn known_models() {
+        assert!(supports_truncation("text-embedding-3-small"));
+        assert!(supports_truncation("voyage-3-lite"));
+        assert!(!supports_truncation("text-embedding-ada-002"));
+    }
+}
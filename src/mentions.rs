@@ -0,0 +1,119 @@
+//! Passages of other notes that talk about a note without linking to it, like Obsidian's
+//! unlinked mentions but matched by meaning rather than by the note's title.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::input::LineRange;
+use crate::links;
+use crate::seeding::{self, SeedMode};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnlinkedMention {
+    pub name: String,
+    pub header: String,
+    /// Best similarity to any section of the note
+    pub score: f32,
+    /// Lines of the passage in its note, when known
+    pub lines: Option<LineRange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnlinkedMentions {
+    /// Wiki link to the note, to insert into the passages
+    pub wikilink: String,
+    /// Passages at least as similar as the threshold, most similar first
+    pub mentions: Vec<UnlinkedMention>,
+}
+
+/// Finds the stored sections of other notes at least `threshold` similar to some section of
+/// the note at `path`, leaving out those whose stored text, kept in the header column, already
+/// links to it.
+pub fn find_unlinked_mentions(rows: &[(String, String, Vec<f32>)], lines: &HashMap<(&str, &str), LineRange>, path: &str, threshold: f32) -> UnlinkedMentions {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let seeds = [name.to_string(), path.to_string()];
+    let mut mentions: Vec<UnlinkedMention> = seeding::score_against_seeds(rows, &seeds, SeedMode::Union).unwrap_or_default().into_iter()
+        .filter(|&(_, score)| score >= threshold)
+        .map(|(index, score)| (&rows[index], score))
+        .filter(|((_, text, _), _)| !links_to(text, path))
+        .map(|((name, header, _), score)| {
+            let lines = lines.get(&(name.as_str(), header.as_str())).copied();
+            UnlinkedMention { name: name.clone(), header: header.clone(), score, lines }
+        })
+        .collect();
+    mentions.sort_by(|a, b| b.score.total_cmp(&a.score));
+    UnlinkedMentions { wikilink: links::note_wikilink(path), mentions }
+}
+
+/// Whether `text` has a wiki link or markdown link to the note at `path`, by its full path or,
+/// as Obsidian writes them by default, by its name alone
+pub fn links_to(text: &str, path: &str) -> bool {
+    let target = note_link(path);
+    let basename = target.rsplit('/').next().unwrap_or(target);
+    let wikilinks = text.split("[[").skip(1).filter_map(|rest| rest.split_once("]]")).map(|(link, _)| link.to_string());
+    let markdown_links = text.split("](").skip(1).filter_map(|rest| rest.split_once(')')).map(|(link, _)| link.replace("%20", " "));
+    wikilinks.chain(markdown_links).any(|link| {
+        let link = link.split(['|', '#']).next().unwrap_or_default().trim().trim_start_matches('/');
+        let link = note_link(link);
+        link == target || link == basename
+    })
+}
+
+/// How links refer to the note at `path`: markdown notes without their extension
+fn note_link(path: &str) -> &str {
+    path.strip_suffix(".md").unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_links_to_the_note() {
+        assert!(links_to("See [[raft]] for details", "Papers/raft.md"));
+        assert!(links_to("See [[Papers/raft#Election|the paper]]", "Papers/raft.md"));
+        assert!(links_to("See [the paper](Papers/raft.md)", "Papers/raft.md"));
+        assert!(links_to("See [it](My%20Notes/raft%20paper.md)", "My Notes/raft paper.md"));
+        assert!(!links_to("Raft elects a leader, see [[raft-extended]]", "Papers/raft.md"));
+        assert!(!links_to("Nothing here [[unclosed", "raft.md"));
+    }
+
+    #[test]
+    fn finds_similar_sections_without_links() {
+        let rows = vec![
+            ("raft.md".to_string(), "Raft consensus".to_string(), vec![1.0, 0.0]),
+            ("notes.md".to_string(), "Linked\nAs [[raft]] shows".to_string(), vec![1.0, 0.1]),
+            ("notes.md".to_string(), "Unlinked\nLeader election".to_string(), vec![1.0, 0.2]),
+            ("far.md".to_string(), "Far".to_string(), vec![0.0, 1.0]),
+        ];
+        let lines = HashMap::from([(("notes.md", "Unlinked\nLeader election"), LineRange { start: 4, end: 6 })]);
+
+        let found = find_unlinked_mentions(&rows, &lines, "raft.md", 0.9);
+
+        assert_eq!(found.wikilink, "[[raft]]");
+        assert_eq!(found.mentions.iter().map(|mention| mention.header.as_str()).collect::<Vec<_>>(), vec!["Unlinked\nLeader election"]);
+        assert_eq!(found.mentions[0].lines, Some(LineRange { start: 4, end: 6 }));
+    }
+
+    #[cfg(feature = "headless")]
+    #[test]
+    fn skips_linking_sections_of_a_pipeline_store() {
+        use crate::embedding_file::read_embedding_file;
+        use crate::headless::{HeadlessIndex, MockEmbeddingProvider, Settings, VaultNote};
+
+        let note = |path: &str, content: &str| VaultNote { path: path.to_string(), content: content.to_string(), times: None };
+        let notes = vec![
+            note("Papers/raft.md", "# Raft\nLeader election and log replication"),
+            note("linked.md", "# Consensus\nLeader election and log replication, see [[raft]]"),
+            note("unlinked.md", "# Consensus\nLeader election and log replication"),
+        ];
+        let settings = Settings { section_delimeter_regex: "^#".to_string(), ..Settings::default() };
+        let (index, _) = HeadlessIndex::build(settings, &notes, MockEmbeddingProvider).unwrap();
+        let store = read_embedding_file(&index.to_csv().unwrap()).unwrap();
+
+        let found = find_unlinked_mentions(&store.rows, &HashMap::new(), "Papers/raft.md", 0.5);
+
+        assert_eq!(found.mentions.iter().map(|mention| mention.name.as_str()).collect::<Vec<_>>(), vec!["unlinked.md"]);
+    }
+}
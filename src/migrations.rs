@@ -0,0 +1,136 @@
+//! Upgrading input.csv and embedding files written by older versions of the plugin.
+//!
+//! The readers understand every layout a store has had, so an upgrade reads a file with them and
+//! writes it back in the current layout. A format change only has to teach the reader the new
+//! layout and bump the schema version for existing files to be upgraded the next time the plugin
+//! loads. Files newer than the plugin are refused rather than rewritten, so they are never
+//! truncated to what this version understands.
+
+use serde::Serialize;
+
+use crate::SemanticSearchError;
+use crate::embedding_file::{self, EMBEDDING_SCHEMA_VERSION};
+use crate::input::{self, INPUT_SCHEMA_VERSION};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    Input,
+    Embeddings,
+}
+
+/// A store rewritten in the current layout
+#[derive(Debug, PartialEq)]
+pub struct Upgrade {
+    pub from: u32,
+    pub to: u32,
+    pub data: String,
+}
+
+/// One file upgraded when the plugin loaded. The file as it was is kept at `backup`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Migration {
+    pub kind: StoreKind,
+    pub path: String,
+    pub from: u32,
+    pub to: u32,
+    pub backup: String,
+}
+
+/// Files upgraded, and the files that were left as they are with the reason why
+#[derive(Debug, Default, Serialize)]
+pub struct MigrationReport {
+    pub migrated: Vec<Migration>,
+    pub refused: Vec<Refusal>,
+}
+
+/// A file left as it is because it could not be upgraded
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Refusal {
+    pub path: String,
+    pub reason: String,
+}
+
+/// Where a file is kept before being rewritten from schema version `from`, e.g. input.csv.v4.bak
+pub fn backup_path(path: &str, from: u32) -> String {
+    format!("{}.v{}.bak", path, from)
+}
+
+/// `data` rewritten in the current layout, or `None` if it already is in it. Fails with
+/// `UnsupportedSchema` for files written by a newer plugin and with the reader's error for files
+/// that cannot be read in full.
+pub fn upgrade(kind: StoreKind, data: &str) -> Result<Option<Upgrade>, SemanticSearchError> {
+    match kind {
+        StoreKind::Input => upgrade_input(data),
+        StoreKind::Embeddings => upgrade_embedding_file(data),
+    }
+}
+
+// files already in the current layout are not read in full, so a malformed row in them is left
+// to the lenient readers instead of being reported on every load
+fn upgrade_input(data: &str) -> Result<Option<Upgrade>, SemanticSearchError> {
+    let from = input::schema_version(data)?;
+    if from == INPUT_SCHEMA_VERSION {
+        return Ok(None);
+    }
+    // refuses files newer than the plugin
+    let records = input::read_input(data)?;
+    Ok(Some(Upgrade { from, to: INPUT_SCHEMA_VERSION, data: input::write_input(&records, input::granularity(data)?)? }))
+}
+
+fn upgrade_embedding_file(data: &str) -> Result<Option<Upgrade>, SemanticSearchError> {
+    if embedding_file::schema_version(data)? == EMBEDDING_SCHEMA_VERSION {
+        return Ok(None);
+    }
+    let mut store = embedding_file::read_embedding_file(data)?;
+    let metadata = match store.metadata.as_mut() {
+        Some(metadata) => metadata,
+        None => return Err(SemanticSearchError::UnsupportedSchema(
+            "the embedding file predates store metadata and does not record its model, generate embeddings again to upgrade it".to_string())),
+    };
+    let from = metadata.schema_version;
+    metadata.schema_version = EMBEDDING_SCHEMA_VERSION;
+    Ok(Some(Upgrade { from, to: EMBEDDING_SCHEMA_VERSION, data: store.to_csv()? }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upgrades_old_input() {
+        let upgrade = upgrade(StoreKind::Input, "note.md,Test,\"Some, text\"\n").unwrap().unwrap();
+
+        assert_eq!(upgrade.from, 1);
+        assert_eq!(upgrade.to, INPUT_SCHEMA_VERSION);
+        assert!(upgrade.data.starts_with(&format!("# schema_version: {}\n", INPUT_SCHEMA_VERSION)));
+        let records = input::read_input(&upgrade.data).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].body, "Some, text");
+        assert_eq!(super::upgrade(StoreKind::Input, &upgrade.data).unwrap(), None);
+    }
+
+    #[test]
+    fn upgrades_old_embedding_file() {
+        let old = "# embedding_store: {\"schema_version\":2,\"model\":\"m\",\"dimensions\":2,\"normalization\":\"none\",\"created_at\":7}\nname,header,embedding\na.md,A,\"1,0\"\n";
+
+        let upgrade = upgrade(StoreKind::Embeddings, old).unwrap().unwrap();
+
+        assert_eq!((upgrade.from, upgrade.to), (2, EMBEDDING_SCHEMA_VERSION));
+        let before = embedding_file::read_embedding_file(old).unwrap();
+        let after = embedding_file::read_embedding_file(&upgrade.data).unwrap();
+        assert_eq!(after.metadata.as_ref().unwrap().schema_version, EMBEDDING_SCHEMA_VERSION);
+        assert_eq!(after.rows, before.rows);
+        assert_eq!(after.provenance, before.provenance);
+        assert_eq!(super::upgrade(StoreKind::Embeddings, &upgrade.data).unwrap(), None);
+    }
+
+    #[test]
+    fn refuses_what_it_cannot_upgrade() {
+        let newer = "# embedding_store: {\"schema_version\":99,\"model\":\"m\",\"dimensions\":1,\"normalization\":\"none\",\"created_at\":0}\nname,header,embedding\n";
+
+        assert!(matches!(upgrade(StoreKind::Embeddings, newer), Err(SemanticSearchError::UnsupportedSchema(_))));
+        assert!(matches!(upgrade(StoreKind::Embeddings, "a.md,A,\"1,0\"\n"), Err(SemanticSearchError::UnsupportedSchema(_))));
+        assert!(matches!(upgrade(StoreKind::Input, "# schema_version: 99\n"), Err(SemanticSearchError::UnsupportedSchema(_))));
+    }
+}
@@ -0,0 +1,102 @@
+//! Maps of content: markdown outlines of the notes on a topic, grouped by sub-topic.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::SemanticSearchError;
+use crate::clustering;
+use crate::links;
+
+/// Most sections of a topic query that go into its map of content
+pub const MOC_SECTIONS: usize = 50;
+
+/// What a map of content is about: the sections best matching `query`, or cluster number
+/// `cluster` of those returned by `cluster_notes` with the same `k`
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MocSource {
+    pub query: Option<String>,
+    pub cluster: Option<usize>,
+    /// Number of clusters, 0 picks it from the size of the store like `cluster_notes`
+    pub k: usize,
+}
+
+impl MocSource {
+    pub fn validate(&self) -> Result<(), SemanticSearchError> {
+        match (self.query.as_deref().map(str::trim), self.cluster) {
+            (Some(""), None) => Err(SemanticSearchError::InvalidArgument("The topic is empty".to_string())),
+            (Some(_), None) | (None, Some(_)) => Ok(()),
+            _ => Err(SemanticSearchError::InvalidArgument("Expected either a topic query or a cluster".to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Moc {
+    pub title: String,
+    pub markdown: String,
+    /// Notes linked from the outline
+    pub notes: usize,
+    /// Where the outline was written, when a path was given
+    pub path: Option<String>,
+}
+
+/// Outlines the notes of `rows`, sections on one topic, under a heading per sub-topic. Each
+/// note is listed once, under the largest sub-topic it has sections in, and linked by its vault
+/// path from `paths` when the note is still in the vault.
+pub fn outline(title: &str, rows: &[(String, String, Vec<f32>)], paths: &HashMap<String, String>) -> Moc {
+    let mut markdown = format!("# {}\n", title.trim());
+    let mut listed = HashSet::new();
+    for sub_topic in clustering::cluster_notes(rows, clustering::default_k(rows.len())) {
+        let notes: Vec<&String> = sub_topic.notes.iter().filter(|name| listed.insert(name.to_string())).collect();
+        if notes.is_empty() {
+            continue;
+        }
+        let label = match sub_topic.label.trim() {
+            "" => "Other",
+            label => label,
+        };
+        markdown.push_str(&format!("\n## {}\n", label));
+        for name in notes {
+            markdown.push_str(&format!("- {}\n", links::note_wikilink(paths.get(name).unwrap_or(name))));
+        }
+    }
+    Moc { title: title.trim().to_string(), markdown, notes: listed.len(), path: None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(name: &str, header: &str, embedding: Vec<f32>) -> (String, String, Vec<f32>) {
+        (name.to_string(), header.to_string(), embedding)
+    }
+
+    #[test]
+    fn outlines_sub_topics() {
+        let rows = vec![
+            row("pasta.md", "Pasta", vec![1.0, 0.1, 0.0]),
+            row("pasta.md", "Sauce", vec![0.9, 0.2, 0.0]),
+            row("bread.md", "Bread", vec![0.95, 0.0, 0.1]),
+            row("knives.md", "Knives", vec![0.0, 0.1, 1.0]),
+            row("pasta.md", "Tools", vec![0.3, 0.0, 0.9]),
+            row("knives.md", "Sharpening", vec![0.0, 0.2, 1.0]),
+        ];
+        let paths = HashMap::from([("pasta.md".to_string(), "Cooking/pasta.md".to_string())]);
+
+        let moc = outline(" Cooking ", &rows, &paths);
+
+        assert_eq!(moc.markdown, "# Cooking\n\n## Pasta\n- [[Cooking/pasta]]\n- [[bread]]\n\n## Knives\n- [[knives]]\n");
+        assert_eq!(moc.notes, 3);
+    }
+
+    #[test]
+    fn source_needs_a_query_or_a_cluster() {
+        assert!(MocSource { query: Some("cooking".to_string()), ..MocSource::default() }.validate().is_ok());
+        assert!(MocSource { cluster: Some(2), k: 5, ..MocSource::default() }.validate().is_ok());
+        assert!(MocSource { query: Some(" ".to_string()), ..MocSource::default() }.validate().is_err());
+        assert!(MocSource::default().validate().is_err());
+        assert!(MocSource { query: Some("cooking".to_string()), cluster: Some(0), k: 0 }.validate().is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! An embedding provider that needs no network or API key, for trying the plugin before signing
+//! up with a provider and for running the whole pipeline in tests.
+//!
+//! Each lowercased word is hashed to one of the dimensions and a sign, and the sums are scaled
+//! to unit length. The same text always gets the same vector and texts sharing words score as
+//! similar, so search results make some sense, but the vectors capture nothing about meaning.
+
+use crate::SemanticSearchError;
+use crate::embedding::{Embedding, EmbeddingInput, EmbeddingResponse, EmbeddingUsage};
+use crate::provenance::fnv1a;
+use crate::providers::EmbeddingProvider;
+
+/// Name the `embeddingProvider` setting picks the mock provider by
+pub const MOCK_PROVIDER: &str = "mock";
+pub const MOCK_MODEL: &str = "mock-embedding";
+pub const MOCK_DIMENSIONS: usize = 256;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MockEmbeddingProvider;
+
+impl EmbeddingProvider for MockEmbeddingProvider {
+    fn name(&self) -> &str {
+        MOCK_PROVIDER
+    }
+
+    fn label(&self) -> &str {
+        "Offline (mock, no API key)"
+    }
+
+    fn api_base(&self) -> &str {
+        ""
+    }
+
+    fn models(&self) -> Vec<String> {
+        vec![MOCK_MODEL.to_string()]
+    }
+
+    fn dollars_per_million_tokens(&self, _model: &str) -> Option<f32> {
+        Some(0.0)
+    }
+
+    fn embed_offline(&self, model: &str, input: &EmbeddingInput) -> Option<Result<EmbeddingResponse, SemanticSearchError>> {
+        let EmbeddingInput::StringArray(texts) = input;
+        let words: usize = texts.iter().map(|text| words(text).count()).sum();
+        Some(Ok(EmbeddingResponse {
+            object: "list".to_string(),
+            model: model.to_string(),
+            data: texts.iter().enumerate().map(|(index, text)| Embedding {
+                index: index as u32,
+                object: "embedding".to_string(),
+                embedding: embed(text, MOCK_DIMENSIONS),
+            }).collect(),
+            usage: EmbeddingUsage { prompt_tokens: words as u32, total_tokens: words as u32 },
+        }))
+    }
+}
+
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty())
+}
+
+/// Unit length pseudo-embedding of `text` with `dimensions` dimensions
+pub fn embed(text: &str, dimensions: usize) -> Vec<f32> {
+    let dimensions = dimensions.max(1);
+    let mut embedding = vec![0.0f32; dimensions];
+    let mut add = |hash: u64| {
+        let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        embedding[(hash % dimensions as u64) as usize] += sign;
+    };
+    let mut any_words = false;
+    for word in words(text) {
+        add(fnv1a(&word.to_lowercase()));
+        any_words = true;
+    }
+    // texts without words still get a vector of their own rather than zeros, which have no direction
+    if !any_words {
+        add(fnv1a(text));
+    }
+    let norm = embedding.iter().map(|value| value * value).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        embedding.iter_mut().for_each(|value| *value /= norm);
+    }
+    embedding
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::ProviderRegistry;
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).map(|(a, b)| a * b).sum()
+    }
+
+    #[test]
+    fn embeddings_are_deterministic_unit_vectors() {
+        let first = embed("Compost the garden beds in autumn", MOCK_DIMENSIONS);
+
+        assert_eq!(first, embed("Compost the garden beds in autumn", MOCK_DIMENSIONS));
+        assert_eq!(first.len(), MOCK_DIMENSIONS);
+        assert!((cosine(&first, &first) - 1.0).abs() < 1e-5);
+        assert!((cosine(&embed("", 8), &embed("", 8)) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn shared_words_make_texts_similar() {
+        let note = embed("Compost and mulch keep garden soil healthy", MOCK_DIMENSIONS);
+
+        assert!(cosine(&note, &embed("garden compost", MOCK_DIMENSIONS)) > cosine(&note, &embed("borrow checker lifetimes", MOCK_DIMENSIONS)));
+    }
+
+    #[test]
+    fn embeds_without_a_request() {
+        let provider = ProviderRegistry::builtin().resolve(MOCK_PROVIDER).unwrap();
+        let input = EmbeddingInput::StringArray(vec!["one".to_string(), "two words".to_string()]);
+
+        let response = provider.embed_offline(MOCK_MODEL, &input).unwrap().unwrap();
+
+        assert_eq!(response.data.len(), 2);
+        assert_eq!(response.data[1].index, 1);
+        assert_eq!(response.usage.total_tokens, 3);
+        assert_eq!(provider.dollars_per_million_tokens(MOCK_MODEL), Some(0.0));
+        assert!(ProviderRegistry::builtin().resolve("openai").unwrap().embed_offline("m", &input).is_none());
+    }
+}
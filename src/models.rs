@@ -0,0 +1,16 @@
+use serde::Deserialize;
+
+/// Response of the `/models` endpoint
+#[derive(Debug, Deserialize, Clone)]
+pub struct ModelListResponse {
+    pub object: String,
+    pub data: Vec<Model>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Model {
+    pub id: String,
+    pub object: String,
+    #[serde(default)]
+    pub owned_by: String,
+}
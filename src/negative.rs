@@ -0,0 +1,51 @@
+//! Negative queries, which steer a search away from a topic.
+//!
+//! A query such as `consensus algorithms NOT raft` searches for `consensus algorithms` and
+//! subtracts each chunk's similarity to `raft` from its score, so a topic that dominates the
+//! vault stops crowding out everything else.
+
+/// Share of a chunk's similarity to the negative query taken off its score
+pub const EXCLUDE_WEIGHT: f32 = 1.0;
+
+/// Word separating a query from the text to steer away from
+const NOT: &str = " NOT ";
+
+/// Splits `query` into what to search for and what to steer away from, at the first
+/// uppercase ` NOT `. Queries without one, or with nothing on either side of it, are
+/// searched as they are.
+pub fn split_query(query: &str) -> (String, Option<String>) {
+    match query.split_once(NOT) {
+        Some((positive, negative)) if !positive.trim().is_empty() && !negative.trim().is_empty() => {
+            (positive.trim().to_string(), Some(negative.trim().to_string()))
+        },
+        _ => (query.trim().to_string(), None),
+    }
+}
+
+/// Score of a chunk with `similarity` to the query and `excluded` similarity to the negative query
+pub fn penalize(similarity: f32, excluded: f32) -> f32 {
+    similarity - EXCLUDE_WEIGHT * excluded.max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split() {
+        assert_eq!(split_query("consensus algorithms NOT raft"), ("consensus algorithms".to_string(), Some("raft".to_string())));
+        assert_eq!(split_query(" a NOT b NOT c "), ("a".to_string(), Some("b NOT c".to_string())));
+        assert_eq!(split_query("do not panic"), ("do not panic".to_string(), None));
+        assert_eq!(split_query("NOT raft"), ("NOT raft".to_string(), None));
+        assert_eq!(split_query("raft NOT "), ("raft NOT".to_string(), None));
+    }
+
+    #[test]
+    fn penalized_chunks_rank_lower() {
+        // The first chunk matches the query best but is about the excluded topic
+        let scores: Vec<f32> = [(0.9, 0.85), (0.8, 0.3), (0.7, -0.2)].iter().map(|&(similarity, excluded)| penalize(similarity, excluded)).collect();
+
+        assert!(scores[1] > scores[0]);
+        assert_eq!(scores[2], 0.7);
+    }
+}
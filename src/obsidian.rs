@@ -9,34 +9,41 @@ extern "C" {
     #[wasm_bindgen(method, getter)]
     pub fn app(this: &Plugin) -> App;
     #[wasm_bindgen(method, getter)]
-    pub fn settings(this: &Plugin) -> semanticSearchSettings;
-
-    pub type semanticSearchSettings;
-
-    #[wasm_bindgen(method, getter)]
-    pub fn apiKey(this: &semanticSearchSettings) -> String;
-    #[wasm_bindgen(method, getter)]
-    pub fn ignoredFolders(this: &semanticSearchSettings) -> String;
-    #[wasm_bindgen(method, getter)]
-    pub fn sectionDelimeterRegex(this: &semanticSearchSettings) -> String;
-    #[wasm_bindgen(method, getter)]
-    pub fn numBatches(this: &semanticSearchSettings) -> u32;
+    pub fn settings(this: &Plugin) -> JsValue;
 
     #[derive(Clone)]
     pub type App;
 
     #[wasm_bindgen(method, getter)]
     pub fn vault(this: &App) -> Vault;
+    #[wasm_bindgen(method, getter)]
+    pub fn metadataCache(this: &App) -> MetadataCache;
+
+    pub type MetadataCache;
+
+    /// The note's `CachedMetadata`, or null if Obsidian has not indexed it yet
+    #[wasm_bindgen(method)]
+    pub fn getFileCache(this: &MetadataCache, file: &TFile) -> JsValue;
+
+    /// Every tag of a note, inline and from its frontmatter, with the leading `#`, or null
+    pub fn getAllTags(cache: &JsValue) -> JsValue;
 
     pub type Vault;
 
+    #[wasm_bindgen(method)]
+    pub fn getName(this: &Vault) -> String;
     #[wasm_bindgen(method)]
     pub fn getRoot(this: &Vault) -> TFolder;
     #[wasm_bindgen(method)]
     pub fn getMarkdownFiles(this: &Vault) -> Vec<TFile>;
+    // Undocumented, but the only way to read settings such as the excluded files
+    #[wasm_bindgen(method)]
+    pub fn getConfig(this: &Vault, key: &str) -> JsValue;
     #[wasm_bindgen(method, catch)]
     pub async fn cachedRead(this: &Vault, file: TFile) -> Result<JsValue, JsValue>;
     #[wasm_bindgen(method, catch)]
+    pub async fn readBinary(this: &Vault, file: TFile) -> Result<JsValue, JsValue>;
+    #[wasm_bindgen(method, catch)]
     pub async fn append(this: &Vault, file: TFile, data: String) -> Result<JsValue, JsValue>;
     #[wasm_bindgen(method, catch)]
     pub async fn create(this: &Vault, path: String, data: String) -> Result<JsValue, JsValue>;
@@ -44,6 +51,33 @@ extern "C" {
     pub async fn delete(this: &Vault, file: TFile) -> Result<JsValue, JsValue>;
     #[wasm_bindgen(method)]
     pub fn getAbstractFileByPath(this: &Vault, path: String) -> TAbstractFile;
+    #[wasm_bindgen(method, getter)]
+    pub fn adapter(this: &Vault) -> DataAdapter;
+
+    /// Raw file access, which unlike the Vault API also reaches hidden folders such as .obsidian
+    pub type DataAdapter;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn exists(this: &DataAdapter, path: String) -> Result<JsValue, JsValue>;
+    #[wasm_bindgen(method, catch)]
+    pub async fn read(this: &DataAdapter, path: String) -> Result<JsValue, JsValue>;
+    #[wasm_bindgen(method, catch)]
+    pub async fn readBinary(this: &DataAdapter, path: String) -> Result<JsValue, JsValue>;
+    #[wasm_bindgen(method, catch)]
+    pub async fn write(this: &DataAdapter, path: String, data: String) -> Result<JsValue, JsValue>;
+    #[wasm_bindgen(method, catch)]
+    pub async fn writeBinary(this: &DataAdapter, path: String, data: js_sys::ArrayBuffer) -> Result<JsValue, JsValue>;
+    #[wasm_bindgen(method, catch)]
+    pub async fn append(this: &DataAdapter, path: String, data: String) -> Result<JsValue, JsValue>;
+    #[wasm_bindgen(method, catch)]
+    pub async fn remove(this: &DataAdapter, path: String) -> Result<JsValue, JsValue>;
+    #[wasm_bindgen(method, catch)]
+    pub async fn mkdir(this: &DataAdapter, path: String) -> Result<JsValue, JsValue>;
+    /// Resolves to `{ctime, mtime, size, type}`, or null if nothing exists at `path`
+    #[wasm_bindgen(method, catch)]
+    pub async fn stat(this: &DataAdapter, path: String) -> Result<JsValue, JsValue>;
+    #[wasm_bindgen(method, catch)]
+    pub async fn rename(this: &DataAdapter, path: String, new_path: String) -> Result<JsValue, JsValue>;
 
     #[derive(Debug)]
     pub type TAbstractFile;
@@ -58,6 +92,17 @@ extern "C" {
     pub fn name(this: &TFile) -> String;
     #[wasm_bindgen(method, getter)]
     pub fn extension(this: &TFile) -> String;
+    #[wasm_bindgen(method, getter)]
+    pub fn stat(this: &TFile) -> FileStats;
+
+    pub type FileStats;
+
+    /// Creation time in ms since the epoch
+    #[wasm_bindgen(method, getter)]
+    pub fn ctime(this: &FileStats) -> f64;
+    /// Last modification time in ms since the epoch
+    #[wasm_bindgen(method, getter)]
+    pub fn mtime(this: &FileStats) -> f64;
 
     #[derive(Debug)]
     #[wasm_bindgen(extends = TAbstractFile)]
@@ -68,12 +113,50 @@ extern "C" {
     #[wasm_bindgen(method, getter)]
     pub fn children(this: &TFolder) -> Vec<TAbstractFile>;
 
+    /// Loads the pdf.js build bundled with Obsidian
+    #[wasm_bindgen(catch)]
+    pub async fn loadPdfJs() -> Result<JsValue, JsValue>;
+
     pub type Notice;
 
     #[wasm_bindgen(constructor)]
     pub fn new(message: &str) -> Notice;
 }
 
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = setTimeout)]
+    pub fn set_timeout(handler: &js_sys::Function, timeout: f64) -> JsValue;
+}
+
+// The subset of the pdf.js API used to extract text from PDF attachments
+#[wasm_bindgen]
+extern "C" {
+    pub type PdfJs;
+    #[wasm_bindgen(method)]
+    pub fn getDocument(this: &PdfJs, source: &JsValue) -> PdfLoadingTask;
+
+    pub type PdfLoadingTask;
+    #[wasm_bindgen(method, getter)]
+    pub fn promise(this: &PdfLoadingTask) -> js_sys::Promise;
+
+    pub type PdfDocument;
+    #[wasm_bindgen(method, getter)]
+    pub fn numPages(this: &PdfDocument) -> u32;
+    #[wasm_bindgen(method)]
+    pub fn getPage(this: &PdfDocument, number: u32) -> js_sys::Promise;
+    #[wasm_bindgen(method)]
+    pub fn destroy(this: &PdfDocument);
+
+    pub type PdfPage;
+    #[wasm_bindgen(method)]
+    pub fn getTextContent(this: &PdfPage) -> js_sys::Promise;
+
+    pub type PdfTextContent;
+    #[wasm_bindgen(method, getter)]
+    pub fn items(this: &PdfTextContent) -> js_sys::Array;
+}
+
 #[wasm_bindgen(module = "main")]
 extern "C" {
     pub type GenerateEmbeddingsModal;
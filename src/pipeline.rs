@@ -0,0 +1,576 @@
+use std::collections::{HashMap, HashSet};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::chunking::Granularity;
+use crate::Client;
+use crate::FileProcessor;
+use crate::file_processor::StoreFiles;
+use crate::SemanticSearchError;
+use crate::embedding::EmbeddingResponse;
+use crate::embedding_file::{RowWriter, StoreMetadata};
+use crate::input::{self, EmbedText, InputRecord, LineRange, NoteTimes, read_input};
+use crate::language;
+use crate::manifest::RunRecorder;
+use crate::matryoshka;
+use crate::provenance::Provenance;
+use crate::providers::InputType;
+use crate::quantization::Quantization;
+use crate::truncation::{TruncationEvent, split_text};
+
+/// Most inputs the embeddings endpoint accepts in a single request
+pub const MAX_INPUTS_PER_REQUEST: usize = 2048;
+
+/// Embeddings longer than this are never written, whatever size the store expects
+pub const MAX_EMBEDDING_DIMENSIONS: usize = 16_384;
+
+/// A single row of input.csv as it moves through the embedding pipeline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PipelineRecord {
+    pub name: String,
+    pub header: String,
+    pub body: String,
+    /// The text sent to the embedding API
+    pub text: String,
+    pub lines: Option<LineRange>,
+    pub times: Option<NoteTimes>,
+    /// Vault path of the file the record was taken from, when known
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+/// A record paired with the embedding returned for it
+#[derive(Debug, Clone)]
+pub struct EmbeddedRecord {
+    pub record: PipelineRecord,
+    pub embedding: Vec<f32>,
+}
+
+/// Summary of a pipeline run that is handed back to the plugin
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PipelineReport {
+    pub records_embedded: usize,
+    /// Records stored with the embedding of an earlier record with the same text instead of being sent
+    pub duplicates_reused: usize,
+    /// Records left out because their embedding had the wrong number of dimensions
+    pub records_rejected: usize,
+    pub batches: usize,
+    pub truncated: Vec<TruncationEvent>,
+}
+
+impl PipelineReport {
+    /// Adds the counts of another run to this one's, e.g. of the run embedding a language with its own model
+    pub fn merge(&mut self, other: PipelineReport) {
+        self.records_embedded += other.records_embedded;
+        self.duplicates_reused += other.duplicates_reused;
+        self.records_rejected += other.records_rejected;
+        self.batches += other.batches;
+        self.truncated.extend(other.truncated);
+    }
+}
+
+/// Middleware that can observe or rewrite records between pipeline stages.
+///
+/// Every method has a pass-through default so a hook only implements the
+/// stages it cares about. Returning an error aborts the run.
+pub trait PipelineHook {
+    /// Runs once over all collected records before batching, e.g. to dedup or redact text
+    fn preprocess(&mut self, records: Vec<PipelineRecord>) -> Result<Vec<PipelineRecord>, SemanticSearchError> {
+        Ok(records)
+    }
+
+    /// Runs before each batch is sent to the API, e.g. to enforce a budget
+    fn before_embed(&mut self, _batch: &[PipelineRecord]) -> Result<(), SemanticSearchError> {
+        Ok(())
+    }
+
+    /// Runs on each validated batch before it is persisted
+    fn after_embed(&mut self, embedded: Vec<EmbeddedRecord>) -> Result<Vec<EmbeddedRecord>, SemanticSearchError> {
+        Ok(embedded)
+    }
+
+    /// Adds anything the hook recorded during the run to the report
+    fn report(&self, _report: &mut PipelineReport) {}
+}
+
+/// Records whose text was already sent in the run, by that text, stored with its embedding once it arrives
+pub type Duplicates = HashMap<String, Vec<PipelineRecord>>;
+
+/// collect → preprocess → batch → dedup → embed → validate → persist
+pub struct EmbeddingPipeline<'a, F: StoreFiles = FileProcessor> {
+    client: &'a Client,
+    file_processor: &'a F,
+    output_path: &'a str,
+    num_batches: u32,
+    max_tokens_per_request: usize,
+    text: EmbedText,
+    settings_hash: &'a str,
+    hooks: Vec<Box<dyn PipelineHook>>,
+    /// Size of every embedding in the store, fixed by the first accepted record
+    dimensions: Option<usize>,
+    quantization: Quantization,
+    /// Formats the rows of every batch in the run
+    writer: RowWriter,
+    granularity: Granularity,
+    /// Dimensions embeddings are truncated to before being stored, 0 keeps them whole
+    truncate_dimensions: usize,
+    /// Length of the embeddings returned by the API, once they have been truncated
+    truncated_from: Option<usize>,
+    wrote_metadata: bool,
+    duplicates: Duplicates,
+    /// Time in ms since the epoch, recorded as when records were embedded
+    clock: fn() -> f64,
+}
+
+impl<'a, F: StoreFiles> EmbeddingPipeline<'a, F> {
+    pub fn new(client: &'a Client, file_processor: &'a F, output_path: &'a str, num_batches: u32, max_tokens_per_request: usize, text: EmbedText, settings_hash: &'a str) -> Self {
+        Self { client, file_processor, output_path, num_batches, max_tokens_per_request, text, settings_hash, hooks: Vec::new(), dimensions: None, quantization: Quantization::None, writer: RowWriter::new(Quantization::None), granularity: Granularity::default(), truncate_dimensions: 0, truncated_from: None, wrote_metadata: false, duplicates: Duplicates::new(), clock: js_sys::Date::now }
+    }
+
+    /// Reads the time from `clock` instead of JavaScript's `Date`, which only exists in wasm
+    #[cfg(feature = "headless")]
+    pub fn clock(&mut self, clock: fn() -> f64) {
+        self.clock = clock;
+    }
+
+    pub fn add_hook(&mut self, hook: Box<dyn PipelineHook>) {
+        self.hooks.push(hook);
+    }
+
+    /// Rejects embeddings that do not match records already in the store, e.g. when re-embedding part of it
+    pub fn expect_dimensions(&mut self, dimensions: usize) {
+        self.dimensions = Some(dimensions);
+    }
+
+    /// Writes the store with int8 vectors instead of full precision floats
+    pub fn quantize(&mut self, quantization: Quantization) {
+        self.quantization = quantization;
+        self.writer = RowWriter::new(quantization);
+    }
+
+    /// Records the granularity the records were extracted at in the store's metadata. `run` reads
+    /// it from input.csv; records that did not come from there need it set.
+    pub fn granularity(&mut self, granularity: Granularity) {
+        self.granularity = granularity;
+    }
+
+    /// Stores only the first `dimensions` of each embedding, renormalized, for models trained
+    /// with Matryoshka Representation Learning. 0 stores embeddings whole.
+    pub fn truncate_dimensions(&mut self, dimensions: usize) {
+        if dimensions > 0 && !matryoshka::supports_truncation(self.client.model()) {
+            warn!("{} is not known to support truncated embeddings, search quality may suffer at {} dimensions", self.client.model(), dimensions);
+        }
+        self.truncate_dimensions = dimensions;
+    }
+
+    pub async fn run(&mut self, input: &str, recorder: &mut RunRecorder) -> Result<PipelineReport, SemanticSearchError> {
+        self.granularity = input::granularity(input)?;
+        let records = collect(input, &self.text)?;
+        self.run_records(records, recorder).await
+    }
+
+    /// Runs the pipeline over records that did not come from input.csv
+    pub async fn run_records(&mut self, records: Vec<PipelineRecord>, recorder: &mut RunRecorder) -> Result<PipelineReport, SemanticSearchError> {
+        debug!("Found {} records.", records.len());
+        recorder.stage("collect", records.len());
+        let records = self.preprocess(records)?;
+        recorder.stage("preprocess", records.len());
+        let (batches, duplicates) = dedup(batch(records, self.num_batches, self.max_tokens_per_request));
+        let reused = duplicates.values().map(Vec::len).sum::<usize>();
+        if reused > 0 {
+            debug!("{} records share their text with another and reuse its embedding", reused);
+        }
+        self.duplicates = duplicates;
+        let mut report = PipelineReport { batches: batches.len(), ..Default::default() };
+        let mut first_rejection = None;
+
+        for (i, records) in batches.iter().enumerate() {
+            debug!("Processing batch {} of {}: {} records", i + 1, batches.len(), records.len());
+            for hook in self.hooks.iter_mut() {
+                hook.before_embed(records)?;
+            }
+            let response = self.embed(records).await?;
+            debug!("Sucessfully obtained {} embeddings", response.data.len());
+            if let Some(e) = self.accept(records, response, (i, batches.len()), &mut report, recorder).await? {
+                first_rejection.get_or_insert(e);
+            }
+        }
+        self.finish(report, first_rejection, recorder).await
+    }
+
+    /// Preprocesses records and splits them into requests without embedding anything,
+    /// for requests sent some other way such as the batch API
+    pub fn prepare(&mut self, records: Vec<PipelineRecord>) -> Result<Vec<Vec<PipelineRecord>>, SemanticSearchError> {
+        let records = self.preprocess(records)?;
+        Ok(batch(records, self.num_batches, self.max_tokens_per_request))
+    }
+
+    /// Validates and writes responses obtained outside the pipeline, each paired with the
+    /// records of the request it answers, as if `run_records` had embedded them
+    pub async fn run_responses(&mut self, responses: Vec<(Vec<PipelineRecord>, EmbeddingResponse)>, recorder: &mut RunRecorder) -> Result<PipelineReport, SemanticSearchError> {
+        let total = responses.len();
+        let mut report = PipelineReport { batches: total, ..Default::default() };
+        let mut first_rejection = None;
+        for (i, (records, response)) in responses.into_iter().enumerate() {
+            if let Some(e) = self.accept(&records, response, (i, total), &mut report, recorder).await? {
+                first_rejection.get_or_insert(e);
+            }
+        }
+        self.finish(report, first_rejection, recorder).await
+    }
+
+    /// Validates the response to batch `i` of `total` and writes the accepted embeddings,
+    /// returning the first dimension mismatch it rejected a record for
+    async fn accept(&mut self, records: &[PipelineRecord], response: EmbeddingResponse, (i, total): (usize, usize), report: &mut PipelineReport, recorder: &mut RunRecorder) -> Result<Option<SemanticSearchError>, SemanticSearchError> {
+        let mut embedded = validate(records, response)?;
+        let dimensions = self.truncate_dimensions;
+        for embedded_record in embedded.iter_mut().filter(|embedded_record| dimensions > 0 && embedded_record.embedding.len() > dimensions) {
+            self.truncated_from.get_or_insert(embedded_record.embedding.len());
+            matryoshka::truncate(&mut embedded_record.embedding, dimensions);
+        }
+        for hook in self.hooks.iter_mut() {
+            embedded = hook.after_embed(embedded)?;
+        }
+        let (embedded, rejected) = check_dimensions(embedded, &mut self.dimensions, self.client.api_base());
+        info!("Batch {}/{}: {} records embedded, {} rejected", i + 1, total, embedded.len(), rejected.len());
+        let mut first_rejection = None;
+        for (record, e) in rejected {
+            for duplicate in self.duplicates.remove(&record.text).unwrap_or_default() {
+                recorder.skip(&duplicate.name, e.to_string());
+                report.records_rejected += 1;
+            }
+            recorder.skip(&record.name, e.to_string());
+            report.records_rejected += 1;
+            first_rejection.get_or_insert(e);
+        }
+        let reused = self.persist(&embedded).await?;
+        report.records_embedded += embedded.len() + reused;
+        report.duplicates_reused += reused;
+        Ok(first_rejection)
+    }
+
+    async fn finish(&mut self, mut report: PipelineReport, first_rejection: Option<SemanticSearchError>, recorder: &mut RunRecorder) -> Result<PipelineReport, SemanticSearchError> {
+        self.file_processor.flush().await?;
+        info!("Embedded {} records in {} batches, {} rejected", report.records_embedded, report.batches, report.records_rejected);
+        recorder.stage("embed", report.records_embedded);
+        if report.records_embedded == 0 {
+            if let Some(e) = first_rejection {
+                return Err(e);
+            }
+        }
+
+        for hook in self.hooks.iter() {
+            hook.report(&mut report);
+        }
+        Ok(report)
+    }
+
+    fn preprocess(&mut self, mut records: Vec<PipelineRecord>) -> Result<Vec<PipelineRecord>, SemanticSearchError> {
+        for hook in self.hooks.iter_mut() {
+            records = hook.preprocess(records)?;
+        }
+        Ok(records)
+    }
+
+    async fn embed(&self, records: &[PipelineRecord]) -> Result<EmbeddingResponse, SemanticSearchError> {
+        let input: Vec<String> = records.iter().map(|record| record.text.clone()).collect();
+        self.client.get_embedding(input.into(), InputType::Document).await
+    }
+
+    /// Appends the embedded records to the store, each followed by the records sharing its
+    /// text, which refer to its row. Returns how many of those were written.
+    async fn persist(&mut self, embedded: &[EmbeddedRecord]) -> Result<usize, SemanticSearchError> {
+        let mut data = String::new();
+        let now = (self.clock)();
+        if !self.wrote_metadata {
+            if let Some(first) = embedded.first() {
+                let metadata = StoreMetadata::new(self.client.model(), first.embedding.len(), now).with_quantization(self.quantization).with_granularity(self.granularity).with_truncated_from(self.truncated_from);
+                data.push_str(&metadata.header()?);
+                self.wrote_metadata = true;
+            }
+        }
+        // The suggestion UI fuzzy-matches the header column against the note's sections,
+        // so the embedded text is stored there rather than the bare header.
+        let mut provenance = Provenance {
+            model: self.client.model().to_string(),
+            provider: self.client.api_base().to_string(),
+            embedded_at: now,
+            settings_hash: self.settings_hash.to_string(),
+            lines: None,
+            note_times: None,
+            language: None,
+            path: None,
+        };
+        let mut reused = 0;
+        for embedded_record in embedded {
+            provenance.lines = embedded_record.record.lines;
+            provenance.note_times = embedded_record.record.times;
+            provenance.path = embedded_record.record.path.clone();
+            // duplicates have the same text, so the same language
+            provenance.language = language::detect(&embedded_record.record.text).map(str::to_string);
+            let row = self.writer.write(&embedded_record.record.name, &embedded_record.record.body, &embedded_record.embedding, Some(&provenance))?;
+            for duplicate in self.duplicates.remove(&embedded_record.record.text).unwrap_or_default() {
+                provenance.lines = duplicate.lines;
+                provenance.note_times = duplicate.times;
+                provenance.path = duplicate.path.clone();
+                self.writer.write_reference(&duplicate.name, &duplicate.body, row, Some(&provenance))?;
+                reused += 1;
+            }
+        }
+        data.push_str(&self.writer.take()?);
+        self.file_processor.queue_append(self.output_path, &data).await?;
+        Ok(reused)
+    }
+}
+
+impl PipelineRecord {
+    pub fn from_input(record: InputRecord, text: &EmbedText) -> Self {
+        PipelineRecord {
+            text: record.text_to_embed(text),
+            lines: record.lines(),
+            times: record.times(),
+            path: record.path,
+            name: record.name,
+            header: record.header,
+            body: record.body,
+        }
+    }
+}
+
+/// Parses input.csv into pipeline records, choosing the text to embed for each
+pub fn collect(input: &str, text: &EmbedText) -> Result<Vec<PipelineRecord>, SemanticSearchError> {
+    Ok(read_input(input)?.into_iter().map(|record| PipelineRecord::from_input(record, text)).collect())
+}
+
+/// Splits records into batches so no request has more than `max_tokens_per_request`
+/// cl100k tokens (0 for no cap) or `MAX_INPUTS_PER_REQUEST` inputs. A non-zero
+/// `num_batches` first divides the records into that many evenly sized groups, which
+/// are never merged; more groups than records gives one record per group.
+///
+/// A record that is larger than the cap on its own is split into several records
+/// with the same name and header, each embedding part of its text.
+pub fn batch(records: Vec<PipelineRecord>, num_batches: u32, max_tokens_per_request: usize) -> Vec<Vec<PipelineRecord>> {
+    if records.is_empty() {
+        return Vec::new();
+    }
+    let groups = (num_batches as usize).clamp(1, records.len());
+    let group_size = records.len().div_ceil(groups);
+    let max_tokens = if max_tokens_per_request == 0 { usize::MAX } else { max_tokens_per_request };
+    let bpe = crate::pricing::bpe();
+
+    let mut batches = Vec::new();
+    for chunk in records.chunks(group_size) {
+        let mut current: Vec<PipelineRecord> = Vec::new();
+        let mut current_tokens = 0;
+        for record in chunk {
+            let tokens = bpe.encode_with_special_tokens(&record.text).len();
+            let parts = if tokens > max_tokens {
+                debug!("Splitting {} > {} ({} tokens) to fit in a request", record.name, record.header, tokens);
+                split_text(bpe, &record.text, max_tokens).into_iter()
+                    .map(|text| (bpe.encode_with_special_tokens(&text).len(), PipelineRecord { text, ..record.clone() }))
+                    .collect()
+            } else {
+                vec![(tokens, record.clone())]
+            };
+            for (tokens, part) in parts {
+                if !current.is_empty() && (current_tokens + tokens > max_tokens || current.len() >= MAX_INPUTS_PER_REQUEST) {
+                    batches.push(std::mem::take(&mut current));
+                    current_tokens = 0;
+                }
+                current_tokens += tokens;
+                current.push(part);
+            }
+        }
+        if !current.is_empty() {
+            batches.push(current);
+        }
+    }
+    batches
+}
+
+/// Takes the records whose text is already in an earlier batch, or earlier in the same batch,
+/// out of `batches` so each distinct text is embedded once, and drops batches left empty.
+/// Templates and boilerplate sections often repeat across notes.
+pub fn dedup(batches: Vec<Vec<PipelineRecord>>) -> (Vec<Vec<PipelineRecord>>, Duplicates) {
+    let mut seen = HashSet::new();
+    let mut duplicates = Duplicates::new();
+    let batches = batches.into_iter()
+        .map(|batch| batch.into_iter().filter_map(|record| {
+            if seen.insert(record.text.clone()) {
+                return Some(record);
+            }
+            duplicates.entry(record.text.clone()).or_default().push(record);
+            None
+        }).collect::<Vec<_>>())
+        .filter(|batch| !batch.is_empty())
+        .collect();
+    (batches, duplicates)
+}
+
+/// Splits off records whose embedding does not have the store's dimensions, so a
+/// misbehaving provider cannot write rows that corrupt the store. `dimensions` is
+/// fixed by the first accepted record when the store is still empty.
+pub fn check_dimensions(embedded: Vec<EmbeddedRecord>, dimensions: &mut Option<usize>, provider: &str) -> (Vec<EmbeddedRecord>, Vec<(PipelineRecord, SemanticSearchError)>) {
+    let mut accepted = Vec::new();
+    let mut rejected = Vec::new();
+    for embedded_record in embedded {
+        let actual = embedded_record.embedding.len();
+        let error = if actual > MAX_EMBEDDING_DIMENSIONS {
+            Some(format!("{} returned {} dimensions for '{}' ({}), more than the maximum of {}",
+                provider, actual, embedded_record.record.name, embedded_record.record.header, MAX_EMBEDDING_DIMENSIONS))
+        } else {
+            match *dimensions {
+                Some(expected) if expected != actual => Some(format!("{} returned {} dimensions for '{}' ({}) but the store has {}",
+                    provider, actual, embedded_record.record.name, embedded_record.record.header, expected)),
+                _ => None,
+            }
+        };
+        match error {
+            Some(error) => rejected.push((embedded_record.record, SemanticSearchError::DimensionMismatch(error))),
+            None => {
+                dimensions.get_or_insert(actual);
+                accepted.push(embedded_record);
+            }
+        }
+    }
+    (accepted, rejected)
+}
+
+/// Pairs each record in a batch with its embedding, failing if the response does not line up
+pub fn validate(records: &[PipelineRecord], response: EmbeddingResponse) -> Result<Vec<EmbeddedRecord>, SemanticSearchError> {
+    if response.data.len() != records.len() {
+        return Err(SemanticSearchError::GetEmbeddingsError(format!("Expected {} embeddings but received {}", records.len(), response.data.len())));
+    }
+    let mut data = response.data;
+    data.sort_by_key(|embedding| embedding.index);
+    records.iter().zip(data).map(|(record, embedding)| {
+        if embedding.embedding.is_empty() {
+            return Err(SemanticSearchError::GetEmbeddingsError(format!("Cannot find matching embedding for filename: {}, header: {}", record.name, record.header)));
+        }
+        Ok(EmbeddedRecord { record: record.clone(), embedding: embedding.embedding })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::{Embedding, EmbeddingUsage};
+    use crate::input::EmbedContent;
+
+    fn records(n: usize) -> Vec<PipelineRecord> {
+        (0..n).map(|i| PipelineRecord { name: format!("note{}", i), header: "Header".to_string(), body: format!("Header body {}", i), text: format!("body {}", i), lines: None, times: None, path: None }).collect()
+    }
+
+    fn response(n: usize) -> EmbeddingResponse {
+        EmbeddingResponse {
+            object: "list".to_string(),
+            model: "text-embedding-ada-002".to_string(),
+            data: (0..n).map(|i| Embedding { index: i as u32, object: "embedding".to_string(), embedding: vec![i as f32, 1.0] }).collect(),
+            usage: EmbeddingUsage { prompt_tokens: 0, total_tokens: 0 },
+        }
+    }
+
+    #[test]
+    fn collect_rows() {
+        let res = collect("# schema_version: 2\nname,header,body\nnote.md,Header,Header some body\nother.md,Other,\"Other quoted, body\"\n", &EmbedText { content: EmbedContent::Body, template: String::new() }).unwrap();
+
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0], PipelineRecord { name: "note.md".to_string(), header: "Header".to_string(), body: "Header some body".to_string(), text: "some body".to_string(), lines: None, times: None, path: None });
+        assert_eq!(res[1].text, "quoted, body");
+    }
+
+    #[test]
+    fn batch_uneven() {
+        let res = batch(records(5), 4, 0);
+
+        assert_eq!(res.iter().map(|b| b.len()).collect::<Vec<_>>(), vec![2, 2, 1]);
+    }
+
+    #[test]
+    fn batch_automatically() {
+        let res = batch(records(3), 0, 0);
+
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].len(), 3);
+        let res = batch(records(MAX_INPUTS_PER_REQUEST + 1), 0, 0);
+        assert_eq!(res.iter().map(|b| b.len()).collect::<Vec<_>>(), vec![MAX_INPUTS_PER_REQUEST, 1]);
+    }
+
+    #[test]
+    fn batch_more_batches_than_records() {
+        let res = batch(records(3), u32::MAX, 0);
+
+        assert_eq!(res.iter().map(|b| b.len()).collect::<Vec<_>>(), vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn batch_by_tokens() {
+        let tokens = crate::pricing::count_tokens("body 0");
+        let res = batch(records(5), 1, 2 * tokens);
+
+        assert_eq!(res.iter().map(|b| b.len()).collect::<Vec<_>>(), vec![2, 2, 1]);
+    }
+
+    #[test]
+    fn batch_splits_oversized_record() {
+        let mut long = records(1);
+        long[0].text = "word ".repeat(30);
+
+        let res = batch(long, 1, 10);
+
+        assert!(res.len() >= 3);
+        assert!(res.iter().all(|b| b.len() == 1 && b[0].name == "note0" && crate::pricing::count_tokens(&b[0].text) <= 10));
+        assert_eq!(res.iter().map(|b| b[0].text.as_str()).collect::<String>(), "word ".repeat(30));
+    }
+
+    #[test]
+    fn dedup_identical_texts() {
+        let mut all = records(4);
+        all[2].text = all[0].text.clone();
+        all[3].text = all[0].text.clone();
+        let batches = vec![all[..2].to_vec(), all[2..].to_vec()];
+
+        let (batches, duplicates) = dedup(batches);
+
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].iter().map(|record| record.name.as_str()).collect::<Vec<_>>(), vec!["note0", "note1"]);
+        assert_eq!(duplicates[&all[0].text].iter().map(|record| record.name.as_str()).collect::<Vec<_>>(), vec!["note2", "note3"]);
+    }
+
+    #[test]
+    fn validate_count_mismatch() {
+        let res = validate(&records(3), response(2));
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn validate_pairs_by_index() {
+        let mut resp = response(2);
+        resp.data.reverse();
+
+        let res = validate(&records(2), resp).unwrap();
+
+        assert_eq!(res[0].record.name, "note0");
+        assert_eq!(res[0].embedding, vec![0.0, 1.0]);
+        assert_eq!(res[1].embedding, vec![1.0, 1.0]);
+    }
+
+    #[test]
+    fn check_dimensions_rejects_mismatched_records() {
+        let mut embedded = validate(&records(3), response(3)).unwrap();
+        embedded[1].embedding.push(0.5);
+        embedded[2].embedding = vec![0.0; MAX_EMBEDDING_DIMENSIONS + 1];
+        let mut dimensions = None;
+
+        let (accepted, rejected) = check_dimensions(embedded, &mut dimensions, "https://proxy.example/v1");
+
+        assert_eq!(dimensions, Some(2));
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(rejected.len(), 2);
+        assert_eq!(rejected[0].0.name, "note1");
+        assert_eq!(rejected[0].1.to_string(), "Unexpected embedding size: https://proxy.example/v1 returned 3 dimensions for 'note1' (Header) but the store has 2");
+        assert_eq!(rejected[1].1.code(), crate::error::ErrorCode::DimensionMismatch);
+    }
+}
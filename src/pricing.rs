@@ -0,0 +1,229 @@
+use std::collections::HashSet;
+
+use log::debug;
+use serde::Serialize;
+use lazy_static::lazy_static;
+use tiktoken_rs::{CoreBPE, cl100k_base};
+
+use crate::pipeline::PipelineRecord;
+
+pub const DEFAULT_MODEL: &str = "text-embedding-ada-002";
+
+pub struct ModelPricing {
+    pub model: &'static str,
+    /// USD per one million input tokens
+    pub dollars_per_million_tokens: f32,
+}
+
+/// Published embedding prices, keyed by model name
+pub const PRICING: &[ModelPricing] = &[
+    ModelPricing { model: "text-embedding-ada-002", dollars_per_million_tokens: 0.10 },
+    ModelPricing { model: "text-embedding-3-small", dollars_per_million_tokens: 0.02 },
+    ModelPricing { model: "text-embedding-3-large", dollars_per_million_tokens: 0.13 },
+    ModelPricing { model: "voyage-3", dollars_per_million_tokens: 0.06 },
+    ModelPricing { model: "voyage-3-lite", dollars_per_million_tokens: 0.02 },
+];
+
+/// Looks up the pricing for a model, falling back to ada-002 for unknown models
+pub fn pricing_for(model: &str) -> &'static ModelPricing {
+    match PRICING.iter().find(|pricing| pricing.model == model) {
+        Some(pricing) => pricing,
+        None => {
+            debug!("No pricing known for model {}, estimating with {}", model, DEFAULT_MODEL);
+            &PRICING[0]
+        }
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct CostEstimate {
+    pub model: String,
+    pub tokens: usize,
+    pub requests: usize,
+    pub dollars: f32,
+}
+
+impl CostEstimate {
+    pub fn from_tokens(model: &str, tokens: usize, requests: usize) -> Self {
+        Self::at_price(model, pricing_for(model).dollars_per_million_tokens, tokens, requests)
+    }
+
+    /// The estimate at a price set by the provider rather than looked up by model
+    pub fn at_price(model: &str, dollars_per_million_tokens: f32, tokens: usize, requests: usize) -> Self {
+        let dollars = tokens as f32 * dollars_per_million_tokens / 1_000_000.0;
+        Self { model: model.to_string(), tokens, requests, dollars }
+    }
+
+    /// The estimate at `factor` times the regular price, e.g. for the batch API
+    pub fn discounted(self, factor: f32) -> Self {
+        Self { dollars: self.dollars * factor, ..self }
+    }
+}
+
+lazy_static! {
+    static ref BPE: CoreBPE = cl100k_base().unwrap();
+}
+
+/// The cl100k_base tokenizer, built on first use. Building it takes long enough in wasm to be
+/// noticeable while typing, so every caller shares the one instance.
+pub fn bpe() -> &'static CoreBPE {
+    &BPE
+}
+
+pub fn count_tokens(text: &str) -> usize {
+    bpe().encode_with_special_tokens(text).len()
+}
+
+pub fn count_tokens_in(texts: &[String]) -> usize {
+    texts.iter().map(|text| count_tokens(text)).sum()
+}
+
+/// Estimates the cost of embedding each record as its own input
+pub fn estimate_records(model: &str, dollars_per_million_tokens: f32, records: &[String], requests: usize) -> CostEstimate {
+    CostEstimate::at_price(model, dollars_per_million_tokens, count_tokens_in(records), requests)
+}
+
+/// What embedding one note would cost
+#[derive(Debug, Serialize, PartialEq)]
+pub struct FilePlan {
+    pub name: String,
+    pub chunks: usize,
+    pub tokens: usize,
+    pub dollars: f32,
+}
+
+/// Dry run of an embedding build, with the notes that cost the most first
+#[derive(Debug, Serialize, PartialEq)]
+pub struct EmbeddingPlan {
+    pub estimate: CostEstimate,
+    pub files: Vec<FilePlan>,
+}
+
+impl EmbeddingPlan {
+    pub fn discounted(self, factor: f32) -> Self {
+        let files = self.files.into_iter().map(|file| FilePlan { dollars: file.dollars * factor, ..file }).collect();
+        Self { estimate: self.estimate.discounted(factor), files }
+    }
+}
+
+/// Breaks down the cost of embedding `records` per note
+pub fn plan_records(model: &str, dollars_per_million_tokens: f32, records: &[PipelineRecord], requests: usize) -> EmbeddingPlan {
+    let mut files: Vec<FilePlan> = Vec::new();
+    for record in records {
+        let tokens = count_tokens(&record.text);
+        match files.iter_mut().find(|file| file.name == record.name) {
+            Some(file) => {
+                file.chunks += 1;
+                file.tokens += tokens;
+            }
+            None => files.push(FilePlan { name: record.name.clone(), chunks: 1, tokens, dollars: 0.0 }),
+        }
+    }
+    for file in files.iter_mut() {
+        file.dollars = CostEstimate::at_price(model, dollars_per_million_tokens, file.tokens, 0).dollars;
+    }
+    files.sort_by(|a, b| b.tokens.cmp(&a.tokens).then_with(|| a.name.cmp(&b.name)));
+    let tokens = files.iter().map(|file| file.tokens).sum();
+    EmbeddingPlan { estimate: CostEstimate::at_price(model, dollars_per_million_tokens, tokens, requests), files }
+}
+
+/// What embedding the notes directly in one folder would cost. `folder` is empty for the vault root.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct FolderPlan {
+    pub folder: String,
+    pub notes: usize,
+    pub chunks: usize,
+    pub tokens: usize,
+    pub dollars: f32,
+}
+
+/// Estimate of embedding the vault as it is now, with the folders that cost the most first
+#[derive(Debug, Serialize, PartialEq)]
+pub struct VaultEstimate {
+    pub estimate: CostEstimate,
+    pub folders: Vec<FolderPlan>,
+}
+
+impl VaultEstimate {
+    pub fn discounted(self, factor: f32) -> Self {
+        let folders = self.folders.into_iter().map(|folder| FolderPlan { dollars: folder.dollars * factor, ..folder }).collect();
+        Self { estimate: self.estimate.discounted(factor), folders }
+    }
+}
+
+/// Breaks down the cost of embedding `records` per folder, given the vault path of the file
+/// each record was taken from
+pub fn plan_folders(model: &str, dollars_per_million_tokens: f32, records: &[(String, PipelineRecord)], requests: usize) -> VaultEstimate {
+    let mut folders: Vec<FolderPlan> = Vec::new();
+    let mut notes = HashSet::new();
+    for (path, record) in records {
+        let folder = path.rsplit_once('/').map_or("", |(folder, _)| folder);
+        let tokens = count_tokens(&record.text);
+        let new_note = notes.insert(path.as_str());
+        match folders.iter_mut().find(|plan| plan.folder == folder) {
+            Some(plan) => {
+                plan.notes += usize::from(new_note);
+                plan.chunks += 1;
+                plan.tokens += tokens;
+            }
+            None => folders.push(FolderPlan { folder: folder.to_string(), notes: 1, chunks: 1, tokens, dollars: 0.0 }),
+        }
+    }
+    for plan in folders.iter_mut() {
+        plan.dollars = CostEstimate::at_price(model, dollars_per_million_tokens, plan.tokens, 0).dollars;
+    }
+    folders.sort_by(|a, b| b.tokens.cmp(&a.tokens).then_with(|| a.folder.cmp(&b.folder)));
+    let tokens = folders.iter().map(|plan| plan.tokens).sum();
+    VaultEstimate { estimate: CostEstimate::at_price(model, dollars_per_million_tokens, tokens, requests), folders }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_model_uses_default() {
+        assert_eq!(pricing_for("some-local-model").model, DEFAULT_MODEL);
+    }
+
+    #[test]
+    fn estimate_per_model() {
+        let small = CostEstimate::from_tokens("text-embedding-3-small", 1_000_000, 1);
+        let large = CostEstimate::from_tokens("text-embedding-3-large", 1_000_000, 1);
+
+        assert!((small.dollars - 0.02).abs() < 1e-6);
+        assert!((large.dollars - 0.13).abs() < 1e-6);
+    }
+
+    #[test]
+    fn estimate_counts_each_record() {
+        let records = vec!["hello world".to_string(), "hello world".to_string()];
+
+        let res = estimate_records(DEFAULT_MODEL, 0.10, &records, 1);
+
+        assert_eq!(res.tokens, 2 * count_tokens("hello world"));
+        assert_eq!(res.requests, 1);
+    }
+
+    #[test]
+    fn plan_groups_by_file() {
+        let record = |name: &str, text: &str| PipelineRecord { name: name.to_string(), header: String::new(), body: String::new(), text: text.to_string(), lines: None, times: None, path: None };
+        let records = vec![record("small.md", "hello"), record("big.md", "hello world"), record("big.md", "hello world")];
+
+        let plan = plan_records(DEFAULT_MODEL, 0.10, &records, 1);
+
+        assert_eq!(plan.files.iter().map(|file| (file.name.as_str(), file.chunks)).collect::<Vec<_>>(), vec![("big.md", 2), ("small.md", 1)]);
+        assert_eq!(plan.estimate.tokens, plan.files.iter().map(|file| file.tokens).sum::<usize>());
+    }
+
+    #[test]
+    fn plan_groups_by_folder() {
+        let record = |path: &str, text: &str| (path.to_string(), PipelineRecord { name: path.rsplit('/').next().unwrap().to_string(), header: String::new(), body: String::new(), text: text.to_string(), lines: None, times: None, path: None });
+        let records = vec![record("inbox.md", "hello"), record("Projects/a.md", "hello world"), record("Projects/a.md", "hello world"), record("Projects/b.md", "hello world")];
+
+        let estimate = plan_folders(DEFAULT_MODEL, 0.10, &records, 1);
+
+        assert_eq!(estimate.folders.iter().map(|plan| (plan.folder.as_str(), plan.notes, plan.chunks)).collect::<Vec<_>>(), vec![("Projects", 2, 3), ("", 1, 1)]);
+        assert_eq!(estimate.estimate.tokens, 3 * count_tokens("hello world") + count_tokens("hello"));
+    }
+}
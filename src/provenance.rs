@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+use crate::embedding_file::EmbeddingFile;
+use crate::input::{LineRange, NoteTimes};
+
+/// Where a single stored record came from, so indexes built over several runs can be audited
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub model: String,
+    /// API base URL the embedding was requested from, or `import:<path>` for imported records
+    pub provider: String,
+    pub embedded_at: f64,
+    /// Fingerprint of the settings that shape the embedded text, see `Settings::embedding_settings_hash`
+    pub settings_hash: String,
+    /// Lines of the note the embedded text was taken from, if it came from a markdown note
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lines: Option<LineRange>,
+    /// Times of the note the record was taken from when it was embedded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note_times: Option<NoteTimes>,
+    /// Language the embedded text was detected to be in, see `language::detect`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Vault path of the file the record was taken from, which tells notes with the same file name apart
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+/// 64-bit FNV-1a hash as hex. Not cryptographic, only used to tell settings apart.
+pub fn fingerprint(text: &str) -> String {
+    format!("{:016x}", fnv1a(text))
+}
+
+pub fn fnv1a(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+/// Selects stored records by provenance. Every field is optional and all set fields must match.
+/// Records without provenance (written before schema version 3) only match `exclude_model`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ProvenanceFilter {
+    pub model: Option<String>,
+    /// Records not embedded with this model, e.g. to find everything that needs re-embedding
+    pub exclude_model: Option<String>,
+    pub provider: Option<String>,
+    pub settings_hash: Option<String>,
+    pub embedded_before: Option<f64>,
+    pub embedded_after: Option<f64>,
+    /// Records detected to be in this language, as an ISO 639-1 code
+    pub language: Option<String>,
+}
+
+impl ProvenanceFilter {
+    pub fn matches(&self, provenance: Option<&Provenance>) -> bool {
+        let provenance = match provenance {
+            Some(provenance) => provenance,
+            None => return self.model.is_none() && self.provider.is_none() && self.settings_hash.is_none()
+                && self.embedded_before.is_none() && self.embedded_after.is_none() && self.language.is_none(),
+        };
+        self.model.as_ref().is_none_or(|model| &provenance.model == model)
+            && self.exclude_model.as_ref().is_none_or(|model| &provenance.model != model)
+            && self.provider.as_ref().is_none_or(|provider| &provenance.provider == provider)
+            && self.settings_hash.as_ref().is_none_or(|hash| &provenance.settings_hash == hash)
+            && self.embedded_before.is_none_or(|before| provenance.embedded_at < before)
+            && self.embedded_after.is_none_or(|after| provenance.embedded_at > after)
+            && self.language.as_ref().is_none_or(|language| provenance.language.as_ref() == Some(language))
+    }
+}
+
+/// Records sharing a model, provider and settings hash
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ProvenanceGroup {
+    /// `None` for records without provenance
+    pub provenance: Option<Provenance>,
+    pub records: usize,
+    pub oldest: f64,
+    pub newest: f64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ProvenanceRecord {
+    pub name: String,
+    pub header: String,
+    pub provenance: Option<Provenance>,
+}
+
+/// Overview of every group in the store, plus the records matching the filter
+#[derive(Debug, Serialize)]
+pub struct ProvenanceReport {
+    pub groups: Vec<ProvenanceGroup>,
+    pub records: Vec<ProvenanceRecord>,
+}
+
+pub fn query(store: &EmbeddingFile, filter: &ProvenanceFilter) -> ProvenanceReport {
+    let mut groups: Vec<ProvenanceGroup> = Vec::new();
+    let mut records = Vec::new();
+    for ((name, header, _), provenance) in store.rows.iter().zip(store.provenance.iter()) {
+        let embedded_at = provenance.as_ref().map_or(0.0, |provenance| provenance.embedded_at);
+        let same_group = |group: &&mut ProvenanceGroup| match (&group.provenance, provenance) {
+            (Some(a), Some(b)) => a.model == b.model && a.provider == b.provider && a.settings_hash == b.settings_hash,
+            (None, None) => true,
+            _ => false,
+        };
+        match groups.iter_mut().find(same_group) {
+            Some(group) => {
+                group.records += 1;
+                group.oldest = group.oldest.min(embedded_at);
+                group.newest = group.newest.max(embedded_at);
+            }
+            None => groups.push(ProvenanceGroup {
+                // a group describes how its records were embedded, not where any one of them came from
+                provenance: provenance.clone().map(|provenance| Provenance { lines: None, note_times: None, language: None, path: None, ..provenance }),
+                records: 1,
+                oldest: embedded_at,
+                newest: embedded_at,
+            }),
+        }
+        if filter.matches(provenance.as_ref()) {
+            records.push(ProvenanceRecord { name: name.clone(), header: header.clone(), provenance: provenance.clone() });
+        }
+    }
+    ProvenanceReport { groups, records }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provenance(model: &str, embedded_at: f64) -> Option<Provenance> {
+        Some(Provenance { model: model.to_string(), provider: "https://api.openai.com/v1".to_string(), embedded_at, settings_hash: fingerprint("settings"), lines: None, note_times: None, language: None, path: None })
+    }
+
+    fn store() -> EmbeddingFile {
+        EmbeddingFile {
+            metadata: None,
+            rows: vec![
+                ("a.md".to_string(), "A".to_string(), vec![1.0]),
+                ("b.md".to_string(), "B".to_string(), vec![1.0]),
+                ("c.md".to_string(), "C".to_string(), vec![1.0]),
+                ("d.md".to_string(), "D".to_string(), vec![1.0]),
+            ],
+            provenance: vec![provenance("ada", 1.0), provenance("ada", 3.0), provenance("small", 5.0), None],
+        }
+    }
+
+    #[test]
+    fn fingerprint_is_stable() {
+        assert_eq!(fingerprint(""), "cbf29ce484222325");
+        assert_ne!(fingerprint("a"), fingerprint("b"));
+    }
+
+    #[test]
+    fn groups_by_model() {
+        let report = query(&store(), &ProvenanceFilter::default());
+
+        assert_eq!(report.records.len(), 4);
+        assert_eq!(report.groups.len(), 3);
+        assert_eq!((report.groups[0].records, report.groups[0].oldest, report.groups[0].newest), (2, 1.0, 3.0));
+        assert_eq!(report.groups[2].provenance, None);
+    }
+
+    #[test]
+    fn filters_records() {
+        let names = |filter: ProvenanceFilter| query(&store(), &filter).records.into_iter().map(|record| record.name).collect::<Vec<_>>();
+
+        assert_eq!(names(ProvenanceFilter { exclude_model: Some("small".to_string()), ..Default::default() }), vec!["a.md", "b.md", "d.md"]);
+        assert_eq!(names(ProvenanceFilter { model: Some("ada".to_string()), embedded_before: Some(2.0), ..Default::default() }), vec!["a.md"]);
+        assert_eq!(names(ProvenanceFilter { embedded_after: Some(2.0), ..Default::default() }), vec!["b.md", "c.md"]);
+    }
+}
@@ -0,0 +1,389 @@
+//! Embedding providers. A provider decides how an embeddings request is built, authenticated,
+//! parsed and priced. The built-in providers are registered together with any OpenAI-compatible
+//! endpoints described in the `customProviders` setting, and the `embeddingProvider` setting
+//! picks one of them by name.
+
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use serde::{Deserialize, Deserializer, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::SemanticSearchError;
+use crate::embedding::{EmbeddingInput, EmbeddingRequest, EmbeddingResponse};
+use crate::mock::MockEmbeddingProvider;
+use crate::pricing;
+
+/// Name of the provider used when the setting is empty
+pub const DEFAULT_PROVIDER: &str = "openai";
+
+/// Whether a text is embedded to be stored or to search the store with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputType {
+    Document,
+    Query,
+}
+
+pub trait EmbeddingProvider: std::fmt::Debug {
+    /// Name the `embeddingProvider` setting refers to the provider by
+    fn name(&self) -> &str;
+
+    /// Name shown in the settings
+    fn label(&self) -> &str;
+
+    /// API base used when the setting is left empty
+    fn api_base(&self) -> &str;
+
+    /// Models the provider is known to serve, the first being the default
+    fn models(&self) -> Vec<String>;
+
+    fn embeddings_path(&self) -> &str {
+        "/embeddings"
+    }
+
+    /// Headers sent with every embeddings request, including the API key
+    fn headers(&self, api_key: &str) -> Result<HeaderMap, SemanticSearchError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, header_value(AUTHORIZATION.as_str(), &format!("Bearer {}", api_key))?);
+        Ok(headers)
+    }
+
+    /// Request field and value telling documents and queries apart, for providers that embed them differently
+    fn input_type_field(&self, _input_type: InputType) -> Option<(&str, &str)> {
+        None
+    }
+
+    fn request_body(&self, model: &str, input: EmbeddingInput, input_type: InputType) -> Result<serde_json::Value, SemanticSearchError> {
+        let request = EmbeddingRequest { model: model.to_string(), input, user: None };
+        let mut body = serde_json::to_value(request).map_err(|e| SemanticSearchError::ConversionError(Box::new(e)))?;
+        if let (Some((field, value)), Some(object)) = (self.input_type_field(input_type), body.as_object_mut()) {
+            object.insert(field.to_string(), value.into());
+        }
+        Ok(body)
+    }
+
+    fn parse_response(&self, body: &[u8]) -> Result<EmbeddingResponse, SemanticSearchError> {
+        serde_json::from_slice(body).map_err(SemanticSearchError::JSONDeserialize)
+    }
+
+    /// USD per million input tokens for `model`, if known
+    fn dollars_per_million_tokens(&self, model: &str) -> Option<f32> {
+        pricing::PRICING.iter().find(|pricing| pricing.model == model).map(|pricing| pricing.dollars_per_million_tokens)
+    }
+
+    /// Embeddings of `input` computed without a request, for providers that need no network
+    fn embed_offline(&self, _model: &str, _input: &EmbeddingInput) -> Option<Result<EmbeddingResponse, SemanticSearchError>> {
+        None
+    }
+
+    /// Whether the provider embeds without a network or an API key
+    fn is_offline(&self) -> bool {
+        self.embed_offline("", &EmbeddingInput::StringArray(Vec::new())).is_some()
+    }
+}
+
+fn header_value(name: &str, value: &str) -> Result<HeaderValue, SemanticSearchError> {
+    HeaderValue::from_str(value).map_err(|_| SemanticSearchError::InvalidSettings(format!("'{}' is not a valid {} header", value, name)))
+}
+
+/// A provider shipped with the plugin
+#[derive(Debug, Clone)]
+pub struct BuiltinProvider {
+    name: &'static str,
+    label: &'static str,
+    api_base: &'static str,
+    models: &'static [&'static str],
+    /// Field, document value and query value of the input type, if the provider has one
+    input_type: Option<(&'static str, &'static str, &'static str)>,
+}
+
+pub const BUILTIN_PROVIDERS: &[BuiltinProvider] = &[
+    BuiltinProvider {
+        name: DEFAULT_PROVIDER,
+        label: "OpenAI",
+        api_base: crate::API_BASE,
+        models: &[pricing::DEFAULT_MODEL, "text-embedding-3-small", "text-embedding-3-large"],
+        input_type: None,
+    },
+    BuiltinProvider {
+        name: "voyage",
+        label: "Voyage AI",
+        api_base: "https://api.voyageai.com/v1",
+        models: &["voyage-3", "voyage-3-lite"],
+        input_type: Some(("input_type", "document", "query")),
+    },
+    BuiltinProvider {
+        name: "jina",
+        label: "Jina AI",
+        api_base: "https://api.jina.ai/v1",
+        models: &["jina-embeddings-v3"],
+        input_type: Some(("task", "retrieval.passage", "retrieval.query")),
+    },
+];
+
+impl EmbeddingProvider for BuiltinProvider {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn label(&self) -> &str {
+        self.label
+    }
+
+    fn api_base(&self) -> &str {
+        self.api_base
+    }
+
+    fn models(&self) -> Vec<String> {
+        self.models.iter().map(|model| model.to_string()).collect()
+    }
+
+    fn input_type_field(&self, input_type: InputType) -> Option<(&str, &str)> {
+        self.input_type.map(|(field, document, query)| match input_type {
+            InputType::Document => (field, document),
+            InputType::Query => (field, query),
+        })
+    }
+}
+
+/// An OpenAI-compatible endpoint described in the settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CustomProvider {
+    pub name: String,
+    /// Shown in the settings, the name when empty
+    pub label: String,
+    pub api_base: String,
+    /// Path of the embeddings endpoint under the API base
+    pub embeddings_path: String,
+    pub models: Vec<String>,
+    /// Header carrying the API key; `Authorization` sends it as a bearer token
+    pub auth_header: String,
+    /// Sent with every embeddings request, e.g. an API version
+    pub headers: BTreeMap<String, String>,
+    /// Request field telling documents and queries apart, empty if the endpoint has none
+    pub input_type_field: String,
+    pub document_input_type: String,
+    pub query_input_type: String,
+    /// Price used for cost estimates, the published price of the model when unset
+    pub dollars_per_million_tokens: Option<f32>,
+}
+
+impl Default for CustomProvider {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            label: String::new(),
+            api_base: String::new(),
+            embeddings_path: "/embeddings".to_string(),
+            models: Vec::new(),
+            auth_header: AUTHORIZATION.to_string(),
+            headers: BTreeMap::new(),
+            input_type_field: String::new(),
+            document_input_type: String::new(),
+            query_input_type: String::new(),
+            dollars_per_million_tokens: None,
+        }
+    }
+}
+
+impl EmbeddingProvider for CustomProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn label(&self) -> &str {
+        if self.label.is_empty() { &self.name } else { &self.label }
+    }
+
+    fn api_base(&self) -> &str {
+        &self.api_base
+    }
+
+    fn models(&self) -> Vec<String> {
+        self.models.clone()
+    }
+
+    fn embeddings_path(&self) -> &str {
+        &self.embeddings_path
+    }
+
+    fn headers(&self, api_key: &str) -> Result<HeaderMap, SemanticSearchError> {
+        let mut headers = HeaderMap::new();
+        let auth_value = match self.auth_header.eq_ignore_ascii_case(AUTHORIZATION.as_str()) {
+            true => format!("Bearer {}", api_key),
+            false => api_key.to_string(),
+        };
+        for (name, value) in self.headers.iter().chain([(&self.auth_header, &auth_value)]) {
+            let header = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| SemanticSearchError::InvalidSettings(format!("'{}' of provider {} is not a valid header name", name, self.name)))?;
+            headers.insert(header, header_value(name, value)?);
+        }
+        Ok(headers)
+    }
+
+    fn input_type_field(&self, input_type: InputType) -> Option<(&str, &str)> {
+        if self.input_type_field.is_empty() {
+            return None;
+        }
+        Some((&self.input_type_field, match input_type {
+            InputType::Document => &self.document_input_type,
+            InputType::Query => &self.query_input_type,
+        }))
+    }
+
+    fn dollars_per_million_tokens(&self, model: &str) -> Option<f32> {
+        self.dollars_per_million_tokens.or_else(|| pricing::PRICING.iter().find(|pricing| pricing.model == model).map(|pricing| pricing.dollars_per_million_tokens))
+    }
+}
+
+/// Parses the `customProviders` setting, a JSON array of `CustomProvider`s kept as text
+pub fn parse_custom_providers(json: &str) -> Result<Vec<CustomProvider>, SemanticSearchError> {
+    if json.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_str(json).map_err(|e| SemanticSearchError::InvalidSettings(format!("customProviders: {}", e)))
+}
+
+pub fn deserialize_custom_providers<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<CustomProvider>, D::Error> {
+    parse_custom_providers(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+/// The providers that can be picked by name
+#[derive(Debug, Clone)]
+pub struct ProviderRegistry {
+    providers: Vec<Rc<dyn EmbeddingProvider>>,
+}
+
+impl ProviderRegistry {
+    /// The providers shipped with the plugin, ending with the offline mock provider
+    pub fn builtin() -> Self {
+        let mut providers: Vec<Rc<dyn EmbeddingProvider>> = BUILTIN_PROVIDERS.iter().map(|provider| Rc::new(provider.clone()) as Rc<dyn EmbeddingProvider>).collect();
+        providers.push(Rc::new(MockEmbeddingProvider));
+        Self { providers }
+    }
+
+    /// The built-in providers along with `custom` ones
+    pub fn with_custom(custom: &[CustomProvider]) -> Result<Self, SemanticSearchError> {
+        let mut registry = Self::builtin();
+        for provider in custom {
+            registry.register(Rc::new(provider.clone()))?;
+        }
+        Ok(registry)
+    }
+
+    pub fn register(&mut self, provider: Rc<dyn EmbeddingProvider>) -> Result<(), SemanticSearchError> {
+        if provider.name().trim().is_empty() {
+            return Err(SemanticSearchError::InvalidSettings("custom providers need a name".to_string()));
+        }
+        if self.get(provider.name()).is_some() {
+            return Err(SemanticSearchError::InvalidSettings(format!("there is already a provider named {}", provider.name())));
+        }
+        self.providers.push(provider);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<Rc<dyn EmbeddingProvider>> {
+        let name = match name.trim() {
+            "" => DEFAULT_PROVIDER,
+            name => name,
+        };
+        self.providers.iter().find(|provider| provider.name() == name).cloned()
+    }
+
+    pub fn resolve(&self, name: &str) -> Result<Rc<dyn EmbeddingProvider>, SemanticSearchError> {
+        self.get(name).ok_or_else(|| SemanticSearchError::InvalidSettings(format!("unknown embedding provider '{}'", name)))
+    }
+
+    fn list(&self) -> Vec<ProviderInfo> {
+        self.providers.iter().map(|provider| ProviderInfo {
+            name: provider.name().to_string(),
+            label: provider.label().to_string(),
+            api_base: provider.api_base().to_string(),
+            models: provider.models(),
+        }).collect()
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProviderInfo {
+    name: String,
+    label: String,
+    api_base: String,
+    models: Vec<String>,
+}
+
+/// Every provider that can be picked in the settings, as `{ name, label, apiBase, models }`,
+/// given the text of the `customProviders` setting
+#[wasm_bindgen]
+pub fn list_providers(custom_providers: &str) -> Result<JsValue, SemanticSearchError> {
+    let registry = ProviderRegistry::with_custom(&parse_custom_providers(custom_providers)?)?;
+    Ok(serde_wasm_bindgen::to_value(&registry.list())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+
+    fn body(provider: &dyn EmbeddingProvider, input_type: InputType) -> String {
+        let model = provider.models().into_iter().next().unwrap_or_default();
+        provider.request_body(&model, EmbeddingInput::StringArray(vec!["text".to_string()]), input_type).unwrap().to_string()
+    }
+
+    #[test]
+    fn builtin_request_bodies() {
+        let registry = ProviderRegistry::builtin();
+        let provider = |name: &str| registry.get(name).unwrap();
+
+        assert_eq!(body(provider("").as_ref(), InputType::Query), r#"{"input":["text"],"model":"text-embedding-ada-002"}"#);
+        assert_eq!(body(provider("voyage").as_ref(), InputType::Document), r#"{"input":["text"],"input_type":"document","model":"voyage-3"}"#);
+        assert_eq!(body(provider("voyage").as_ref(), InputType::Query), r#"{"input":["text"],"input_type":"query","model":"voyage-3"}"#);
+        assert_eq!(body(provider("jina").as_ref(), InputType::Document), r#"{"input":["text"],"model":"jina-embeddings-v3","task":"retrieval.passage"}"#);
+        assert_eq!(body(provider("jina").as_ref(), InputType::Query), r#"{"input":["text"],"model":"jina-embeddings-v3","task":"retrieval.query"}"#);
+    }
+
+    #[test]
+    fn custom_providers_from_settings() {
+        let settings: Settings = serde_json::from_str(r#"{"customProviders": "[{\"name\": \"azure\", \"apiBase\": \"https://example.openai.azure.com/openai/deployments/embed\", \"embeddingsPath\": \"/embeddings?api-version=2024-02-01\", \"models\": [\"text-embedding-3-small\"], \"authHeader\": \"api-key\", \"headers\": {\"x-team\": \"notes\"}, \"inputTypeField\": \"input_kind\", \"documentInputType\": \"doc\", \"queryInputType\": \"q\", \"dollarsPerMillionTokens\": 0.5}]"}"#).unwrap();
+
+        let registry = ProviderRegistry::with_custom(&settings.custom_providers).unwrap();
+        let azure = registry.resolve("azure").unwrap();
+        let headers = azure.headers("secret").unwrap();
+
+        assert_eq!(azure.embeddings_path(), "/embeddings?api-version=2024-02-01");
+        assert_eq!(headers.get("api-key").unwrap(), "secret");
+        assert_eq!(headers.get("x-team").unwrap(), "notes");
+        assert!(headers.get(AUTHORIZATION).is_none());
+        assert_eq!(body(azure.as_ref(), InputType::Query), r#"{"input":["text"],"input_kind":"q","model":"text-embedding-3-small"}"#);
+        assert_eq!(azure.dollars_per_million_tokens("text-embedding-3-small"), Some(0.5));
+        assert!(registry.resolve("missing").is_err());
+    }
+
+    #[test]
+    fn custom_provider_defaults() {
+        let provider = CustomProvider { name: "local".to_string(), api_base: "http://localhost:8080/v1".to_string(), ..Default::default() };
+
+        assert_eq!(provider.headers("key").unwrap().get(AUTHORIZATION).unwrap(), "Bearer key");
+        assert_eq!(provider.label(), "local");
+        assert_eq!(provider.dollars_per_million_tokens("text-embedding-3-small"), Some(0.02));
+        assert_eq!(provider.dollars_per_million_tokens("nomic-embed-text"), None);
+    }
+
+    #[test]
+    fn rejects_clashing_names() {
+        let mut registry = ProviderRegistry::builtin();
+
+        assert!(registry.register(Rc::new(CustomProvider { name: "voyage".to_string(), ..Default::default() })).is_err());
+        assert!(registry.register(Rc::new(CustomProvider::default())).is_err());
+        assert!(registry.register(Rc::new(CustomProvider { name: "local".to_string(), ..Default::default() })).is_ok());
+    }
+
+    #[test]
+    fn invalid_custom_providers_are_invalid_settings() {
+        assert!(serde_json::from_str::<Settings>(r#"{"customProviders": "[{\"name\": 3}]"}"#).is_err());
+        assert!(serde_json::from_str::<Settings>(r#"{"customProviders": ""}"#).unwrap().custom_providers.is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::SemanticSearchError;
+
+/// How vectors are written to the embedding file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Quantization {
+    /// Full precision floats, as returned by the API
+    #[default]
+    None,
+    /// One signed byte per component plus a per-vector scale
+    Int8,
+}
+
+/// A vector stored as `scale * values`, with its largest component mapped to ±127
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizedVector {
+    pub scale: f32,
+    pub values: Vec<i8>,
+    /// Euclidean norm of `values`, kept so cosine similarity only needs a dot product
+    norm: f32,
+}
+
+impl QuantizedVector {
+    pub fn new(scale: f32, values: Vec<i8>) -> Self {
+        let norm = (values.iter().map(|&value| value as i32 * value as i32).sum::<i32>() as f32).sqrt();
+        Self { scale, values, norm }
+    }
+
+    pub fn quantize(embedding: &[f32]) -> Self {
+        let max = embedding.iter().fold(0.0f32, |max, value| max.max(value.abs()));
+        let scale = if max > 0.0 { max / 127.0 } else { 1.0 };
+        let values = embedding.iter().map(|value| (value / scale).round().clamp(-127.0, 127.0) as i8).collect();
+        Self::new(scale, values)
+    }
+
+    /// Whether the vector has no direction to compare, see `math::is_degenerate`
+    pub fn is_degenerate(&self) -> bool {
+        !self.scale.is_finite() || self.values.iter().all(|&value| value == 0)
+    }
+
+    pub fn dequantize(&self) -> Vec<f32> {
+        self.values.iter().map(|&value| value as f32 * self.scale).collect()
+    }
+
+    /// Cosine similarity computed on the int8 values directly, since the scales cancel out.
+    /// Sums fit in an i32 for up to 2^31 / 127^2 (about 133,000) dimensions.
+    pub fn cosine(&self, other: &QuantizedVector) -> f32 {
+        let dot: i32 = self.values.iter().zip(&other.values).map(|(&a, &b)| a as i32 * b as i32).sum();
+        dot as f32 / (self.norm * other.norm)
+    }
+
+    /// Parses the `scale:v1,v2,...` form written to the embedding file
+    pub fn parse(text: &str) -> Result<Self, SemanticSearchError> {
+        let invalid = || SemanticSearchError::UnsupportedSchema(format!("invalid quantized embedding '{:.40}'", text));
+        let (scale, values) = text.split_once(':').ok_or_else(invalid)?;
+        let scale = scale.trim().parse::<f32>().map_err(|_| invalid())?;
+        let values = values.split(',')
+            .map(|value| value.trim().parse::<i8>())
+            .collect::<Result<Vec<i8>, _>>()
+            .map_err(|_| invalid())?;
+        Ok(Self::new(scale, values))
+    }
+}
+
+impl fmt::Display for QuantizedVector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.scale)?;
+        for (i, value) in self.values.iter().enumerate() {
+            match i {
+                0 => write!(f, "{}", value)?,
+                _ => write!(f, ",{}", value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+        dot / (a.iter().map(|a| a * a).sum::<f32>().sqrt() * b.iter().map(|b| b * b).sum::<f32>().sqrt())
+    }
+
+    #[test]
+    fn round_trip_is_close() {
+        let embedding = vec![0.021, -0.5, 0.25, 0.0, -0.0031];
+
+        let quantized = QuantizedVector::quantize(&embedding);
+
+        assert_eq!(quantized.values[1], -127);
+        for (original, restored) in embedding.iter().zip(quantized.dequantize()) {
+            assert!((original - restored).abs() <= quantized.scale / 2.0);
+        }
+        assert_eq!(QuantizedVector::parse(&quantized.to_string()).unwrap(), quantized);
+    }
+
+    #[test]
+    fn cosine_matches_full_precision() {
+        let a = vec![0.12, -0.4, 0.33, 0.05, -0.21, 0.6];
+        let b = vec![0.1, -0.35, 0.2, 0.15, -0.3, 0.5];
+
+        let res = QuantizedVector::quantize(&a).cosine(&QuantizedVector::quantize(&b));
+
+        assert!((res - cosine(&a, &b)).abs() < 0.01);
+    }
+
+    #[test]
+    fn degenerate_vectors() {
+        assert!(QuantizedVector::quantize(&[0.0, 0.0]).is_degenerate());
+        assert!(QuantizedVector::parse("NaN:1,2").unwrap().is_degenerate());
+        assert!(!QuantizedVector::quantize(&[0.0, 0.3]).is_degenerate());
+    }
+
+    #[test]
+    fn requantizing_is_stable() {
+        let quantized = QuantizedVector::quantize(&[0.3, -0.7, 0.11]);
+
+        assert_eq!(QuantizedVector::quantize(&quantized.dequantize()).values, quantized.values);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(QuantizedVector::parse("0.5,1,2").is_err());
+        assert!(QuantizedVector::parse("0.5:1,300").is_err());
+        assert_eq!(QuantizedVector::parse("0.5:1, -2").unwrap().values, vec![1, -2]);
+    }
+}
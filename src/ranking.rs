@@ -0,0 +1,455 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cosine_similarity;
+use crate::fusion::ScoredCandidate;
+
+/// Whether results are individual chunks or one per note, and how a note's chunk scores are combined
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FileGrouping {
+    #[default]
+    Off,
+    Max,
+    Mean,
+}
+
+/// A step turning vector-ranked candidates into the results shown. A query runs the
+/// stages built by `Settings::ranking_stages`, in order, on the output of the previous one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stage {
+    /// Reorders the top candidates with the reranking endpoint, replacing their scores
+    Rerank,
+    /// Adds tag boosts to scores and decays them by note age, see `ScoreAdjustment`
+    Boost { tag_boosts: Vec<TagBoost>, recency_half_life_days: Option<f64> },
+    /// Collapses the chunks of each note into one result, see `group_by_file`
+    GroupByFile(FileGrouping),
+    /// Picks `count` varied results from the best `pool_size` with MMR, see `mmr`
+    Diversify { lambda: f32, count: usize, pool_size: usize },
+    /// Cuts the results at `max_results`, or earlier at the score elbow, see `truncate`
+    Truncate { max_results: usize, adaptive: bool },
+}
+
+/// A gap counts as the elbow when it is this many times larger than the average gap
+const ELBOW_GAP_FACTOR: f32 = 2.0;
+
+/// Number of results to return from a list of scores sorted in descending order.
+///
+/// Without `adaptive` this is simply `max_results`. With it, the list is cut at
+/// the largest drop in score among the top results when that drop stands out
+/// from the rest, so precise queries return only the few clearly relevant hits.
+pub fn result_count(scores: &[f32], max_results: usize, adaptive: bool) -> usize {
+    let n = scores.len().min(max_results);
+    if !adaptive || n < 3 {
+        return n;
+    }
+    let gaps: Vec<f32> = scores[..n].windows(2).map(|pair| pair[0] - pair[1]).collect();
+    let mean_gap = gaps.iter().sum::<f32>() / gaps.len() as f32;
+    let (elbow, largest_gap) = gaps.iter().enumerate()
+        .fold((0, f32::MIN), |best, (i, gap)| if *gap > best.1 { (i, *gap) } else { best });
+    if mean_gap > 0.0 && largest_gap > ELBOW_GAP_FACTOR * mean_gap {
+        elbow + 1
+    } else {
+        n
+    }
+}
+
+/// A score added to results from notes carrying `tag`, or taken off when `weight` is negative
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagBoost {
+    /// Lowercase and without the `#`
+    pub tag: String,
+    pub weight: f32,
+}
+
+impl TagBoost {
+    /// Whether a note tag, such as `#Project/alpha`, is `tag` or nested below it
+    fn applies_to(&self, note_tag: &str) -> bool {
+        let note_tag = note_tag.trim_start_matches('#').to_lowercase();
+        note_tag == self.tag || note_tag.strip_prefix(&self.tag).is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+/// Parses one `#tag weight` pair per line, e.g. `#permanent 0.1`, also accepting `#tag: weight`.
+/// Blank lines are skipped, and lines that cannot be read are returned as errors.
+pub fn parse_tag_boosts(text: &str) -> (Vec<TagBoost>, Vec<String>) {
+    let mut boosts = Vec::new();
+    let mut errors = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let parsed = line.rsplit_once(|c: char| c.is_whitespace() || c == ':')
+            .and_then(|(tag, weight)| Some((tag.trim().trim_end_matches(':').trim_start_matches('#').to_lowercase(), weight.trim().parse::<f32>().ok()?)))
+            .filter(|(tag, weight)| !tag.is_empty() && !tag.contains(char::is_whitespace) && weight.is_finite());
+        match parsed {
+            Some((tag, weight)) => boosts.push(TagBoost { tag, weight }),
+            None => errors.push(format!("'{}' should be a tag followed by a weight, e.g. #permanent 0.1", line)),
+        }
+    }
+    (boosts, errors)
+}
+
+/// A step that changes the scores of ranked candidates before they are grouped and cut,
+/// such as a tag boost or recency decay
+pub trait ScoreAdjustment {
+    /// The new score of `candidate`, given its current one
+    fn adjust(&self, candidate: &ScoredCandidate) -> f32;
+}
+
+/// Orders scores from highest to lowest with NaN last, for `sort_by`
+pub fn descending(a: f32, b: f32) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (false, false) => b.total_cmp(&a),
+        (a_nan, b_nan) => a_nan.cmp(&b_nan),
+    }
+}
+
+/// Applies every adjustment in turn to each candidate, then sorts them by the adjusted score
+pub fn adjust_scores(candidates: &mut [ScoredCandidate], adjustments: &[Box<dyn ScoreAdjustment + '_>]) {
+    if adjustments.is_empty() {
+        return;
+    }
+    for candidate in candidates.iter_mut() {
+        for adjustment in adjustments {
+            candidate.score = adjustment.adjust(candidate);
+        }
+    }
+    candidates.sort_by(|a, b| descending(a.score, b.score));
+}
+
+/// Adds the weight of every boost that applies to a note's tags to the scores of its candidates
+pub struct TagBoosting<'a> {
+    pub boosts: &'a [TagBoost],
+    /// Tags of each note by name, notes missing from it have none
+    pub tags: HashMap<String, Vec<String>>,
+}
+
+impl ScoreAdjustment for TagBoosting<'_> {
+    fn adjust(&self, candidate: &ScoredCandidate) -> f32 {
+        let tags = self.tags.get(&candidate.name).map(Vec::as_slice).unwrap_or_default();
+        candidate.score + self.boosts.iter()
+            .filter(|boost| tags.iter().any(|tag| boost.applies_to(tag)))
+            .map(|boost| boost.weight)
+            .sum::<f32>()
+    }
+}
+
+/// Lowers the scores of notes by how long ago they were last modified, halving them every
+/// `half_life_days`. Notes without a known modification time keep their score.
+pub struct RecencyDecay {
+    pub half_life_days: f64,
+    /// Current time in ms since the epoch
+    pub now: f64,
+    /// Last modification time of each note by name, in ms since the epoch
+    pub modified: HashMap<String, f64>,
+}
+
+impl RecencyDecay {
+    const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+    /// Share of the score kept by a note last modified at `modified`, notes dated in the future count as new
+    fn factor(&self, modified: f64) -> f32 {
+        let age_days = (self.now - modified).max(0.0) / Self::DAY_MS;
+        0.5f64.powf(age_days / self.half_life_days) as f32
+    }
+}
+
+impl ScoreAdjustment for RecencyDecay {
+    /// Scores below zero, as z-score normalization gives, are pushed further down
+    /// rather than towards zero, so age never raises a result
+    fn adjust(&self, candidate: &ScoredCandidate) -> f32 {
+        match self.modified.get(&candidate.name) {
+            Some(&modified) => candidate.score - candidate.score.abs() * (1.0 - self.factor(modified)),
+            None => candidate.score,
+        }
+    }
+}
+
+/// Collapses the chunks of each note into a single candidate, sorted by score.
+///
+/// The best-matching chunk represents the note, scored by either the best or
+/// the mean score of all the note's chunks. `candidates` must be sorted by score.
+pub fn group_by_file(candidates: Vec<ScoredCandidate>, grouping: FileGrouping) -> Vec<ScoredCandidate> {
+    if grouping == FileGrouping::Off {
+        return candidates;
+    }
+    let mut groups: Vec<(ScoredCandidate, f32, usize)> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for candidate in candidates {
+        match positions.get(&candidate.name) {
+            Some(&position) => {
+                let (_, total, count) = &mut groups[position];
+                *total += candidate.score;
+                *count += 1;
+            }
+            None => {
+                positions.insert(candidate.name.clone(), groups.len());
+                let score = candidate.score;
+                groups.push((candidate, score, 1));
+            }
+        }
+    }
+    let mut grouped: Vec<ScoredCandidate> = groups.into_iter().map(|(mut best, total, count)| {
+        if grouping == FileGrouping::Mean {
+            best.score = total / count as f32;
+        }
+        best
+    }).collect();
+    grouped.sort_by(|a, b| descending(a.score, b.score));
+    grouped
+}
+
+/// Keeps as many of the best-scoring candidates as `result_count` allows, in their current order.
+///
+/// Diversifying leaves candidates out of score order, so the count is turned into a score
+/// cutoff on the sorted scores rather than applied to the order the candidates are in.
+pub fn truncate(mut candidates: Vec<ScoredCandidate>, max_results: usize, adaptive: bool) -> Vec<ScoredCandidate> {
+    let mut scores: Vec<f32> = candidates.iter().map(|candidate| candidate.score).collect();
+    scores.sort_by(|a, b| descending(*a, *b));
+    let cutoff = match result_count(&scores, max_results, adaptive).checked_sub(1) {
+        Some(last) => scores[last],
+        None => return Vec::new(),
+    };
+    candidates.retain(|candidate| candidate.score >= cutoff);
+    candidates.truncate(max_results);
+    candidates
+}
+
+/// Reorders the first `pool_size` candidates with Maximal Marginal Relevance and keeps `count` of them.
+///
+/// Each pick maximises `lambda * relevance - (1 - lambda) * max similarity to the
+/// picks so far`, so `lambda = 1` is plain relevance ranking and lower values
+/// favour results that differ from those already chosen. `candidates` must be
+/// sorted by score.
+pub fn mmr(mut candidates: Vec<ScoredCandidate>, lambda: f32, count: usize, pool_size: usize) -> Vec<ScoredCandidate> {
+    candidates.truncate(pool_size.max(count));
+    let mut selected: Vec<ScoredCandidate> = Vec::with_capacity(count);
+    while selected.len() < count && !candidates.is_empty() {
+        let (best, _) = candidates.iter().enumerate()
+            .map(|(i, candidate)| {
+                let redundancy = selected.iter()
+                    .map(|chosen| cosine_similarity(&candidate.embedding, &chosen.embedding))
+                    .fold(0.0, f32::max);
+                (i, lambda * candidate.score - (1.0 - lambda) * redundancy)
+            })
+            .fold((0, f32::MIN), |best, (i, value)| if value > best.1 { (i, value) } else { best });
+        selected.push(candidates.remove(best));
+    }
+    selected
+}
+
+/// A score in the top-k heap, ordered so the worst score (and among equal scores the latest row) is on top
+#[derive(Debug, PartialEq)]
+struct HeapEntry {
+    score: f32,
+    index: usize,
+}
+
+impl Eq for HeapEntry {}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.score.total_cmp(&self.score).then(self.index.cmp(&other.index))
+    }
+}
+
+/// Indices of the `k` highest of `scores` that `keep` accepts, best first. NaN scores, which
+/// cosine similarity gives for degenerate vectors, are never picked.
+///
+/// Keeps a heap of at most `k` entries, so selecting from n scores takes O(n log k) instead of
+/// sorting them all. Equal scores keep their order in `scores`, as a stable sort would.
+pub fn top_k(scores: &[f32], k: usize, keep: impl Fn(usize) -> bool) -> Vec<usize> {
+    let mut heap = BinaryHeap::with_capacity(k.saturating_add(1).min(scores.len() + 1));
+    for (index, &score) in scores.iter().enumerate() {
+        if k == 0 || score.is_nan() || !keep(index) {
+            continue;
+        }
+        if heap.len() == k {
+            match heap.peek() {
+                Some(worst) if HeapEntry { score, index } < *worst => { heap.pop(); },
+                _ => continue,
+            }
+        }
+        heap.push(HeapEntry { score, index });
+    }
+    heap.into_sorted_vec().into_iter().map(|entry| entry.index).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(name: &str, score: f32, embedding: Vec<f32>) -> ScoredCandidate {
+        ScoredCandidate { name: name.to_string(), header: "Header".to_string(), score, source: "test".to_string(), embedding }
+    }
+
+    fn chunk(name: &str, header: &str, score: f32) -> ScoredCandidate {
+        ScoredCandidate { header: header.to_string(), ..candidate(name, score, vec![]) }
+    }
+
+    #[test]
+    fn fixed_count() {
+        let scores = [0.9, 0.5, 0.4, 0.3];
+
+        assert_eq!(result_count(&scores, 3, false), 3);
+        assert_eq!(result_count(&scores, 10, false), 4);
+    }
+
+    #[test]
+    fn cut_at_elbow() {
+        let scores = [0.92, 0.90, 0.89, 0.60, 0.58, 0.57, 0.55];
+
+        assert_eq!(result_count(&scores, 10, true), 3);
+    }
+
+    #[test]
+    fn no_clear_elbow() {
+        let scores = [0.9, 0.8, 0.7, 0.6, 0.5];
+
+        assert_eq!(result_count(&scores, 10, true), 5);
+    }
+
+    #[test]
+    fn truncate_after_diversifying() {
+        let candidates = vec![chunk("a.md", "A", 0.9), chunk("c.md", "C", 0.2), chunk("b.md", "B", 0.85), chunk("d.md", "D", 0.1)];
+
+        let kept = truncate(candidates.clone(), 3, false);
+        assert_eq!(kept.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["a.md", "c.md", "b.md"]);
+
+        let kept = truncate(candidates.clone(), 2, false);
+        assert_eq!(kept.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["a.md", "b.md"]);
+
+        let kept = truncate(candidates, 10, true);
+        assert_eq!(kept.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["a.md", "b.md"]);
+    }
+
+    #[test]
+    fn top_k_matches_a_full_sort() {
+        let scores: [f32; 7] = [0.3, 0.9, 0.5, 0.9, 0.1, 0.7, 0.5];
+        let mut sorted: Vec<usize> = (0..scores.len()).collect();
+        sorted.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+
+        assert_eq!(top_k(&scores, 4, |_| true), sorted[..4]);
+        assert_eq!(top_k(&scores, 10, |_| true), sorted);
+        assert!(top_k(&scores, 0, |_| true).is_empty());
+    }
+
+    #[test]
+    fn nan_scores_rank_last() {
+        let mut scores = [0.2, f32::NAN, 0.9, f32::NAN, -0.4];
+        scores.sort_by(|a, b| descending(*a, *b));
+
+        assert_eq!(scores[..3], [0.9, 0.2, -0.4]);
+        assert!(scores[3..].iter().all(|score| score.is_nan()));
+        assert_eq!(top_k(&[0.2, f32::NAN, 0.9], 3, |_| true), vec![2, 0]);
+    }
+
+    #[test]
+    fn top_k_skips_rejected_rows() {
+        let scores = [0.3, 0.9, 0.5, 0.8];
+
+        assert_eq!(top_k(&scores, 2, |i| i != 1), vec![3, 2]);
+    }
+
+    #[test]
+    fn mmr_prefers_diverse_results() {
+        let candidates = vec![
+            candidate("long.md", 0.95, vec![1.0, 0.0]),
+            candidate("long.md", 0.94, vec![0.99, 0.01]),
+            candidate("other.md", 0.80, vec![0.0, 1.0]),
+        ];
+
+        let res = mmr(candidates, 0.5, 2, 10);
+
+        assert_eq!(res[0].name, "long.md");
+        assert_eq!(res[1].name, "other.md");
+    }
+
+    #[test]
+    fn mmr_lambda_one_is_relevance() {
+        let candidates = vec![
+            candidate("a", 0.95, vec![1.0, 0.0]),
+            candidate("b", 0.94, vec![1.0, 0.0]),
+            candidate("c", 0.80, vec![0.0, 1.0]),
+        ];
+
+        let res = mmr(candidates, 1.0, 3, 10);
+
+        assert_eq!(res.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn group_by_file_max() {
+        let candidates = vec![chunk("long.md", "Intro", 0.9), chunk("long.md", "Details", 0.8), chunk("other.md", "Other", 0.7)];
+
+        let res = group_by_file(candidates, FileGrouping::Max);
+
+        assert_eq!(res.len(), 2);
+        assert_eq!((res[0].name.as_str(), res[0].header.as_str(), res[0].score), ("long.md", "Intro", 0.9));
+        assert_eq!(res[1].name, "other.md");
+    }
+
+    #[test]
+    fn group_by_file_mean_reorders() {
+        let candidates = vec![chunk("long.md", "Intro", 0.9), chunk("long.md", "Details", 0.3), chunk("other.md", "Other", 0.7)];
+
+        let res = group_by_file(candidates, FileGrouping::Mean);
+
+        assert_eq!(res[0].name, "other.md");
+        assert_eq!(res[1].header, "Intro");
+        assert!((res[1].score - 0.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn tag_boosts_parse() {
+        let (boosts, errors) = parse_tag_boosts("#permanent 0.1\n\n  #Fleeting: -0.05 \nproject/alpha\t0.2\nno weight\n#a b 1\n");
+
+        assert_eq!(boosts, vec![
+            TagBoost { tag: "permanent".to_string(), weight: 0.1 },
+            TagBoost { tag: "fleeting".to_string(), weight: -0.05 },
+            TagBoost { tag: "project/alpha".to_string(), weight: 0.2 },
+        ]);
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn tag_boosts_reorder() {
+        let tags = HashMap::from([
+            ("permanent.md".to_string(), vec!["#permanent".to_string()]),
+            ("fleeting.md".to_string(), vec!["#Fleeting/inbox".to_string(), "#permanentish".to_string()]),
+        ]);
+        let mut candidates = vec![chunk("fleeting.md", "A", 0.9), chunk("other.md", "B", 0.85), chunk("permanent.md", "C", 0.8)];
+        let (boosts, _) = parse_tag_boosts("#permanent 0.1\n#fleeting -0.1");
+
+        adjust_scores(&mut candidates, &[Box::new(TagBoosting { boosts: &boosts, tags })]);
+
+        assert_eq!(candidates.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["permanent.md", "other.md", "fleeting.md"]);
+        assert!((candidates[0].score - 0.9).abs() < 1e-6);
+    }
+
+    #[test]
+    fn recency_decay_halves_scores_per_half_life() {
+        let day = 24.0 * 60.0 * 60.0 * 1000.0;
+        let now = 100.0 * day;
+        let modified = HashMap::from([
+            ("old.md".to_string(), now - 30.0 * day),
+            ("new.md".to_string(), now - day),
+            ("future.md".to_string(), now + day),
+            ("negative.md".to_string(), now - 30.0 * day),
+        ]);
+        let mut candidates = vec![chunk("old.md", "A", 0.9), chunk("new.md", "B", 0.6), chunk("undated.md", "C", 0.5), chunk("future.md", "D", 0.4), chunk("negative.md", "E", -0.2)];
+
+        adjust_scores(&mut candidates, &[Box::new(RecencyDecay { half_life_days: 30.0, now, modified })]);
+
+        assert_eq!(candidates.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["new.md", "undated.md", "old.md", "future.md", "negative.md"]);
+        assert!((candidates[2].score - 0.45).abs() < 1e-6);
+        assert_eq!(candidates[3].score, 0.4);
+        assert!((candidates[4].score + 0.3).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,125 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use log::info;
+use wasm_bindgen_futures::JsFuture;
+
+use crate::obsidian;
+use crate::settings::Settings;
+
+const WINDOW_MS: f64 = 60_000.0;
+
+/// Requests and tokens the API allows per minute, as listed for the account's usage tier.
+/// 0 leaves either unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    pub requests_per_minute: usize,
+    pub tokens_per_minute: usize,
+}
+
+impl RateLimit {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self { requests_per_minute: settings.requests_per_minute, tokens_per_minute: settings.tokens_per_minute }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.requests_per_minute > 0 || self.tokens_per_minute > 0
+    }
+}
+
+/// Requests sent or reserved in the last minute, with their token counts
+#[derive(Debug, Default)]
+pub struct UsageWindow {
+    sent: VecDeque<(f64, usize)>,
+}
+
+impl UsageWindow {
+    /// Milliseconds to wait from `now` before a request of `tokens` fits in `limit`.
+    /// A request larger than the whole token budget waits for the window to empty.
+    pub fn delay(&mut self, limit: RateLimit, now: f64, tokens: usize) -> f64 {
+        while self.sent.front().is_some_and(|(sent_at, _)| sent_at + WINDOW_MS <= now) {
+            self.sent.pop_front();
+        }
+        let mut delay: f64 = 0.0;
+        let requests = limit.requests_per_minute;
+        if requests > 0 && self.sent.len() >= requests {
+            let (sent_at, _) = self.sent[self.sent.len() - requests];
+            delay = delay.max(sent_at + WINDOW_MS - now);
+        }
+        if limit.tokens_per_minute > 0 {
+            let needed = tokens.min(limit.tokens_per_minute);
+            let mut used: usize = self.sent.iter().map(|(_, tokens)| tokens).sum();
+            for (sent_at, sent_tokens) in &self.sent {
+                if used + needed <= limit.tokens_per_minute {
+                    break;
+                }
+                used -= sent_tokens;
+                delay = delay.max(sent_at + WINDOW_MS - now);
+            }
+        }
+        delay
+    }
+
+    pub fn record(&mut self, sent_at: f64, tokens: usize) {
+        self.sent.push_back((sent_at, tokens));
+    }
+}
+
+thread_local! {
+    // shared by every command, since they all draw from the same account's limits
+    static USAGE: RefCell<UsageWindow> = const { RefCell::new(UsageWindow { sent: VecDeque::new() }) };
+}
+
+/// Waits until a request of `tokens` fits in `limit`, reserving its place in the window
+/// first so concurrent commands queue up behind each other.
+pub async fn acquire(limit: RateLimit, tokens: usize) {
+    let now = js_sys::Date::now();
+    let delay = USAGE.with(|usage| {
+        let mut usage = usage.borrow_mut();
+        let delay = usage.delay(limit, now, tokens);
+        usage.record(now + delay, tokens);
+        delay
+    });
+    if delay > 0.0 {
+        info!("Waiting {:.0} ms to stay within the rate limit", delay);
+        sleep(delay).await;
+    }
+}
+
+pub(crate) async fn sleep(ms: f64) {
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        obsidian::set_timeout(&resolve, ms);
+    });
+    let _ = JsFuture::from(promise).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_per_minute() {
+        let limit = RateLimit { requests_per_minute: 2, tokens_per_minute: 0 };
+        let mut usage = UsageWindow::default();
+
+        assert_eq!(usage.delay(limit, 0.0, 10), 0.0);
+        usage.record(0.0, 10);
+        usage.record(1000.0, 10);
+        assert_eq!(usage.delay(limit, 2000.0, 10), 58_000.0);
+        assert_eq!(usage.delay(limit, 60_000.0, 10), 0.0);
+    }
+
+    #[test]
+    fn tokens_per_minute() {
+        let limit = RateLimit { requests_per_minute: 0, tokens_per_minute: 100 };
+        let mut usage = UsageWindow::default();
+        usage.record(0.0, 60);
+        usage.record(10_000.0, 30);
+
+        assert_eq!(usage.delay(limit, 20_000.0, 10), 0.0);
+        assert_eq!(usage.delay(limit, 20_000.0, 50), 40_000.0);
+        assert_eq!(usage.delay(limit, 20_000.0, 80), 50_000.0);
+        // larger than the whole budget, so it only waits for the window to empty
+        assert_eq!(usage.delay(limit, 20_000.0, 500), 50_000.0);
+    }
+}
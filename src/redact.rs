@@ -0,0 +1,100 @@
+//! Keeping API keys and note text out of what is logged or shown to the user, so logs and error
+//! messages can be pasted into bug reports as they are.
+
+use std::cell::RefCell;
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+use crate::checksum;
+use crate::settings::Settings;
+
+/// Quoted text longer than this is cut short, since it is most likely note text echoed back
+const MAX_QUOTED_CHARS: usize = 40;
+/// Characters of a quoted text kept when cutting it short
+const QUOTED_PREFIX_CHARS: usize = 16;
+/// Secrets shorter than this are too likely to occur in ordinary text to be masked everywhere
+const MIN_SECRET_CHARS: usize = 6;
+const MASK: &str = "[redacted]";
+
+thread_local! {
+    /// The keys and passphrases from settings, masked wherever they appear
+    static SECRETS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Remembers the secrets in `settings`, so they are masked even where nothing marks them as keys
+pub fn configure(settings: &Settings) {
+    let mut secrets: Vec<String> = [&settings.api_key, &settings.rerank_api_key, &settings.encryption_passphrase].iter()
+        .map(|secret| secret.trim().to_string())
+        .chain(settings.custom_providers.iter().flat_map(|provider| provider.headers.values().map(|value| value.trim().to_string())))
+        .filter(|secret| secret.chars().count() >= MIN_SECRET_CHARS)
+        .collect();
+    // longer secrets first, so one containing another is masked whole
+    secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+    secrets.dedup();
+    SECRETS.with(|configured| configured.replace(secrets));
+}
+
+/// `text` with API keys masked and long quoted text, such as a note echoed back in an error,
+/// cut short and labelled with its length and a hash so occurrences can still be matched up
+pub fn redact(text: &str) -> String {
+    let text = SECRETS.with(|secrets| secrets.borrow().iter().fold(text.to_string(), |text, secret| text.replace(secret.as_str(), MASK)));
+    redact_text(&text)
+}
+
+fn redact_text(text: &str) -> String {
+    lazy_static! {
+        static ref BEARER: Regex = Regex::new(r"(?i)\b(bearer\s+)[^\s'`,;]+").unwrap();
+        static ref KEY_FIELD: Regex = Regex::new(r#"(?i)\b((?:api[_-]?key|access[_-]?token|secret|password|passphrase|key)["']?\s*[:=]\s*["']?)[^\s"'&,;}]+"#).unwrap();
+        static ref SECRET_KEY: Regex = Regex::new(r"\b(?:sk|pk|rk)-[A-Za-z0-9_\-]{8,}").unwrap();
+        static ref QUOTED: Regex = Regex::new(r#""((?:[^"\\]|\\.)*)""#).unwrap();
+    }
+    let text = BEARER.replace_all(text, |caps: &Captures| format!("{}{}", &caps[1], MASK));
+    let text = KEY_FIELD.replace_all(&text, |caps: &Captures| format!("{}{}", &caps[1], MASK));
+    let text = SECRET_KEY.replace_all(&text, MASK);
+    QUOTED.replace_all(&text, |caps: &Captures| format!("\"{}\"", excerpt(&caps[1]))).to_string()
+}
+
+/// `text` if it is short, otherwise its start with its length and a hash of the whole
+pub fn excerpt(text: &str) -> String {
+    let chars = text.chars().count();
+    if chars <= MAX_QUOTED_CHARS {
+        return text.to_string();
+    }
+    let start: String = text.chars().take(QUOTED_PREFIX_CHARS).collect();
+    format!("{}… ({} chars, {:08x})", start, chars, checksum::crc32(text.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_keys() {
+        assert_eq!(redact_text("Authorization: Bearer abc.def-123"), "Authorization: Bearer [redacted]");
+        assert_eq!(redact_text("Incorrect API key provided: sk-proj-abcdefghijkl."), "Incorrect API key provided: [redacted].");
+        assert_eq!(redact_text("GET /v1/models?key=AIzaSyExample&alt=json"), "GET /v1/models?key=[redacted]&alt=json");
+        assert_eq!(redact_text(r#"{"api_key": "secret123"}"#), r#"{"api_key": "[redacted]"}"#);
+        assert_eq!(redact_text("Batch 1/2: 10 records embedded"), "Batch 1/2: 10 records embedded");
+    }
+
+    #[test]
+    fn masks_configured_secrets() {
+        let settings = Settings { api_key: "abc123secret".to_string(), rerank_api_key: "abc".to_string(), ..Settings::default() };
+        configure(&settings);
+
+        assert_eq!(redact("rejected abc123secret for abc"), "rejected [redacted] for abc");
+        configure(&Settings::default());
+    }
+
+    #[test]
+    fn shortens_quoted_text() {
+        let note = "My private journal entry about a very personal matter";
+
+        let redacted = redact_text(&format!("invalid type: string \"{}\", expected f32", note));
+
+        assert!(redacted.starts_with("invalid type: string \"My private journ… (53 chars, "), "{}", redacted);
+        assert!(redacted.ends_with(")\", expected f32"), "{}", redacted);
+        assert_eq!(redact_text("unknown model \"text-embedding-3-small\""), "unknown model \"text-embedding-3-small\"");
+    }
+}
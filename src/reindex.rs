@@ -0,0 +1,97 @@
+//! Rebuilding everything searches need in one call: input.csv, the embedding file and the
+//! resident index, reporting progress through a single callback.
+
+use log::warn;
+use serde::Serialize;
+use wasm_bindgen::JsValue;
+
+use crate::SemanticSearchError;
+use crate::pipeline::{PipelineHook, PipelineRecord, PipelineReport};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReindexStage {
+    /// Reading the vault into input.csv, counted in files
+    Input,
+    /// Embedding input.csv, counted in records
+    Embeddings,
+    /// Loading the embedding file into memory, counted in stored chunks
+    Index,
+}
+
+/// One update of a reindex: `done` of `total` units of `stage` are finished
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReindexProgress {
+    pub stage: ReindexStage,
+    pub done: usize,
+    pub total: usize,
+}
+
+/// Calls an optional JS function with each `ReindexProgress`. A failing callback is logged
+/// rather than stopping the reindex.
+#[derive(Clone, Default)]
+pub struct Progress(Option<js_sys::Function>);
+
+impl Progress {
+    pub fn new(callback: Option<js_sys::Function>) -> Self {
+        Self(callback)
+    }
+
+    pub fn report(&self, stage: ReindexStage, done: usize, total: usize) {
+        let callback = match &self.0 {
+            Some(callback) => callback,
+            None => return,
+        };
+        let update = match serde_wasm_bindgen::to_value(&ReindexProgress { stage, done, total }) {
+            Ok(update) => update,
+            Err(e) => return warn!("Failed to convert reindex progress: {}", e),
+        };
+        if let Err(e) = callback.call1(&JsValue::NULL, &update) {
+            warn!("Reindex progress callback failed: {:?}", e);
+        }
+    }
+}
+
+/// Reports embedding progress before each batch is sent
+pub struct ProgressHook {
+    progress: Progress,
+    done: usize,
+    total: usize,
+}
+
+impl ProgressHook {
+    pub fn new(progress: Progress) -> Self {
+        Self { progress, done: 0, total: 0 }
+    }
+}
+
+impl PipelineHook for ProgressHook {
+    fn preprocess(&mut self, records: Vec<PipelineRecord>) -> Result<Vec<PipelineRecord>, SemanticSearchError> {
+        self.total = records.len();
+        self.progress.report(ReindexStage::Embeddings, 0, self.total);
+        Ok(records)
+    }
+
+    fn before_embed(&mut self, batch: &[PipelineRecord]) -> Result<(), SemanticSearchError> {
+        self.progress.report(ReindexStage::Embeddings, self.done, self.total);
+        self.done += batch.len();
+        Ok(())
+    }
+
+    fn report(&self, _report: &mut PipelineReport) {
+        self.progress.report(ReindexStage::Embeddings, self.total, self.total);
+    }
+}
+
+/// What a reindex did, stage by stage
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReindexReport {
+    /// Sections written to input.csv
+    pub input_records: usize,
+    /// Sections left out of input.csv for being too short
+    pub skipped_sections: usize,
+    pub embeddings: PipelineReport,
+    /// Chunks loaded into the resident index
+    pub indexed: usize,
+}
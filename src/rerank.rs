@@ -0,0 +1,179 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Client;
+use crate::SemanticSearchError;
+use crate::fusion::ScoredCandidate;
+use crate::rate_limit::RateLimit;
+use crate::settings::Settings;
+
+/// Number of top vector search candidates sent to the reranker
+pub const RERANK_CANDIDATES: usize = 30;
+
+/// API shape of the reranking endpoint
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RerankFormat {
+    /// Cohere's `/rerank`, also served by Jina, Voyage and most self-hosted rerankers
+    #[default]
+    Cohere,
+    /// The `/score` endpoint of OpenAI-compatible servers such as vLLM
+    Score,
+}
+
+#[derive(Debug, Serialize)]
+struct RerankRequest<'a> {
+    model: &'a str,
+    query: &'a str,
+    documents: &'a [String],
+    top_n: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct RerankResponse {
+    results: Vec<RerankResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RerankResult {
+    index: usize,
+    relevance_score: f32,
+}
+
+#[derive(Debug, Serialize)]
+struct ScoreRequest<'a> {
+    model: &'a str,
+    text_1: &'a str,
+    text_2: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct ScoreResponse {
+    data: Vec<ScoreResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScoreResult {
+    index: usize,
+    score: f32,
+}
+
+/// Scores query/document pairs with a cross-encoder or LLM reranking endpoint
+pub struct Reranker {
+    format: RerankFormat,
+    client: Client,
+}
+
+impl Reranker {
+    /// `None` unless reranking is enabled. The endpoint falls back to the embedding API's, and
+    /// so does the key when the endpoint is the embedding API. A separate endpoint without its own
+    /// key leaves reranking off, see `needs_own_key`, rather than sending it the embedding key.
+    pub fn from_settings(settings: &Settings) -> Option<Self> {
+        if !settings.enable_rerank || needs_own_key(settings) {
+            return None;
+        }
+        let embedding_client = Client::new(settings);
+        let api_base = match settings.rerank_api_base.trim().trim_end_matches('/') {
+            "" => embedding_client.api_base().to_string(),
+            api_base => api_base.to_string(),
+        };
+        let api_key = match settings.rerank_api_key.trim() {
+            "" => embedding_client.api_key().to_string(),
+            api_key => api_key.to_string(),
+        };
+        let client = Client { api_key, api_base, org_id: String::new(), project_id: String::new(), model: settings.rerank_model.trim().to_string(), provider: embedding_client.provider, rate_limit: RateLimit::default(), request_timeout_ms: embedding_client.request_timeout_ms };
+        Some(Self { format: settings.rerank_format, client })
+    }
+
+    /// Relevance of each document to `query`, as (document index, score) pairs
+    pub async fn score(&self, query: &str, documents: &[String]) -> Result<Vec<(usize, f32)>, SemanticSearchError> {
+        let model = self.client.model();
+        let request = reqwest::Client::new();
+        let request = match self.format {
+            RerankFormat::Cohere => request.post(format!("{}/rerank", self.client.api_base()))
+                .json(&RerankRequest { model, query, documents, top_n: documents.len() }),
+            RerankFormat::Score => request.post(format!("{}/score", self.client.api_base()))
+                .json(&ScoreRequest { model, text_1: query, text_2: documents }),
+        };
+        let request = request.bearer_auth(self.client.api_key()).headers(self.client.headers()?).build()?;
+        let scores = match self.format {
+            RerankFormat::Cohere => self.client.execute::<RerankResponse>(request).await?
+                .results.into_iter().map(|result| (result.index, result.relevance_score)).collect(),
+            RerankFormat::Score => self.client.execute::<ScoreResponse>(request).await?
+                .data.into_iter().map(|result| (result.index, result.score)).collect(),
+        };
+        Ok(scores)
+    }
+}
+
+/// Whether reranking is pointed at an endpoint other than the embedding API without a key of
+/// its own. The embedding key is never sent to another endpoint, so reranking stays off.
+pub fn needs_own_key(settings: &Settings) -> bool {
+    let api_base = settings.rerank_api_base.trim().trim_end_matches('/');
+    settings.rerank_api_key.trim().is_empty() && !api_base.is_empty() && api_base != Client::new(settings).api_base().trim_end_matches('/')
+}
+
+/// The text the reranker reads for each candidate: the section text stored in its header column
+pub fn candidate_texts(candidates: &[ScoredCandidate]) -> Vec<String> {
+    candidates.iter().map(|candidate| candidate.header.clone()).collect()
+}
+
+/// Orders candidates by reranker score, replacing their vector scores.
+/// Candidates the reranker did not score keep their order after the scored ones.
+pub fn reorder(candidates: Vec<ScoredCandidate>, scores: &[(usize, f32)]) -> Vec<ScoredCandidate> {
+    let mut reranked: Vec<Option<f32>> = vec![None; candidates.len()];
+    for &(index, score) in scores {
+        if let Some(slot) = reranked.get_mut(index) {
+            *slot = Some(score);
+        }
+    }
+    let (mut scored, unscored): (Vec<_>, Vec<_>) = candidates.into_iter().zip(reranked)
+        .partition(|(_, score)| score.is_some());
+    scored.sort_by(|(_, a), (_, b)| b.unwrap_or_default().total_cmp(&a.unwrap_or_default()));
+    scored.into_iter()
+        .map(|(candidate, score)| ScoredCandidate { score: score.unwrap_or(candidate.score), ..candidate })
+        .chain(unscored.into_iter().map(|(candidate, _)| candidate))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(name: &str, header: &str, score: f32) -> ScoredCandidate {
+        ScoredCandidate { name: name.to_string(), header: header.to_string(), score, source: String::new(), embedding: vec![] }
+    }
+
+    #[test]
+    fn reorder_by_reranker_score() {
+        let candidates = vec![candidate("a", "A", 0.9), candidate("b", "B", 0.8), candidate("c", "C", 0.7)];
+
+        let res = reorder(candidates, &[(2, 0.95), (0, 0.1), (7, 1.0)]);
+
+        assert_eq!(res.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["c", "a", "b"]);
+        assert_eq!(res[0].score, 0.95);
+        assert_eq!(res[2].score, 0.8);
+    }
+
+    #[test]
+    fn embedding_key_only_goes_to_the_embedding_api() {
+        let settings = Settings { enable_rerank: true, api_key: "sk-embedding".to_string(), ..Settings::default() };
+        let embedding_api = Client::new(&settings).api_base().to_string();
+        let third_party = Settings { rerank_api_base: "https://api.cohere.com/v2".to_string(), ..settings.clone() };
+
+        assert_eq!(Reranker::from_settings(&settings).unwrap().client.api_key(), "sk-embedding");
+        assert!(Reranker::from_settings(&Settings { rerank_api_base: format!("{}/", embedding_api), ..settings.clone() }).is_some());
+        assert!(needs_own_key(&third_party));
+        assert!(Reranker::from_settings(&third_party).is_none());
+        let own_key = Reranker::from_settings(&Settings { rerank_api_key: "co-rerank".to_string(), ..third_party }).unwrap();
+        assert_eq!(own_key.client.api_key(), "co-rerank");
+    }
+
+    #[test]
+    fn response_formats() {
+        let cohere: RerankResponse = serde_json::from_str(r#"{"id": "x", "results": [{"index": 1, "relevance_score": 0.5}]}"#).unwrap();
+        let score: ScoreResponse = serde_json::from_str(r#"{"object": "list", "data": [{"index": 0, "object": "score", "score": 0.25}]}"#).unwrap();
+
+        assert_eq!((cohere.results[0].index, cohere.results[0].relevance_score), (1, 0.5));
+        assert_eq!((score.data[0].index, score.data[0].score), (0, 0.25));
+    }
+}
@@ -0,0 +1,101 @@
+//! Writing the results of a search to a note, so a research session can be kept, linked to
+//! and annotated like any other note.
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::{QueryCommand, SemanticSearchError, Suggestions};
+use crate::links;
+use crate::obsidian;
+use crate::settings::Settings;
+use crate::snippets::Snippet;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResultsNote {
+    pub path: String,
+    /// Results listed in the note
+    pub results: usize,
+}
+
+/// The note listing `results` for `query`: frontmatter recording the search, then each result
+/// as a wiki link to its section with its score and, when there is one, its snippet quoted
+pub fn results_markdown(query: &str, model: &str, searched_at: &str, results: &[Suggestions]) -> String {
+    let query = query.trim();
+    // a JSON string is a valid YAML scalar, whatever the query contains
+    let quoted = serde_json::to_string(query).unwrap_or_default();
+    let mut markdown = format!("---\nquery: {}\nmodel: {}\nsearched: {}\n---\n# {}\n\n", quoted, model, searched_at, query);
+    if results.is_empty() {
+        markdown.push_str("No results.\n");
+    }
+    for (rank, result) in results.iter().enumerate() {
+        let link = match &result.link {
+            Some(link) => format!("[[{}]]", link.link_text),
+            None => links::note_wikilink(&result.name),
+        };
+        markdown.push_str(&format!("{}. {} ({:.3})\n", rank + 1, link, result.score));
+        if let Some(snippet) = &result.snippet {
+            markdown.push_str(&format!("   > {}\n", quote(snippet)));
+        }
+    }
+    markdown
+}
+
+/// `snippet` on one line, with its best matching sentence in bold
+fn quote(snippet: &Snippet) -> String {
+    let one_line = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let (before, sentence, after) = (one_line(&snippet.before), one_line(&snippet.sentence), one_line(&snippet.after));
+    [before, format!("**{}**", sentence), after].iter().filter(|part| !part.is_empty()).cloned().collect::<Vec<_>>().join(" ")
+}
+
+/// Searches for `query` and writes the results, with snippets, to a new note at `path`, adding
+/// `.md` if it has no extension. Returns a `ResultsNote`. Fails if the note already exists.
+#[wasm_bindgen]
+pub async fn write_results_note(app: &obsidian::App, settings: JsValue, query: String, path: String) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    if query.trim().is_empty() {
+        return Err(SemanticSearchError::InvalidArgument("The query is empty".to_string()));
+    }
+    let path = match path.trim() {
+        "" => return Err(SemanticSearchError::InvalidArgument("No path given for the results note".to_string())),
+        path if path.rsplit('/').next().unwrap_or_default().contains('.') => path.to_string(),
+        path => format!("{}.md", path),
+    };
+    let mut query_cmd = QueryCommand::new(app, &settings);
+    if query_cmd.file_processor.check_file_exists_at_path(&path).await? {
+        return Err(SemanticSearchError::InvalidArgument(format!("{} already exists", path)));
+    }
+    // the note is for reading later, so it gets snippets even when the search modal shows none
+    query_cmd.show_snippets = true;
+    let results = query_cmd.suggest(&query, &JsValue::UNDEFINED).await?;
+    let searched_at = String::from(js_sys::Date::new_0().to_iso_string());
+    let markdown = results_markdown(&query, query_cmd.client.model(), &searched_at, &results);
+    query_cmd.file_processor.write_to_path(&path, &markdown).await?;
+    Ok(serde_wasm_bindgen::to_value(&ResultsNote { path, results: results.len() })?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::links::SectionLink;
+
+    #[test]
+    fn lists_results_with_links_and_snippets() {
+        let snippet = Snippet { before: "Notes on\nsoil. ".to_string(), sentence: "Compost feeds it.".to_string(), after: String::new() };
+        let results = vec![
+            Suggestions { name: "Soil.md".to_string(), header: "Compost".to_string(), score: 0.8123, lines: None, snippet: Some(snippet),
+                link: Some(SectionLink::new("vault", "garden/Soil.md", vec!["Compost".to_string()], None)) },
+            Suggestions { name: "Old.md".to_string(), header: "Old".to_string(), score: 0.5, lines: None, snippet: None, link: None },
+        ];
+
+        let markdown = results_markdown(" compost \"tea\" ", "text-embedding-3-small", "2024-05-01T10:00:00.000Z", &results);
+
+        assert_eq!(markdown, "---\nquery: \"compost \\\"tea\\\"\"\nmodel: text-embedding-3-small\nsearched: 2024-05-01T10:00:00.000Z\n---\n# compost \"tea\"\n\n\
+            1. [[garden/Soil#Compost]] (0.812)\n   > Notes on soil. **Compost feeds it.**\n\
+            2. [[Old]] (0.500)\n");
+    }
+
+    #[test]
+    fn says_when_nothing_was_found() {
+        assert!(results_markdown("q", "m", "t", &[]).ends_with("# q\n\nNo results.\n"));
+    }
+}
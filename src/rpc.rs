@@ -0,0 +1,155 @@
+//! One entry point other plugins and scripts can drive the plugin through, as
+//! `api.invoke(command, payload)`, for callers such as Dataview or QuickAdd scripts that would
+//! rather not depend on the shape of the rest of the API.
+//!
+//! Commands are named `<name>.v<version>`, e.g. `query.v1`. A command whose payload or result
+//! has to change gets a new version next to the old one instead of changing in place, so callers
+//! pinning a version keep working. A name without a version picks the latest one, which is handy
+//! for trying commands out but may change shape. `commands.v1` lists every command offered.
+
+use js_sys::{JsString, Reflect};
+use log::debug;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::SemanticSearchError;
+use crate::index::SemanticIndex;
+use crate::obsidian;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Commands,
+    Query,
+    Semantic,
+    Related,
+    Links,
+    Context,
+    Answer,
+    Reindex,
+    Export,
+    Provenance,
+    Stats,
+    Coverage,
+}
+
+/// A version of a command, with the payload it takes and what it returns, as shown by `commands.v1`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CommandSpec {
+    #[serde(skip)]
+    pub command: Command,
+    pub name: &'static str,
+    pub version: u32,
+    pub payload: &'static str,
+    pub result: &'static str,
+}
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec { command: Command::Commands, name: "commands", version: 1, payload: "{}", result: "CommandSpec[]" },
+    CommandSpec { command: Command::Query, name: "query", version: 1,
+        payload: "{ query: string } or { queries: string[] }, with optional filters: QueryFilters", result: "Suggestion[]" },
+    CommandSpec { command: Command::Semantic, name: "semantic", version: 1,
+        payload: "{ query: string, filters?: QueryFilters, limit?: number, files?: boolean }", result: "SemanticRow[]" },
+    CommandSpec { command: Command::Related, name: "related", version: 1, payload: "{ notes: string[], mode?: 'centroid' | 'union' }", result: "Suggestion[]" },
+    CommandSpec { command: Command::Links, name: "links", version: 1, payload: "{ text: string, path?: string }", result: "LinkCandidate[]" },
+    CommandSpec { command: Command::Context, name: "context", version: 1, payload: "{ query: string, tokenBudget: number }", result: "RagContext" },
+    CommandSpec { command: Command::Answer, name: "answer", version: 1, payload: "{ question: string }", result: "Answer" },
+    CommandSpec { command: Command::Reindex, name: "reindex", version: 1, payload: "{}", result: "ReindexReport" },
+    CommandSpec { command: Command::Export, name: "export", version: 1, payload: "{ format: 'jsonl' | 'npy' }", result: "ExportReport" },
+    CommandSpec { command: Command::Provenance, name: "provenance", version: 1, payload: "{ filter?: ProvenanceFilter }", result: "ProvenanceReport" },
+    CommandSpec { command: Command::Stats, name: "stats", version: 1, payload: "{}", result: "IndexStats" },
+    CommandSpec { command: Command::Coverage, name: "coverage", version: 1, payload: "{}", result: "CoverageDiff" },
+];
+
+/// The command `id` names, `query.v1` for a version or `query` for the latest one
+pub fn resolve(id: &str) -> Result<&'static CommandSpec, SemanticSearchError> {
+    let id = id.trim();
+    let (name, version) = match id.rsplit_once(".v") {
+        Some((name, version)) if !version.is_empty() && version.chars().all(|c| c.is_ascii_digit()) => (name, Some(version.parse::<u32>().unwrap_or(0))),
+        _ => (id, None),
+    };
+    let versions = COMMANDS.iter().filter(|spec| spec.name == name);
+    let spec = match version {
+        Some(version) => versions.clone().find(|spec| spec.version == version),
+        None => versions.clone().max_by_key(|spec| spec.version),
+    };
+    spec.ok_or_else(|| match versions.map(|spec| format!("{}.v{}", spec.name, spec.version)).collect::<Vec<_>>() {
+        offered if offered.is_empty() => SemanticSearchError::InvalidArgument(format!("Unknown command '{}', invoke 'commands.v1' to list them", id)),
+        offered => SemanticSearchError::InvalidArgument(format!("'{}' is not offered, use one of {}", id, offered.join(", "))),
+    })
+}
+
+/// `payload[name]`, undefined when it or the payload is missing
+fn field(payload: &JsValue, name: &str) -> JsValue {
+    if !payload.is_object() {
+        return JsValue::UNDEFINED;
+    }
+    Reflect::get(payload, &name.into()).unwrap_or(JsValue::UNDEFINED)
+}
+
+fn string_field(payload: &JsValue, name: &str) -> Result<String, SemanticSearchError> {
+    field(payload, name).as_string()
+        .ok_or_else(|| SemanticSearchError::InvalidArgument(format!("The payload needs a '{}' string", name)))
+}
+
+#[wasm_bindgen]
+impl SemanticIndex {
+    /// Runs `command`, e.g. `query.v1`, with its `payload` object and returns its result. See
+    /// `COMMANDS` for what each command takes and returns.
+    pub async fn invoke(&self, app: &obsidian::App, settings: JsValue, command: String, payload: JsValue) -> Result<JsValue, SemanticSearchError> {
+        let spec = resolve(&command)?;
+        debug!("Invoking {}.v{}", spec.name, spec.version);
+        match spec.command {
+            Command::Commands => Ok(serde_wasm_bindgen::to_value(COMMANDS)?),
+            Command::Query => match field(&payload, "queries") {
+                queries if queries.is_undefined() => {
+                    let query = JsString::from(string_field(&payload, "query")?);
+                    self.query(app, settings, query, field(&payload, "filters")).await
+                },
+                queries => crate::get_suggestions_for_queries(app, settings, queries, field(&payload, "filters")).await,
+            },
+            Command::Semantic => {
+                let limit = field(&payload, "limit").as_f64().unwrap_or(0.0).max(0.0) as usize;
+                let files = field(&payload, "files").is_truthy();
+                crate::dataview::semantic(app, settings, string_field(&payload, "query")?, field(&payload, "filters"), limit, files).await
+            },
+            Command::Related => crate::get_similar_to_notes(app, settings, field(&payload, "notes"), field(&payload, "mode")).await,
+            Command::Links => {
+                let text = JsString::from(string_field(&payload, "text")?);
+                crate::suggest_links(app, settings, text, field(&payload, "path").as_string().unwrap_or_default()).await
+            },
+            Command::Context => {
+                let query = JsString::from(string_field(&payload, "query")?);
+                let token_budget = field(&payload, "tokenBudget").as_f64()
+                    .ok_or_else(|| SemanticSearchError::InvalidArgument("The payload needs a 'tokenBudget' number".to_string()))?;
+                crate::build_context(app, settings, query, token_budget.max(0.0) as usize).await
+            },
+            Command::Answer => crate::answer_query(app, settings, JsString::from(string_field(&payload, "question")?)).await,
+            Command::Reindex => self.reindex_vault(app, settings, None).await,
+            Command::Export => crate::export_embeddings(app, settings, field(&payload, "format")).await,
+            Command::Provenance => crate::query_provenance(app, settings, field(&payload, "filter")).await,
+            Command::Stats => crate::stats::index_stats(app, settings).await,
+            Command::Coverage => crate::coverage::coverage_diff(app, settings).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_versioned_and_latest_names() {
+        assert_eq!(resolve("query.v1").unwrap().command, Command::Query);
+        assert_eq!(resolve(" reindex ").unwrap().command, Command::Reindex);
+        assert!(COMMANDS.iter().all(|spec| resolve(&format!("{}.v{}", spec.name, spec.version)).unwrap() == spec));
+    }
+
+    #[test]
+    fn rejects_unknown_commands_and_versions() {
+        let message = |id: &str| resolve(id).unwrap_err().to_string();
+
+        assert!(message("delete.v1").contains("Unknown command 'delete.v1'"));
+        assert!(message("query.v9").contains("use one of query.v1"));
+        assert!(message("query.vx").contains("Unknown command"));
+    }
+}
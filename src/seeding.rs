@@ -0,0 +1,122 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cosine_similarity;
+
+/// How the vectors of several seed notes are combined into one search
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SeedMode {
+    /// Search with the average of the seed notes, each note weighted equally
+    #[default]
+    Centroid,
+    /// Score each chunk by its best similarity to any chunk of any seed note
+    Union,
+}
+
+/// Scores every chunk that does not belong to a seed note against the seed set.
+///
+/// Returns `(row index, score)` pairs in store order, or `None` when none of the seeds are in
+/// the store.
+pub fn score_against_seeds(rows: &[(String, String, Vec<f32>)], seeds: &[String], mode: SeedMode) -> Option<Vec<(usize, f32)>> {
+    let seed_names: HashSet<&str> = seeds.iter().map(String::as_str).collect();
+    let (seed_rows, other_rows): (Vec<_>, Vec<_>) = rows.iter().enumerate().partition(|(_, (name, _, _))| seed_names.contains(name.as_str()));
+    if seed_rows.is_empty() {
+        return None;
+    }
+    let seed_rows: Vec<_> = seed_rows.into_iter().map(|(_, row)| row).collect();
+    let scored = match mode {
+        SeedMode::Centroid => {
+            let centroid = centroid(&seed_rows, seeds);
+            other_rows.into_iter()
+                .map(|(index, (_, _, embedding))| (index, cosine_similarity(&centroid, embedding)))
+                .collect()
+        }
+        SeedMode::Union => other_rows.into_iter().map(|(index, (_, _, embedding))| {
+            let score = seed_rows.iter()
+                .map(|(_, _, seed)| cosine_similarity(seed, embedding))
+                .fold(f32::MIN, f32::max);
+            (index, score)
+        }).collect(),
+    };
+    Some(scored)
+}
+
+/// Mean of the per-note mean vectors, so long notes with many chunks do not dominate
+fn centroid(seed_rows: &[&(String, String, Vec<f32>)], seeds: &[String]) -> Vec<f32> {
+    let dimensions = seed_rows[0].2.len();
+    let mut centroid = vec![0.0; dimensions];
+    let mut notes = 0;
+    for seed in seeds {
+        let chunks: Vec<&Vec<f32>> = seed_rows.iter().filter(|(name, _, _)| name == seed).map(|(_, _, embedding)| embedding).collect();
+        if chunks.is_empty() {
+            continue;
+        }
+        for chunk in &chunks {
+            for (total, value) in centroid.iter_mut().zip(chunk.iter()) {
+                *total += value / chunks.len() as f32;
+            }
+        }
+        notes += 1;
+    }
+    centroid.iter_mut().for_each(|value| *value /= notes as f32);
+    centroid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows() -> Vec<(String, String, Vec<f32>)> {
+        vec![
+            ("a.md".to_string(), "A1".to_string(), vec![1.0, 0.0, 0.0]),
+            ("a.md".to_string(), "A2".to_string(), vec![1.0, 0.0, 0.0]),
+            ("a.md".to_string(), "A3".to_string(), vec![1.0, 0.0, 0.0]),
+            ("b.md".to_string(), "B".to_string(), vec![0.0, 1.0, 0.0]),
+            ("between.md".to_string(), "Between".to_string(), vec![1.0, 1.0, 0.0]),
+            ("near-b.md".to_string(), "Near B".to_string(), vec![0.1, 1.0, 0.0]),
+            ("far.md".to_string(), "Far".to_string(), vec![0.0, 0.0, 1.0]),
+        ]
+    }
+
+    fn best(rows: &[(String, String, Vec<f32>)], scored: &[(usize, f32)]) -> String {
+        let (index, _) = scored.iter().fold(scored[0], |best, &row| if row.1 > best.1 { row } else { best });
+        rows[index].0.clone()
+    }
+
+    #[test]
+    fn centroid_weights_notes_equally() {
+        let seeds = vec!["a.md".to_string(), "b.md".to_string()];
+
+        let rows = rows();
+        let scored = score_against_seeds(&rows, &seeds, SeedMode::Centroid).unwrap();
+
+        assert!(scored.iter().all(|&(index, _)| !seeds.contains(&rows[index].0)));
+        assert_eq!(best(&rows, &scored), "between.md");
+    }
+
+    #[test]
+    fn union_keeps_neighbors_of_each_seed() {
+        let seeds = vec!["a.md".to_string(), "b.md".to_string()];
+
+        let rows = rows();
+        let scored = score_against_seeds(&rows, &seeds, SeedMode::Union).unwrap();
+
+        let score = |name: &str| scored.iter().find(|&&(index, _)| rows[index].0 == name).unwrap().1;
+        assert!(score("near-b.md") > 0.99);
+        assert!(score("far.md").abs() < 1e-6);
+    }
+
+    #[test]
+    fn unknown_seeds() {
+        assert!(score_against_seeds(&rows(), &["missing.md".to_string()], SeedMode::Centroid).is_none());
+    }
+
+    #[test]
+    fn only_seeds_stored() {
+        let seeds = vec!["a.md".to_string()];
+
+        assert_eq!(score_against_seeds(&rows()[..3], &seeds, SeedMode::Union), Some(vec![]));
+    }
+}
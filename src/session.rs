@@ -0,0 +1,259 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use js_sys::JsString;
+use log::debug;
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+use crate::FileProcessor;
+use crate::file_processor::FileFilter;
+use crate::QueryCommand;
+use crate::SemanticSearchError;
+use crate::Suggestions;
+use crate::embedding_file::EmbeddingFile;
+use crate::glob::PathPattern;
+use crate::input::NoteTimes;
+use crate::negative;
+use crate::obsidian::{self, TFile};
+use crate::quantization::QuantizedVector;
+use crate::settings::Settings;
+
+/// Filters applied to the stored chunks after scoring, so changing them never needs a new query embedding
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct QueryFilters {
+    /// Only keep notes under this folder, empty keeps everything
+    pub folder: String,
+    /// Only keep notes whose vault path matches this `PathPattern`, e.g. `Projects/**`, empty keeps everything
+    pub path: String,
+    /// Steers results away from chunks similar to this text, in place of a ` NOT ` in the query
+    pub exclude: String,
+    /// Only keep notes modified or created in a range, in ms since the epoch. Chunks whose note
+    /// times were not stored, such as those embedded before version 6 of the embedding file, are
+    /// left out when any of these is set.
+    pub modified_after: Option<f64>,
+    pub modified_before: Option<f64>,
+    pub created_after: Option<f64>,
+    pub created_before: Option<f64>,
+    /// Names of the files under `folder`, for chunks stored before their note's path was recorded
+    #[serde(skip)]
+    names: Option<HashSet<String>>,
+    #[serde(skip)]
+    pattern: Option<PathPattern>,
+    /// Names of the files matching `path`, for chunks without a recorded path
+    #[serde(skip)]
+    path_names: Option<HashSet<String>>,
+}
+
+impl QueryFilters {
+    pub fn from_js(value: &JsValue) -> Result<Self, SemanticSearchError> {
+        if value.is_undefined() || value.is_null() {
+            return Ok(Self::default());
+        }
+        serde_wasm_bindgen::from_value(value.clone())
+            .map_err(|e| SemanticSearchError::InvalidArgument(format!("Invalid query filters: {}", e)))
+    }
+
+    pub fn for_folder(folder: &str) -> Self {
+        Self { folder: folder.to_string(), ..Self::default() }
+    }
+
+    /// Looks up the files under `folder` and matching `path`, so chunks that only recorded their
+    /// note's file name can be matched by it
+    pub async fn resolve(&mut self, file_processor: &FileProcessor) -> Result<(), SemanticSearchError> {
+        let folder = self.folder.trim().trim_matches('/');
+        if !folder.is_empty() {
+            let files = file_processor.list_files(&FileFilter::in_folder(folder)).await;
+            self.names = Some(files.iter().map(TFile::name).collect());
+        }
+        if !self.path.trim().is_empty() {
+            let pattern = PathPattern::new(&self.path)?;
+            let files = file_processor.list_files(&FileFilter::default()).await;
+            self.path_names = Some(files.iter().filter(|file| pattern.matches(&file.path())).map(TFile::name).collect());
+            self.pattern = Some(pattern);
+        }
+        Ok(())
+    }
+
+    /// Whether the stored chunk at `index` of `store` is kept
+    pub fn keeps(&self, store: &EmbeddingFile, index: usize) -> bool {
+        let provenance = store.provenance.get(index).and_then(Option::as_ref);
+        let path = provenance.and_then(|provenance| provenance.path.as_deref());
+        self.matches(&store.rows[index].0, path) && self.matches_times(provenance.and_then(|provenance| provenance.note_times))
+    }
+
+    pub fn matches_times(&self, times: Option<NoteTimes>) -> bool {
+        if self.modified_after.is_none() && self.modified_before.is_none() && self.created_after.is_none() && self.created_before.is_none() {
+            return true;
+        }
+        times.is_some_and(|times| {
+            self.modified_after.is_none_or(|after| times.modified >= after)
+                && self.modified_before.is_none_or(|before| times.modified < before)
+                && self.created_after.is_none_or(|after| times.created >= after)
+                && self.created_before.is_none_or(|before| times.created < before)
+        })
+    }
+
+    /// Whether a chunk of the note named `name` is kept. Chunks that recorded their note's vault
+    /// `path` are matched by it; others by file name, which cannot tell notes with the same name apart.
+    pub fn matches(&self, name: &str, path: Option<&str>) -> bool {
+        let folder = self.folder.trim().trim_matches('/');
+        let under_folder = |path: &str| folder.is_empty() || path.strip_prefix(folder).is_some_and(|rest| rest.starts_with('/'));
+        match path {
+            Some(path) => under_folder(path) && self.pattern.as_ref().is_none_or(|pattern| pattern.matches(path)),
+            None => (under_folder(name) || self.names.as_ref().is_some_and(|names| names.contains(name)))
+                && self.pattern.as_ref().is_none_or(|pattern| pattern.matches(name) || self.path_names.as_ref().is_some_and(|names| names.contains(name))),
+        }
+    }
+}
+
+struct CachedQuery {
+    query: String,
+    model: String,
+    api_base: String,
+    expansion: Option<(String, usize)>,
+    exclude: Option<String>,
+    /// The query's embedding followed by those of its reformulations, if any
+    embeddings: Vec<Vec<f32>>,
+    exclude_embedding: Option<Vec<f32>>,
+}
+
+impl CachedQuery {
+    fn matches(&self, query: &str, exclude: &Option<String>, query_cmd: &QueryCommand) -> bool {
+        self.query == query && &self.exclude == exclude && self.model == query_cmd.client.model() && self.api_base == query_cmd.client.api_base()
+            && self.expansion == query_cmd.expansion
+    }
+}
+
+/// Searches made from one open query modal.
+///
+/// The last query's embeddings are kept so that re-running it with different
+/// filters only re-scores the stored chunks instead of calling the API again.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct QuerySession {
+    cached: RefCell<Option<CachedQuery>>,
+}
+
+#[wasm_bindgen]
+impl QuerySession {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> QuerySession {
+        Self::default()
+    }
+
+    /// Returns suggestions for `query` among the chunks matching `filters`
+    pub async fn search(&self, app: &obsidian::App, settings: JsValue, query: JsString, filters: JsValue) -> Result<JsValue, SemanticSearchError> {
+        let settings = Settings::from_js(&settings)?;
+        let mut filters = QueryFilters::from_js(&filters)?;
+        let query = query.as_string().unwrap_or_default();
+        let mut query_cmd = QueryCommand::new(app, &settings);
+        query_cmd.route_language(&negative::split_query(&query).0).await?;
+        filters.resolve(&query_cmd.file_processor).await?;
+
+        let (store, quantized) = query_cmd.load_comparable_store().await?;
+        let ranked_suggestions = self.search_store(&query_cmd, &store, quantized.as_deref(), &query, &filters).await?;
+        query_cmd.suggestions_value(&ranked_suggestions)
+    }
+}
+
+impl QuerySession {
+    /// Ranks the chunks of an already loaded store that match `filters` against `query`,
+    /// scoring with int8 copies of the stored vectors if `quantized` is given. Text after a
+    /// ` NOT ` in the query, or the `exclude` filter, is steered away from. `store` is the one
+    /// `query_cmd` was routed to for the query's language, see `QueryCommand::route_language`.
+    pub(crate) async fn search_store(&self, query_cmd: &QueryCommand, store: &EmbeddingFile, quantized: Option<&[QuantizedVector]>, query: &str, filters: &QueryFilters) -> Result<Vec<Suggestions>, SemanticSearchError> {
+        let (query, exclude) = negative::split_query(query);
+        let exclude = match filters.exclude.trim() {
+            "" => exclude,
+            filter => Some(filter.to_string()),
+        };
+        let (query_embeddings, exclude_embedding) = self.query_embeddings(query_cmd, &query, &exclude).await?;
+        let query_embeddings: Vec<Vec<f32>> = query_embeddings.into_iter().map(|query_embedding| store.fit_query(query_embedding)).collect();
+        let exclude_embedding = exclude_embedding.map(|embedding| store.fit_query(embedding));
+        for query_embedding in query_embeddings.iter().chain(&exclude_embedding) {
+            store.check_dimensions(query_embedding.len())?;
+        }
+        let candidates = query_cmd.rank(&query_embeddings, exclude_embedding.as_deref(), &store.rows, quantized, &|i| filters.keeps(store, i));
+        Ok(query_cmd.run_stages(Some(&query), candidates, store).await)
+    }
+
+    /// Embeddings of `query` and its reformulations, and of `exclude` if given
+    async fn query_embeddings(&self, query_cmd: &QueryCommand, query: &str, exclude: &Option<String>) -> Result<(Vec<Vec<f32>>, Option<Vec<f32>>), SemanticSearchError> {
+        if let Some(cached) = self.cached.borrow().as_ref().filter(|cached| cached.matches(query, exclude, query_cmd)) {
+            debug!("Reusing cached embeddings for query");
+            return Ok((cached.embeddings.clone(), cached.exclude_embedding.clone()));
+        }
+        let mut queries = query_cmd.expand_queries(vec![query.to_string()]).await;
+        queries.extend(exclude.clone());
+        let mut embeddings = query_cmd.client.embed_queries(&queries).await?;
+        let exclude_embedding = if exclude.is_some() { embeddings.pop() } else { None };
+        self.cached.replace(Some(CachedQuery {
+            query: query.to_string(),
+            model: query_cmd.client.model().to_string(),
+            api_base: query_cmd.client.api_base().to_string(),
+            expansion: query_cmd.expansion.clone(),
+            exclude: exclude.clone(),
+            embeddings: embeddings.clone(),
+            exclude_embedding: exclude_embedding.clone(),
+        }));
+        Ok((embeddings, exclude_embedding))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folder_filter() {
+        let filters = QueryFilters { folder: "/Projects/".to_string(), ..QueryFilters::default() };
+
+        assert!(filters.matches("Projects/plan.md", None));
+        assert!(filters.matches("Projects/2023/plan.md", None));
+        assert!(!filters.matches("ProjectsArchive/plan.md", None));
+        assert!(!filters.matches("plan.md", None));
+        assert!(QueryFilters::default().matches("plan.md", None));
+
+        let resolved = QueryFilters { folder: "Projects".to_string(), names: Some(HashSet::from(["plan.md".to_string()])), ..QueryFilters::default() };
+        assert!(resolved.matches("plan.md", None));
+        assert!(!resolved.matches("other.md", None));
+        // a recorded path tells apart notes with the same file name in other folders
+        assert!(resolved.matches("plan.md", Some("Projects/2023/plan.md")));
+        assert!(!resolved.matches("plan.md", Some("Archive/plan.md")));
+    }
+
+    #[test]
+    fn date_filter() {
+        let times = Some(NoteTimes { created: 100.0, modified: 200.0 });
+        let recent = QueryFilters { modified_after: Some(150.0), ..QueryFilters::default() };
+
+        assert!(recent.matches_times(times));
+        assert!(!recent.matches_times(None));
+        assert!(!QueryFilters { modified_after: Some(250.0), ..QueryFilters::default() }.matches_times(times));
+        assert!(!QueryFilters { created_before: Some(100.0), ..QueryFilters::default() }.matches_times(times));
+        assert!(QueryFilters { created_after: Some(50.0), created_before: Some(150.0), ..QueryFilters::default() }.matches_times(times));
+        assert!(QueryFilters::default().matches_times(None));
+    }
+
+    #[test]
+    fn path_filter() {
+        let filters = QueryFilters {
+            path: "Projects/**/*.md".to_string(),
+            pattern: Some(PathPattern::new("Projects/**/*.md").unwrap()),
+            path_names: Some(HashSet::from(["plan.md".to_string()])),
+            ..QueryFilters::default()
+        };
+
+        assert!(filters.matches("plan.md", None));
+        assert!(filters.matches("Projects/2023/budget.md", None));
+        assert!(!filters.matches("other.md", None));
+        assert!(filters.matches("plan.md", Some("Projects/plan.md")));
+        assert!(!filters.matches("plan.md", Some("Archive/plan.md")));
+
+        let both = QueryFilters { folder: "Archive".to_string(), ..filters };
+        assert!(!both.matches("plan.md", None));
+        assert!(!both.matches("plan.md", Some("Projects/plan.md")));
+    }
+}
@@ -0,0 +1,484 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+
+use crate::SemanticSearchError;
+use crate::chunking::Granularity;
+use crate::file_processor::storage_path;
+use crate::stores::store_file_name;
+use crate::fusion::ScoreNormalization;
+use crate::input::{EmbedContent, EmbedText};
+use crate::language::{self, LanguageRoute};
+use crate::logging::LogLevel;
+use crate::provenance::fingerprint;
+use crate::providers::{self, CustomProvider, EmbeddingProvider, ProviderRegistry};
+use crate::quantization::Quantization;
+use crate::ranking::{self, FileGrouping, Stage};
+use crate::rerank::RerankFormat;
+
+/// Typed view of the plugin's `semanticSearchSettings`, deserialized once per command.
+///
+/// Missing fields fall back to the same defaults as `DEFAULT_SETTINGS` in main.ts.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    /// Name of a built-in or custom provider
+    pub embedding_provider: String,
+    /// OpenAI-compatible endpoints to offer alongside the built-in providers
+    #[serde(deserialize_with = "providers::deserialize_custom_providers")]
+    pub custom_providers: Vec<CustomProvider>,
+    pub api_key: String,
+    pub api_base: String,
+    /// Sent as the `OpenAI-Organization` header when set
+    pub organization_id: String,
+    /// Sent as the `OpenAI-Project` header when set
+    pub project_id: String,
+    pub model: String,
+    /// One `language model` pair per line, see `language::parse_language_models`
+    pub language_models: String,
+    pub ignored_folders: String,
+    pub section_delimeter_regex: String,
+    /// Non-markdown file types to embed, e.g. "pdf, txt, org"
+    pub attachment_types: String,
+    pub strip_code_blocks: bool,
+    pub strip_inline_code: bool,
+    pub strip_math: bool,
+    pub strip_dataview: bool,
+    /// Sections with fewer characters than this, ignoring whitespace, are not embedded
+    pub min_section_chars: usize,
+    /// Split sections longer than this many words into overlapping chunks, 0 keeps whole sections
+    pub chunk_size: usize,
+    /// Words repeated at the start of each chunk from the end of the previous one
+    pub chunk_overlap: usize,
+    /// Whether markdown notes are embedded whole, per section or per paragraph
+    pub embedding_granularity: Granularity,
+    /// Vault relative folder for input.csv and the embedding file, empty for the vault root
+    pub storage_folder: String,
+    pub embedding_file_name: String,
+    /// Named embedding store to build and query, empty for the default store
+    pub embedding_store: String,
+    /// Forces records into this many batches before the token and input caps apply, 0 to
+    /// size batches by those caps alone
+    pub num_batches: u32,
+    /// Embedding requests sent per minute at most, 0 for no limit
+    pub requests_per_minute: usize,
+    /// Tokens embedded per minute at most, 0 for no limit
+    pub tokens_per_minute: usize,
+    /// Batches are split so no request exceeds this many tokens, 0 disables the cap
+    pub max_tokens_per_request: usize,
+    /// Records longer than this are truncated before embedding, 0 disables truncation
+    pub max_tokens_per_record: usize,
+    /// USD a single embedding run may cost at most, as estimated before sending anything, 0 for no cap
+    pub max_cost_per_run: f32,
+    /// Seconds an API request may take before it is abandoned, 0 for no timeout
+    pub request_timeout: u32,
+    /// Minutes an embedding run may take before it stops sending batches, 0 for no deadline
+    pub run_deadline: u32,
+    /// Submit Generate Embeddings through the OpenAI batch API instead of embedding right away
+    pub use_batch_api: bool,
+    pub embed_content: EmbedContent,
+    /// Template wrapping each section with its note title and headings before embedding, e.g. "{path}: {text}"
+    pub embed_template: String,
+    /// Store vectors as int8 to shrink the embedding file and speed up searching
+    pub embedding_quantization: Quantization,
+    /// Truncate stored vectors to this many dimensions for models trained to allow it, 0 keeps them whole
+    pub embedding_dimensions: usize,
+    pub score_normalization: ScoreNormalization,
+    /// Cut results at the largest score gap instead of always returning ten
+    pub adaptive_result_count: bool,
+    /// Re-rank results with Maximal Marginal Relevance so they span distinct notes
+    pub enable_mmr: bool,
+    /// Trade-off between relevance (1.0) and diversity (0.0) when MMR is enabled
+    pub mmr_lambda: f32,
+    /// Return one suggestion per note instead of one per chunk
+    pub group_by_file: FileGrouping,
+    /// One `#tag weight` pair per line, see `ranking::parse_tag_boosts`
+    pub tag_boosts: String,
+    /// Days over which a note's score halves since it was last modified, 0 disables recency decay
+    pub recency_half_life_days: f64,
+    /// Return an excerpt of each result's text around the sentence that best matches the query
+    pub show_snippets: bool,
+    /// Search the readable rows of a partly corrupted input.csv or embedding file instead of failing
+    pub skip_malformed_rows: bool,
+    /// Encrypt the embedding files as they are written, with `encryption_passphrase`
+    pub encrypt_embeddings: bool,
+    /// Passphrase encrypted embedding files are read and written with. Kept on this device
+    /// rather than in the synced plugin data.
+    pub encryption_passphrase: String,
+    /// Chat model used to reformulate queries before searching, empty disables query expansion
+    pub query_expansion_model: String,
+    /// Number of reformulations searched alongside the original query
+    pub query_expansion_count: usize,
+    /// Chat model that answers questions from the notes, empty disables answering
+    pub answer_model: String,
+    /// Most tokens of note text sent to the answer model with a question
+    pub answer_context_tokens: usize,
+    /// Reorder the top candidates with a reranking endpoint before returning them
+    pub enable_rerank: bool,
+    pub rerank_format: RerankFormat,
+    /// Reranking API base URL, empty to use `api_base`
+    pub rerank_api_base: String,
+    /// Reranking API key, empty to use `api_key`
+    pub rerank_api_key: String,
+    pub rerank_model: String,
+    pub enable_link_recommendation_suggestor: bool,
+    /// Re-embed notes in the background as they are created, edited, renamed or deleted
+    pub auto_index: bool,
+    /// Seconds without further changes before changed notes are re-embedded
+    pub auto_index_delay: u32,
+    /// Leave auto-index updates to `auto_index_tick`, which the plugin calls when Obsidian is idle
+    pub idle_indexing: bool,
+    pub log_level: LogLevel,
+    /// Also write log lines to `semantic-search.log` in the storage folder
+    pub log_to_file: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            embedding_provider: providers::DEFAULT_PROVIDER.to_string(),
+            custom_providers: Vec::new(),
+            api_key: String::new(),
+            api_base: crate::API_BASE.to_string(),
+            organization_id: String::new(),
+            project_id: String::new(),
+            model: crate::pricing::DEFAULT_MODEL.to_string(),
+            language_models: String::new(),
+            ignored_folders: String::new(),
+            section_delimeter_regex: ".".to_string(),
+            attachment_types: String::new(),
+            strip_code_blocks: false,
+            strip_inline_code: false,
+            strip_math: false,
+            strip_dataview: false,
+            min_section_chars: 1,
+            chunk_size: 0,
+            chunk_overlap: 0,
+            embedding_granularity: Granularity::default(),
+            storage_folder: String::new(),
+            embedding_file_name: crate::EMBEDDING_FILE_NAME.to_string(),
+            embedding_store: String::new(),
+            num_batches: 0,
+            requests_per_minute: 0,
+            tokens_per_minute: 0,
+            max_tokens_per_request: 250_000,
+            max_tokens_per_record: 8191,
+            max_cost_per_run: 1.0,
+            request_timeout: 60,
+            run_deadline: 0,
+            use_batch_api: false,
+            embed_content: EmbedContent::default(),
+            embed_template: String::new(),
+            embedding_quantization: Quantization::default(),
+            embedding_dimensions: 0,
+            score_normalization: ScoreNormalization::default(),
+            adaptive_result_count: false,
+            enable_mmr: false,
+            mmr_lambda: 0.7,
+            group_by_file: FileGrouping::default(),
+            tag_boosts: String::new(),
+            recency_half_life_days: 0.0,
+            show_snippets: true,
+            skip_malformed_rows: true,
+            encrypt_embeddings: false,
+            encryption_passphrase: String::new(),
+            query_expansion_model: String::new(),
+            query_expansion_count: 3,
+            answer_model: String::new(),
+            answer_context_tokens: 3000,
+            enable_rerank: false,
+            rerank_format: RerankFormat::default(),
+            rerank_api_base: String::new(),
+            rerank_api_key: String::new(),
+            rerank_model: "rerank-v3.5".to_string(),
+            enable_link_recommendation_suggestor: false,
+            auto_index: false,
+            auto_index_delay: 30,
+            idle_indexing: false,
+            log_level: LogLevel::default(),
+            log_to_file: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn from_js(value: &JsValue) -> Result<Self, SemanticSearchError> {
+        if value.is_undefined() || value.is_null() {
+            return Ok(Self::default());
+        }
+        let settings: Self = serde_wasm_bindgen::from_value(value.clone())
+            .map_err(|e| SemanticSearchError::InvalidSettings(e.to_string()))?;
+        settings.provider()?;
+        Ok(settings)
+    }
+
+    /// The provider named by `embedding_provider`
+    pub fn provider(&self) -> Result<Rc<dyn EmbeddingProvider>, SemanticSearchError> {
+        ProviderRegistry::with_custom(&self.custom_providers)?.resolve(&self.embedding_provider)
+    }
+
+    /// Everything that would make commands fail or misbehave with these settings, so the
+    /// settings tab can point at it before anything is run
+    pub fn problems(&self) -> Vec<SettingsProblem> {
+        let mut problems = Vec::new();
+        let mut problem = |setting: &'static str, message: String| problems.push(SettingsProblem { setting, message });
+
+        let provider = match ProviderRegistry::with_custom(&self.custom_providers).and_then(|registry| registry.resolve(&self.embedding_provider)) {
+            Ok(provider) => Some(provider),
+            Err(e) => {
+                problem("embeddingProvider", format!("{}. Pick one of the listed providers.", e));
+                None
+            },
+        };
+        let offline = matches!(&provider, Some(provider) if provider.is_offline());
+        if self.api_key.trim().is_empty() && !offline {
+            problem("apiKey", "No API key is set, so requests to the embedding provider will be refused. Paste the key from your provider's dashboard.".to_string());
+        }
+        if let Some(message) = url_problem(&self.api_base) {
+            problem("apiBase", message);
+        }
+        if let Some(provider) = &provider {
+            let model = self.model.trim();
+            let models = provider.models();
+            let priced = provider.dollars_per_million_tokens(model).is_some();
+            if !model.is_empty() && !models.is_empty() && !models.iter().any(|known| known == model) && !priced {
+                problem("model", format!("'{}' is not a model {} is known to serve ({}), so requests may fail and cost estimates assume {} prices.",
+                    model, provider.label(), models.join(", "), crate::pricing::DEFAULT_MODEL));
+            }
+            if self.embedding_dimensions > 0 && !crate::matryoshka::supports_truncation(model) {
+                problem("embeddingDimensions", format!("'{}' is not known to support truncated embeddings, so searches may get worse. Set this to 0 or use a model such as text-embedding-3-small.", model));
+            }
+        }
+        if let Some(error) = language::parse_language_models(&self.language_models).1.into_iter().next() {
+            problem("languageModels", error);
+        }
+        if let Err(e) = Regex::new(&self.section_delimeter_regex) {
+            problem("sectionDelimeterRegex", format!("Not a valid regular expression: {}", e));
+        }
+        if self.chunk_size > 0 && self.chunk_overlap >= self.chunk_size {
+            problem("chunkOverlap", format!("Must be less than the chunk size of {} words, or chunks never advance.", self.chunk_size));
+        }
+        if let Some(error) = ranking::parse_tag_boosts(&self.tag_boosts).1.into_iter().next() {
+            problem("tagBoosts", error);
+        }
+        if !(self.recency_half_life_days >= 0.0 && self.recency_half_life_days.is_finite()) {
+            problem("recencyHalfLifeDays", "Must be a number of days, or 0 to rank notes regardless of age.".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.mmr_lambda) {
+            problem("mmrLambda", "Must be between 0 (most diverse) and 1 (most relevant).".to_string());
+        }
+        if self.enable_rerank {
+            if self.rerank_model.trim().is_empty() {
+                problem("rerankModel", "Reranking is enabled but no model is set.".to_string());
+            }
+            if let Some(message) = url_problem(&self.rerank_api_base) {
+                problem("rerankApiBase", message);
+            }
+            if crate::rerank::needs_own_key(self) {
+                problem("rerankApiKey", "Reranking uses a different base URL from the embedding API, which is not sent the API key. Reranking is off until a reranking API key is set.".to_string());
+            }
+        }
+        if self.encrypt_embeddings && self.encryption_passphrase.is_empty() {
+            problem("encryptionPassphrase", "Encryption is turned on but no passphrase is set, so embedding files are written unencrypted.".to_string());
+        }
+        problems
+    }
+
+    pub fn input_path(&self) -> String {
+        storage_path(&self.storage_folder, crate::INPUT_FILE_NAME)
+    }
+
+    pub fn manifest_path(&self) -> String {
+        storage_path(&self.storage_folder, crate::manifest::MANIFEST_FILE_NAME)
+    }
+
+    pub fn index_queue_path(&self) -> String {
+        storage_path(&self.storage_folder, crate::index_queue::INDEX_QUEUE_FILE_NAME)
+    }
+
+    pub fn batch_job_path(&self) -> String {
+        storage_path(&self.storage_folder, crate::batch_api::BATCH_JOB_FILE_NAME)
+    }
+
+    pub fn log_file_path(&self) -> String {
+        storage_path(&self.storage_folder, crate::logging::LOG_FILE_NAME)
+    }
+
+    pub fn store_registry_path(&self) -> String {
+        storage_path(&self.storage_folder, crate::stores::STORE_REGISTRY_FILE_NAME)
+    }
+
+    pub fn search_history_path(&self) -> String {
+        storage_path(&self.storage_folder, crate::history::SEARCH_HISTORY_FILE_NAME)
+    }
+
+    pub fn embedding_path(&self) -> String {
+        let file_name = match self.embedding_file_name.trim() {
+            "" => crate::EMBEDDING_FILE_NAME,
+            name => name,
+        };
+        storage_path(&self.storage_folder, &store_file_name(file_name, &self.embedding_store))
+    }
+
+    /// Languages embedded with their own model, each into its own store next to the embedding file
+    pub fn language_routes(&self) -> Vec<LanguageRoute> {
+        language::parse_language_models(&self.language_models).0
+    }
+
+    /// The store holding the records in `language` when it is embedded with its own model
+    pub fn language_embedding_path(&self, language: &str) -> String {
+        store_file_name(&self.embedding_path(), language)
+    }
+
+    /// These settings, embedding with `model` instead
+    pub fn with_model(&self, model: &str) -> Settings {
+        Settings { model: model.to_string(), ..self.clone() }
+    }
+
+    /// The steps a query's vector ranking goes through before results are shown.
+    ///
+    /// Boosts adjust the vector scores, which grouping and diversifying then work on. The
+    /// reranker reorders the diversified results, so it sees boosted candidates and its order is
+    /// kept, and the results are cut last.
+    pub fn ranking_stages(&self) -> Vec<Stage> {
+        let mut stages = Vec::new();
+        let tag_boosts = ranking::parse_tag_boosts(&self.tag_boosts).0;
+        let recency_half_life_days = Some(self.recency_half_life_days).filter(|days| *days > 0.0);
+        if !tag_boosts.is_empty() || recency_half_life_days.is_some() {
+            stages.push(Stage::Boost { tag_boosts, recency_half_life_days });
+        }
+        stages.push(Stage::GroupByFile(self.group_by_file));
+        if self.enable_mmr {
+            stages.push(Stage::Diversify { lambda: self.mmr_lambda.clamp(0.0, 1.0), count: crate::NUM_SUGGESTIONS, pool_size: crate::MMR_POOL_SIZE });
+        }
+        if self.enable_rerank {
+            stages.push(Stage::Rerank);
+        }
+        stages.push(Stage::Truncate { max_results: crate::NUM_SUGGESTIONS, adaptive: self.adaptive_result_count });
+        stages
+    }
+
+    pub fn embed_text(&self) -> EmbedText {
+        EmbedText { content: self.embed_content, template: self.embed_template.clone() }
+    }
+
+    /// Fingerprint of the settings that decide what text gets embedded, recorded with each
+    /// stored record so records embedded under different settings can be told apart
+    pub fn embedding_settings_hash(&self) -> String {
+        fingerprint(&format!("{:?}|{}|{}|{}{}{}{}|{:?}|{}|{}/{}|{:?}|{:?}|{}|{}",
+            self.section_delimeter_regex, self.ignored_folders, self.attachment_types,
+            self.strip_code_blocks, self.strip_inline_code, self.strip_math, self.strip_dataview,
+            self.embedding_granularity, self.min_section_chars, self.chunk_size, self.chunk_overlap,
+            self.embed_content, self.embed_template, self.max_tokens_per_record, self.max_tokens_per_request))
+    }
+}
+
+/// A setting that needs fixing, keyed by its name in `semanticSearchSettings`
+#[derive(Debug, Serialize, PartialEq)]
+pub struct SettingsProblem {
+    pub setting: &'static str,
+    pub message: String,
+}
+
+/// Why `url` cannot be used as an API base, if it cannot. Empty falls back to a default.
+fn url_problem(url: &str) -> Option<String> {
+    let url = url.trim();
+    if url.is_empty() {
+        return None;
+    }
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => None,
+        Ok(parsed) => Some(format!("'{}' uses {}, but only http and https are supported.", url, parsed.scheme())),
+        Err(e) => Some(format!("'{}' is not a valid URL ({}). It should look like https://api.openai.com/v1", url, e)),
+    }
+}
+
+/// Returns every `SettingsProblem` with `settings`, as `{ setting, message }`. Settings that
+/// cannot be read at all are reported as a single problem rather than an error.
+#[wasm_bindgen]
+pub fn validate_settings(settings: JsValue) -> Vec<JsValue> {
+    let problems = match serde_wasm_bindgen::from_value::<Settings>(settings) {
+        Ok(settings) => settings.problems(),
+        Err(e) => {
+            let message = e.to_string();
+            let setting = if message.contains("customProviders") { "customProviders" } else { "" };
+            vec![SettingsProblem { setting, message }]
+        },
+    };
+    problems.iter().filter_map(|problem| serde_wasm_bindgen::to_value(problem).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_partial() {
+        let settings: Settings = serde_json::from_str(r#"{"apiKey": "sk-test", "numBatches": 4}"#).unwrap();
+
+        assert_eq!(settings.api_key, "sk-test");
+        assert_eq!(settings.num_batches, 4);
+        assert_eq!(settings.section_delimeter_regex, ".");
+        assert!(!settings.enable_link_recommendation_suggestor);
+    }
+
+    #[test]
+    fn storage_paths() {
+        let settings: Settings = serde_json::from_str(r#"{"storageFolder": ".obsidian/plugins/semantic-search/", "embeddingFileName": ""}"#).unwrap();
+
+        assert_eq!(settings.input_path(), ".obsidian/plugins/semantic-search/input.csv");
+        assert_eq!(settings.embedding_path(), ".obsidian/plugins/semantic-search/embedding.csv");
+        assert_eq!(Settings::default().embedding_path(), "embedding.csv");
+        let work = Settings { embedding_store: "work".to_string(), ..settings };
+        assert_eq!(work.embedding_path(), ".obsidian/plugins/semantic-search/embedding-work.csv");
+        assert_eq!(work.language_embedding_path("ja"), ".obsidian/plugins/semantic-search/embedding-work-ja.csv");
+    }
+
+    #[test]
+    fn problems() {
+        let settings: Settings = serde_json::from_str(r#"{"apiKey": "sk-test", "apiBase": "api.openai.com", "model": "text-embedding-3-small", "sectionDelimeterRegex": "(", "chunkSize": 100, "chunkOverlap": 100, "recencyHalfLifeDays": -7, "languageModels": "klingon model"}"#).unwrap();
+
+        let problems: Vec<&str> = settings.problems().iter().map(|problem| problem.setting).collect();
+
+        assert_eq!(problems, vec!["apiBase", "languageModels", "sectionDelimeterRegex", "chunkOverlap", "recencyHalfLifeDays"]);
+        let valid = Settings { api_key: "sk-test".to_string(), ..Settings::default() };
+        assert!(valid.problems().is_empty(), "{:?}", valid.problems());
+    }
+
+    #[test]
+    fn unknown_models_and_providers() {
+        let settings = Settings { api_key: "sk-test".to_string(), embedding_provider: "voyage".to_string(), model: "voyage-9".to_string(), ..Settings::default() };
+        let problems = settings.problems();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].setting, "model");
+        assert!(problems[0].message.contains("voyage-3, voyage-3-lite"), "{}", problems[0].message);
+
+        let missing = Settings { api_key: "sk-test".to_string(), embedding_provider: "azure".to_string(), ..Settings::default() };
+        assert_eq!(missing.problems()[0].setting, "embeddingProvider");
+    }
+
+    #[test]
+    fn ranking_stages() {
+        let plain = Settings::default().ranking_stages();
+        assert_eq!(plain, vec![Stage::GroupByFile(FileGrouping::Off), Stage::Truncate { max_results: crate::NUM_SUGGESTIONS, adaptive: false }]);
+
+        let settings = Settings { enable_rerank: true, enable_mmr: true, mmr_lambda: 1.5, recency_half_life_days: 30.0, group_by_file: FileGrouping::Max, ..Settings::default() };
+        let stages = settings.ranking_stages();
+        assert_eq!(stages.len(), 5);
+        assert_eq!(stages[0], Stage::Boost { tag_boosts: Vec::new(), recency_half_life_days: Some(30.0) });
+        assert_eq!(stages[2], Stage::Diversify { lambda: 1.0, count: crate::NUM_SUGGESTIONS, pool_size: crate::MMR_POOL_SIZE });
+        assert_eq!(stages[3], Stage::Rerank);
+    }
+
+    #[test]
+    fn settings_hash_ignores_search_settings() {
+        let settings = Settings::default();
+        let diversified = Settings { enable_mmr: true, ..settings.clone() };
+        let truncated = Settings { max_tokens_per_record: 512, ..settings.clone() };
+
+        assert_eq!(settings.embedding_settings_hash(), diversified.embedding_settings_hash());
+        assert_ne!(settings.embedding_settings_hash(), truncated.embedding_settings_hash());
+    }
+}
@@ -1,20 +1,123 @@
 import SemanticSearch from "main";
-import { App, PluginSettingTab, Setting, TextComponent } from "obsidian";
+import { App, Notice, PluginSettingTab, Setting, TextComponent } from "obsidian";
+import { noticeForError } from "src/ui/errors";
+import { renderIndexStats } from "src/ui/indexStats";
+import { renderRunReport } from "src/ui/runReport";
+
+import * as plugin from "../../pkg/obsidian_rust_plugin.js";
 
 export interface semanticSearchSettings {
+  embeddingProvider: string;
+  customProviders: string;
 	apiKey: string;
+  apiBase: string;
+  organizationId: string;
+  projectId: string;
+  model: string;
+  languageModels: string;
   ignoredFolders: string;
   sectionDelimeterRegex: string;
+  attachmentTypes: string;
+  stripCodeBlocks: boolean;
+  stripInlineCode: boolean;
+  stripMath: boolean;
+  stripDataview: boolean;
+  minSectionChars: number;
+  chunkSize: number;
+  chunkOverlap: number;
+  embeddingGranularity: 'note' | 'heading' | 'paragraph';
+  storageFolder: string;
+  embeddingFileName: string;
+  embeddingStore: string;
   numBatches: number;
+  requestsPerMinute: number;
+  tokensPerMinute: number;
+  maxTokensPerRequest: number;
+  maxTokensPerRecord: number;
+  maxCostPerRun: number;
+  requestTimeout: number;
+  runDeadline: number;
+  useBatchApi: boolean;
+  embedContent: 'heading' | 'body' | 'headingAndBody';
+  embedTemplate: string;
+  embeddingQuantization: 'none' | 'int8';
+  embeddingDimensions: number;
+  scoreNormalization: 'none' | 'minMax' | 'zScore';
+  adaptiveResultCount: boolean;
+  enableMmr: boolean;
+  mmrLambda: number;
+  groupByFile: 'off' | 'max' | 'mean';
+  tagBoosts: string;
+  recencyHalfLifeDays: number;
+  showSnippets: boolean;
+  skipMalformedRows: boolean;
+  encryptEmbeddings: boolean;
+  // kept in this device's local storage rather than the synced plugin data
+  encryptionPassphrase: string;
+  queryExpansionModel: string;
+  queryExpansionCount: number;
+  answerModel: string;
+  answerContextTokens: number;
+  enableRerank: boolean;
+  rerankFormat: 'cohere' | 'score';
+  rerankApiBase: string;
+  rerankApiKey: string;
+  rerankModel: string;
   enableLinkRecommendationSuggestor: boolean;
+  autoIndex: boolean;
+  autoIndexDelay: number;
+  idleIndexing: boolean;
+  logLevel: 'off' | 'error' | 'warn' | 'info' | 'debug' | 'trace';
+  logToFile: boolean;
 }
 
+/// Names of the rows `validate_settings` can report problems for
+const SETTING_NAMES: Partial<Record<keyof semanticSearchSettings, string>> = {
+	embeddingProvider: 'Embedding provider',
+	customProviders: 'Custom providers',
+	apiKey: 'API Key',
+	apiBase: 'API Base URL',
+	model: 'Embedding model',
+	languageModels: 'Language models',
+	sectionDelimeterRegex: 'Section Header Delimeter Regex',
+	chunkOverlap: 'Chunk overlap',
+	embeddingDimensions: 'Embedding dimensions',
+	mmrLambda: 'Diversity trade-off',
+	tagBoosts: 'Tag boosts',
+	recencyHalfLifeDays: 'Recency half-life',
+	rerankApiBase: 'Reranking base URL',
+	rerankApiKey: 'Reranking API key',
+	rerankModel: 'Reranking model',
+	encryptionPassphrase: 'Encryption passphrase',
+};
+
 export class SemanticSearchSettingTab extends PluginSettingTab {
 	plugin: SemanticSearch;
 
 	constructor(app: App, plugin: SemanticSearch) {
 		super(app, plugin);
 		this.plugin = plugin;
+		this.containerEl.addEventListener('change', () => this.showProblems());
+	}
+
+	/// Shows each problem `validate_settings` finds under the row it is about
+	showProblems(): void {
+		const {containerEl} = this;
+		containerEl.querySelectorAll('.ss-setting-error').forEach(el => el.remove());
+
+		const problems: {setting: keyof semanticSearchSettings | '', message: string}[] = plugin.validate_settings(this.plugin.settings);
+		const rows = Array.from(containerEl.querySelectorAll('.setting-item'));
+		for (const problem of problems) {
+			const name = problem.setting && SETTING_NAMES[problem.setting];
+			const row = rows.find(row => row.querySelector('.setting-item-name')?.textContent === name);
+			const info = row?.querySelector('.setting-item-info');
+			if (info instanceof HTMLElement) {
+				info.createDiv({cls: 'ss-setting-error', text: problem.message});
+			} else {
+				const el = containerEl.createDiv({cls: 'ss-setting-error', text: problem.message});
+				containerEl.insertAfter(el, containerEl.querySelector('h2'));
+			}
+		}
 	}
 
 	display(): void {
@@ -24,9 +127,44 @@ export class SemanticSearchSettingTab extends PluginSettingTab {
 
 		containerEl.createEl('h2', {text: 'Obsidian Semantic Search'});
 
+		let providers: {name: string, label: string, apiBase: string, models: string[]}[] = [];
+		try {
+			providers = plugin.list_providers(this.plugin.settings.customProviders);
+		} catch (error) {
+			console.error(error);
+		}
+		new Setting(containerEl)
+			.setName('Embedding provider')
+			.setDesc('Service notes and queries are embedded with. Voyage and Jina are told whether a text is a note or a query, which improves retrieval. Picking a provider fills in its API base URL and default model. The offline mock provider needs no API key, for trying the plugin out. Changing the provider requires regenerating embeddings.')
+			.addDropdown(dropdown => dropdown
+				.addOptions(Object.fromEntries(providers.map(provider => [provider.name, provider.label])))
+				.setValue(this.plugin.settings.embeddingProvider)
+				.onChange(async (value) => {
+					const provider = providers.find(provider => provider.name === value);
+					this.plugin.settings.embeddingProvider = value;
+					this.plugin.settings.apiBase = provider?.apiBase ?? '';
+					this.plugin.settings.model = provider?.models[0] ?? '';
+					await this.plugin.saveSettings();
+					this.display();
+				}));
+
+		new Setting(containerEl)
+			.setName('Custom providers')
+			.setDesc('OpenAI-compatible endpoints to add to the providers above, as a JSON array. Each needs a name and apiBase, and may set label, embeddingsPath, models, authHeader, headers, inputTypeField, documentInputType, queryInputType and dollarsPerMillionTokens. See the README for an example.')
+			.addTextArea(text => {
+				text
+					.setPlaceholder('[{"name": "local", "apiBase": "http://localhost:8080/v1", "models": ["nomic-embed-text"]}]')
+					.setValue(this.plugin.settings.customProviders)
+					.onChange(async (value) => {
+						this.plugin.settings.customProviders = value;
+						await this.plugin.saveSettings();
+					});
+				text.inputEl.addEventListener('blur', () => this.display());
+			});
+
 		new Setting(containerEl)
-			.setName('OpenAI API Key')
-			.setDesc('https://platform.openai.com/account/api-keys')
+			.setName('API Key')
+			.setDesc('Key for the embedding provider, e.g. from https://platform.openai.com/account/api-keys')
 			.addText(text => text
 				.setPlaceholder('Enter your secret')
 				.setValue(this.plugin.settings.apiKey)
@@ -35,6 +173,77 @@ export class SemanticSearchSettingTab extends PluginSettingTab {
 					await this.plugin.saveSettings();
 				}));
 
+		new Setting(containerEl)
+			.setName('API Base URL')
+			.setDesc('Base URL of the OpenAI-compatible embeddings API.')
+			.addText(text => text
+				.setValue(this.plugin.settings.apiBase)
+				.onChange(async (value) => {
+					this.plugin.settings.apiBase = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Organization ID')
+			.setDesc('Sent as the OpenAI-Organization header so usage is billed to this organization. Leave empty to use the key\'s default organization.')
+			.addText(text => text
+				.setPlaceholder('org-...')
+				.setValue(this.plugin.settings.organizationId)
+				.onChange(async (value) => {
+					this.plugin.settings.organizationId = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Project ID')
+			.setDesc('Sent as the OpenAI-Project header so usage is attributed to this project. Leave empty to use the key\'s default project.')
+			.addText(text => text
+				.setPlaceholder('proj_...')
+				.setValue(this.plugin.settings.projectId)
+				.onChange(async (value) => {
+					this.plugin.settings.projectId = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Embedding model')
+			.setDesc('Model used to embed notes and queries. Changing the model requires regenerating embeddings.')
+			.addText(text => text
+				.setValue(this.plugin.settings.model)
+				.onChange(async (value) => {
+					this.plugin.settings.model = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Language models')
+			.setDesc('Embed notes in some languages with a model suited to them, one language code and model per line, e.g. "ja intfloat/multilingual-e5-large". Each language gets its own store next to the embedding file, and queries in that language search it. Detected languages: en, de, fr, es, it, pt, nl, ru, uk, el, ar, he, hi, th, ko, ja, zh.')
+			.addTextArea(text => text
+				.setPlaceholder('ja intfloat/multilingual-e5-large')
+				.setValue(this.plugin.settings.languageModels)
+				.onChange(async (value) => {
+					this.plugin.settings.languageModels = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Test connection')
+			.setDesc('Check that the API key and base URL work before generating embeddings.')
+			.addButton(button => button
+				.setButtonText('Test')
+				.onClick(async () => {
+					try {
+						const result = await plugin.test_api_connection(this.plugin.settings);
+						if (result.success) {
+							new Notice(`Connected to ${result.api_base} in ${Math.round(result.latency_ms)}ms. ${result.models.length} models available.`);
+						} else {
+							noticeForError(result.error, "Connection failed");
+						}
+					} catch (error) {
+						noticeForError(error, "Connection failed");
+					}
+				}));
+
     const presetRegexes: Record<string, string> = {
       ".": "Match every line",
       "^#{1,6} ": "Match every heading",
@@ -80,19 +289,519 @@ export class SemanticSearchSettingTab extends PluginSettingTab {
 					await this.plugin.saveSettings();
 				}));
 
+		new Setting(containerEl)
+			.setName('Attachment types')
+			.setDesc('Other file types to embed alongside notes, separated by commas. Supported: pdf, txt, org. PDFs are split into one section per page.')
+			.addText(text => text
+				.setPlaceholder('pdf, txt, org')
+				.setValue(this.plugin.settings.attachmentTypes)
+				.onChange(async (value) => {
+					this.plugin.settings.attachmentTypes = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Strip code blocks')
+			.setDesc('Remove fenced code blocks from notes before embedding.')
+			.addToggle(toggle => toggle
+				.setValue(this.plugin.settings.stripCodeBlocks)
+				.onChange(async (value) => {
+					this.plugin.settings.stripCodeBlocks = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Strip inline code')
+			.setDesc('Remove `inline code` from notes before embedding.')
+			.addToggle(toggle => toggle
+				.setValue(this.plugin.settings.stripInlineCode)
+				.onChange(async (value) => {
+					this.plugin.settings.stripInlineCode = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Strip math')
+			.setDesc('Remove $inline$ and $$display$$ LaTeX math from notes before embedding.')
+			.addToggle(toggle => toggle
+				.setValue(this.plugin.settings.stripMath)
+				.onChange(async (value) => {
+					this.plugin.settings.stripMath = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Strip dataview queries')
+			.setDesc('Remove dataview and dataviewjs blocks and inline `= queries` from notes before embedding.')
+			.addToggle(toggle => toggle
+				.setValue(this.plugin.settings.stripDataview)
+				.onChange(async (value) => {
+					this.plugin.settings.stripDataview = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Minimum section length')
+			.setDesc('Sections with fewer characters than this, not counting whitespace, are left out of input.csv instead of being embedded. The default of 1 only skips empty sections.')
+			.addText(text => text
+				.setValue(String(this.plugin.settings.minSectionChars))
+				.onChange(async (value) => {
+					const parsed = parseInt(value);
+					this.plugin.settings.minSectionChars = isNaN(parsed) ? 1 : parsed;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Embedding granularity')
+			.setDesc('How much of a note each embedding covers. Searches over whole-note embeddings always return one result per note. Run Generate Input again after changing it.')
+			.addDropdown(dropdown => dropdown
+				.addOptions({
+					note: 'Whole note',
+					heading: 'Section',
+					paragraph: 'Paragraph',
+				})
+				.setValue(this.plugin.settings.embeddingGranularity)
+				.onChange(async (value: semanticSearchSettings['embeddingGranularity']) => {
+					this.plugin.settings.embeddingGranularity = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Chunk size')
+			.setDesc('Split sections longer than this many words into overlapping chunks, so long sections are searchable passage by passage and results open at the matching lines. Set to 0 to keep whole sections. Run Generate Input again after changing it.')
+			.addText(text => text
+				.setValue(String(this.plugin.settings.chunkSize))
+				.onChange(async (value) => {
+					const parsed = parseInt(value);
+					this.plugin.settings.chunkSize = isNaN(parsed) ? 0 : parsed;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Chunk overlap')
+			.setDesc('Words each chunk repeats from the end of the previous one, so passages cut at a chunk boundary are still found.')
+			.addText(text => text
+				.setValue(String(this.plugin.settings.chunkOverlap))
+				.onChange(async (value) => {
+					const parsed = parseInt(value);
+					this.plugin.settings.chunkOverlap = isNaN(parsed) ? 0 : parsed;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Storage folder')
+			.setDesc('Folder where input.csv and the embedding file are saved, e.g. .obsidian/plugins/semantic-search to keep them out of your notes. Leave empty for the vault root. Missing folders are created.')
+			.addText(text => text
+				.setPlaceholder('.obsidian/plugins/semantic-search')
+				.setValue(this.plugin.settings.storageFolder)
+				.onChange(async (value) => {
+					this.plugin.settings.storageFolder = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Embedding file name')
+			.setDesc('Name of the file embeddings are saved to.')
+			.addText(text => text
+				.setValue(this.plugin.settings.embeddingFileName)
+				.onChange(async (value) => {
+					this.plugin.settings.embeddingFileName = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Embedding store')
+			.setDesc('Name of the embedding store to generate and search, e.g. "work" or a model name. Each store is saved to its own file so switching stores or models does not overwrite other stores. Leave empty for the default store.')
+			.addText(text => text
+				.setValue(this.plugin.settings.embeddingStore)
+				.onChange(async (value) => {
+					this.plugin.settings.embeddingStore = value;
+					await this.plugin.saveSettings();
+				}));
+
 		new Setting(containerEl)
 			.setName('Number of batches')
-			.setDesc("Number of batches used to call OpenAI's endpoint. If you have lots of data, and are facing invalid request errors, try increasing this number.")
+			.setDesc('Batches are sized automatically to stay under the token limit below and 2048 inputs per request. Set a number to force the records into at least that many batches instead, or 0 for automatic.')
 			.addSlider(slider => slider
 				.setValue(this.plugin.settings.numBatches)
 				.onChange(async (value) => {
 					this.plugin.settings.numBatches = value;
 					await this.plugin.saveSettings();
         })
-        .setLimits(1, 100, 1)
+        .setLimits(0, 100, 1)
         .setDynamicTooltip()
         .showTooltip());
 
+		new Setting(containerEl)
+			.setName('Max tokens per request')
+			.setDesc("Batches are split so that no request to the embedding API exceeds this many tokens. Sections larger than this are embedded in parts. Set to 0 to disable.")
+			.addText(text => text
+				.setValue(String(this.plugin.settings.maxTokensPerRequest))
+				.onChange(async (value) => {
+					const parsed = Number(value.trim());
+					// a typo must not turn the cap off, so anything but a whole number is left unsaved
+					const valid = value.trim() !== "" && Number.isInteger(parsed) && parsed >= 0;
+					text.inputEl.toggleClass('ss-invalid-input', !valid);
+					if (!valid) {
+						return;
+					}
+					this.plugin.settings.maxTokensPerRequest = parsed;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Max cost per run')
+			.setDesc("Embedding runs estimated to cost more than this many US dollars are refused before anything is sent. Generate Embeddings asks before going over it. Set to 0 to disable.")
+			.addText(text => text
+				.setValue(String(this.plugin.settings.maxCostPerRun))
+				.onChange(async (value) => {
+					const parsed = parseFloat(value);
+					this.plugin.settings.maxCostPerRun = isNaN(parsed) ? 0 : parsed;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Request timeout')
+			.setDesc("Seconds to wait for a response from the API before giving up on a request, so a hung connection doesn't stall the whole run. Set to 0 to disable.")
+			.addText(text => text
+				.setValue(String(this.plugin.settings.requestTimeout))
+				.onChange(async (value) => {
+					const parsed = parseInt(value);
+					this.plugin.settings.requestTimeout = isNaN(parsed) ? 0 : parsed;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Run deadline')
+			.setDesc("Minutes an embedding run may take before it stops sending batches. Set to 0 to disable.")
+			.addText(text => text
+				.setValue(String(this.plugin.settings.runDeadline))
+				.onChange(async (value) => {
+					const parsed = parseInt(value);
+					this.plugin.settings.runDeadline = isNaN(parsed) ? 0 : parsed;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Use the batch API')
+			.setDesc("Generate Embeddings submits a batch job through OpenAI's Batch API instead of embedding right away, at half the price. Results usually arrive within a few hours and at most 24; the plugin checks on the job every few minutes and replaces the embedding file once it has completed. Only works with OpenAI's API.")
+			.addToggle(toggle => toggle
+				.setValue(this.plugin.settings.useBatchApi)
+				.onChange(async (value) => {
+					this.plugin.settings.useBatchApi = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Requests per minute')
+			.setDesc("Embedding requests are paced so no more than this many are sent in any minute. Use your API tier's RPM limit to avoid rate limit errors. Set to 0 to disable.")
+			.addText(text => text
+				.setValue(String(this.plugin.settings.requestsPerMinute))
+				.onChange(async (value) => {
+					const parsed = parseInt(value);
+					this.plugin.settings.requestsPerMinute = isNaN(parsed) ? 0 : parsed;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Tokens per minute')
+			.setDesc("Embedding requests are paced so no more than this many tokens are embedded in any minute. Use your API tier's TPM limit. Set to 0 to disable.")
+			.addText(text => text
+				.setValue(String(this.plugin.settings.tokensPerMinute))
+				.onChange(async (value) => {
+					const parsed = parseInt(value);
+					this.plugin.settings.tokensPerMinute = isNaN(parsed) ? 0 : parsed;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Embedded content')
+			.setDesc('Which part of each section is embedded. Requires regenerating embeddings.')
+			.addDropdown(dropdown => dropdown
+				.addOptions({
+					headingAndBody: 'Heading and body',
+					body: 'Body only',
+					heading: 'Heading only',
+				})
+				.setValue(this.plugin.settings.embedContent)
+				.onChange(async (value: semanticSearchSettings['embedContent']) => {
+					this.plugin.settings.embedContent = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Embedded text template')
+			.setDesc('Adds context to each section before it is embedded. {title} is the note title, {headings} the headings the section is under, {path} both joined with " > " and {text} the embedded content. Leave empty to embed sections alone. Requires regenerating embeddings.')
+			.addText(text => text
+				.setPlaceholder('{path}: {text}')
+				.setValue(this.plugin.settings.embedTemplate)
+				.onChange(async (value) => {
+					this.plugin.settings.embedTemplate = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Embedding precision')
+			.setDesc('Store embeddings as 8-bit integers to make the embedding file about three times smaller and searches faster, with a negligible effect on ranking. Applies the next time embeddings are generated.')
+			.addDropdown(dropdown => dropdown
+				.addOptions({
+					none: 'Full (32-bit floats)',
+					int8: 'Compact (8-bit integers)',
+				})
+				.setValue(this.plugin.settings.embeddingQuantization)
+				.onChange(async (value: semanticSearchSettings['embeddingQuantization']) => {
+					this.plugin.settings.embeddingQuantization = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Embedding dimensions')
+			.setDesc('Keep only the first dimensions of each embedding, e.g. 256 or 512, for models trained to allow it such as text-embedding-3-small. Shrinks the embedding file and speeds up searches with little loss in quality. Set to 0 to keep embeddings whole. Requires regenerating embeddings.')
+			.addText(text => text
+				.setValue(String(this.plugin.settings.embeddingDimensions))
+				.onChange(async (value) => {
+					const parsed = parseInt(value);
+					this.plugin.settings.embeddingDimensions = isNaN(parsed) ? 0 : parsed;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Score normalization')
+			.setDesc('How scores from different sources are rescaled before results are merged and ranked.')
+			.addDropdown(dropdown => dropdown
+				.addOptions({
+					minMax: 'Min-max',
+					zScore: 'Z-score',
+					none: 'None',
+				})
+				.setValue(this.plugin.settings.scoreNormalization)
+				.onChange(async (value: semanticSearchSettings['scoreNormalization']) => {
+					this.plugin.settings.scoreNormalization = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Adaptive result count')
+			.setDesc('Only show results above the largest drop in relevance instead of always showing ten.')
+			.addToggle(toggle => toggle
+				.setValue(this.plugin.settings.adaptiveResultCount)
+				.onChange(async (value) => {
+					this.plugin.settings.adaptiveResultCount = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Diversify results')
+			.setDesc('Re-rank results with Maximal Marginal Relevance so one long note does not fill the whole list.')
+			.addToggle(toggle => toggle
+				.setValue(this.plugin.settings.enableMmr)
+				.onChange(async (value) => {
+					this.plugin.settings.enableMmr = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Diversity trade-off')
+			.setDesc('How strongly to favour relevance over diversity when diversifying results. 1 is pure relevance, 0 is pure diversity.')
+			.addSlider(slider => slider
+				.setLimits(0, 1, 0.05)
+				.setValue(this.plugin.settings.mmrLambda)
+				.setDynamicTooltip()
+				.onChange(async (value) => {
+					this.plugin.settings.mmrLambda = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Group results by note')
+			.setDesc("Show one result per note with its best-matching heading, scored by the note's best or average section score.")
+			.addDropdown(dropdown => dropdown
+				.addOptions({
+					off: 'Off',
+					max: 'Best section',
+					mean: 'Average of sections',
+				})
+				.setValue(this.plugin.settings.groupByFile)
+				.onChange(async (value: semanticSearchSettings['groupByFile']) => {
+					this.plugin.settings.groupByFile = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Tag boosts')
+			.setDesc('Raise results from notes with certain tags, one tag and weight per line, e.g. "#permanent 0.1". The weight is added to the score, so a negative weight such as "#fleeting -0.05" lowers them. Nested tags like #project/alpha count for #project.')
+			.addTextArea(text => text
+				.setPlaceholder('#permanent 0.1\n#fleeting -0.05')
+				.setValue(this.plugin.settings.tagBoosts)
+				.onChange(async (value) => {
+					this.plugin.settings.tagBoosts = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Recency half-life')
+			.setDesc('Rank recently edited notes higher: a note\'s score halves for every this many days since it was last modified. 0 ranks notes regardless of age. Needs an index built with modification times, older notes are ranked as before.')
+			.addText(text => text
+				.setValue(String(this.plugin.settings.recencyHalfLifeDays))
+				.onChange(async (value) => {
+					const parsed = parseFloat(value);
+					this.plugin.settings.recencyHalfLifeDays = isNaN(parsed) ? 0 : parsed;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Show snippets')
+			.setDesc('Show an excerpt of each result under its heading, highlighting the sentence that shares the most words with the query. The text is read from input.csv.')
+			.addToggle(toggle => toggle
+				.setValue(this.plugin.settings.showSnippets)
+				.onChange(async (value) => {
+					this.plugin.settings.showSnippets = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Skip malformed rows')
+			.setDesc('Search the rows that can still be read when input.csv or the embedding file is partly corrupted, instead of failing. Skipped rows of the embedding file are moved to a .corrupt file next to it, and the "Check index files" command lists skipped rows.')
+			.addToggle(toggle => toggle
+				.setValue(this.plugin.settings.skipMalformedRows)
+				.onChange(async (value) => {
+					this.plugin.settings.skipMalformedRows = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Encrypt embeddings')
+			.setDesc('Encrypt the embedding files with the passphrase below as they are written, for vaults synced to cloud services. Existing files are encrypted the next time they are rewritten, e.g. by Compact embedding store.')
+			.addToggle(toggle => toggle
+				.setValue(this.plugin.settings.encryptEmbeddings)
+				.onChange(async (value) => {
+					this.plugin.settings.encryptEmbeddings = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Encryption passphrase')
+			.setDesc('Needed to read encrypted embedding files. It is kept on this device only, so enter it on every device the vault is synced to. Embeddings encrypted with a forgotten passphrase have to be generated again.')
+			.addText(text => {
+				text.inputEl.type = 'password';
+				text
+					.setValue(this.plugin.settings.encryptionPassphrase)
+					.onChange(async (value) => {
+						this.plugin.settings.encryptionPassphrase = value;
+						await this.plugin.saveSettings();
+					});
+			});
+
+		new Setting(containerEl)
+			.setName('Query expansion model')
+			.setDesc('Chat model used to rephrase each search, e.g. gpt-4o-mini. The rephrasings are searched too and the results merged. Leave empty to disable.')
+			.addText(text => text
+				.setPlaceholder('gpt-4o-mini')
+				.setValue(this.plugin.settings.queryExpansionModel)
+				.onChange(async (value) => {
+					this.plugin.settings.queryExpansionModel = value.trim();
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Query rephrasings')
+			.setDesc('How many rephrasings of each search to ask the query expansion model for.')
+			.addSlider(slider => slider
+				.setLimits(1, 5, 1)
+				.setValue(this.plugin.settings.queryExpansionCount)
+				.setDynamicTooltip()
+				.onChange(async (value) => {
+					this.plugin.settings.queryExpansionCount = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Answer model')
+			.setDesc('Chat model that answers questions from your notes with the Answer question command, e.g. gpt-4o-mini. Leave empty to disable.')
+			.addText(text => text
+				.setPlaceholder('gpt-4o-mini')
+				.setValue(this.plugin.settings.answerModel)
+				.onChange(async (value) => {
+					this.plugin.settings.answerModel = value.trim();
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Answer context tokens')
+			.setDesc('Most tokens of note text sent to the answer model with each question.')
+			.addText(text => text
+				.setValue(String(this.plugin.settings.answerContextTokens))
+				.onChange(async (value) => {
+					const parsed = parseInt(value);
+					this.plugin.settings.answerContextTokens = isNaN(parsed) ? 3000 : parsed;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Rerank results')
+			.setDesc('Send the top 30 results and the query to a reranking model and reorder them by its relevance scores. Slower, but usually more accurate.')
+			.addToggle(toggle => toggle
+				.setValue(this.plugin.settings.enableRerank)
+				.onChange(async (value) => {
+					this.plugin.settings.enableRerank = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Reranking API')
+			.setDesc('Cohere-style /rerank (Cohere, Jina, Voyage, most self-hosted rerankers) or the /score endpoint of OpenAI-compatible servers such as vLLM.')
+			.addDropdown(dropdown => dropdown
+				.addOptions({
+					cohere: 'Rerank (Cohere)',
+					score: 'Score (OpenAI-compatible)',
+				})
+				.setValue(this.plugin.settings.rerankFormat)
+				.onChange(async (value: semanticSearchSettings['rerankFormat']) => {
+					this.plugin.settings.rerankFormat = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Reranking base URL')
+			.setDesc('Base URL of the reranking API, e.g. https://api.cohere.com/v2. Leave empty to use the API base URL.')
+			.addText(text => text
+				.setValue(this.plugin.settings.rerankApiBase)
+				.onChange(async (value) => {
+					this.plugin.settings.rerankApiBase = value.trim();
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Reranking API key')
+			.setDesc('Leave empty to use the OpenAI API key, which is only sent when the reranking base URL is empty or the API base URL.')
+			.addText(text => text
+				.setValue(this.plugin.settings.rerankApiKey)
+				.onChange(async (value) => {
+					this.plugin.settings.rerankApiKey = value.trim();
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Reranking model')
+			.addText(text => text
+				.setPlaceholder('rerank-v3.5')
+				.setValue(this.plugin.settings.rerankModel)
+				.onChange(async (value) => {
+					this.plugin.settings.rerankModel = value.trim();
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Max tokens per section')
+			.setDesc("Sections longer than this are shortened before embedding, keeping whole sentences from their start and end. Set to 0 to disable.")
+			.addText(text => text
+				.setValue(String(this.plugin.settings.maxTokensPerRecord))
+				.onChange(async (value) => {
+					const parsed = parseInt(value);
+					this.plugin.settings.maxTokensPerRecord = isNaN(parsed) ? 0 : parsed;
+					await this.plugin.saveSettings();
+				}));
+
     new Setting(containerEl)
     .setName("Enable link recommendation using {{}}")
     .setDesc("Typing '{{}}' will generate link recommendations for the text within the braces (requires reload).")
@@ -102,6 +811,87 @@ export class SemanticSearchSettingTab extends PluginSettingTab {
                  this.plugin.settings.enableLinkRecommendationSuggestor = value;
                  await this.plugin.saveSettings();
                }));
+
+		new Setting(containerEl)
+			.setName('Update index automatically')
+			.setDesc('Re-embed notes in the background when they are created, edited, renamed or deleted, keeping input.csv and the embedding file up to date. Only changed notes are sent to the API. Generate embeddings once before turning this on.')
+			.addToggle(toggle => toggle
+				.setValue(this.plugin.settings.autoIndex)
+				.onChange(async (value) => {
+					this.plugin.settings.autoIndex = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Auto-index delay')
+			.setDesc('Seconds to wait after the last change before re-embedding, so a note is not embedded after every keystroke.')
+			.addText(text => text
+				.setValue(String(this.plugin.settings.autoIndexDelay))
+				.onChange(async (value) => {
+					const parsed = parseInt(value);
+					this.plugin.settings.autoIndexDelay = isNaN(parsed) || parsed < 0 ? 30 : parsed;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Index when idle')
+			.setDesc('Instead of updating all changed notes at once after the delay, update a few at a time while Obsidian is idle, so typing never stutters during an update. Takes effect after restarting Obsidian.')
+			.addToggle(toggle => toggle
+				.setValue(this.plugin.settings.idleIndexing)
+				.onChange(async (value) => {
+					this.plugin.settings.idleIndexing = value;
+					await this.plugin.saveSettings();
+				}));
+
+		containerEl.createEl('h3', {text: 'Logging'});
+
+		new Setting(containerEl)
+			.setName('Log level')
+			.setDesc('Most detailed messages written to the developer console and the log file. Debug includes every file read and written.')
+			.addDropdown(dropdown => dropdown
+				.addOptions({
+					off: 'Off',
+					error: 'Errors',
+					warn: 'Warnings',
+					info: 'Info',
+					debug: 'Debug',
+					trace: 'Trace',
+				})
+				.setValue(this.plugin.settings.logLevel)
+				.onChange(async (value: semanticSearchSettings['logLevel']) => {
+					this.plugin.settings.logLevel = value;
+					await this.plugin.saveSettings();
+				}));
+
+		new Setting(containerEl)
+			.setName('Write a log file')
+			.setDesc('Also write log messages, including API request timings and batch statistics, to semantic-search.log in the storage folder so they can be attached to bug reports. The file is moved to semantic-search.log.1 once it grows past 1 MB.')
+			.addToggle(toggle => toggle
+				.setValue(this.plugin.settings.logToFile)
+				.onChange(async (value) => {
+					this.plugin.settings.logToFile = value;
+					await this.plugin.saveSettings();
+				}));
+
+		containerEl.createEl('h3', {text: 'Index health'});
+		const statsEl = containerEl.createDiv({cls: 'ss-index-stats'});
+		plugin.index_stats(this.app, this.plugin.settings)
+			.then(stats => renderIndexStats(statsEl, stats))
+			.catch(error => {
+				console.error(error);
+				statsEl.setText('No embeddings have been generated yet.');
+			});
+
+		containerEl.createEl('h3', {text: 'Last run'});
+		const reportEl = containerEl.createDiv({cls: 'ss-run-report'});
+		plugin.get_last_run_report(this.app, this.plugin.settings)
+			.then(manifest => renderRunReport(reportEl, manifest))
+			.catch(error => {
+				console.error(error);
+				reportEl.setText('Could not load the last run report.');
+			});
+
+		this.showProblems();
 	}
 }
 
@@ -0,0 +1,151 @@
+//! Short excerpts of a result's text, centred on the sentence that best matches the query.
+
+use std::cmp::Reverse;
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+/// Longest snippet shown under a result, in characters
+pub const SNIPPET_CHARS: usize = 200;
+
+/// Common words that say nothing about which sentence matches a query
+const STOP_WORDS: [&str; 14] = ["and", "are", "but", "can", "for", "from", "has", "how", "not", "the", "this", "that", "was", "with"];
+
+/// An excerpt of a result's text, with `sentence` the part to highlight. Shown as
+/// `before`, `sentence` and `after` one after another.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snippet {
+    pub before: String,
+    pub sentence: String,
+    pub after: String,
+}
+
+/// A snippet of `text` around the sentence sharing the most words with `query`, or around
+/// the first sentence without a query or any shared words. None for empty text.
+pub fn snippet(text: &str, query: Option<&str>) -> Option<Snippet> {
+    let text = text.trim();
+    let spans = sentence_spans(text);
+    let query_terms = query.map(terms).unwrap_or_default();
+    let (start, end) = spans.iter().copied().enumerate()
+        .max_by_key(|&(i, (start, end))| (terms(&text[start..end]).intersection(&query_terms).count(), Reverse(i)))
+        .map(|(_, span)| span)?;
+    let sentence = head(&text[start..end], SNIPPET_CHARS);
+    let budget = SNIPPET_CHARS.saturating_sub(sentence.chars().count());
+    let before = tail(&text[..start], budget / 2);
+    let after = head(&text[end..], budget - before.chars().count());
+    Some(Snippet { before, sentence, after })
+}
+
+/// Byte ranges of the sentences of `text`, split after `.`, `!` or `?` followed by
+/// whitespace and at line breaks, without surrounding whitespace
+fn sentence_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next_is_space = chars.peek().is_none_or(|(_, next)| next.is_whitespace());
+        let end = match c {
+            '\n' => i,
+            '.' | '!' | '?' if next_is_space => i + c.len_utf8(),
+            _ => continue,
+        };
+        push_span(text, start, end, &mut spans);
+        start = end;
+    }
+    push_span(text, start, text.len(), &mut spans);
+    spans
+}
+
+fn push_span(text: &str, start: usize, end: usize, spans: &mut Vec<(usize, usize)>) {
+    let part = &text[start..end];
+    let trimmed = part.trim_start();
+    let start = start + part.len() - trimmed.len();
+    let end = start + trimmed.trim_end().len();
+    if end > start {
+        spans.push((start, end));
+    }
+}
+
+/// Lowercase words of `text` that can tell sentences apart
+fn terms(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|word| word.chars().count() >= 3 && !STOP_WORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// The start of `text`, cut at a word boundary with an ellipsis if longer than `max_chars`
+fn head(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.trim_end().to_string();
+    }
+    let cut: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    let cut = match cut.rfind(char::is_whitespace) {
+        Some(space) if !cut[..space].trim().is_empty() => &cut[..space],
+        _ => &cut,
+    };
+    format!("{}…", cut.trim_end())
+}
+
+/// The end of `text`, cut at a word boundary with an ellipsis if longer than `max_chars`
+fn tail(text: &str, max_chars: usize) -> String {
+    let length = text.chars().count();
+    if length <= max_chars {
+        return text.trim_start().to_string();
+    }
+    let cut: String = text.chars().skip(length - max_chars.saturating_sub(1)).collect();
+    let cut = match cut.find(char::is_whitespace) {
+        Some(space) if !cut[space..].trim().is_empty() => &cut[space..],
+        _ => &cut,
+    };
+    format!("…{}", cut.trim_start())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_the_matching_sentence() {
+        let text = "Raft elects a leader. Paxos needs a proposer! Leader election in Raft uses randomized timeouts.\nSee also: consensus.";
+
+        let snippet = snippet(text, Some("how does raft leader election work")).unwrap();
+
+        assert_eq!(snippet.sentence, "Leader election in Raft uses randomized timeouts.");
+        assert_eq!(snippet.before, "Raft elects a leader. Paxos needs a proposer! ");
+        assert_eq!(snippet.after, "\nSee also: consensus.");
+    }
+
+    #[test]
+    fn first_sentence_without_a_match() {
+        let snippet = snippet("  First sentence. Version 1.2 is out. ", Some("unrelated")).unwrap();
+        assert_eq!((snippet.before.as_str(), snippet.sentence.as_str(), snippet.after.as_str()), ("", "First sentence.", " Version 1.2 is out."));
+
+        assert_eq!(super::snippet("   ", None), None);
+    }
+
+    #[test]
+    fn long_text_is_cut_around_the_sentence() {
+        let filler = "Nothing to see here. ".repeat(20);
+        let text = format!("{}The gardening schedule moved to April. {}", filler, filler);
+
+        let snippet = snippet(&text, Some("gardening")).unwrap();
+
+        assert_eq!(snippet.sentence, "The gardening schedule moved to April.");
+        assert!(snippet.before.starts_with('…') && snippet.before.ends_with("here. "), "{:?}", snippet.before);
+        assert!(snippet.after.starts_with(" Nothing") && snippet.after.ends_with('…'), "{:?}", snippet.after);
+        let length = [&snippet.before, &snippet.sentence, &snippet.after].iter().map(|part| part.chars().count()).sum::<usize>();
+        assert!(length <= SNIPPET_CHARS, "{}", length);
+    }
+
+    #[test]
+    fn long_sentences_are_shortened() {
+        let text = "ångström ".repeat(60);
+
+        let snippet = snippet(&text, None).unwrap();
+
+        assert!(snippet.sentence.ends_with("ångström…"));
+        assert!(snippet.sentence.chars().count() <= SNIPPET_CHARS);
+        assert_eq!(snippet.after, "");
+    }
+}
@@ -0,0 +1,124 @@
+//! How healthy the index is: what the embedding store holds, how much of the vault it covers and
+//! how much of it is left over from notes that are gone.
+
+use std::collections::HashSet;
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::{QueryCommand, SemanticSearchError};
+use crate::compaction;
+use crate::embedding_file::{self, EmbeddingFile};
+use crate::file_processor::FileFilter;
+use crate::obsidian;
+use crate::settings::Settings;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStats {
+    /// Stored sections
+    pub records: usize,
+    /// Notes and documents with at least one stored section
+    pub notes: usize,
+    pub dimensions: usize,
+    /// Model the store was built with, None for stores without metadata
+    pub model: Option<String>,
+    /// Size of the embedding file on disk
+    pub bytes: usize,
+    /// Sections of notes no longer in the vault, which Compact embedding store removes
+    pub orphans: usize,
+    /// Rows that cannot be read, see Check index files
+    pub malformed: usize,
+    /// When the least and most recently embedded sections were embedded, in ms since the epoch
+    pub oldest: Option<f64>,
+    pub newest: Option<f64>,
+    /// Markdown notes Generate Input would read, and how many of them have stored sections
+    pub markdown_notes: usize,
+    pub indexed_notes: usize,
+    /// Share of `markdown_notes` that is indexed, in percent, 0 for a vault without notes
+    pub coverage: f32,
+}
+
+/// Statistics of `store`, `bytes` long on disk, for a vault with the files named in
+/// `vault_names` of which those in `markdown_names` are notes Generate Input would read.
+/// Sections without provenance count as embedded when the store was created.
+pub fn stats(store: &EmbeddingFile, bytes: usize, vault_names: &HashSet<String>, markdown_names: &HashSet<String>) -> IndexStats {
+    let stored: HashSet<&str> = store.rows.iter().map(|(name, _, _)| name.as_str()).collect();
+    let created_at = store.metadata.as_ref().map(|metadata| metadata.created_at);
+    let embedded_at: Vec<f64> = (0..store.rows.len())
+        .filter_map(|row| store.provenance.get(row).and_then(Option::as_ref).map(|provenance| provenance.embedded_at).or(created_at))
+        .collect();
+    let indexed_notes = markdown_names.iter().filter(|name| stored.contains(name.as_str())).count();
+    IndexStats {
+        records: store.rows.len(),
+        notes: stored.len(),
+        dimensions: store.rows.first().map_or(0, |(_, _, vector)| vector.len()),
+        model: store.metadata.as_ref().map(|metadata| metadata.model.clone()),
+        bytes,
+        orphans: store.rows.iter().filter(|(name, _, _)| compaction::is_orphan(name, vault_names)).count(),
+        malformed: 0,
+        oldest: embedded_at.iter().copied().reduce(f64::min),
+        newest: embedded_at.iter().copied().reduce(f64::max),
+        markdown_notes: markdown_names.len(),
+        indexed_notes,
+        coverage: match markdown_names.len() {
+            0 => 0.0,
+            total => indexed_notes as f32 * 100.0 / total as f32,
+        },
+    }
+}
+
+/// Returns `IndexStats` for the configured embedding store, reading it leniently so a store
+/// with unreadable rows can still be looked at
+#[wasm_bindgen]
+pub async fn index_stats(app: &obsidian::App, settings: JsValue) -> Result<JsValue, SemanticSearchError> {
+    let settings = Settings::from_js(&settings)?;
+    let query_cmd = QueryCommand::new(app, &settings);
+    let file_processor = &query_cmd.file_processor;
+    let data = file_processor.read_from_path(&query_cmd.embedding_path).await.map_err(SemanticSearchError::missing_embedding)?;
+    let (store, malformed) = embedding_file::read_embedding_file_lenient(&data)?;
+    let bytes = file_processor.size_of(&query_cmd.embedding_path).await?.map_or(data.len(), |size| size as usize);
+    let vault_names: HashSet<String> = file_processor.list_files(&FileFilter::default()).await.iter().map(|file| file.name()).collect();
+    let markdown_names: HashSet<String> = file_processor.list_markdown_files(&FileFilter::indexed(&settings.ignored_folders)).await.iter()
+        .map(|file| file.name())
+        .collect();
+    let stats = IndexStats { malformed: malformed.len(), ..stats(&store, bytes, &vault_names, &markdown_names) };
+    Ok(serde_wasm_bindgen::to_value(&stats)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding_file::StoreMetadata;
+    use crate::provenance::{fingerprint, Provenance};
+
+    fn names(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn counts_coverage_and_orphans() {
+        let row = |name: &str| (name.to_string(), "H".to_string(), vec![1.0, 0.0, 0.0]);
+        let provenance = |embedded_at: f64| Some(Provenance { model: "m".to_string(), provider: "p".to_string(), embedded_at, settings_hash: fingerprint("s"), lines: None, note_times: None, language: None, path: None });
+        let store = EmbeddingFile {
+            metadata: Some(StoreMetadata::new("m", 3, 5.0)),
+            rows: vec![row("a.md"), row("a.md"), row("gone.md"), row("readwise/1")],
+            provenance: vec![provenance(20.0), provenance(10.0), None, provenance(30.0)],
+        };
+
+        let stats = stats(&store, 1234, &names(&["a.md", "b.md", "c.pdf"]), &names(&["a.md", "b.md"]));
+
+        assert_eq!((stats.records, stats.notes, stats.dimensions, stats.bytes), (4, 3, 3, 1234));
+        assert_eq!(stats.model.as_deref(), Some("m"));
+        assert_eq!(stats.orphans, 1);
+        assert_eq!((stats.oldest, stats.newest), (Some(5.0), Some(30.0)));
+        assert_eq!((stats.markdown_notes, stats.indexed_notes, stats.coverage), (2, 1, 50.0));
+    }
+
+    #[test]
+    fn empty_store() {
+        let stats = stats(&EmbeddingFile { metadata: None, rows: Vec::new(), provenance: Vec::new() }, 0, &HashSet::new(), &HashSet::new());
+
+        assert_eq!(stats, IndexStats::default());
+    }
+}
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+
+use crate::FileProcessor;
+use crate::SemanticSearchError;
+
+/// Name of the registry listing every embedding store, stored next to input.csv
+pub const STORE_REGISTRY_FILE_NAME: &str = "stores.json";
+
+/// An embedding file built for one named store, e.g. "work" or a model name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoreEntry {
+    /// Empty for the default store
+    pub name: String,
+    pub model: String,
+    /// Vault relative path of the embedding file
+    pub path: String,
+    pub records: usize,
+    pub updated_at: f64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StoreRegistry {
+    pub stores: Vec<StoreEntry>,
+}
+
+impl StoreRegistry {
+    pub async fn load(file_processor: &FileProcessor, path: &str) -> Result<Self, SemanticSearchError> {
+        if !file_processor.check_file_exists_at_path(path).await? {
+            return Ok(Self::default());
+        }
+        let data = file_processor.read_from_path(path).await?;
+        serde_json::from_str(&data).map_err(SemanticSearchError::JSONDeserialize)
+    }
+
+    pub async fn save(&self, file_processor: &FileProcessor, path: &str) -> Result<(), SemanticSearchError> {
+        let data = serde_json::to_string_pretty(self).map_err(|e| SemanticSearchError::ConversionError(Box::new(e)))?;
+        file_processor.delete_file_at_path(path).await?;
+        file_processor.write_to_path(path, &data).await
+    }
+
+    /// Adds `entry`, replacing any existing store with the same name
+    pub fn upsert(&mut self, entry: StoreEntry) {
+        match self.stores.iter_mut().find(|store| store.name == entry.name) {
+            Some(store) => *store = entry,
+            None => self.stores.push(entry),
+        }
+        self.stores.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+}
+
+/// Adds or replaces `entry` in the registry at `path`
+pub async fn register(file_processor: &FileProcessor, path: &str, entry: StoreEntry) -> Result<(), SemanticSearchError> {
+    let mut registry = StoreRegistry::load(file_processor, path).await?;
+    registry.upsert(entry);
+    registry.save(file_processor, path).await
+}
+
+/// Embedding file name for `store`, e.g. `embedding-work.csv` for store "work"
+pub fn store_file_name(file_name: &str, store: &str) -> String {
+    let slug: String = store.trim().chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' || c == '.' { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    if slug.is_empty() {
+        return file_name.to_string();
+    }
+    match file_name.rsplit_once('.') {
+        Some((stem, extension)) => format!("{}-{}.{}", stem, slug, extension),
+        None => format!("{}-{}", file_name, slug),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, records: usize) -> StoreEntry {
+        StoreEntry { name: name.to_string(), model: "text-embedding-3-small".to_string(), path: store_file_name("embedding.csv", name), records, updated_at: 0.0 }
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(store_file_name("embedding.csv", ""), "embedding.csv");
+        assert_eq!(store_file_name("embedding.csv", "Work"), "embedding-work.csv");
+        assert_eq!(store_file_name("embedding.csv", "text-embedding-3-large"), "embedding-text-embedding-3-large.csv");
+        assert_eq!(store_file_name("embeddings", "my notes/2"), "embeddings-my-notes-2");
+    }
+
+    #[test]
+    fn upsert_replaces_by_name() {
+        let mut registry = StoreRegistry::default();
+        registry.upsert(entry("work", 1));
+        registry.upsert(entry("", 2));
+        registry.upsert(entry("work", 3));
+
+        assert_eq!(registry.stores.iter().map(|store| (store.name.as_str(), store.records)).collect::<Vec<_>>(), vec![("", 2), ("work", 3)]);
+    }
+}
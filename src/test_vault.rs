@@ -0,0 +1,225 @@
+//! Synthetic vaults for benchmarks, fuzzing and reproducing scaling issues.
+//!
+//! Only built with the `test-vault` feature. Generation is deterministic for a
+//! given `VaultSpec`, so a reported problem can be reproduced from its spec alone.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::SemanticSearchError;
+use crate::chunking::Granularity;
+use crate::generate_input::extract_sections;
+use crate::input::{InputRecord, write_input};
+
+const TOPICS: &[(&str, &[&str])] = &[
+    ("gardening", &["soil", "compost", "seedlings", "pruning", "tomatoes", "watering", "mulch", "perennials"]),
+    ("programming", &["compiler", "borrow", "closure", "iterator", "allocation", "trait", "thread", "lifetime"]),
+    ("cooking", &["braise", "stock", "knife", "dough", "caramel", "simmer", "seasoning", "roast"]),
+    ("astronomy", &["nebula", "telescope", "orbit", "parallax", "eclipse", "galaxy", "redshift", "comet"]),
+    ("finance", &["budget", "interest", "dividend", "mortgage", "index", "savings", "inflation", "ledger"]),
+    ("music", &["chord", "tempo", "melody", "scale", "rhythm", "harmony", "octave", "cadence"]),
+];
+
+const FILLER: &[&str] = &["the", "a", "with", "about", "and", "for", "notes", "on", "when", "usually", "today", "again"];
+
+/// Size and shape of a generated vault
+#[derive(Debug, Clone)]
+pub struct VaultSpec {
+    pub notes: usize,
+    /// Number of topic clusters notes are drawn from, capped at the built-in topic list
+    pub topics: usize,
+    pub sections_per_note: usize,
+    pub sentences_per_section: usize,
+    /// Fraction of notes, between 0 and 1, that get a markdown edge case
+    pub edge_case_rate: f32,
+    pub seed: u64,
+}
+
+impl Default for VaultSpec {
+    fn default() -> Self {
+        Self { notes: 100, topics: 4, sections_per_note: 4, sentences_per_section: 3, edge_case_rate: 0.1, seed: 1 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeCase {
+    Empty,
+    HeadingsOnly,
+    Frontmatter,
+    CodeBlock,
+    CsvSpecialCharacters,
+    Unicode,
+    LongSection,
+    LinksAndEmbeds,
+    NoHeadings,
+}
+
+const EDGE_CASES: &[EdgeCase] = &[
+    EdgeCase::Empty,
+    EdgeCase::HeadingsOnly,
+    EdgeCase::Frontmatter,
+    EdgeCase::CodeBlock,
+    EdgeCase::CsvSpecialCharacters,
+    EdgeCase::Unicode,
+    EdgeCase::LongSection,
+    EdgeCase::LinksAndEmbeds,
+    EdgeCase::NoHeadings,
+];
+
+#[derive(Debug, Clone)]
+pub struct GeneratedNote {
+    /// Vault relative path, e.g. `gardening/note-0003.md`
+    pub path: String,
+    pub content: String,
+    /// The topic cluster the note was drawn from, usable as ground truth for clustering
+    pub topic: String,
+    pub edge_case: Option<EdgeCase>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GeneratedVault {
+    pub notes: Vec<GeneratedNote>,
+}
+
+impl GeneratedVault {
+    pub fn generate(spec: &VaultSpec) -> Self {
+        let mut rng = Rng::new(spec.seed);
+        let topics = &TOPICS[..spec.topics.clamp(1, TOPICS.len())];
+        let notes = (0..spec.notes).map(|i| {
+            let (topic, words) = topics[i % topics.len()];
+            let edge_case = if rng.next_f32() < spec.edge_case_rate { Some(*rng.choose(EDGE_CASES)) } else { None };
+            let content = match edge_case {
+                Some(edge_case) => edge_case_note(edge_case, words, spec, &mut rng),
+                None => note(words, spec, &mut rng),
+            };
+            GeneratedNote { path: format!("{}/note-{:04}.md", topic, i), content, topic: topic.to_string(), edge_case }
+        }).collect();
+        Self { notes }
+    }
+
+    /// Writes every note below `dir`, creating topic folders as needed
+    pub fn write_to(&self, dir: &Path) -> io::Result<()> {
+        for note in &self.notes {
+            let path = dir.join(&note.path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, &note.content)?;
+        }
+        Ok(())
+    }
+
+    /// Splits the notes into sections the same way the Generate Input command does and returns input.csv
+    pub fn input_csv(&self, delimeter: &str) -> Result<String, SemanticSearchError> {
+        let mut records = Vec::new();
+        for note in &self.notes {
+            for (name, header, body) in extract_sections(&note.path, &note.content, delimeter)? {
+                records.push(InputRecord::new(name, header, body));
+            }
+        }
+        write_input(&records, Granularity::Heading)
+    }
+}
+
+fn sentence(words: &[&str], rng: &mut Rng) -> String {
+    let len = 6 + rng.below(8);
+    let mut sentence: Vec<&str> = (0..len).map(|i| if i % 2 == 0 { *rng.choose(words) } else { *rng.choose(FILLER) }).collect();
+    sentence.push("end");
+    let mut text = sentence.join(" ");
+    text.replace_range(..1, &text[..1].to_uppercase());
+    text.push('.');
+    text
+}
+
+fn paragraph(words: &[&str], sentences: usize, rng: &mut Rng) -> String {
+    (0..sentences).map(|_| sentence(words, rng)).collect::<Vec<_>>().join(" ")
+}
+
+fn note(words: &[&str], spec: &VaultSpec, rng: &mut Rng) -> String {
+    (0..spec.sections_per_note.max(1)).map(|i| {
+        let level = if i == 0 { 1 } else { 2 + rng.below(2) };
+        format!("{} {} {}\n\n{}\n", "#".repeat(level), rng.choose(words), i, paragraph(words, spec.sentences_per_section, rng))
+    }).collect::<Vec<_>>().join("\n")
+}
+
+fn edge_case_note(edge_case: EdgeCase, words: &[&str], spec: &VaultSpec, rng: &mut Rng) -> String {
+    let body = paragraph(words, spec.sentences_per_section, rng);
+    match edge_case {
+        EdgeCase::Empty => String::new(),
+        EdgeCase::HeadingsOnly => (0..spec.sections_per_note.max(1)).map(|i| format!("## {} {}\n", rng.choose(words), i)).collect(),
+        EdgeCase::Frontmatter => format!("---\ntags: [{}]\naliases: [\"{}\"]\n---\n# {}\n\n{}\n", words[0], words[1], words[2], body),
+        EdgeCase::CodeBlock => format!("# {}\n\n{}\n\n```rust\n// # not a heading\nfn main() {{}}\n```\n", words[0], body),
+        EdgeCase::CsvSpecialCharacters => format!("# {}, \"quoted\"\n\n{}, with \"quotes\", commas\nand a line break\n", words[0], body),
+        EdgeCase::Unicode => format!("# Ünïcödé {} 🌱\n\n{} 日本語のテキスト。 Ελληνικά. 🚀🔭\n", words[0], body),
+        EdgeCase::LongSection => format!("# {}\n\n{}\n", words[0], paragraph(words, 400, rng)),
+        EdgeCase::LinksAndEmbeds => format!("# {}\n\n{} [[{}]] ![diagram](diagram.png) #{} [site](https://example.com)\n", words[0], body, words[1], words[2]),
+        EdgeCase::NoHeadings => format!("{}\n", body),
+    }
+}
+
+/// Small xorshift generator so the generated vault does not depend on a `rand` version
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deterministic_for_seed() {
+        let spec = VaultSpec { notes: 20, ..Default::default() };
+
+        let a = GeneratedVault::generate(&spec);
+        let b = GeneratedVault::generate(&spec);
+
+        assert_eq!(a.notes.len(), 20);
+        assert!(a.notes.iter().zip(&b.notes).all(|(a, b)| a.path == b.path && a.content == b.content));
+    }
+
+    #[test]
+    fn topics_and_edge_cases() {
+        let spec = VaultSpec { notes: 60, topics: 3, edge_case_rate: 1.0, ..Default::default() };
+
+        let vault = GeneratedVault::generate(&spec);
+
+        let mut topics: Vec<&str> = vault.notes.iter().map(|note| note.topic.as_str()).collect();
+        topics.sort();
+        topics.dedup();
+        assert_eq!(topics.len(), 3);
+        assert!(vault.notes.iter().all(|note| note.edge_case.is_some()));
+    }
+
+    #[test]
+    fn input_csv_round_trips() {
+        let spec = VaultSpec { notes: 30, edge_case_rate: 0.5, ..Default::default() };
+
+        let csv = GeneratedVault::generate(&spec).input_csv("^#").unwrap();
+
+        assert!(!crate::input::read_input(&csv).unwrap().is_empty());
+    }
+}
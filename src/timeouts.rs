@@ -0,0 +1,95 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::SemanticSearchError;
+use crate::pipeline::{PipelineHook, PipelineRecord};
+use crate::rate_limit;
+
+/// Resolves to `None` once `ms` milliseconds pass before `future` completes, dropping it,
+/// which aborts a pending fetch. A timeout of 0 or less waits as long as it takes.
+pub async fn with_timeout<F: Future>(future: F, ms: f64) -> Option<F::Output> {
+    if ms <= 0.0 {
+        return Some(future.await);
+    }
+    Race { future: Box::pin(future), timer: Box::pin(rate_limit::sleep(ms)) }.await
+}
+
+struct Race<F, T> {
+    future: Pin<Box<F>>,
+    timer: Pin<Box<T>>,
+}
+
+impl<F: Future, T: Future<Output = ()>> Future for Race<F, T> {
+    type Output = Option<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Poll::Ready(output) = self.future.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        match self.timer.as_mut().poll(cx) {
+            Poll::Ready(()) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Point in time after which a run stops sending batches
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Deadline {
+    started_at: f64,
+    limit_ms: f64,
+}
+
+impl Deadline {
+    pub fn new(started_at: f64, limit_minutes: u32) -> Self {
+        Self { started_at, limit_ms: f64::from(limit_minutes) * 60_000.0 }
+    }
+
+    pub fn check(&self, now: f64) -> Result<(), SemanticSearchError> {
+        if now - self.started_at <= self.limit_ms {
+            return Ok(());
+        }
+        Err(SemanticSearchError::DeadlineExceeded(format!(
+            "the run was stopped after {:.0} minutes", self.limit_ms / 60_000.0)))
+    }
+}
+
+/// Aborts the run before the next batch once the deadline has passed
+pub struct DeadlineHook {
+    deadline: Deadline,
+}
+
+impl DeadlineHook {
+    pub fn start(limit_minutes: u32) -> Self {
+        Self { deadline: Deadline::new(js_sys::Date::now(), limit_minutes) }
+    }
+}
+
+impl PipelineHook for DeadlineHook {
+    fn before_embed(&mut self, _batch: &[PipelineRecord]) -> Result<(), SemanticSearchError> {
+        self.deadline.check(js_sys::Date::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deadline_passes_after_the_limit() {
+        let deadline = Deadline::new(1000.0, 2);
+
+        assert!(deadline.check(1000.0).is_ok());
+        assert!(deadline.check(121_000.0).is_ok());
+        let e = deadline.check(121_001.0).unwrap_err();
+        assert_eq!(e.code(), crate::ErrorCode::DeadlineExceeded);
+        assert!(!e.code().is_retriable());
+    }
+
+    #[test]
+    fn timeouts_are_retriable() {
+        assert!(SemanticSearchError::Timeout("POST /embeddings".to_string()).code().is_retriable());
+        assert!(!SemanticSearchError::Cancelled.code().is_retriable());
+    }
+}
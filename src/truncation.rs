@@ -0,0 +1,222 @@
+use serde::Serialize;
+use tiktoken_rs::CoreBPE;
+
+use crate::SemanticSearchError;
+use crate::pipeline::{PipelineHook, PipelineRecord, PipelineReport};
+
+/// Inserted between the sampled head and tail of a truncated record
+const ELLIPSIS: &str = " … ";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TruncationEvent {
+    pub name: String,
+    pub header: String,
+    pub original_tokens: usize,
+    pub truncated_tokens: usize,
+}
+
+/// Shortens records longer than `max_tokens`, keeping whole sentences from
+/// the start and end of the text rather than cutting it off mid-sentence
+pub struct TruncationHook {
+    bpe: &'static CoreBPE,
+    max_tokens: usize,
+    events: Vec<TruncationEvent>,
+}
+
+impl TruncationHook {
+    pub fn new(max_tokens: usize) -> Self {
+        Self { bpe: crate::pricing::bpe(), max_tokens, events: Vec::new() }
+    }
+}
+
+impl PipelineHook for TruncationHook {
+    fn preprocess(&mut self, mut records: Vec<PipelineRecord>) -> Result<Vec<PipelineRecord>, SemanticSearchError> {
+        for record in records.iter_mut() {
+            let original_tokens = count(self.bpe, &record.text);
+            if original_tokens <= self.max_tokens {
+                continue;
+            }
+            record.text = truncate_text(self.bpe, &record.text, self.max_tokens);
+            self.events.push(TruncationEvent {
+                name: record.name.clone(),
+                header: record.header.clone(),
+                original_tokens,
+                truncated_tokens: count(self.bpe, &record.text),
+            });
+        }
+        Ok(records)
+    }
+
+    fn report(&self, report: &mut PipelineReport) {
+        report.truncated.extend(self.events.iter().cloned());
+    }
+}
+
+fn count(bpe: &CoreBPE, text: &str) -> usize {
+    bpe.encode_with_special_tokens(text).len()
+}
+
+/// Samples whole sentences from the head and tail of `text` to fit within `max_tokens`.
+///
+/// Falls back to a hard token cut when not even a single sentence fits.
+pub fn truncate_text(bpe: &CoreBPE, text: &str, max_tokens: usize) -> String {
+    if count(bpe, text) <= max_tokens {
+        return text.to_string();
+    }
+    let sentences = split_sentences(text);
+    let counts: Vec<usize> = sentences.iter().map(|sentence| count(bpe, sentence) + 1).collect();
+    let budget = max_tokens.saturating_sub(count(bpe, ELLIPSIS));
+
+    let mut used = 0;
+    let mut head = 0;
+    while head < sentences.len() && used + counts[head] <= budget / 2 {
+        used += counts[head];
+        head += 1;
+    }
+    let mut tail = sentences.len();
+    while tail > head && used + counts[tail - 1] <= budget {
+        used += counts[tail - 1];
+        tail -= 1;
+    }
+
+    let head_text = sentences[..head].join(" ");
+    let tail_text = sentences[tail..].join(" ");
+    let sampled = match (head_text.is_empty(), tail_text.is_empty()) {
+        (true, true) => text.to_string(),
+        (false, true) => head_text,
+        (true, false) => tail_text,
+        (false, false) => format!("{}{}{}", head_text, ELLIPSIS, tail_text),
+    };
+    hard_cut(bpe, &sampled, max_tokens)
+}
+
+fn hard_cut(bpe: &CoreBPE, text: &str, max_tokens: usize) -> String {
+    let tokens = bpe.encode_with_special_tokens(text);
+    if tokens.len() <= max_tokens {
+        return text.to_string();
+    }
+    // a cut can land inside a multi-byte character, so back off until it decodes
+    let mut end = max_tokens;
+    while end > 0 {
+        if let Ok(decoded) = bpe.decode(tokens[..end].to_vec()) {
+            return decoded;
+        }
+        end -= 1;
+    }
+    String::new()
+}
+
+/// Splits text into consecutive pieces of at most `max_tokens` tokens each, which together are
+/// exactly `text`. A character encoded as more than `max_tokens` tokens is kept whole in a
+/// longer piece.
+pub fn split_text(bpe: &CoreBPE, text: &str, max_tokens: usize) -> Vec<String> {
+    let tokens = bpe.encode_with_special_tokens(text);
+    if tokens.len() <= max_tokens || max_tokens == 0 {
+        return vec![text.to_string()];
+    }
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    while start < tokens.len() {
+        let limit = (start + max_tokens).min(tokens.len());
+        // as in hard_cut, back off until the range does not end inside a character, and only
+        // grow it past the limit when even its first token does
+        let piece = (start + 1..=limit).rev().chain(limit + 1..=tokens.len())
+            .find_map(|end| bpe.decode(tokens[start..end].to_vec()).ok().map(|piece| (end, piece)));
+        match piece {
+            Some((end, piece)) => {
+                pieces.push(piece);
+                start = end;
+            },
+            None => break,
+        }
+    }
+    pieces
+}
+
+/// Splits after sentence-ending punctuation that is followed by whitespace
+fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if matches!(c, '.' | '!' | '?') && chars.peek().is_some_and(|(_, next)| next.is_whitespace()) {
+            let end = i + c.len_utf8();
+            let sentence = text[start..end].trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence);
+            }
+            start = end;
+        }
+    }
+    let rest = text[start..].trim();
+    if !rest.is_empty() {
+        sentences.push(rest);
+    }
+    sentences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_on_sentence_end() {
+        let res = split_sentences("First one. Second! Third? v1.2 stays");
+
+        assert_eq!(res, vec!["First one.", "Second!", "Third?", "v1.2 stays"]);
+    }
+
+    #[test]
+    fn short_text_untouched() {
+        let bpe = crate::pricing::bpe();
+
+        assert_eq!(truncate_text(bpe, "Short text.", 100), "Short text.");
+    }
+
+    #[test]
+    fn keeps_head_and_tail_sentences() {
+        let bpe = crate::pricing::bpe();
+        let text = (1..=50).map(|i| format!("Sentence number {}.", i)).collect::<Vec<_>>().join(" ");
+
+        let res = truncate_text(bpe, &text, 40);
+
+        assert!(count(bpe, &res) <= 40);
+        assert!(res.starts_with("Sentence number 1."));
+        assert!(res.ends_with("Sentence number 50."));
+        assert!(res.contains(ELLIPSIS));
+    }
+
+    #[test]
+    fn split_text_covers_everything() {
+        let bpe = crate::pricing::bpe();
+        let text = "word ".repeat(25);
+
+        let res = split_text(bpe, &text, 10);
+
+        assert_eq!(res.len(), 3);
+        assert!(res.iter().all(|piece| count(bpe, piece) <= 10));
+        assert_eq!(res.concat(), text);
+    }
+
+    #[test]
+    fn split_text_keeps_multi_byte_characters() {
+        let bpe = crate::pricing::bpe();
+        let text = "🦀 Ferris 🦀 and 漢字 ".repeat(5);
+
+        let res = split_text(bpe, &text, 1);
+
+        assert!(res.len() > 1);
+        assert_eq!(res.concat(), text);
+    }
+
+    #[test]
+    fn hard_cut_single_long_sentence() {
+        let bpe = crate::pricing::bpe();
+        let text = "word ".repeat(100);
+
+        let res = truncate_text(bpe, &text, 10);
+
+        assert!(count(bpe, &res) <= 10);
+        assert!(!res.is_empty());
+    }
+}
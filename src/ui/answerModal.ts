@@ -0,0 +1,55 @@
+import { App, Component, MarkdownRenderer, Modal, setIcon } from "obsidian";
+import { Answer } from "src/api";
+import { semanticSearchSettings } from "src/settings/settings";
+import { noticeForError } from "./errors";
+
+import * as plugin from "../../pkg/obsidian_rust_plugin.js";
+
+// Asks the answer model a question about the notes and shows its answer with the notes it used
+export class AnswerModal extends Modal {
+  settings: semanticSearchSettings;
+  // Owns the rendered answer, so links in it are cleaned up with the modal
+  component = new Component();
+
+  constructor(app: App, settings: semanticSearchSettings) {
+    super(app);
+    this.settings = settings;
+  }
+
+  onOpen() {
+    this.component.load();
+    const contentEl = this.contentEl;
+    contentEl.createEl("h2", {text: "Answer question from notes"});
+    const input = contentEl.createEl("input", {cls: "prompt-input", placeholder: "Ask a question about your notes"});
+    const button = contentEl.createEl("button", {text: "Ask"});
+    const answerEl = contentEl.createDiv({cls: "ss-answer"});
+
+    const ask = async () => {
+      if (input.value.trim() === "") return;
+      answerEl.replaceChildren();
+      setIcon(answerEl, "loader");
+      let answer: Answer;
+      try {
+        answer = await plugin.answer_query(this.app, this.settings, input.value);
+      } catch (error) {
+        answerEl.replaceChildren();
+        noticeForError(error, "Failed to answer the question");
+        return;
+      }
+      answerEl.replaceChildren();
+      await MarkdownRenderer.render(this.app, answer.answer, answerEl.createDiv(), "", this.component);
+      const sourcesEl = answerEl.createDiv({cls: "setting-item-description"});
+      sourcesEl.setText(`Answered by ${answer.model} from ${answer.sources.map(source => source.name).join(", ")}`);
+    };
+    button.onclick = ask;
+    input.addEventListener("keydown", (event: KeyboardEvent) => {
+      if (event.key === "Enter") ask();
+    });
+    input.focus();
+  }
+
+  onClose() {
+    this.component.unload();
+    this.contentEl.empty();
+  }
+}
@@ -0,0 +1,81 @@
+import { App, Modal, normalizePath, Notice, setIcon, TFile } from "obsidian";
+import { semanticSearchSettings } from "src/settings/settings";
+import { noticeForError } from "./errors";
+
+import * as plugin from "../../pkg/obsidian_rust_plugin.js";
+
+type NoteCluster = {
+  label: string
+  notes: string[]
+  chunks: {name: string, header: string}[]
+}
+
+export class ClustersModal extends Modal {
+  settings: semanticSearchSettings;
+  // 0 lets the plugin pick the number of clusters from the size of the store
+  k: number;
+
+  constructor(app: App, settings: semanticSearchSettings, k = 0) {
+    super(app);
+    this.settings = settings;
+    this.k = k;
+  }
+
+  async onOpen() {
+    const contentEl = this.contentEl;
+    contentEl.createEl("h2", {text: "Note clusters"});
+    const resultsDiv = contentEl.createDiv();
+    setIcon(resultsDiv, "loader");
+
+    let clusters: NoteCluster[] = [];
+    try {
+      clusters = await plugin.cluster_notes(this.app, this.settings, this.k);
+    } catch (error) {
+      noticeForError(error, "Failed to cluster notes");
+    }
+
+    resultsDiv.replaceChildren();
+    clusters.forEach((cluster, index) => {
+      const clusterEl = resultsDiv.createDiv({cls: "ss-duplicate-cluster"});
+      clusterEl.createEl("h4", {text: `${cluster.label} (${cluster.chunks.length} sections)`});
+      const mocButton = clusterEl.createEl("button", {text: "Create map of content"});
+      mocButton.onclick = async () => await this.createMoc(cluster, index);
+      cluster.notes.forEach(name => {
+        const noteEl = clusterEl.createDiv({text: name, cls: ["suggestion-item", "ss-suggestion-item"]});
+        noteEl.onclick = async () => await this.openNote(name);
+      });
+    });
+  }
+
+  // Writes an outline of the cluster's notes by sub-topic into a new note and opens it
+  async createMoc(cluster: NoteCluster, index: number) {
+    const title = cluster.label.replace(/[\\/:*?"<>|#^\[\]]/g, " ").trim() || `Cluster ${index + 1}`;
+    const path = normalizePath(`MOC - ${title}.md`);
+    try {
+      await plugin.generate_moc(this.app, this.settings, { cluster: index, k: this.k }, path);
+    } catch (error) {
+      noticeForError(error, "Failed to create the map of content");
+      return;
+    }
+    const file = this.app.vault.getAbstractFileByPath(path);
+    if (file instanceof TFile) {
+      this.close();
+      await this.app.workspace.getLeaf("tab").openFile(file);
+    } else {
+      new Notice(`Created ${path}`);
+    }
+  }
+
+  async openNote(name: string) {
+    const file = this.app.vault.getMarkdownFiles().find((file: TFile) => file.name === name);
+    if (file) {
+      this.close();
+      await this.app.workspace.getLeaf("tab").openFile(file);
+    }
+  }
+
+  onClose() {
+    let { contentEl } = this;
+    contentEl.empty();
+  }
+}
@@ -0,0 +1,75 @@
+import { App, Modal, setIcon, TFile } from "obsidian";
+import { semanticSearchSettings } from "src/settings/settings";
+import { noticeForError } from "./errors";
+
+import * as plugin from "../../pkg/obsidian_rust_plugin.js";
+
+// Mirrors CoverageDiff in src/coverage.rs
+export type CoverageDiff = {
+  unindexed: string[]
+  stale: { path: string, modified: number, indexedAt: number }[]
+}
+
+// Lists the notes the index is missing and those edited since they were indexed, which is
+// what the next incremental update or reindex will embed.
+export class CoverageModal extends Modal {
+  settings: semanticSearchSettings;
+
+  constructor(app: App, settings: semanticSearchSettings) {
+    super(app);
+    this.settings = settings;
+  }
+
+  async onOpen() {
+    const contentEl = this.contentEl;
+    contentEl.createEl("h2", {text: "Notes missing from the index"});
+    const resultsDiv = contentEl.createDiv();
+    setIcon(resultsDiv, "loader");
+
+    let diff: CoverageDiff = {unindexed: [], stale: []};
+    try {
+      diff = await plugin.coverage_diff(this.app, this.settings);
+    } catch (error) {
+      noticeForError(error, "Failed to compare the vault with the index");
+    }
+
+    resultsDiv.replaceChildren();
+    if (diff.unindexed.length === 0 && diff.stale.length === 0) {
+      resultsDiv.setText("Every note is indexed and up to date.");
+      return;
+    }
+    this.renderList(resultsDiv, `Not indexed (${diff.unindexed.length})`, diff.unindexed.map(path => ({path, note: ""})));
+    this.renderList(resultsDiv, `Changed since indexed (${diff.stale.length})`, diff.stale.map(stale => ({
+      path: stale.path,
+      note: `edited ${new Date(stale.modified).toLocaleString()}, indexed version from ${new Date(stale.indexedAt).toLocaleString()}`,
+    })));
+  }
+
+  renderList(el: HTMLElement, title: string, notes: {path: string, note: string}[]) {
+    if (notes.length === 0) {
+      return;
+    }
+    el.createEl("h3", {text: title});
+    notes.forEach(({path, note}) => {
+      const itemEl = el.createDiv({cls: ["suggestion-item", "ss-suggestion-item"]});
+      itemEl.createDiv({text: path, cls: "suggestion-title"});
+      if (note !== "") {
+        itemEl.createDiv({text: note, cls: "suggestion-note"});
+      }
+      itemEl.onclick = async () => await this.openNote(path);
+    });
+  }
+
+  async openNote(path: string) {
+    const file = this.app.vault.getAbstractFileByPath(path);
+    if (file instanceof TFile) {
+      this.close();
+      await this.app.workspace.getLeaf("tab").openFile(file);
+    }
+  }
+
+  onClose() {
+    let { contentEl } = this;
+    contentEl.empty();
+  }
+}
@@ -0,0 +1,42 @@
+import { Suggestion } from "./suggestion";
+
+// Normalized form of a header used to detect results that would look the same.
+function key(text: string): string {
+  return text.trim().toLowerCase().replace(/\s+/g, " ");
+}
+
+// Appends the note title, then the parent heading, to headers shared by several
+// results until they can be told apart, and drops results whose display string
+// is still identical to a higher ranked one. Suggestions must be ranked by score.
+export function disambiguate(suggestions: Suggestion[]): Suggestion[] {
+  const headerCounts = countBy(suggestions, suggestion => key(suggestion.header));
+  suggestions.forEach(suggestion => {
+    suggestion.displayHeader = suggestion.header;
+    if ((headerCounts.get(key(suggestion.header)) ?? 0) > 1) {
+      suggestion.displayHeader = `${suggestion.header} — ${suggestion.noteTitle()}`;
+    }
+  });
+
+  const titledCounts = countBy(suggestions, suggestion => key(suggestion.displayHeader));
+  suggestions.forEach(suggestion => {
+    if ((titledCounts.get(key(suggestion.displayHeader)) ?? 0) > 1 && suggestion.parentHeading) {
+      suggestion.displayHeader = `${suggestion.displayHeader} › ${suggestion.parentHeading}`;
+    }
+  });
+
+  const seen = new Set<string>();
+  return suggestions.filter(suggestion => {
+    const displayKey = key(suggestion.displayHeader);
+    if (seen.has(displayKey)) {
+      return false;
+    }
+    seen.add(displayKey);
+    return true;
+  });
+}
+
+function countBy<T>(items: T[], keyFn: (item: T) => string): Map<string, number> {
+  const counts = new Map<string, number>();
+  items.forEach(item => counts.set(keyFn(item), (counts.get(keyFn(item)) ?? 0) + 1));
+  return counts;
+}
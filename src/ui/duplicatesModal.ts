@@ -0,0 +1,78 @@
+import { App, Modal, setIcon, TFile } from "obsidian";
+import { semanticSearchSettings } from "src/settings/settings";
+import { noticeForError } from "./errors";
+
+import * as plugin from "../../pkg/obsidian_rust_plugin.js";
+
+type DuplicateMember = {
+  name: string
+  header: string
+  score: number
+}
+
+type DuplicateCluster = {
+  members: DuplicateMember[]
+}
+
+// Mirrors DuplicateReport in src/duplicates.rs
+type DuplicateReport = {
+  clusters: DuplicateCluster[]
+  truncated: boolean
+}
+
+export class DuplicatesModal extends Modal {
+  settings: semanticSearchSettings;
+  threshold: number;
+
+  constructor(app: App, settings: semanticSearchSettings, threshold = 0.95) {
+    super(app);
+    this.settings = settings;
+    this.threshold = threshold;
+  }
+
+  async onOpen() {
+    const contentEl = this.contentEl;
+    contentEl.createEl("h2", {text: "Near-duplicate sections"});
+    const resultsDiv = contentEl.createDiv();
+    setIcon(resultsDiv, "loader");
+
+    let report: DuplicateReport = {clusters: [], truncated: false};
+    try {
+      report = await plugin.find_duplicates(this.app, this.settings, this.threshold);
+    } catch (error) {
+      noticeForError(error, "Failed to find duplicates");
+    }
+
+    resultsDiv.replaceChildren();
+    if (report.truncated) {
+      resultsDiv.createDiv({text: "The index is too large to compare every pair of sections, so some duplicates may be missing.", cls: "suggestion-note"});
+    }
+    if (report.clusters.length === 0) {
+      resultsDiv.createDiv({text: `No sections are more than ${this.threshold * 100}% similar.`});
+      return;
+    }
+
+    report.clusters.forEach(cluster => {
+      const clusterEl = resultsDiv.createDiv({cls: "ss-duplicate-cluster"});
+      cluster.members.forEach(member => {
+        const memberEl = clusterEl.createDiv({cls: ["suggestion-item", "ss-suggestion-item"]});
+        memberEl.createDiv({text: member.header, cls: "suggestion-title"});
+        memberEl.createDiv({text: `${member.name} (${(member.score * 100).toFixed(1)}%)`, cls: "suggestion-note"});
+        memberEl.onclick = async () => await this.openMember(member);
+      });
+    });
+  }
+
+  async openMember(member: DuplicateMember) {
+    const file = this.app.vault.getMarkdownFiles().find((file: TFile) => file.name === member.name);
+    if (file) {
+      this.close();
+      await this.app.workspace.getLeaf("tab").openFile(file);
+    }
+  }
+
+  onClose() {
+    let { contentEl } = this;
+    contentEl.empty();
+  }
+}
@@ -0,0 +1,101 @@
+import { Notice } from "obsidian";
+
+// Mirrors ErrorCode in src/error.rs
+export type WASMErrorCode =
+  | "AuthFailed"
+  | "RateLimited"
+  | "QuotaExceeded"
+  | "InvalidRequest"
+  | "ApiError"
+  | "NetworkError"
+  | "MalformedCsv"
+  | "MalformedResponse"
+  | "MissingInputFile"
+  | "MissingEmbeddingFile"
+  | "FileNotFound"
+  | "InvalidArgument"
+  | "InvalidSettings"
+  | "Cancelled"
+  | "StoreLocked"
+  | "SchemaMismatch"
+  | "ReindexRequired"
+  | "DimensionMismatch"
+  | "BudgetExceeded"
+  | "Timeout"
+  | "DeadlineExceeded"
+  | "DecryptionFailed"
+  | "ObsidianError"
+  | "InternalError";
+
+export type WASMError = {
+  code: WASMErrorCode
+  message: string
+  // Whether trying again later may succeed
+  retriable: boolean
+}
+
+export function isWASMError(error: unknown): error is WASMError {
+  return typeof error === "object" && error !== null && "code" in error && "message" in error;
+}
+
+// Shows a notice tailored to the error code, falling back to the given message.
+export function noticeForError(error: unknown, fallback: string) {
+  console.error(error);
+  if (!isWASMError(error)) {
+    new Notice(fallback);
+    return;
+  }
+
+  switch (error.code) {
+    case "AuthFailed":
+      new Notice("Authentication failed. Check your API key in the Semantic Search settings.");
+      break;
+    case "RateLimited":
+      new Notice("Rate limited by the embedding API. Wait a moment and try again, or increase the number of batches.");
+      break;
+    case "QuotaExceeded":
+      new Notice("Your API quota has been exceeded. Check your plan and billing details.");
+      break;
+    case "MissingInputFile":
+      new Notice("No input found. Run the 'Generate Input' command first.");
+      break;
+    case "MissingEmbeddingFile":
+      new Notice("No embeddings found. Run the 'Generate Embeddings' command first.");
+      break;
+    case "MalformedCsv":
+      new Notice("Generated files are malformed. Re-run 'Generate Input' and 'Generate Embeddings'.");
+      break;
+    case "SchemaMismatch":
+      new Notice(`${error.message}. Re-run 'Generate Input' and 'Generate Embeddings'.`);
+      break;
+    case "ReindexRequired":
+      new Notice(`${error.message}. Re-run 'Generate Embeddings' with the current model, or switch back to the model the embeddings were created with.`);
+      break;
+    case "DimensionMismatch":
+      new Notice(`${error.message}. Check that your API base URL and model return embeddings of a consistent size.`);
+      break;
+    case "BudgetExceeded":
+      new Notice(`${error.message}. Raise 'Max cost per run' in the Semantic Search settings, or ignore the largest folders.`);
+      break;
+    case "StoreLocked":
+      new Notice("Another Semantic Search command is still running. Try again once it has finished.");
+      break;
+    case "Cancelled":
+      new Notice("Cancelled.");
+      break;
+    case "Timeout":
+      new Notice(`${error.message}. The API may be overloaded; try again, or raise 'Request timeout' in the Semantic Search settings.`);
+      break;
+    case "DeadlineExceeded":
+      new Notice(`${error.message}. Raise 'Run deadline' in the Semantic Search settings to let longer runs finish.`);
+      break;
+    case "DecryptionFailed":
+      new Notice(`${error.message}. The passphrase is under 'Encryption passphrase' in the Semantic Search settings.`);
+      break;
+    case "NetworkError":
+      new Notice("Could not reach the embedding API. Check your connection.");
+      break;
+    default:
+      new Notice(`${fallback}: ${error.message}`);
+  }
+}
@@ -0,0 +1,59 @@
+import { App, Modal, Notice, Setting } from "obsidian";
+import { semanticSearchSettings } from "src/settings/settings";
+import { noticeForError } from "./errors";
+
+import * as plugin from "../../pkg/obsidian_rust_plugin.js";
+
+export type ExportFormat = 'jsonl' | 'npy';
+
+// Mirrors ExportReport in src/export.rs
+export type ExportReport = {
+  records: number
+  dimensions: number
+  paths: string[]
+}
+
+export class ExportEmbeddingsModal extends Modal {
+  settings: semanticSearchSettings;
+  format: ExportFormat = 'jsonl';
+
+  constructor(app: App, settings: semanticSearchSettings) {
+    super(app);
+    this.settings = settings;
+  }
+
+  onOpen() {
+    const contentEl = this.contentEl;
+    contentEl.createEl("h2", {text: "Export embeddings"});
+    contentEl.createEl("p", {text: "The export is written next to the embedding file and replaces any earlier export in the same format."});
+
+    new Setting(contentEl)
+      .setName("Format")
+      .addDropdown(dropdown => dropdown
+        .addOptions({
+          jsonl: "JSON lines ({id, text, vector} per line)",
+          npy: "NumPy matrix (.npy) with a JSON manifest",
+        })
+        .setValue(this.format)
+        .onChange((value: ExportFormat) => this.format = value));
+
+    new Setting(contentEl)
+      .addButton(button => button
+        .setButtonText("Export")
+        .setCta()
+        .onClick(async () => {
+          this.close();
+          try {
+            const report: ExportReport = await plugin.export_embeddings(this.app, this.settings, this.format);
+            new Notice(`Exported ${report.records} embeddings to ${report.paths.join(" and ")}`);
+          } catch (error) {
+            noticeForError(error, "Failed to export embeddings");
+          }
+        }));
+  }
+
+  onClose() {
+    let { contentEl } = this;
+    contentEl.empty();
+  }
+}
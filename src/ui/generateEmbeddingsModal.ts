@@ -1,13 +1,26 @@
 import { App, Modal, Notice } from "obsidian";
 import { semanticSearchSettings } from "src/settings/settings.js";
+import { noticeForError } from "./errors";
 
 import * as plugin from "../../pkg/obsidian_rust_plugin.js";
 
+type FilePlan = {
+  name: string
+  chunks: number
+  tokens: number
+  dollars: number
+}
+
+// Number of the most expensive notes listed in the plan
+const PLAN_FILES_SHOWN = 10;
+
 export class GenerateEmbeddingsModal extends Modal {
   wasmGenerateEmbeddingsCommand : plugin.GenerateEmbeddingsCommand;
+  settings: semanticSearchSettings;
 
   constructor(app: App, settings: semanticSearchSettings) {
     super(app);
+    this.settings = settings;
     this.wasmGenerateEmbeddingsCommand = new plugin.GenerateEmbeddingsCommand(app, settings);
   }
 
@@ -17,31 +30,74 @@ export class GenerateEmbeddingsModal extends Modal {
      const exists_container = contentEl.createDiv();
      const estimate_text = estimate_container.createDiv();
      estimate_text.setText("Estimated cost of query: ...");
+     let overBudget = false;
 
      try {
-       const cost = await this.wasmGenerateEmbeddingsCommand.get_input_cost_estimate();
+       const estimate = await this.wasmGenerateEmbeddingsCommand.get_input_cost_estimate();
        const exists = await this.wasmGenerateEmbeddingsCommand.check_embedding_file_exists();
        if (exists) {
-         exists_container.createSpan({text: "Warning: the file 'embedding.csv' already exists.", cls: "ss-exists-text"})
+         exists_container.createSpan({text: `Warning: the file '${this.settings.embeddingFileName}' already exists.`, cls: "ss-exists-text"})
+       }
+       estimate_text.setText(`Estimated cost of query: $${estimate.dollars.toFixed(4)} (${estimate.tokens} tokens in ${estimate.requests} requests to ${estimate.model})`);
+       const plan = await this.wasmGenerateEmbeddingsCommand.plan_embeddings();
+       this.renderPlan(estimate_container, plan.files);
+       overBudget = this.settings.maxCostPerRun > 0 && plan.estimate.dollars > this.settings.maxCostPerRun;
+       if (overBudget) {
+         estimate_container.createDiv({text: `Warning: this exceeds your cap of $${this.settings.maxCostPerRun.toFixed(2)} per run.`, cls: "ss-exists-text"});
        }
-       estimate_text.setText("Estimated cost of query: $" + cost);
      } catch (error) {
        console.error(error)
      }
 
-     const confirm_button = contentEl.createEl("button", {text: "Generate Embeddings"})
+     const action = this.settings.useBatchApi ? "Submit Batch Job" : "Generate Embeddings";
+     const confirm_button = contentEl.createEl("button", {text: overBudget ? `${action} anyway` : action})
      confirm_button.onclick = async () => {
        this.close();
+       if (overBudget) {
+         this.wasmGenerateEmbeddingsCommand.allow_over_budget();
+       }
+       if (this.settings.useBatchApi) {
+         await this.submitBatchJob();
+         return;
+       }
        try {
-         await this.wasmGenerateEmbeddingsCommand.get_embeddings();
-         new Notice("Successfully generated embeddings in 'embedding.csv'");
+         const report = await this.wasmGenerateEmbeddingsCommand.get_embeddings();
+         new Notice(`Successfully generated ${report.records_embedded} embeddings in '${this.settings.embeddingFileName}'`);
+         if (report.truncated.length > 0) {
+           new Notice(`${report.truncated.length} sections were too long and have been truncated. See the console for details.`);
+           console.table(report.truncated);
+         }
+         if (report.records_rejected > 0) {
+           new Notice(`${report.records_rejected} sections were left out because the API returned embeddings of an unexpected size. See the last run report in settings for details.`);
+         }
        } catch (error) {
-         console.error(error);
-         new Notice("Failed to create embeddings. Error: ", error);
+         noticeForError(error, "Failed to create embeddings");
        }
      }
   }
 
+  async submitBatchJob() {
+    try {
+      const status = await this.wasmGenerateEmbeddingsCommand.submit_batch_job();
+      new Notice(`Submitted batch job ${status.id} with ${status.requests} requests. The embedding file is replaced once it completes, usually within a few hours.`);
+    } catch (error) {
+      noticeForError(error, "Failed to submit batch job");
+    }
+  }
+
+  // Lists the notes that dominate the cost so they can be excluded before embedding.
+  renderPlan(el: HTMLElement, files: FilePlan[]) {
+    if (files.length === 0) {
+      return;
+    }
+    const details = el.createEl("details");
+    details.createEl("summary", {text: `Most expensive notes (${files.length} notes in total)`});
+    const list = details.createEl("ul");
+    files.slice(0, PLAN_FILES_SHOWN).forEach(file => {
+      list.createEl("li", {text: `${file.name}: ${file.chunks} sections, ${file.tokens} tokens, $${file.dollars.toFixed(4)}`});
+    });
+  }
+
   onClose() {
     let { contentEl } = this;
     contentEl.empty();
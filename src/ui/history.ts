@@ -0,0 +1,23 @@
+// Mirrors SearchHistory in src/history.rs
+export type HistoryResult = {
+  name: string
+  header: string
+  score: number
+}
+
+export type HistoryEntry = {
+  query: string
+  searchedAt: number
+  results: HistoryResult[]
+}
+
+export type SavedSearch = {
+  name: string
+  query: string
+  savedAt: number
+}
+
+export type SearchHistory = {
+  history: HistoryEntry[]
+  saved: SavedSearch[]
+}
@@ -0,0 +1,113 @@
+import { App, Modal, Notice, Setting, ToggleComponent } from "obsidian";
+import { semanticSearchSettings } from "src/settings/settings";
+import { noticeForError } from "./errors";
+
+import * as plugin from "../../pkg/obsidian_rust_plugin.js";
+
+type ImportFormat = 'smartConnections' | 'khoj' | 'jsonl' | 'npy';
+
+type ImportReport = {
+  imported: number
+  skipped: number
+  replaced: number
+  path: string
+}
+
+const DEFAULT_PATHS: Record<ImportFormat, string> = {
+  smartConnections: ".smart-connections/embeddings-2.json",
+  khoj: "khoj-entries.jsonl",
+  jsonl: "embedding.jsonl",
+  npy: "embedding.npy",
+};
+
+// Formats produced by your own scripts are usually partial, so they are merged by default
+const MERGE_BY_DEFAULT: Record<ImportFormat, boolean> = {
+  smartConnections: false,
+  khoj: false,
+  jsonl: true,
+  npy: true,
+};
+
+export class ImportEmbeddingsModal extends Modal {
+  settings: semanticSearchSettings;
+  format: ImportFormat = 'smartConnections';
+  path = DEFAULT_PATHS.smartConnections;
+  sourceModel = "";
+  merge = false;
+
+  constructor(app: App, settings: semanticSearchSettings) {
+    super(app);
+    this.settings = settings;
+  }
+
+  onOpen() {
+    const contentEl = this.contentEl;
+    contentEl.createEl("h2", {text: "Import embeddings"});
+    contentEl.createEl("p", {text: `Imported embeddings must have been created with the configured model (${this.settings.model}).`});
+
+    let pathInput: HTMLInputElement;
+    let mergeToggle: ToggleComponent;
+    new Setting(contentEl)
+      .setName("Format")
+      .addDropdown(dropdown => dropdown
+        .addOptions({
+          smartConnections: "Smart Connections",
+          khoj: "khoj (entries exported as JSON lines)",
+          jsonl: "JSON lines ({name, header, vector} or {id, text, vector} per line)",
+          npy: "NumPy matrix (.npy) with a .manifest.json naming each row",
+        })
+        .setValue(this.format)
+        .onChange((value: ImportFormat) => {
+          this.format = value;
+          this.path = DEFAULT_PATHS[value];
+          pathInput.value = this.path;
+          mergeToggle.setValue(MERGE_BY_DEFAULT[value]);
+        }));
+
+    new Setting(contentEl)
+      .setName("Index file")
+      .setDesc("Path of the index file, relative to the vault root.")
+      .addText(text => {
+        pathInput = text.inputEl;
+        text.setValue(this.path)
+          .onChange(value => this.path = value);
+      });
+
+    new Setting(contentEl)
+      .setName("Index model")
+      .setDesc("Model the index was created with. Leave empty to use the format's default model.")
+      .addText(text => text
+        .setPlaceholder("text-embedding-ada-002")
+        .onChange(value => this.sourceModel = value));
+
+    new Setting(contentEl)
+      .setName("Merge into the current store")
+      .setDesc("Replace only the notes found in the index and keep every other note. Otherwise the import replaces the whole store.")
+      .addToggle(toggle => {
+        mergeToggle = toggle;
+        toggle.setValue(this.merge)
+          .onChange(value => this.merge = value);
+      });
+
+    new Setting(contentEl)
+      .addButton(button => button
+        .setButtonText("Import")
+        .setCta()
+        .onClick(async () => {
+          this.close();
+          try {
+            const report: ImportReport = await plugin.import_embeddings(this.app, this.settings, this.format, this.path, this.sourceModel, this.merge);
+            new Notice(`Imported ${report.imported} embeddings into '${report.path}'`
+              + (report.replaced > 0 ? `, replacing ${report.replaced} stored sections` : "")
+              + (report.skipped > 0 ? `, skipped ${report.skipped} entries without usable vectors.` : "."));
+          } catch (error) {
+            noticeForError(error, "Failed to import embeddings");
+          }
+        }));
+  }
+
+  onClose() {
+    let { contentEl } = this;
+    contentEl.empty();
+  }
+}
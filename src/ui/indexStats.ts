@@ -0,0 +1,42 @@
+// Mirrors IndexStats in src/stats.rs
+export type IndexStats = {
+  records: number
+  notes: number
+  dimensions: number
+  model: string | null
+  bytes: number
+  orphans: number
+  malformed: number
+  oldest: number | null
+  newest: number | null
+  markdownNotes: number
+  indexedNotes: number
+  coverage: number
+}
+
+// One line summing up the index, for notices
+export function summarizeIndexStats(stats: IndexStats): string {
+  return `${stats.records} sections of ${stats.notes} notes, covering ${stats.coverage.toFixed(0)}% of the vault (${stats.indexedNotes} of ${stats.markdownNotes} notes)`;
+}
+
+// Renders the health of the embedding store, pointing at the commands that fix what it finds.
+export function renderIndexStats(el: HTMLElement, stats: IndexStats) {
+  el.empty();
+  el.createEl("p", {text: summarizeIndexStats(stats)});
+
+  const list = el.createEl("ul");
+  list.createEl("li", {text: `Model: ${stats.model ?? "unknown"}, ${stats.dimensions} dimensions`});
+  list.createEl("li", {text: `Embedding file: ${(stats.bytes / 1024).toFixed(1)} KB`});
+  if (stats.oldest !== null && stats.newest !== null) {
+    list.createEl("li", {text: `Embedded between ${new Date(stats.oldest).toLocaleString()} and ${new Date(stats.newest).toLocaleString()}`});
+  }
+  if (stats.orphans > 0) {
+    list.createEl("li", {text: `${stats.orphans} sections of deleted notes, run Compact embedding store to remove them`});
+  }
+  if (stats.malformed > 0) {
+    list.createEl("li", {text: `${stats.malformed} unreadable rows, run Check index files to see them`});
+  }
+  if (stats.indexedNotes < stats.markdownNotes) {
+    list.createEl("li", {text: `${stats.markdownNotes - stats.indexedNotes} notes are not indexed yet, run Generate Input and Generate Embeddings`});
+  }
+}
@@ -1,5 +1,6 @@
 import { App, debounce, Debouncer, Editor, EditorPosition, EditorSuggest, EditorSuggestContext, EditorSuggestTriggerInfo, normalizePath, renderResults, SearchResult, setIcon, TFile } from "obsidian";
 import { semanticSearchSettings } from "src/settings/settings";
+import { disambiguate } from "./disambiguate";
 import { Suggestion, WASMSuggestion } from "./suggestion";
 
 import * as plugin from "../../pkg/obsidian_rust_plugin.js";
@@ -7,12 +8,14 @@ import * as plugin from "../../pkg/obsidian_rust_plugin.js";
 export class LinkSuggest extends EditorSuggest<Suggestion> {
     app: App;
     settings: semanticSearchSettings;
+    index: plugin.SemanticIndex;
     debouncer: Debouncer<[EditorSuggestContext, (suggestions: Suggestion[]) => void], void> | undefined;
 
-    constructor(app: App, settings: semanticSearchSettings) {
+    constructor(app: App, settings: semanticSearchSettings, index: plugin.SemanticIndex) {
       super(app);
       this.app = app;
       this.settings = settings;
+      this.index = index;
     }
 
     onTrigger(cursor: EditorPosition, editor: Editor, file: TFile): EditorSuggestTriggerInfo | null {
@@ -55,14 +58,12 @@ export class LinkSuggest extends EditorSuggest<Suggestion> {
           return []
         }
 
-        const wasmSuggestions: WASMSuggestion[] = await plugin.get_suggestions(this.app, this.settings.apiKey, query);
+        const wasmSuggestions: WASMSuggestion[] = await this.index.query(this.app, this.settings, query, null);
         const suggestions: Suggestion[] = wasmSuggestions.map(wasmSuggestion => new Suggestion(this.app, wasmSuggestion, this.settings.sectionDelimeterRegex));
 
-        suggestions.forEach(suggestion => {
-          suggestion.addSuggestionFile().addSuggestionHeading();
-        })
+        await Promise.all(suggestions.map(suggestion => suggestion.addSuggestionFile().addSuggestionHeading()));
 
-        cb(suggestions);
+        cb(disambiguate(suggestions));
       }, 500, true);
 
 
@@ -79,7 +80,7 @@ export class LinkSuggest extends EditorSuggest<Suggestion> {
       console.log(suggestion);
       const resultContainer = el.createDiv({cls: ["suggestion-item", "mod-complex" ]})
       if (suggestion.match && suggestion.file) {
-        const div = this.renderContent(resultContainer, suggestion.header, suggestion.match);
+        const div = this.renderContent(resultContainer, suggestion.displayHeader, suggestion.match);
         this.renderPath(div, suggestion.file, suggestion.match);
       }
     }
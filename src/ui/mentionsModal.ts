@@ -0,0 +1,80 @@
+import { App, Modal, Notice, setIcon, TFile } from "obsidian";
+import { semanticSearchSettings } from "src/settings/settings";
+import { noticeForError } from "./errors";
+
+import * as plugin from "../../pkg/obsidian_rust_plugin.js";
+
+// Mirrors UnlinkedMention in src/mentions.rs
+type UnlinkedMention = {
+  name: string
+  header: string
+  score: number
+  lines: { start: number, end: number } | null
+}
+
+// Mirrors UnlinkedMentions in src/mentions.rs
+type UnlinkedMentions = {
+  wikilink: string
+  mentions: UnlinkedMention[]
+}
+
+// Passages of other notes that are about a note but don't link to it
+export class MentionsModal extends Modal {
+  settings: semanticSearchSettings;
+  file: TFile;
+  threshold: number;
+
+  constructor(app: App, settings: semanticSearchSettings, file: TFile, threshold = 0.85) {
+    super(app);
+    this.settings = settings;
+    this.file = file;
+    this.threshold = threshold;
+  }
+
+  async onOpen() {
+    const contentEl = this.contentEl;
+    contentEl.createEl("h2", {text: `Unlinked mentions of ${this.file.basename}`});
+    const resultsDiv = contentEl.createDiv();
+    setIcon(resultsDiv, "loader");
+
+    let found: UnlinkedMentions | undefined;
+    try {
+      found = await plugin.find_unlinked_mentions(this.app, this.settings, this.file.path, this.threshold);
+    } catch (error) {
+      noticeForError(error, "Failed to find unlinked mentions");
+    }
+
+    resultsDiv.replaceChildren();
+    if (!found || found.mentions.length === 0) {
+      resultsDiv.setText(`No passages are more than ${this.threshold * 100}% similar without linking here.`);
+      return;
+    }
+
+    const wikilink = found.wikilink;
+    resultsDiv.createDiv({text: `Choose a passage to open it with ${wikilink} copied, ready to paste.`, cls: "setting-item-description"});
+    found.mentions.forEach(mention => {
+      const mentionEl = resultsDiv.createDiv({cls: ["suggestion-item", "ss-suggestion-item"]});
+      mentionEl.createDiv({text: mention.header, cls: "suggestion-title"});
+      mentionEl.createDiv({text: `${mention.name} (${(mention.score * 100).toFixed(1)}%)`, cls: "suggestion-note"});
+      mentionEl.onclick = async () => await this.openMention(mention, wikilink);
+    });
+  }
+
+  async openMention(mention: UnlinkedMention, wikilink: string) {
+    const file = this.app.vault.getFiles().find((file: TFile) => file.name === mention.name || file.path === mention.name);
+    if (!file) return;
+    this.close();
+    await navigator.clipboard.writeText(wikilink);
+    new Notice(`Copied ${wikilink}`);
+    const line = mention.lines?.start ?? 0;
+    await this.app.workspace.getLeaf("tab").openFile(file, {
+      active: true,
+      eState: { line, cursor: { from: { line, ch: 0 }, to: { line, ch: 0 } } }
+    });
+  }
+
+  onClose() {
+    let { contentEl } = this;
+    contentEl.empty();
+  }
+}
@@ -1,21 +1,40 @@
-import { App, Editor, Modal, normalizePath, Notice, OpenViewState, PaneType, renderResults, SearchResult, setIcon, SplitDirection, TFile, WorkspaceLeaf } from "obsidian";
+import { App, Editor, Modal, moment, normalizePath, Notice, OpenViewState, PaneType, renderResults, SearchResult, setIcon, SplitDirection, TFile, WorkspaceLeaf } from "obsidian";
 import { semanticSearchSettings } from "src/settings/settings";
-import { Suggestion, WASMSuggestion } from "./suggestion";
+import { disambiguate } from "./disambiguate";
+import { noticeForError } from "./errors";
+import { SearchHistory } from "./history";
+import { StoreEntry } from "./stores";
+import { Snippet, Suggestion, WASMSuggestion, WASMSuggestions } from "./suggestion";
 
 import * as plugin from "../../pkg/obsidian_rust_plugin.js";
 
+// Folder the query modal saves results notes to
+const RESULTS_FOLDER = "Search results";
+
 export class QueryModal extends Modal {
   settings: semanticSearchSettings;
-  estimatedCost = 0;
-  timerId: number;
-  delay = 200;
-
-  constructor(app: App, settings: semanticSearchSettings) {
+  // Searches the plugin's resident embeddings when given, otherwise a session reading the embedding file
+  index: plugin.SemanticIndex | plugin.QuerySession | undefined;
+  folderFilter = "";
+  // Text to steer results away from
+  exclude = "";
+  // Only notes modified within this many days are searched, 0 for any time
+  modifiedWithinDays = 0;
+  // Store chosen in the modal, overriding the one in settings
+  store: StoreEntry | undefined;
+  // Sections the last search left out because their embeddings are unusable
+  skipped = 0;
+  // Rows of the embedding file the last search could not read, moved to its .corrupt file
+  malformed = 0;
+
+  constructor(app: App, settings: semanticSearchSettings, index?: plugin.SemanticIndex) {
     super(app);
     this.settings = settings;
+    this.index = index;
   }
 
   onOpen(): void {
+      this.index ??= new plugin.QuerySession();
       const contentEl = this.modalEl;
       this.modalEl.removeClass("modal");
       this.modalEl.addClass("prompt");
@@ -27,34 +46,146 @@ export class QueryModal extends Modal {
       const estimate_container = contentEl.createDiv({cls: "prompt-instructions"});
       const estimate_text = estimate_container.createDiv({cls: "prompt-instruction"});
       estimate_text.setText("Estimated cost of query: $0");
-      input.addEventListener("input", (e) => {
-        this.debounce(() => this.update_query_cost_estimate(e, estimate_text), this.delay);
-      })
+      // Counting tokens is cheap once the tokenizer is built, so the estimate follows every keystroke
+      input.addEventListener("input", () => this.update_query_cost_estimate(input.value, estimate_text));
 
       const button = inputContainer.createEl("button", {text: "Submit", cls: "ss-query-submit-button"});
+      const folderInput = estimate_container.createEl("input", {cls: "ss-query-folder-filter", placeholder: "Filter by folder or path, e.g. Projects/**"});
+      folderInput.addEventListener("change", () => {
+        this.folderFilter = folderInput.value;
+        // Only the filter changed, so the query embedding is reused
+        if (input.value !== "") button.click();
+      })
+      const excludeInput = estimate_container.createEl("input", {cls: "ss-query-folder-filter", placeholder: "Not like…"});
+      excludeInput.addEventListener("change", () => {
+        this.exclude = excludeInput.value;
+        if (input.value !== "") button.click();
+      })
+      const dateSelect = estimate_container.createEl("select", {cls: "dropdown ss-query-date-filter"});
+      [["Any time", 0], ["Last 7 days", 7], ["Last 30 days", 30], ["Last 90 days", 90], ["Last year", 365]].forEach(([text, days]) => {
+        dateSelect.createEl("option", {text: String(text), value: String(days)});
+      });
+      dateSelect.addEventListener("change", () => {
+        this.modifiedWithinDays = parseInt(dateSelect.value);
+        if (input.value !== "") button.click();
+      });
+      this.addStorePicker(estimate_container, () => {
+        if (input.value !== "") button.click();
+      });
+      this.addHistoryPicker(estimate_container, input, button);
+
       const resultsDiv = contentEl.createDiv({cls: "prompt-results"});
       button.onclick = async () => {
         resultsDiv.replaceChildren();
         setIcon(resultsDiv, "loader");
-        const suggestions: Suggestion[] = await this.getSuggestions(input.value);
+        let suggestions: Suggestion[] = [];
+        try {
+          suggestions = await this.getSuggestions(input.value);
+        } catch (error) {
+          noticeForError(error, "Failed to get suggestions");
+        }
         resultsDiv.replaceChildren();
         suggestions.forEach(suggestion => {
           this.renderSuggestion(suggestion, resultsDiv);
         })
+        if (this.skipped > 0) {
+          resultsDiv.createDiv({cls: "suggestion-note", text: `Skipped ${this.skipped} sections whose embeddings are empty or invalid, regenerate embeddings to search them`});
+        }
+        if (this.malformed > 0) {
+          resultsDiv.createDiv({cls: "suggestion-note", text: `Skipped ${this.malformed} unreadable rows of the embedding file, moved to the .corrupt file next to it`});
+        }
+      }
+  }
+
+  // Lets the user pick which embedding store to search when more than one has been built.
+  async addStorePicker(el: HTMLElement, onChange: () => void) {
+    let stores: StoreEntry[] = [];
+    try {
+      stores = await plugin.list_embedding_stores(this.app, this.settings);
+    } catch (error) {
+      console.error(error);
+    }
+    if (stores.length < 2) {
+      return;
+    }
+    const select = el.createEl("select", {cls: "dropdown ss-query-store-picker"});
+    stores.forEach(store => {
+      select.createEl("option", {text: store.name === "" ? "Default store" : store.name, value: store.name});
+    });
+    select.value = this.settings.embeddingStore;
+    select.addEventListener("change", () => {
+      this.store = stores.find(store => store.name === select.value);
+      onChange();
+    });
+  }
+
+  // Lets the user rerun a saved or recent search, and save the current query under a name.
+  async addHistoryPicker(el: HTMLElement, input: HTMLInputElement, button: HTMLButtonElement) {
+    let history: SearchHistory = {history: [], saved: []};
+    try {
+      history = await plugin.get_search_history(this.app, this.settings);
+    } catch (error) {
+      console.error(error);
+    }
+    const select = el.createEl("select", {cls: "dropdown ss-query-history"});
+    select.createEl("option", {text: "Recent searches", value: ""});
+    if (history.saved.length > 0) {
+      const saved = select.createEl("optgroup", {attr: {label: "Saved"}});
+      history.saved.forEach(search => saved.createEl("option", {text: search.name, value: search.query}));
+    }
+    if (history.history.length > 0) {
+      const recent = select.createEl("optgroup", {attr: {label: "Recent"}});
+      history.history.forEach(entry => recent.createEl("option", {text: entry.query, value: entry.query}));
+    }
+    select.addEventListener("change", () => {
+      if (select.value === "") return;
+      input.value = select.value;
+      input.dispatchEvent(new InputEvent("input"));
+      select.value = "";
+      button.click();
+    });
+
+    const save = el.createEl("button", {text: "Save search", cls: "ss-query-save-button"});
+    save.onclick = async () => {
+      const query = input.value.trim();
+      if (query === "") return;
+      try {
+        await plugin.save_search(this.app, this.settings, query, query);
+        new Notice(`Saved search "${query}"`);
+      } catch (error) {
+        noticeForError(error, "Failed to save search");
+      }
+    };
+
+    const saveResults = el.createEl("button", {text: "Save results to note", cls: "ss-query-save-button"});
+    saveResults.onclick = async () => {
+      const query = input.value.trim();
+      if (query === "") return;
+      const name = query.replace(/[\\/:*?"<>|#^[\]]/g, " ").replace(/\s+/g, " ").trim().slice(0, 80);
+      const path = normalizePath(`${RESULTS_FOLDER}/${name} ${moment().format("YYYY-MM-DD HHmm")}.md`);
+      try {
+        const note: {path: string, results: number} = await plugin.write_results_note(this.app, this.searchSettings(), query, path);
+        new Notice(`Saved ${note.results} results to ${note.path}`);
+        await this.app.workspace.openLinkText(note.path, "");
+        this.close();
+      } catch (error) {
+        noticeForError(error, "Failed to save results");
       }
+    };
   }
 
-  update_query_cost_estimate(e: Event, estimate_text: HTMLElement) {
-    if (e.target) {
-      const input = e.target as HTMLInputElement;
-      this.estimatedCost = plugin.get_query_cost_estimate(input.value);
+  // Settings for the current search, using the model the chosen store was built with.
+  searchSettings(): semanticSearchSettings {
+    if (!this.store) {
+      return this.settings;
     }
-    estimate_text.setText("Estimated cost of query: $" + this.estimatedCost);
+    return {...this.settings, embeddingStore: this.store.name, model: this.store.model};
   }
 
-  debounce(fn: Function, delay_in_ms: number) {
-    clearTimeout(this.timerId);
-    this.timerId = setTimeout(fn, delay_in_ms);
+  update_query_cost_estimate(query: string, estimate_text: HTMLElement) {
+    const tokens = plugin.count_tokens(query);
+    const estimatedCost = plugin.get_query_cost_estimate(query, this.settings.model);
+    estimate_text.setText(`Estimated cost of query: $${estimatedCost} (${tokens} tokens)`);
   }
 
   onClose() {
@@ -64,14 +195,22 @@ export class QueryModal extends Modal {
 
   // Returns all available suggestions.
   async getSuggestions(query: string): Promise<Suggestion[]> {
-    const wasmSuggestions: WASMSuggestion[] = await plugin.get_suggestions(this.app, this.settings.apiKey, query);
+    const filters = {
+      path: this.folderFilter,
+      exclude: this.exclude,
+      modifiedAfter: this.modifiedWithinDays > 0 ? Date.now() - this.modifiedWithinDays * 24 * 60 * 60 * 1000 : undefined,
+    };
+    const wasmSuggestions: WASMSuggestions = this.index instanceof plugin.SemanticIndex
+      ? await this.index.query(this.app, this.searchSettings(), query, filters)
+      : await this.index!.search(this.app, this.searchSettings(), query, filters);
+    this.skipped = wasmSuggestions.skipped ?? 0;
+    this.malformed = wasmSuggestions.malformed ?? 0;
+    plugin.record_search(this.app, this.settings, query, wasmSuggestions).catch(console.error);
     const suggestions: Suggestion[] = wasmSuggestions.map(wasmSuggestion => new Suggestion(this.app, wasmSuggestion, this.settings.sectionDelimeterRegex));
 
-    suggestions.forEach(async suggestion => {
-      await suggestion.addSuggestionFile().addSuggestionHeading();
-    })
+    await Promise.all(suggestions.map(suggestion => suggestion.addSuggestionFile().addSuggestionHeading()));
 
-    return suggestions;
+    return disambiguate(suggestions);
   }
 
   // Renders each suggestion item.
@@ -79,11 +218,23 @@ export class QueryModal extends Modal {
     const resultContainer = el.createDiv({cls: ["suggestion-item", "mod-complex", "ss-suggestion-item"]})
     resultContainer.onclick = async () => await this.onChooseSuggestion(suggestion);
     if (suggestion.match && suggestion.file) {
-      const div = this.renderContent(resultContainer, suggestion.header, suggestion.match);
+      const div = this.renderContent(resultContainer, suggestion.displayHeader, suggestion.match);
+      this.renderSnippet(div, suggestion.snippet);
       this.renderPath(div, suggestion.file, suggestion.match);
     }
   }
 
+  // Shows an excerpt of the result's text with the sentence matching the query highlighted
+  renderSnippet(parentEl: HTMLElement, snippet: Snippet | undefined) {
+    if (!snippet) {
+      return;
+    }
+    const snippetEl = parentEl.createDiv({cls: 'suggestion-note ss-snippet'});
+    snippetEl.appendText(snippet.before);
+    snippetEl.createEl('mark', {text: snippet.sentence});
+    snippetEl.appendText(snippet.after);
+  }
+
   renderContent(
     parentEl: HTMLElement,
     content: string,
@@ -168,7 +319,10 @@ export class QueryModal extends Modal {
     }
 
     if (matchingLeaf === undefined) {
-      if (suggestion.file) {
+      if (suggestion.link && !suggestion.pos) {
+        // the section couldn't be located in the note, let Obsidian resolve its heading path
+        await this.app.workspace.openLinkText(suggestion.link.linkText, "", "tab");
+      } else if (suggestion.file) {
         await this.openFileInLeaf(suggestion.file, "tab", "vertical", {
           active: true,
           eState
@@ -190,8 +344,8 @@ export class QueryModal extends Modal {
 export class LinkSuggestQueryModal extends QueryModal {
   editor: Editor;
 
-  constructor(app: App, settings: semanticSearchSettings, editor: Editor) {
-    super(app, settings);
+  constructor(app: App, settings: semanticSearchSettings, editor: Editor, index?: plugin.SemanticIndex) {
+    super(app, settings, index);
     this.editor = editor;
   }
 
@@ -215,8 +369,88 @@ export class LinkSuggestQueryModal extends QueryModal {
 
   async onChooseSuggestion(suggestion: Suggestion) {
     this.close();
-    const linkPath = normalizePath(encodeURI(suggestion.file?.path + "#" + suggestion.header));
+    const linkPath = normalizePath(encodeURI(suggestion.link?.linkText ?? suggestion.file?.path + "#" + suggestion.header));
     const textToLink = this.editor.getSelection();
     this.editor.replaceSelection(`[${textToLink}](${linkPath})`);
   }
 }
+
+export class RelatedTextModal extends QueryModal {
+  editor: Editor;
+
+  constructor(app: App, settings: semanticSearchSettings, editor: Editor) {
+    super(app, settings);
+    this.editor = editor;
+  }
+
+  onOpen(): void {
+    const selection = this.editor.getSelection();
+    if (selection === "") {
+      new Notice("No selection found");
+      this.close();
+      return
+    }
+
+    super.onOpen();
+    const input: HTMLInputElement | null = this.modalEl.querySelector(".prompt-input");
+    const button: HTMLButtonElement | null = this.modalEl.querySelector(".ss-query-submit-button");
+
+    if (input && button) {
+      input.value = selection;
+      input.dispatchEvent(new InputEvent("input"));
+      button.click();
+    }
+  }
+
+  async getSuggestions(text: string): Promise<Suggestion[]> {
+    const wasmSuggestions: WASMSuggestion[] = await plugin.get_suggestions_for_text(this.app, this.settings, text);
+    const suggestions: Suggestion[] = wasmSuggestions.map(wasmSuggestion => new Suggestion(this.app, wasmSuggestion, this.settings.sectionDelimeterRegex));
+
+    await Promise.all(suggestions.map(suggestion => suggestion.addSuggestionFile().addSuggestionHeading()));
+
+    return disambiguate(suggestions);
+  }
+}
+
+// Finds notes similar to a set of selected notes, using their stored embeddings.
+export class SimilarNotesModal extends QueryModal {
+  files: TFile[];
+  mode: 'centroid' | 'union' = 'centroid';
+
+  constructor(app: App, settings: semanticSearchSettings, files: TFile[]) {
+    super(app, settings);
+    this.files = files;
+  }
+
+  onOpen(): void {
+    super.onOpen();
+    const input: HTMLInputElement | null = this.modalEl.querySelector(".prompt-input");
+    const button: HTMLButtonElement | null = this.modalEl.querySelector(".ss-query-submit-button");
+    const instructions = this.modalEl.querySelector(".prompt-instructions");
+
+    if (input && button && instructions) {
+      input.value = this.files.map(file => file.basename).join(", ");
+      input.readOnly = true;
+      // Seeds are looked up in the store, so searching costs nothing
+      instructions.querySelector(".prompt-instruction")?.setText("Similar to the selected notes (no API cost)");
+      const select = instructions.createEl("select", {cls: "dropdown"});
+      select.createEl("option", {text: "Similar to all notes together", value: "centroid"});
+      select.createEl("option", {text: "Similar to any of the notes", value: "union"});
+      select.addEventListener("change", () => {
+        this.mode = select.value as 'centroid' | 'union';
+        button.click();
+      });
+      button.click();
+    }
+  }
+
+  async getSuggestions(_: string): Promise<Suggestion[]> {
+    const names = this.files.map(file => file.name);
+    const wasmSuggestions: WASMSuggestion[] = await plugin.get_similar_to_notes(this.app, this.searchSettings(), names, this.mode);
+    const suggestions: Suggestion[] = wasmSuggestions.map(wasmSuggestion => new Suggestion(this.app, wasmSuggestion, this.settings.sectionDelimeterRegex));
+
+    await Promise.all(suggestions.map(suggestion => suggestion.addSuggestionFile().addSuggestionHeading()));
+
+    return disambiguate(suggestions);
+  }
+}
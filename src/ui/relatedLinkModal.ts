@@ -0,0 +1,50 @@
+import { App, Editor, SuggestModal } from "obsidian";
+
+// Mirrors LinkCandidate in src/links.rs
+export type LinkCandidate = {
+  wikilink: string
+  path: string
+  headings: string[]
+  score: number
+}
+
+// The paragraph around the cursor: the lines between the nearest blank lines above and below
+export function currentParagraph(editor: Editor): string {
+  const cursor = editor.getCursor();
+  let start = cursor.line;
+  let end = cursor.line;
+  while (start > 0 && editor.getLine(start - 1).trim() !== "") start--;
+  while (end < editor.lastLine() && editor.getLine(end + 1).trim() !== "") end++;
+  const lines: string[] = [];
+  for (let line = start; line <= end; line++) {
+    lines.push(editor.getLine(line));
+  }
+  return lines.join("\n");
+}
+
+// Lets the user pick one of the links suggested for a paragraph and inserts it at the cursor
+export class RelatedLinkModal extends SuggestModal<LinkCandidate> {
+  editor: Editor;
+  candidates: LinkCandidate[];
+
+  constructor(app: App, editor: Editor, candidates: LinkCandidate[]) {
+    super(app);
+    this.editor = editor;
+    this.candidates = candidates;
+    this.setPlaceholder("Insert a link to a related section");
+  }
+
+  getSuggestions(query: string): LinkCandidate[] {
+    const text = query.toLowerCase();
+    return this.candidates.filter(candidate => candidate.wikilink.toLowerCase().includes(text));
+  }
+
+  renderSuggestion(candidate: LinkCandidate, el: HTMLElement) {
+    el.createDiv({text: candidate.headings[candidate.headings.length - 1] ?? candidate.path, cls: "suggestion-title"});
+    el.createDiv({text: `${candidate.wikilink} (${(candidate.score * 100).toFixed(1)}%)`, cls: "suggestion-note"});
+  }
+
+  onChooseSuggestion(candidate: LinkCandidate) {
+    this.editor.replaceSelection(candidate.wikilink);
+  }
+}
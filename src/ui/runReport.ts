@@ -0,0 +1,46 @@
+import { WASMError } from "./errors";
+
+// Mirrors RunManifest in src/manifest.rs
+export type RunManifest = {
+  command: string
+  started_at: number
+  finished_at: number
+  success: boolean
+  error: WASMError | null
+  stages: { name: string, duration_ms: number, items: number }[]
+  warnings: string[]
+  skipped: { path: string, reason: string }[]
+}
+
+// Renders the manifest of the last Generate Input or Generate Embeddings run.
+export function renderRunReport(el: HTMLElement, manifest: RunManifest | null) {
+  el.empty();
+  if (manifest === null) {
+    el.createEl("p", {text: "Nothing has been generated yet."});
+    return;
+  }
+
+  const finished = new Date(manifest.finished_at).toLocaleString();
+  const seconds = ((manifest.finished_at - manifest.started_at) / 1000).toFixed(1);
+  const status = manifest.success ? "succeeded" : `failed: ${manifest.error?.message ?? "unknown error"}`;
+  el.createEl("p", {text: `'${manifest.command}' ${status} (${finished}, ${seconds}s)`});
+
+  const stages = el.createEl("ul");
+  manifest.stages.forEach(stage => {
+    stages.createEl("li", {text: `${stage.name}: ${Math.round(stage.duration_ms)}ms, ${stage.items} items`});
+  });
+
+  if (manifest.warnings.length > 0) {
+    const details = el.createEl("details");
+    details.createEl("summary", {text: `${manifest.warnings.length} warnings`});
+    const list = details.createEl("ul");
+    manifest.warnings.forEach(warning => list.createEl("li", {text: warning}));
+  }
+
+  if (manifest.skipped.length > 0) {
+    const details = el.createEl("details");
+    details.createEl("summary", {text: `${manifest.skipped.length} skipped files`});
+    const list = details.createEl("ul");
+    manifest.skipped.forEach(skipped => list.createEl("li", {text: `${skipped.path}: ${skipped.reason}`}));
+  }
+}
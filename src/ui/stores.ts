@@ -0,0 +1,8 @@
+// Mirrors StoreEntry in src/stores.rs
+export type StoreEntry = {
+  name: string
+  model: string
+  path: string
+  records: number
+  updated_at: number
+}
@@ -1,11 +1,43 @@
 import { App, Loc, Pos, SearchMatchPart, SearchResult, TFile } from "obsidian";
 import Fuse from 'fuse.js';
 
+// Lines of the note a result was embedded from, counted from 0, both ends included
+export type LineRange = {
+  start: number
+  end: number
+}
+
+// Mirrors Snippet in src/snippets.rs: an excerpt of the result's text, shown as
+// before, sentence and after one after another with the sentence highlighted
+export type Snippet = {
+  before: string
+  sentence: string
+  after: string
+}
+
+// Mirrors SectionLink in src/links.rs: where to open a result, with the headings
+// its section is nested under, outermost first and ending with its own
+export type SectionLink = {
+  path: string
+  headings: string[]
+  line: number | null
+  linkText: string
+  url: string
+}
+
 export type WASMSuggestion = {
   name: string
   header: string
+  score: number
+  lines?: LineRange | null
+  snippet?: Snippet | null
+  link?: SectionLink | null
 }
 
+// Results of a search, with the number of stored sections left out because their embeddings
+// are all zeros or not numbers
+export type WASMSuggestions = WASMSuggestion[] & { skipped?: number, malformed?: number }
+
 type Section = {
   text: string;
   start: number;
@@ -16,7 +48,15 @@ export class Suggestion {
   app: App;
   name: string;
   header: string;
+  // Header shown in result lists, with extra context when it is ambiguous
+  displayHeader: string;
+  // Heading above the one this section belongs to, if any
+  parentHeading: string | undefined;
   pos: Pos | undefined;
+  // Set for results embedded from notes since line offsets were stored
+  lines: LineRange | undefined;
+  snippet: Snippet | undefined;
+  link: SectionLink | undefined;
   file: TFile | undefined;
   match: SearchResult | undefined;
   sectionDelimeterRegex: string;
@@ -25,18 +65,39 @@ export class Suggestion {
     this.app = app;
     this.name = wasmSuggestion.name;
     this.header = wasmSuggestion.header;
+    this.displayHeader = wasmSuggestion.header;
+    this.lines = wasmSuggestion.lines ?? undefined;
+    this.snippet = wasmSuggestion.snippet ?? undefined;
+    this.link = wasmSuggestion.link ?? undefined;
     this.sectionDelimeterRegex = sectionDelimeterRegex;
   }
 
   // Find corresponding suggestion file
   addSuggestionFile() : Suggestion {
-    const files = this.app.vault.getMarkdownFiles();
+    const files = this.app.vault.getFiles();
     const matching_file = files.find(file => file.name === this.name);
     this.file = matching_file;
     return this;
   }
 
+  noteTitle(): string {
+    return this.file?.basename ?? this.name.replace(/\.md$/, "");
+  }
+
   async addSuggestionHeading() {
+    // PDF pages and canvas cards have no lines of text to locate the header in
+    if (this.file?.extension === "pdf" || this.file?.extension === "canvas") {
+      this.match = {score: 0, matches: []};
+      return;
+    }
+    if (this.file && this.lines) {
+      // the stored offsets point at the exact chunk, no need to search for the header
+      const contents = await this.app.vault.cachedRead(this.file);
+      this.match = {score: 0, matches: []};
+      this.pos = {start: getLocFromLine(contents, this.lines.start), end: getLocFromLine(contents, this.lines.end)};
+      this.parentHeading = this.findParentHeading(this.lines.start);
+      return;
+    }
     if (this.file) {
       const contents = await this.app.vault.cachedRead(this.file);
       const lines = contents.split("\n");
@@ -84,9 +145,24 @@ export class Suggestion {
         }
 
         this.pos = {start: getLocFromIndex(contents, bestMatch.item.start), end: getLocFromIndex(contents, bestMatch.item.end)};
+        this.parentHeading = this.findParentHeading(this.pos.start.line - 1);
       }
     }
   }
+
+  // The nearest heading above the section's own heading with a lower level.
+  findParentHeading(line: number): string | undefined {
+    if (!this.file) {
+      return undefined;
+    }
+    const headings = (this.app.metadataCache.getFileCache(this.file)?.headings ?? [])
+      .filter(heading => heading.position.start.line <= line);
+    const own = headings.pop();
+    if (!own) {
+      return undefined;
+    }
+    return headings.reverse().find(heading => heading.level < own.level)?.heading;
+  }
 }
 
 function getLocFromIndex(
@@ -101,3 +177,11 @@ function getLocFromIndex(
 
   return { line: l, col: 0, offset: index };
 }
+
+// Editor location of the start of a line counted from 0, clamped to the last line
+function getLocFromLine(content: string, line: number): Loc {
+  const lines = content.split("\n");
+  const clamped = Math.min(line, lines.length - 1);
+  const offset = lines.slice(0, clamped).reduce((total, text) => total + text.length + 1, 0);
+  return { line: clamped, col: 0, offset };
+}
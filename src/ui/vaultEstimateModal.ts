@@ -0,0 +1,44 @@
+import { App, Modal, setIcon } from "obsidian";
+import { VaultEstimate } from "src/api";
+import { semanticSearchSettings } from "src/settings/settings";
+import { noticeForError } from "./errors";
+
+import * as plugin from "../../pkg/obsidian_rust_plugin.js";
+
+// Shows what embedding the vault would cost, per folder, straight from the notes
+export class VaultEstimateModal extends Modal {
+  settings: semanticSearchSettings;
+
+  constructor(app: App, settings: semanticSearchSettings) {
+    super(app);
+    this.settings = settings;
+  }
+
+  async onOpen() {
+    const contentEl = this.contentEl;
+    contentEl.createEl("h2", {text: "Embedding cost estimate"});
+    const resultsDiv = contentEl.createDiv();
+    setIcon(resultsDiv, "loader");
+
+    let estimate: VaultEstimate;
+    try {
+      estimate = await plugin.estimate_vault_cost(this.app, this.settings);
+    } catch (error) {
+      resultsDiv.replaceChildren();
+      noticeForError(error, "Failed to estimate the cost");
+      return;
+    }
+
+    resultsDiv.replaceChildren();
+    const total = estimate.estimate;
+    resultsDiv.createDiv({text: `$${total.dollars.toFixed(4)} (${total.tokens} tokens in ${total.requests} requests to ${total.model})`});
+    const list = resultsDiv.createEl("ul");
+    estimate.folders.forEach(folder => {
+      list.createEl("li", {text: `${folder.folder || "/"}: ${folder.notes} notes, ${folder.chunks} sections, ${folder.tokens} tokens, $${folder.dollars.toFixed(4)}`});
+    });
+  }
+
+  onClose() {
+    this.contentEl.empty();
+  }
+}